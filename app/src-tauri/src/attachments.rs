@@ -0,0 +1,40 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use todo_shared::attachments;
+
+/// Where the contents with `hash` are cached, or an error for anything that
+/// is not a hash and so could point outside the cache
+pub fn cache_path(dir: &Path, hash: &str) -> io::Result<PathBuf> {
+    if !attachments::is_valid_hash(hash) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Not an attachment hash: {}", hash)));
+    }
+    Ok(dir.join(hash))
+}
+
+/// Cache downloaded contents, checking that they are what was attached
+pub async fn store(dir: &Path, hash: &str, contents: &[u8]) -> io::Result<PathBuf> {
+    let path = cache_path(dir, hash)?;
+    if attachments::content_hash(contents) != hash {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Attachment contents do not match their hash"));
+    }
+    tokio::fs::create_dir_all(dir).await?;
+    tokio::fs::write(&path, contents).await?;
+    Ok(path)
+}
+
+/// Remove cached contents no attachment in `keep` uses any more
+pub async fn prune(dir: &Path, keep: &HashSet<String>) -> io::Result<()> {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !keep.contains(&name) {
+            tokio::fs::remove_file(entry.path()).await?;
+        }
+    }
+    Ok(())
+}
@@ -1,17 +1,118 @@
+use crate::attachments;
+use crate::db;
+use crate::history::{self, History, HistoryEntry, TodoChange};
 use crate::models::{CreateTodoRequest, Priority, Todo, UpdateTodoRequest};
-use chrono::Utc;
-use sqlx::SqlitePool;
+use crate::pinned_http::{self, HttpRequest, HttpResponse};
+use crate::reminders::Scheduler;
+use crate::revisions::{self, Revision};
+use crate::settings;
+use chrono::{Duration, Utc};
+use sqlx::types::Json;
+use sqlx::{Sqlite, SqliteConnection, SqlitePool, Transaction};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
 use tauri::State;
+use todo_shared::attachments::Attachment;
+use todo_shared::comments::{self, Comment};
+use todo_shared::due::{self, DueBucket};
+use todo_shared::formats::{self, ExportFormat, TodoRecord};
+use todo_shared::import::{self, ImportReport};
+use todo_shared::quick_add;
+use todo_shared::recurrence;
+use todo_shared::reminders::{self, Reminder};
+use todo_shared::views::{self, SavedView, SmartView, ViewCount, ViewFilter};
+use tokio::sync::Mutex;
+
+/// Days a todo stays in the trash before it is purged
+pub const DEFAULT_TRASH_RETENTION_DAYS: i64 = 30;
 
 /// Application state holding database pool
 pub struct AppState {
     pub db: SqlitePool,
+    /// How long trashed todos are kept before being purged
+    pub trash_retention: Duration,
+    /// Undo/redo stacks of local mutations
+    pub history: Mutex<History>,
+    /// Fires reminders as they come due
+    pub reminders: Arc<Scheduler>,
+    /// Downloaded attachment contents, one file per content hash
+    pub attachments_dir: PathBuf,
+    /// Client for the sync server, built for the certificate pin it was made with
+    pub pinned_client: Mutex<Option<(String, reqwest::Client)>>,
+    /// Trace export, when a collector is configured
+    #[cfg(feature = "otel")]
+    pub telemetry: Option<crate::telemetry::Telemetry>,
+}
+
+/// Record the revisions an undoable action made, commit them with the action's
+/// own writes in `tx`, then add the action to the history
+async fn record(
+    state: &AppState,
+    mut tx: Transaction<'_, Sqlite>,
+    action: &str,
+    changes: Vec<TodoChange>,
+) -> Result<(), String> {
+    record_revisions(&mut tx, action, &changes).await?;
+    tx.commit().await.map_err(|e| e.to_string())?;
+    state
+        .history
+        .lock()
+        .await
+        .record(&state.db, HistoryEntry::new(action, changes))
+        .await
+        .map_err(|e| e.to_string())
 }
 
-/// Get all todos
+/// Record the revisions of a change on the connection that made it
+async fn record_revisions(conn: &mut SqliteConnection, action: &str, changes: &[TodoChange]) -> Result<(), String> {
+    let device_id = settings::device_id(&mut *conn).await.map_err(|e| e.to_string())?;
+    revisions::record(conn, &device_id, action, changes)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Check that `parent_id` can be the parent of the todo `todo_id` (`None` for
+/// a new todo). Subtasks are one level deep, so neither the parent nor a todo
+/// with subtasks of its own can be a subtask.
+async fn check_parent(db: &SqlitePool, todo_id: Option<&str>, parent_id: &str) -> Result<(), String> {
+    if todo_id == Some(parent_id) {
+        return Err("A todo cannot be its own subtask".to_string());
+    }
+    let parent: Option<Todo> = sqlx::query_as("SELECT * FROM todos WHERE id = ? AND deleted_at IS NULL")
+        .bind(parent_id)
+        .fetch_optional(db)
+        .await
+        .map_err(|e| e.to_string())?;
+    match parent {
+        None => return Err(format!("Todo not found: {}", parent_id)),
+        Some(parent) if parent.parent_id.is_some() => return Err("Subtasks cannot have subtasks".to_string()),
+        Some(_) => {}
+    }
+    if let Some(todo_id) = todo_id {
+        let has_subtasks: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM todos WHERE parent_id = ? AND deleted_at IS NULL)"
+        )
+        .bind(todo_id)
+        .fetch_one(db)
+        .await
+        .map_err(|e| e.to_string())?;
+        if has_subtasks {
+            return Err("A todo with subtasks cannot become a subtask".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Get all todos, and those in the trash too if `include_deleted` is set
 #[tauri::command]
-pub async fn get_todos(state: State<'_, AppState>) -> Result<Vec<Todo>, String> {
-    sqlx::query_as::<_, Todo>("SELECT * FROM todos ORDER BY created_at DESC")
+pub async fn get_todos(include_deleted: Option<bool>, state: State<'_, AppState>) -> Result<Vec<Todo>, String> {
+    let query = if include_deleted.unwrap_or(false) {
+        "SELECT * FROM todos ORDER BY created_at DESC"
+    } else {
+        "SELECT * FROM todos WHERE deleted_at IS NULL ORDER BY created_at DESC"
+    };
+    sqlx::query_as::<_, Todo>(query)
         .fetch_all(&state.db)
         .await
         .map_err(|e| e.to_string())
@@ -20,42 +121,89 @@ pub async fn get_todos(state: State<'_, AppState>) -> Result<Vec<Todo>, String>
 /// Create a new todo
 #[tauri::command]
 pub async fn create_todo(request: CreateTodoRequest, state: State<'_, AppState>) -> Result<Todo, String> {
+    if let Some(recurrence) = &request.recurrence {
+        if !recurrence::is_valid_rrule(recurrence) {
+            return Err(format!("Invalid recurrence rule: {}", recurrence));
+        }
+    }
+    if request.reminders.as_ref().is_some_and(|reminders| !reminders.iter().all(Reminder::is_valid)) {
+        return Err(format!("Reminders can be at most {} minutes from the due date", reminders::MAX_BEFORE_DUE_MINUTES));
+    }
+    
+    if let Some(parent_id) = &request.parent_id {
+        check_parent(&state.db, None, parent_id).await?;
+    }
+    
     let mut todo = Todo::new(
         request.title,
         request.description,
         request.priority.unwrap_or(Priority::Medium),
     );
     todo.due_date = request.due_date;
+    todo.all_day = request.all_day.unwrap_or(false);
+    todo.list = request.list;
+    if let Some(tags) = request.tags {
+        todo.tags = Json(tags);
+    }
+    todo.recurrence = request.recurrence;
+    if let Some(reminders) = request.reminders {
+        todo.reminders = Json(reminders);
+    }
+    todo.list_id = request.list_id;
+    todo.assignee_id = request.assignee_id;
+    todo.parent_id = request.parent_id;
     
+    let mut tx = state.db.begin().await.map_err(|e| e.to_string())?;
     sqlx::query(
         r#"
-        INSERT INTO todos (id, title, description, completed, priority, created_at, updated_at, due_date)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        INSERT INTO todos (id, title, description, completed, priority, created_at, updated_at, due_date, all_day, list, tags, recurrence, reminders, list_id, assignee_id, parent_id)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         "#
     )
     .bind(&todo.id)
     .bind(&todo.title)
     .bind(&todo.description)
     .bind(todo.completed)
-    .bind(&todo.priority)
-    .bind(&todo.created_at)
-    .bind(&todo.updated_at)
-    .bind(&todo.due_date)
-    .execute(&state.db)
+    .bind(todo.priority)
+    .bind(todo.created_at)
+    .bind(todo.updated_at)
+    .bind(todo.due_date)
+    .bind(todo.all_day)
+    .bind(&todo.list)
+    .bind(&todo.tags)
+    .bind(&todo.recurrence)
+    .bind(&todo.reminders)
+    .bind(&todo.list_id)
+    .bind(&todo.assignee_id)
+    .bind(&todo.parent_id)
+    .execute(&mut *tx)
     .await
     .map_err(|e| e.to_string())?;
     
+    record(&state, tx, "create", vec![TodoChange { before: None, after: Some(todo.clone()) }]).await?;
+    
+    state.reminders.reschedule();
     Ok(todo)
 }
 
 /// Update an existing todo
 #[tauri::command]
 pub async fn update_todo(id: String, request: UpdateTodoRequest, state: State<'_, AppState>) -> Result<Todo, String> {
+    if let Some(recurrence) = &request.recurrence {
+        if !recurrence::is_valid_rrule(recurrence) {
+            return Err(format!("Invalid recurrence rule: {}", recurrence));
+        }
+    }
+    if request.reminders.as_ref().is_some_and(|reminders| !reminders.iter().all(Reminder::is_valid)) {
+        return Err(format!("Reminders can be at most {} minutes from the due date", reminders::MAX_BEFORE_DUE_MINUTES));
+    }
+    
     let mut todo: Todo = sqlx::query_as("SELECT * FROM todos WHERE id = ?")
         .bind(&id)
         .fetch_one(&state.db)
         .await
         .map_err(|e| e.to_string())?;
+    let before = todo.clone();
     
     if let Some(title) = request.title {
         todo.title = title;
@@ -72,26 +220,68 @@ pub async fn update_todo(id: String, request: UpdateTodoRequest, state: State<'_
     if let Some(due_date) = request.due_date {
         todo.due_date = Some(due_date);
     }
+    if let Some(all_day) = request.all_day {
+        todo.all_day = all_day;
+    }
+    if let Some(list) = request.list {
+        todo.list = Some(list);
+    }
+    if let Some(tags) = request.tags {
+        todo.tags = Json(tags);
+    }
+    if let Some(recurrence) = request.recurrence {
+        todo.recurrence = Some(recurrence);
+    }
+    if let Some(reminders) = request.reminders {
+        todo.reminders = Json(reminders);
+    }
+    if let Some(list_id) = request.list_id {
+        todo.list_id = (!list_id.is_empty()).then_some(list_id);
+    }
+    if let Some(assignee_id) = request.assignee_id {
+        todo.assignee_id = (!assignee_id.is_empty()).then_some(assignee_id);
+    }
+    if let Some(parent_id) = request.parent_id {
+        let parent_id = (!parent_id.is_empty()).then_some(parent_id);
+        if parent_id != todo.parent_id {
+            if let Some(parent_id) = &parent_id {
+                check_parent(&state.db, Some(&id), parent_id).await?;
+            }
+            todo.parent_id = parent_id;
+        }
+    }
     todo.updated_at = Utc::now();
     
+    let mut tx = state.db.begin().await.map_err(|e| e.to_string())?;
     sqlx::query(
         r#"
         UPDATE todos 
-        SET title = ?, description = ?, completed = ?, priority = ?, updated_at = ?, due_date = ?
+        SET title = ?, description = ?, completed = ?, priority = ?, updated_at = ?, due_date = ?, all_day = ?, list = ?, tags = ?, recurrence = ?, reminders = ?, list_id = ?, assignee_id = ?, parent_id = ?
         WHERE id = ?
         "#
     )
     .bind(&todo.title)
     .bind(&todo.description)
     .bind(todo.completed)
-    .bind(&todo.priority)
-    .bind(&todo.updated_at)
-    .bind(&todo.due_date)
+    .bind(todo.priority)
+    .bind(todo.updated_at)
+    .bind(todo.due_date)
+    .bind(todo.all_day)
+    .bind(&todo.list)
+    .bind(&todo.tags)
+    .bind(&todo.recurrence)
+    .bind(&todo.reminders)
+    .bind(&todo.list_id)
+    .bind(&todo.assignee_id)
+    .bind(&todo.parent_id)
     .bind(&todo.id)
-    .execute(&state.db)
+    .execute(&mut *tx)
     .await
     .map_err(|e| e.to_string())?;
     
+    record(&state, tx, "update", vec![TodoChange { before: Some(before), after: Some(todo.clone()) }]).await?;
+    
+    state.reminders.reschedule();
     Ok(todo)
 }
 
@@ -103,73 +293,943 @@ pub async fn toggle_todo(id: String, state: State<'_, AppState>) -> Result<Todo,
         .fetch_one(&state.db)
         .await
         .map_err(|e| e.to_string())?;
+    let before = todo.clone();
     
     todo.completed = !todo.completed;
     todo.updated_at = Utc::now();
     
+    let mut tx = state.db.begin().await.map_err(|e| e.to_string())?;
     sqlx::query("UPDATE todos SET completed = ?, updated_at = ? WHERE id = ?")
         .bind(todo.completed)
-        .bind(&todo.updated_at)
+        .bind(todo.updated_at)
         .bind(&id)
-        .execute(&state.db)
+        .execute(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
     
+    record(&state, tx, "toggle", vec![TodoChange { before: Some(before), after: Some(todo.clone()) }]).await?;
+    
+    state.reminders.reschedule();
     Ok(todo)
 }
 
-/// Delete a todo
+/// Move a todo to the trash
 #[tauri::command]
 pub async fn delete_todo(id: String, state: State<'_, AppState>) -> Result<(), String> {
-    sqlx::query("DELETE FROM todos WHERE id = ?")
+    let before: Option<Todo> = sqlx::query_as("SELECT * FROM todos WHERE id = ? AND deleted_at IS NULL")
         .bind(&id)
-        .execute(&state.db)
+        .fetch_optional(&state.db)
         .await
         .map_err(|e| e.to_string())?;
+    let Some(before) = before else {
+        return Ok(());
+    };
+    
+    let now = Utc::now();
+    let mut tx = state.db.begin().await.map_err(|e| e.to_string())?;
+    sqlx::query("UPDATE todos SET deleted_at = ?, updated_at = ? WHERE id = ?")
+        .bind(now)
+        .bind(now)
+        .bind(&id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    
+    let mut after = before.clone();
+    after.deleted_at = Some(now);
+    after.updated_at = now;
+    record(&state, tx, "delete", vec![TodoChange { before: Some(before), after: Some(after) }]).await?;
+    
+    state.reminders.reschedule();
     Ok(())
 }
 
-/// Sync local state with remote todos
+/// Sync local state with remote todos, and saved views, comments and
+/// attachment metadata if the server sent them
 #[tauri::command]
-pub async fn sync_local(remote_todos: Vec<Todo>, state: State<'_, AppState>) -> Result<(), String> {
+pub async fn sync_local(
+    remote_todos: Vec<Todo>,
+    remote_views: Option<Vec<SavedView>>,
+    remote_comments: Option<Vec<Comment>>,
+    remote_attachments: Option<Vec<Attachment>>,
+    remote_purged: Option<Vec<String>>,
+    sent_purged: Option<Vec<String>>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    #[cfg(feature = "otel")]
+    let _span = state.telemetry.as_ref().and_then(|telemetry| telemetry.sync_step("sync_local"));
     let mut tx = state.db.begin().await.map_err(|e| e.to_string())?;
     
+    // The server has seen the purges sent with this sync, so its copy wins from
+    // here on; purges made since still need to keep their todos from coming back
+    if let Some(sent_purged) = sent_purged {
+        let ids = serde_json::to_string(&sent_purged).map_err(|e| e.to_string())?;
+        sqlx::query("DELETE FROM purged_todos WHERE id IN (SELECT value FROM json_each(?))")
+            .bind(ids)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    let tombstones: HashSet<String> = sqlx::query_scalar("SELECT id FROM purged_todos")
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .collect();
+    
+    let mut local: HashMap<String, Todo> = sqlx::query_as::<_, Todo>("SELECT * FROM todos")
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|todo| (todo.id.clone(), todo))
+        .collect();
+    let mut changes = Vec::with_capacity(remote_todos.len());
+    
     sqlx::query("DELETE FROM todos")
         .execute(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
         
     for todo in remote_todos {
+        if tombstones.contains(&todo.id) {
+            continue;
+        }
         sqlx::query(
             r#"
-            INSERT INTO todos (id, title, description, completed, priority, created_at, updated_at, due_date)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            INSERT INTO todos (id, title, description, completed, priority, created_at, updated_at, due_date, all_day, list, tags, recurrence, reminders, list_id, assignee_id, parent_id, deleted_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#
         )
         .bind(&todo.id)
         .bind(&todo.title)
         .bind(&todo.description)
         .bind(todo.completed)
-        .bind(&todo.priority)
-        .bind(&todo.created_at)
-        .bind(&todo.updated_at)
-        .bind(&todo.due_date)
+        .bind(todo.priority)
+        .bind(todo.created_at)
+        .bind(todo.updated_at)
+        .bind(todo.due_date)
+        .bind(todo.all_day)
+        .bind(&todo.list)
+        .bind(&todo.tags)
+        .bind(&todo.recurrence)
+        .bind(&todo.reminders)
+        .bind(&todo.list_id)
+        .bind(&todo.assignee_id)
+        .bind(&todo.parent_id)
+        .bind(todo.deleted_at)
         .execute(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
+        changes.push(TodoChange { before: local.remove(&todo.id), after: Some(todo) });
     }
     
+    if let Some(remote_views) = remote_views {
+        sqlx::query("DELETE FROM views")
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+        for view in remote_views {
+            insert_view(&mut tx, &view).await.map_err(|e| e.to_string())?;
+        }
+    }
+    
+    if let Some(remote_comments) = remote_comments {
+        sqlx::query("DELETE FROM comments")
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+        for comment in remote_comments {
+            insert_comment(&mut tx, &comment).await.map_err(|e| e.to_string())?;
+        }
+    }
+    
+    let synced_attachments = remote_attachments.is_some();
+    if let Some(remote_attachments) = remote_attachments {
+        sqlx::query("DELETE FROM attachments")
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+        for attachment in remote_attachments {
+            insert_attachment(&mut tx, &attachment).await.map_err(|e| e.to_string())?;
+        }
+    }
+    
+    if let Some(remote_purged) = remote_purged {
+        db::delete_todo_records(&mut tx, &remote_purged).await.map_err(|e| e.to_string())?;
+    }
+    
+    record_revisions(&mut tx, "sync", &changes).await?;
     tx.commit().await.map_err(|e| e.to_string())?;
+    if synced_attachments {
+        prune_attachment_cache(&state).await?;
+    }
+    state.reminders.reschedule();
     Ok(())
 }
 
-/// Clear all completed todos
+/// Move all completed todos to the trash
 #[tauri::command]
 pub async fn clear_completed(state: State<'_, AppState>) -> Result<Vec<Todo>, String> {
-    sqlx::query("DELETE FROM todos WHERE completed = 1")
-        .execute(&state.db)
+    let mut tx = state.db.begin().await.map_err(|e| e.to_string())?;
+    let cleared: Vec<Todo> = sqlx::query_as("SELECT * FROM todos WHERE completed = 1 AND deleted_at IS NULL")
+        .fetch_all(&mut *tx)
         .await
         .map_err(|e| e.to_string())?;
+    
+    let now = Utc::now();
+    sqlx::query("UPDATE todos SET deleted_at = ?, updated_at = ? WHERE completed = 1 AND deleted_at IS NULL")
+        .bind(now)
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    
+    let changes = cleared
+        .into_iter()
+        .map(|before| {
+            let mut after = before.clone();
+            after.deleted_at = Some(now);
+            after.updated_at = now;
+            TodoChange { before: Some(before), after: Some(after) }
+        })
+        .collect();
+    record(&state, tx, "clear_completed", changes).await?;
         
-    get_todos(state).await
+    state.reminders.reschedule();
+    get_todos(None, state).await
+}
+
+/// List the todos in the trash, purging any past the retention period first
+#[tauri::command]
+pub async fn list_trash(state: State<'_, AppState>) -> Result<Vec<Todo>, String> {
+    db::purge_trash(&state.db, Utc::now() - state.trash_retention)
+        .await
+        .map_err(|e| e.to_string())?;
+    
+    sqlx::query_as::<_, Todo>("SELECT * FROM todos WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC")
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Ids of todos purged on this device that the server has not heard about yet
+#[tauri::command]
+pub async fn list_purged(state: State<'_, AppState>) -> Result<Vec<String>, String> {
+    sqlx::query_scalar("SELECT id FROM purged_todos ORDER BY purged_at")
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Restore a todo from the trash
+#[tauri::command]
+pub async fn restore_todo(id: String, state: State<'_, AppState>) -> Result<Todo, String> {
+    let mut todo: Todo = sqlx::query_as("SELECT * FROM todos WHERE id = ? AND deleted_at IS NOT NULL")
+        .bind(&id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    let before = todo.clone();
+    
+    // Bumping updated_at lets the restore win over trashed copies on other devices
+    todo.deleted_at = None;
+    todo.updated_at = Utc::now();
+    
+    let mut tx = state.db.begin().await.map_err(|e| e.to_string())?;
+    sqlx::query("UPDATE todos SET deleted_at = NULL, updated_at = ? WHERE id = ?")
+        .bind(todo.updated_at)
+        .bind(&id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    record_revisions(&mut tx, "restore", &[TodoChange { before: Some(before), after: Some(todo.clone()) }]).await?;
+    tx.commit().await.map_err(|e| e.to_string())?;
+    
+    state.reminders.reschedule();
+    Ok(todo)
+}
+
+/// Permanently delete everything in the trash
+#[tauri::command]
+pub async fn empty_trash(state: State<'_, AppState>) -> Result<(), String> {
+    let mut tx = state.db.begin().await.map_err(|e| e.to_string())?;
+    let purged: Vec<Todo> = sqlx::query_as("SELECT * FROM todos WHERE deleted_at IS NOT NULL")
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    let ids: Vec<String> = purged.iter().map(|todo| todo.id.clone()).collect();
+    db::purge_todos(&mut tx, &ids).await.map_err(|e| e.to_string())?;
+    
+    let changes: Vec<TodoChange> = purged
+        .into_iter()
+        .map(|todo| TodoChange { before: Some(todo), after: None })
+        .collect();
+    record_revisions(&mut tx, "purge", &changes).await?;
+    tx.commit().await.map_err(|e| e.to_string())
+}
+
+/// Undo the most recent local mutation, returning the todos it touched
+#[tauri::command]
+pub async fn undo(state: State<'_, AppState>) -> Result<Vec<Todo>, String> {
+    let changes = state
+        .history
+        .lock()
+        .await
+        .undo(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    state.reminders.reschedule();
+    Ok(changes.into_iter().filter_map(|change| change.after).collect())
+}
+
+/// Redo the most recently undone mutation, returning the todos it touched
+#[tauri::command]
+pub async fn redo(state: State<'_, AppState>) -> Result<Vec<Todo>, String> {
+    let changes = state
+        .history
+        .lock()
+        .await
+        .redo(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    state.reminders.reschedule();
+    Ok(changes.into_iter().filter_map(|change| change.after).collect())
+}
+
+/// Get the revision history of a todo, newest first
+#[tauri::command]
+pub async fn get_todo_history(id: String, state: State<'_, AppState>) -> Result<Vec<Revision>, String> {
+    revisions::history(&state.db, &id).await.map_err(|e| e.to_string())
+}
+
+/// Put a todo back the way it was after an earlier revision. Like other edits,
+/// the revert can be undone and syncs as a fresh change.
+#[tauri::command]
+pub async fn revert_todo(id: String, revision_id: i64, state: State<'_, AppState>) -> Result<Todo, String> {
+    let snapshot = revisions::snapshot(&state.db, &id, revision_id)
+        .await?
+        .ok_or_else(|| format!("Revision {} deleted the todo for good", revision_id))?;
+    
+    let mut tx = state.db.begin().await.map_err(|e| e.to_string())?;
+    let change = history::apply_snapshot(&mut tx, &id, Some(&snapshot))
+        .await
+        .map_err(|e| e.to_string())?;
+    
+    let todo = change.after.clone().ok_or_else(|| format!("Todo not found: {}", id))?;
+    record(&state, tx, "revert", vec![change]).await?;
+    
+    state.reminders.reschedule();
+    Ok(todo)
+}
+
+/// Get the id this device sends with requests to the server
+#[tauri::command]
+pub async fn get_device_id(state: State<'_, AppState>) -> Result<String, String> {
+    let mut conn = state.db.acquire().await.map_err(|e| e.to_string())?;
+    settings::device_id(&mut conn).await.map_err(|e| e.to_string())
+}
+
+/// Get how many days revisions are kept
+#[tauri::command]
+pub async fn get_revision_retention(state: State<'_, AppState>) -> Result<i64, String> {
+    let retention = settings::revision_retention(&state.db).await.map_err(|e| e.to_string())?;
+    Ok(retention.num_days())
+}
+
+/// Set how many days revisions are kept, purging any older ones now
+#[tauri::command]
+pub async fn set_revision_retention(days: i64, state: State<'_, AppState>) -> Result<i64, String> {
+    if !(1..=todo_shared::revisions::MAX_RETENTION_DAYS).contains(&days) {
+        return Err(format!(
+            "Retention must be between 1 and {} days, got {}",
+            todo_shared::revisions::MAX_RETENTION_DAYS,
+            days
+        ));
+    }
+    let cutoff = Duration::try_days(days)
+        .and_then(|retention| Utc::now().checked_sub_signed(retention))
+        .ok_or_else(|| format!("Retention is too long: {} days", days))?;
+    settings::set_revision_retention(&state.db, days)
+        .await
+        .map_err(|e| e.to_string())?;
+    revisions::purge(&state.db, cutoff)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(days)
+}
+
+/// Whether the undo history is kept across restarts
+#[tauri::command]
+pub async fn get_history_persistence(state: State<'_, AppState>) -> Result<bool, String> {
+    Ok(state.history.lock().await.persisted())
+}
+
+/// Choose whether the undo history is kept across restarts. Turning it off
+/// deletes the history kept so far; the current session keeps its stacks.
+#[tauri::command]
+pub async fn set_history_persistence(persist: bool, state: State<'_, AppState>) -> Result<bool, String> {
+    let mut history = state.history.lock().await;
+    history
+        .set_persisted(&state.db, persist)
+        .await
+        .map_err(|e| e.to_string())?;
+    settings::set_persist_history(&state.db, persist)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(persist)
+}
+
+/// Make a request to the sync server, accepting only the certificate whose
+/// SHA-256 fingerprint is `pin`
+#[tauri::command]
+pub async fn pinned_fetch(request: HttpRequest, pin: String, state: State<'_, AppState>) -> Result<HttpResponse, String> {
+    let client = {
+        let mut cached = state.pinned_client.lock().await;
+        match cached.as_ref() {
+            Some((cached_pin, client)) if *cached_pin == pin => client.clone(),
+            _ => {
+                let client = pinned_http::client(&pin)?;
+                *cached = Some((pin, client.clone()));
+                client
+            }
+        }
+    };
+    pinned_http::send(&client, request).await
+}
+
+/// Start tracing a sync, returning the `traceparent` header to send with it,
+/// or nothing when traces are not exported
+#[tauri::command]
+#[cfg_attr(not(feature = "otel"), allow(unused_variables))]
+pub fn start_sync_trace(state: State<'_, AppState>) -> Option<String> {
+    #[cfg(feature = "otel")]
+    if let Some(telemetry) = &state.telemetry {
+        return Some(telemetry.start_sync());
+    }
+    None
+}
+
+/// End the traced sync, as failed if there is an `error`
+#[tauri::command]
+#[cfg_attr(not(feature = "otel"), allow(unused_variables))]
+pub fn finish_sync_trace(error: Option<String>, state: State<'_, AppState>) {
+    #[cfg(feature = "otel")]
+    if let Some(telemetry) = &state.telemetry {
+        telemetry.finish_sync(error);
+    }
+}
+
+/// Export all todos outside the trash in the given format
+#[tauri::command]
+pub async fn export_todos(format: ExportFormat, state: State<'_, AppState>) -> Result<String, String> {
+    let todos = sqlx::query_as::<_, Todo>("SELECT * FROM todos WHERE deleted_at IS NULL ORDER BY created_at")
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    
+    let records: Vec<TodoRecord> = todos.into_iter().map(TodoRecord::from).collect();
+    formats::export_todos(&records, format).map_err(|e| e.to_string())
+}
+
+/// Import todos, skipping duplicates. With `dry_run` nothing is written.
+#[tauri::command]
+pub async fn import_todos(
+    content: String,
+    format: ExportFormat,
+    dry_run: bool,
+    state: State<'_, AppState>,
+) -> Result<ImportReport, String> {
+    let incoming = formats::parse_todos(&content, format).map_err(|e| e.to_string())?;
+    
+    // Trashed todos count too, so that ids never collide
+    let existing = sqlx::query_as::<_, Todo>("SELECT * FROM todos")
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    let existing: Vec<TodoRecord> = existing.into_iter().map(TodoRecord::from).collect();
+    
+    let report = import::plan_import(&existing, incoming, dry_run);
+    let parents = import::check_imported(&report.imported).map_err(|e| e.to_string())?;
+    for parent_id in parents {
+        check_parent(&state.db, None, parent_id).await?;
+    }
+    if report.dry_run {
+        return Ok(report);
+    }
+    
+    // Imported todos count as changed now, so the next sync pushes them
+    let now = Utc::now();
+    let mut changes = Vec::with_capacity(report.imported.len());
+    let mut tx = state.db.begin().await.map_err(|e| e.to_string())?;
+    for record in &report.imported {
+        let mut todo = Todo::from(record.clone());
+        todo.updated_at = now;
+        sqlx::query(
+            r#"
+            INSERT INTO todos (id, title, description, completed, priority, created_at, updated_at, due_date, all_day, list, tags, recurrence, parent_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#
+        )
+        .bind(&todo.id)
+        .bind(&todo.title)
+        .bind(&todo.description)
+        .bind(todo.completed)
+        .bind(todo.priority)
+        .bind(todo.created_at)
+        .bind(todo.updated_at)
+        .bind(todo.due_date)
+        .bind(todo.all_day)
+        .bind(&todo.list)
+        .bind(&todo.tags)
+        .bind(&todo.recurrence)
+        .bind(&todo.parent_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+        changes.push(TodoChange { before: None, after: Some(todo) });
+    }
+    
+    record(&state, tx, "import", changes).await?;
+    
+    state.reminders.reschedule();
+    Ok(report)
+}
+
+/// Parse a natural-language line such as "Pay rent every month on the 1st !high tomorrow 9am"
+/// into a todo to create. Relative dates are resolved in `timezone`, an IANA name,
+/// or in the user's time zone.
+#[tauri::command]
+pub async fn parse_quick_add(
+    input: String,
+    timezone: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<CreateTodoRequest, String> {
+    let timezone = match timezone {
+        Some(timezone) => due::parse_timezone(&timezone)?,
+        None => settings::timezone(&state.db).await.map_err(|e| e.to_string())?,
+    };
+    let parsed = quick_add::parse(&input, &Utc::now().with_timezone(&timezone));
+    Ok(parsed.into())
+}
+
+/// Get the user's IANA time zone
+#[tauri::command]
+pub async fn get_timezone(state: State<'_, AppState>) -> Result<String, String> {
+    let timezone = settings::timezone(&state.db).await.map_err(|e| e.to_string())?;
+    Ok(timezone.name().to_string())
+}
+
+/// Set the user's time zone, used to work out which day todos are due on
+#[tauri::command]
+pub async fn set_timezone(timezone: String, state: State<'_, AppState>) -> Result<String, String> {
+    let timezone = due::parse_timezone(&timezone)?;
+    settings::set_timezone(&state.db, timezone)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(timezone.name().to_string())
+}
+
+/// Open todos due today in the user's time zone, including those whose time has passed
+#[tauri::command]
+pub async fn get_today(state: State<'_, AppState>) -> Result<Vec<Todo>, String> {
+    todos_due(&state, DueBucket::Today, due::DEFAULT_UPCOMING_DAYS).await
+}
+
+/// Open todos due before today in the user's time zone
+#[tauri::command]
+pub async fn get_overdue(state: State<'_, AppState>) -> Result<Vec<Todo>, String> {
+    todos_due(&state, DueBucket::Overdue, due::DEFAULT_UPCOMING_DAYS).await
+}
+
+/// Open todos due in the `days` days after today, 7 by default
+#[tauri::command]
+pub async fn get_upcoming(days: Option<i64>, state: State<'_, AppState>) -> Result<Vec<Todo>, String> {
+    todos_due(&state, DueBucket::Upcoming, days.unwrap_or(due::DEFAULT_UPCOMING_DAYS)).await
+}
+
+/// Open todos whose due date falls in `bucket`
+async fn todos_due(state: &AppState, bucket: DueBucket, upcoming_days: i64) -> Result<Vec<Todo>, String> {
+    let timezone = settings::timezone(&state.db).await.map_err(|e| e.to_string())?;
+    let now = Utc::now().with_timezone(&timezone);
+    
+    let todos = sqlx::query_as::<_, Todo>(
+        "SELECT * FROM todos WHERE deleted_at IS NULL AND completed = 0 AND due_date IS NOT NULL ORDER BY due_date"
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| e.to_string())?;
+    
+    Ok(todos
+        .into_iter()
+        .filter(|todo| {
+            todo.due_date
+                .is_some_and(|due| due::bucket(due, todo.all_day, &now, upcoming_days) == bucket)
+        })
+        .collect())
+}
+
+/// Count the todos in every smart view and saved view, for badges
+#[tauri::command]
+pub async fn get_view_counts(state: State<'_, AppState>) -> Result<Vec<ViewCount>, String> {
+    let timezone = settings::timezone(&state.db).await.map_err(|e| e.to_string())?;
+    let saved = load_views(&state.db, false).await.map_err(|e| e.to_string())?;
+    let todos = sqlx::query_as::<_, Todo>("SELECT * FROM todos WHERE deleted_at IS NULL")
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    
+    let records: Vec<TodoRecord> = todos.into_iter().map(TodoRecord::from).collect();
+    Ok(views::count_views(&records, &saved, &Utc::now().with_timezone(&timezone)))
+}
+
+/// List the todos in a smart view such as `today`, or in a saved view
+#[tauri::command]
+pub async fn get_view_todos(view: String, state: State<'_, AppState>) -> Result<Vec<Todo>, String> {
+    let saved = load_views(&state.db, false).await.map_err(|e| e.to_string())?;
+    let filter = views::find_filter(&view, &saved).ok_or_else(|| format!("View not found: {}", view))?;
+    let timezone = settings::timezone(&state.db).await.map_err(|e| e.to_string())?;
+    let now = Utc::now().with_timezone(&timezone);
+    
+    let todos = sqlx::query_as::<_, Todo>(
+        "SELECT * FROM todos WHERE deleted_at IS NULL ORDER BY due_date IS NULL, due_date, created_at"
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| e.to_string())?;
+    
+    Ok(todos
+        .into_iter()
+        .filter(|todo| filter.matches(&TodoRecord::from(todo.clone()), &now))
+        .collect())
+}
+
+/// List saved views. Deleted views are only included for syncing.
+#[tauri::command]
+pub async fn list_views(include_deleted: Option<bool>, state: State<'_, AppState>) -> Result<Vec<SavedView>, String> {
+    load_views(&state.db, include_deleted.unwrap_or(false))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Save a custom view, creating it if `id` is not given
+#[tauri::command]
+pub async fn save_view(
+    id: Option<String>,
+    name: String,
+    filter: ViewFilter,
+    state: State<'_, AppState>,
+) -> Result<SavedView, String> {
+    let existing = match &id {
+        Some(id) => sqlx::query_as::<_, SavedView>("SELECT * FROM views WHERE id = ? AND deleted_at IS NULL")
+            .bind(id)
+            .fetch_optional(&state.db)
+            .await
+            .map_err(|e| e.to_string())?,
+        None => None,
+    };
+    
+    let view = match existing {
+        Some(mut view) => {
+            view.name = name;
+            view.filter = Json(filter);
+            view.updated_at = Utc::now();
+            view
+        }
+        None => {
+            let mut view = SavedView::new(name, filter);
+            if let Some(id) = id {
+                view.id = id;
+            }
+            view
+        }
+    };
+    if SmartView::from_id(&view.id).is_some() {
+        return Err(format!("View id is reserved: {}", view.id));
+    }
+    if !view.filter.is_valid() {
+        return Err(format!("Day counts in a filter must be between 0 and {}", views::MAX_FILTER_DAYS));
+    }
+    
+    let mut tx = state.db.begin().await.map_err(|e| e.to_string())?;
+    insert_view(&mut tx, &view).await.map_err(|e| e.to_string())?;
+    tx.commit().await.map_err(|e| e.to_string())?;
+    Ok(view)
+}
+
+/// Delete a saved view, keeping the deletion so that it syncs
+#[tauri::command]
+pub async fn delete_view(id: String, state: State<'_, AppState>) -> Result<(), String> {
+    let now = Utc::now();
+    sqlx::query("UPDATE views SET deleted_at = ?, updated_at = ? WHERE id = ? AND deleted_at IS NULL")
+        .bind(now)
+        .bind(now)
+        .bind(&id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+async fn load_views(db: &SqlitePool, include_deleted: bool) -> Result<Vec<SavedView>, sqlx::Error> {
+    let query = if include_deleted {
+        "SELECT * FROM views ORDER BY created_at"
+    } else {
+        "SELECT * FROM views WHERE deleted_at IS NULL ORDER BY created_at"
+    };
+    sqlx::query_as::<_, SavedView>(query).fetch_all(db).await
+}
+
+async fn insert_view(tx: &mut Transaction<'_, Sqlite>, view: &SavedView) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT OR REPLACE INTO views (id, name, filter, created_at, updated_at, deleted_at)
+        VALUES (?, ?, ?, ?, ?, ?)
+        "#
+    )
+    .bind(&view.id)
+    .bind(&view.name)
+    .bind(&view.filter)
+    .bind(view.created_at)
+    .bind(view.updated_at)
+    .bind(view.deleted_at)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// List the comments on a todo, oldest first
+#[tauri::command]
+pub async fn get_comments(todo_id: String, state: State<'_, AppState>) -> Result<Vec<Comment>, String> {
+    sqlx::query_as::<_, Comment>("SELECT * FROM comments WHERE todo_id = ? AND deleted_at IS NULL ORDER BY created_at")
+        .bind(&todo_id)
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// List all comments. Deleted comments are only included for syncing.
+#[tauri::command]
+pub async fn list_comments(include_deleted: Option<bool>, state: State<'_, AppState>) -> Result<Vec<Comment>, String> {
+    let query = if include_deleted.unwrap_or(false) {
+        "SELECT * FROM comments ORDER BY created_at"
+    } else {
+        "SELECT * FROM comments WHERE deleted_at IS NULL ORDER BY created_at"
+    };
+    sqlx::query_as::<_, Comment>(query)
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Comment on a todo. The comment reaches the server on the next sync.
+#[tauri::command]
+pub async fn add_comment(
+    todo_id: String,
+    author_id: String,
+    body: String,
+    state: State<'_, AppState>,
+) -> Result<Comment, String> {
+    comments::validate_body(&body)?;
+    let comment = Comment::new(todo_id, author_id, body);
+    
+    let mut tx = state.db.begin().await.map_err(|e| e.to_string())?;
+    insert_comment(&mut tx, &comment).await.map_err(|e| e.to_string())?;
+    tx.commit().await.map_err(|e| e.to_string())?;
+    Ok(comment)
+}
+
+/// Edit the body of a comment
+#[tauri::command]
+pub async fn edit_comment(id: String, body: String, state: State<'_, AppState>) -> Result<Comment, String> {
+    comments::validate_body(&body)?;
+    let mut comment: Comment = sqlx::query_as("SELECT * FROM comments WHERE id = ? AND deleted_at IS NULL")
+        .bind(&id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    comment.body = body;
+    comment.updated_at = Utc::now();
+    
+    sqlx::query("UPDATE comments SET body = ?, updated_at = ? WHERE id = ?")
+        .bind(&comment.body)
+        .bind(comment.updated_at)
+        .bind(&id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(comment)
+}
+
+/// Delete a comment, keeping the deletion so that it syncs
+#[tauri::command]
+pub async fn delete_comment(id: String, state: State<'_, AppState>) -> Result<(), String> {
+    let now = Utc::now();
+    sqlx::query("UPDATE comments SET deleted_at = ?, updated_at = ? WHERE id = ? AND deleted_at IS NULL")
+        .bind(now)
+        .bind(now)
+        .bind(&id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+async fn insert_comment(tx: &mut Transaction<'_, Sqlite>, comment: &Comment) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT OR REPLACE INTO comments (id, todo_id, author_id, body, created_at, updated_at, deleted_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#
+    )
+    .bind(&comment.id)
+    .bind(&comment.todo_id)
+    .bind(&comment.author_id)
+    .bind(&comment.body)
+    .bind(comment.created_at)
+    .bind(comment.updated_at)
+    .bind(comment.deleted_at)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// List the files attached to a todo, as of the last sync
+#[tauri::command]
+pub async fn list_attachments(todo_id: String, state: State<'_, AppState>) -> Result<Vec<Attachment>, String> {
+    sqlx::query_as::<_, Attachment>("SELECT * FROM attachments WHERE todo_id = ? AND deleted_at IS NULL ORDER BY created_at")
+        .bind(&todo_id)
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Path of an attachment's contents if they have been downloaded before
+#[tauri::command]
+pub async fn cached_attachment(id: String, state: State<'_, AppState>) -> Result<Option<String>, String> {
+    let attachment: Option<Attachment> = sqlx::query_as("SELECT * FROM attachments WHERE id = ? AND deleted_at IS NULL")
+        .bind(&id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    let Some(attachment) = attachment else {
+        return Ok(None);
+    };
+    
+    let path = attachments::cache_path(&state.attachments_dir, &attachment.hash).map_err(|e| e.to_string())?;
+    Ok(path.exists().then(|| path.to_string_lossy().into_owned()))
+}
+
+/// Keep the contents of an attachment, downloaded or just uploaded, and its
+/// metadata. Returns the path of the cached file.
+#[tauri::command]
+pub async fn cache_attachment(
+    attachment: Attachment,
+    contents: Vec<u8>,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let path = attachments::store(&state.attachments_dir, &attachment.hash, &contents)
+        .await
+        .map_err(|e| e.to_string())?;
+    
+    let mut tx = state.db.begin().await.map_err(|e| e.to_string())?;
+    insert_attachment(&mut tx, &attachment).await.map_err(|e| e.to_string())?;
+    tx.commit().await.map_err(|e| e.to_string())?;
+    
+    Ok(path.to_string_lossy().into_owned())
+}
+
+async fn insert_attachment(tx: &mut Transaction<'_, Sqlite>, attachment: &Attachment) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT OR REPLACE INTO attachments (id, todo_id, name, content_type, size, hash, uploaded_by, created_at, updated_at, deleted_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#
+    )
+    .bind(&attachment.id)
+    .bind(&attachment.todo_id)
+    .bind(&attachment.name)
+    .bind(&attachment.content_type)
+    .bind(attachment.size)
+    .bind(&attachment.hash)
+    .bind(&attachment.uploaded_by)
+    .bind(attachment.created_at)
+    .bind(attachment.updated_at)
+    .bind(attachment.deleted_at)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Drop cached contents that no attachment uses any more
+async fn prune_attachment_cache(state: &AppState) -> Result<(), String> {
+    let hashes: Vec<(String,)> = sqlx::query_as("SELECT DISTINCT hash FROM attachments WHERE deleted_at IS NULL")
+        .fetch_all(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    let keep: HashSet<String> = hashes.into_iter().map(|(hash,)| hash).collect();
+    attachments::prune(&state.attachments_dir, &keep)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Snooze a reminder so that it fires again in `minutes`
+#[tauri::command]
+pub async fn snooze_reminder(
+    todo_id: String,
+    reminder_id: String,
+    minutes: i64,
+    state: State<'_, AppState>,
+) -> Result<Todo, String> {
+    if minutes < 1 {
+        return Err(format!("Snooze must be at least one minute, got {}", minutes));
+    }
+    let until = Duration::try_minutes(minutes)
+        .and_then(|snooze| Utc::now().checked_add_signed(snooze))
+        .ok_or_else(|| format!("Snooze is too long: {} minutes", minutes))?;
+    update_reminder(&state, &todo_id, &reminder_id, |reminder| reminder.snooze(until)).await
+}
+
+/// Dismiss a reminder. The dismissal syncs, clearing the reminder on other devices too.
+#[tauri::command]
+pub async fn dismiss_reminder(todo_id: String, reminder_id: String, state: State<'_, AppState>) -> Result<Todo, String> {
+    let now = Utc::now();
+    update_reminder(&state, &todo_id, &reminder_id, |reminder| reminder.dismiss(now)).await
+}
+
+/// Change one reminder of a todo, bumping `updated_at` so that the change syncs
+async fn update_reminder(
+    state: &AppState,
+    todo_id: &str,
+    reminder_id: &str,
+    change: impl FnOnce(&mut Reminder),
+) -> Result<Todo, String> {
+    let mut todo: Todo = sqlx::query_as("SELECT * FROM todos WHERE id = ?")
+        .bind(todo_id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    
+    let before = todo.clone();
+    let reminder = todo
+        .reminders
+        .iter_mut()
+        .find(|reminder| reminder.id == reminder_id)
+        .ok_or_else(|| format!("Reminder not found: {}", reminder_id))?;
+    change(reminder);
+    todo.updated_at = Utc::now();
+    
+    let mut tx = state.db.begin().await.map_err(|e| e.to_string())?;
+    sqlx::query("UPDATE todos SET reminders = ?, updated_at = ? WHERE id = ?")
+        .bind(&todo.reminders)
+        .bind(todo.updated_at)
+        .bind(&todo.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    record_revisions(&mut tx, "reminder", &[TodoChange { before: Some(before), after: Some(todo.clone()) }]).await?;
+    tx.commit().await.map_err(|e| e.to_string())?;
+    
+    state.reminders.reschedule();
+    Ok(todo)
 }
@@ -1,4 +1,5 @@
-use sqlx::{sqlite::SqliteConnectOptions, SqlitePool};
+use chrono::{DateTime, Utc};
+use sqlx::{sqlite::SqliteConnectOptions, SqliteConnection, SqlitePool};
 use std::fs;
 use tauri::{AppHandle, Manager};
 use std::str::FromStr;
@@ -14,8 +15,13 @@ pub async fn init_db(app_handle: &AppHandle) -> Result<SqlitePool, Box<dyn std::
         .create_if_missing(true);
         
     let pool = SqlitePool::connect_with(options).await?;
+    migrate(&pool).await?;
     
-    // Create tables if they don't exist
+    Ok(pool)
+}
+
+/// Create tables if they don't exist and add columns missing from older databases
+pub async fn migrate(pool: &SqlitePool) -> Result<(), sqlx::Error> {
     sqlx::query(
         r#"
         CREATE TABLE IF NOT EXISTS todos (
@@ -26,12 +32,215 @@ pub async fn init_db(app_handle: &AppHandle) -> Result<SqlitePool, Box<dyn std::
             priority TEXT NOT NULL,
             created_at DATETIME NOT NULL,
             updated_at DATETIME NOT NULL,
-            due_date DATETIME
+            due_date DATETIME,
+            all_day BOOLEAN NOT NULL DEFAULT 0,
+            list TEXT,
+            tags TEXT NOT NULL DEFAULT '[]',
+            recurrence TEXT,
+            reminders TEXT NOT NULL DEFAULT '[]',
+            list_id TEXT,
+            assignee_id TEXT,
+            parent_id TEXT,
+            deleted_at DATETIME
         )
         "#
     )
-    .execute(&pool)
+    .execute(pool)
     .await?;
     
-    Ok(pool)
+    add_column_if_missing(pool, "todos", "deleted_at", "DATETIME").await?;
+    add_column_if_missing(pool, "todos", "list", "TEXT").await?;
+    add_column_if_missing(pool, "todos", "tags", "TEXT NOT NULL DEFAULT '[]'").await?;
+    add_column_if_missing(pool, "todos", "recurrence", "TEXT").await?;
+    add_column_if_missing(pool, "todos", "reminders", "TEXT NOT NULL DEFAULT '[]'").await?;
+    add_column_if_missing(pool, "todos", "all_day", "BOOLEAN NOT NULL DEFAULT 0").await?;
+    add_column_if_missing(pool, "todos", "list_id", "TEXT").await?;
+    add_column_if_missing(pool, "todos", "assignee_id", "TEXT").await?;
+    add_column_if_missing(pool, "todos", "parent_id", "TEXT").await?;
+    
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            stack TEXT NOT NULL,
+            entry TEXT NOT NULL
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+    
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS views (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            filter TEXT NOT NULL,
+            created_at DATETIME NOT NULL,
+            updated_at DATETIME NOT NULL,
+            deleted_at DATETIME
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+    
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS comments (
+            id TEXT PRIMARY KEY,
+            todo_id TEXT NOT NULL,
+            author_id TEXT NOT NULL,
+            body TEXT NOT NULL,
+            created_at DATETIME NOT NULL,
+            updated_at DATETIME NOT NULL,
+            deleted_at DATETIME
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+    
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS attachments (
+            id TEXT PRIMARY KEY,
+            todo_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            content_type TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            hash TEXT NOT NULL,
+            uploaded_by TEXT NOT NULL,
+            created_at DATETIME NOT NULL,
+            updated_at DATETIME NOT NULL,
+            deleted_at DATETIME
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+    
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS revisions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            todo_id TEXT NOT NULL,
+            actor_id TEXT,
+            device_id TEXT,
+            action TEXT NOT NULL,
+            changes TEXT NOT NULL,
+            snapshot TEXT,
+            created_at DATETIME NOT NULL
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+    
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_revisions_todo_id ON revisions(todo_id)")
+        .execute(pool)
+        .await?;
+    
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+    
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS purged_todos (
+            id TEXT PRIMARY KEY,
+            purged_at DATETIME NOT NULL
+        )
+        "#
+    )
+    .execute(pool)
+    .await?;
+    
+    Ok(())
+}
+
+/// Add a column to an existing table, for databases created before the column existed
+async fn add_column_if_missing(
+    pool: &SqlitePool,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), sqlx::Error> {
+    let exists: Option<(String,)> = sqlx::query_as("SELECT name FROM pragma_table_info(?) WHERE name = ?")
+        .bind(table)
+        .bind(column)
+        .fetch_optional(pool)
+        .await?;
+    
+    if exists.is_none() {
+        sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+            .execute(pool)
+            .await?;
+    }
+    
+    Ok(())
+}
+
+/// Permanently delete todos that have been in the trash since before `cutoff`
+pub async fn purge_trash(pool: &SqlitePool, cutoff: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let ids: Vec<String> = sqlx::query_scalar("SELECT id FROM todos WHERE deleted_at IS NOT NULL AND deleted_at < ?")
+        .bind(cutoff)
+        .fetch_all(&mut *tx)
+        .await?;
+    
+    purge_todos(&mut tx, &ids).await?;
+    tx.commit().await?;
+    
+    Ok(ids.len() as u64)
+}
+
+/// Permanently delete todos, leaving a tombstone for each so the next sync
+/// purges them on the server instead of bringing them back
+pub async fn purge_todos(conn: &mut SqliteConnection, todo_ids: &[String]) -> Result<(), sqlx::Error> {
+    if todo_ids.is_empty() {
+        return Ok(());
+    }
+    let ids = serde_json::to_string(todo_ids).map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+    
+    sqlx::query(
+        r#"
+        INSERT OR REPLACE INTO purged_todos (id, purged_at)
+        SELECT id, ? FROM todos WHERE id IN (SELECT value FROM json_each(?))
+        "#
+    )
+    .bind(Utc::now())
+    .bind(&ids)
+    .execute(&mut *conn)
+    .await?;
+    
+    delete_todo_records(conn, todo_ids).await
+}
+
+/// Delete todos along with their comments, attachments and revisions
+pub async fn delete_todo_records(conn: &mut SqliteConnection, todo_ids: &[String]) -> Result<(), sqlx::Error> {
+    if todo_ids.is_empty() {
+        return Ok(());
+    }
+    let ids = serde_json::to_string(todo_ids).map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+    
+    for table in ["comments", "attachments", "revisions"] {
+        sqlx::query(&format!("DELETE FROM {} WHERE todo_id IN (SELECT value FROM json_each(?))", table))
+            .bind(&ids)
+            .execute(&mut *conn)
+            .await?;
+    }
+    sqlx::query("DELETE FROM todos WHERE id IN (SELECT value FROM json_each(?))")
+        .bind(&ids)
+        .execute(&mut *conn)
+        .await?;
+    
+    Ok(())
 }
@@ -0,0 +1,292 @@
+use crate::models::Todo;
+use crate::{revisions, settings};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::{Sqlite, SqliteConnection, SqlitePool, Transaction};
+
+/// Number of entries kept on each of the undo and redo stacks
+pub const HISTORY_LIMIT: usize = 100;
+
+/// The state of a single todo before and after a mutation.
+/// `None` means the todo did not exist yet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TodoChange {
+    pub before: Option<Todo>,
+    pub after: Option<Todo>,
+}
+
+/// One undoable action, possibly touching several todos
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub action: String,
+    pub changes: Vec<TodoChange>,
+}
+
+impl HistoryEntry {
+    pub fn new(action: &str, changes: Vec<TodoChange>) -> Self {
+        Self {
+            action: action.to_string(),
+            changes,
+        }
+    }
+}
+
+/// Undo/redo stacks of local mutations.
+///
+/// Undoing or redoing writes the recorded snapshot back as a regular mutation
+/// with a fresh `updated_at`, so the result syncs like any other edit, and
+/// records its revisions in the same transaction.
+///
+/// A persisted history keeps each entry in a row of the `history` table and
+/// writes only the rows an action adds, moves or drops.
+pub struct History {
+    undo: Vec<Stacked>,
+    redo: Vec<Stacked>,
+    limit: usize,
+    persist: bool,
+}
+
+/// An entry on one of the stacks, and its row if the history is persisted
+struct Stacked {
+    row: Option<i64>,
+    entry: HistoryEntry,
+}
+
+impl History {
+    /// In-memory history that is lost when the app exits
+    pub fn new(limit: usize) -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            limit,
+            persist: false,
+        }
+    }
+
+    /// History persisted in the `history` table, restored from previous runs
+    pub async fn load(pool: &SqlitePool, limit: usize) -> Result<Self, sqlx::Error> {
+        let rows: Vec<(i64, String, String)> = sqlx::query_as("SELECT id, stack, entry FROM history ORDER BY id")
+            .fetch_all(pool)
+            .await?;
+
+        let mut history = Self {
+            persist: true,
+            ..Self::new(limit)
+        };
+        for (row, stack, entry) in rows {
+            let entry: HistoryEntry =
+                serde_json::from_str(&entry).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+            let stacked = Stacked { row: Some(row), entry };
+            match stack.as_str() {
+                "undo" => history.undo.push(stacked),
+                "redo" => history.redo.push(stacked),
+                _ => {}
+            }
+        }
+
+        Ok(history)
+    }
+
+    /// Whether the history outlives the app
+    pub fn persisted(&self) -> bool {
+        self.persist
+    }
+
+    /// Start or stop keeping the history in the `history` table. Stopping
+    /// deletes what was kept; starting saves the stacks as they are.
+    pub async fn set_persisted(&mut self, pool: &SqlitePool, persist: bool) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+        sqlx::query("DELETE FROM history").execute(&mut *tx).await?;
+        let stacks = [("undo", &mut self.undo), ("redo", &mut self.redo)];
+        for (stack, entries) in stacks {
+            for stacked in entries.iter_mut() {
+                stacked.row = if persist { Some(insert(&mut tx, stack, &stacked.entry).await?) } else { None };
+            }
+        }
+        tx.commit().await?;
+        self.persist = persist;
+        Ok(())
+    }
+
+    /// Record a new action. This clears the redo stack.
+    pub async fn record(&mut self, pool: &SqlitePool, entry: HistoryEntry) -> Result<(), sqlx::Error> {
+        if entry.changes.is_empty() {
+            return Ok(());
+        }
+
+        let excess = (self.undo.len() + 1).saturating_sub(self.limit);
+        let mut stacked = Stacked { row: None, entry };
+        if self.persist {
+            let mut tx = pool.begin().await?;
+            for dropped in self.undo.iter().take(excess).chain(&self.redo) {
+                delete(&mut tx, dropped).await?;
+            }
+            stacked.row = Some(insert(&mut tx, "undo", &stacked.entry).await?);
+            tx.commit().await?;
+        }
+
+        self.undo.push(stacked);
+        self.undo.drain(..excess.min(self.undo.len()));
+        self.redo.clear();
+        Ok(())
+    }
+
+    /// Revert the most recent action, returning the changes made to the todos it touched
+    pub async fn undo(&mut self, pool: &SqlitePool) -> Result<Vec<TodoChange>, sqlx::Error> {
+        let Some(stacked) = self.undo.last() else {
+            return Ok(Vec::new());
+        };
+
+        let mut tx = pool.begin().await?;
+        let mut applied = Vec::with_capacity(stacked.entry.changes.len());
+        for change in stacked.entry.changes.iter().rev() {
+            let id = change_id(change);
+            applied.push(apply_snapshot(&mut tx, id, change.before.as_ref()).await?);
+        }
+        let device_id = settings::device_id(&mut tx).await?;
+        revisions::record(&mut tx, &device_id, "undo", &applied).await?;
+        let row = self.move_row(&mut tx, stacked, "redo").await?;
+        tx.commit().await?;
+
+        // Only moved once applied, so that a failed undo can be tried again
+        let mut stacked = self.undo.pop().expect("entry was just applied");
+        stacked.row = row;
+        self.redo.push(stacked);
+        Ok(applied)
+    }
+
+    /// Re-apply the most recently undone action, returning the changes made to the todos it touched
+    pub async fn redo(&mut self, pool: &SqlitePool) -> Result<Vec<TodoChange>, sqlx::Error> {
+        let Some(stacked) = self.redo.last() else {
+            return Ok(Vec::new());
+        };
+
+        let mut tx = pool.begin().await?;
+        let mut applied = Vec::with_capacity(stacked.entry.changes.len());
+        for change in &stacked.entry.changes {
+            let id = change_id(change);
+            applied.push(apply_snapshot(&mut tx, id, change.after.as_ref()).await?);
+        }
+        let device_id = settings::device_id(&mut tx).await?;
+        revisions::record(&mut tx, &device_id, "redo", &applied).await?;
+        let row = self.move_row(&mut tx, stacked, "undo").await?;
+        tx.commit().await?;
+
+        // Only moved once applied, so that a failed redo can be tried again
+        let mut stacked = self.redo.pop().expect("entry was just applied");
+        stacked.row = row;
+        self.undo.push(stacked);
+        Ok(applied)
+    }
+
+    /// Move an entry's row to the top of `stack`. Rows are read back in id
+    /// order, so the entry gets a new row rather than a new stack name.
+    async fn move_row(&self, conn: &mut SqliteConnection, stacked: &Stacked, stack: &str) -> Result<Option<i64>, sqlx::Error> {
+        if !self.persist {
+            return Ok(None);
+        }
+        delete(conn, stacked).await?;
+        insert(conn, stack, &stacked.entry).await.map(Some)
+    }
+}
+
+async fn insert(conn: &mut SqliteConnection, stack: &str, entry: &HistoryEntry) -> Result<i64, sqlx::Error> {
+    let entry = serde_json::to_string(entry).map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+    let result = sqlx::query("INSERT INTO history (stack, entry) VALUES (?, ?)")
+        .bind(stack)
+        .bind(entry)
+        .execute(conn)
+        .await?;
+    Ok(result.last_insert_rowid())
+}
+
+async fn delete(conn: &mut SqliteConnection, stacked: &Stacked) -> Result<(), sqlx::Error> {
+    if let Some(row) = stacked.row {
+        sqlx::query("DELETE FROM history WHERE id = ?").bind(row).execute(conn).await?;
+    }
+    Ok(())
+}
+
+fn change_id(change: &TodoChange) -> &str {
+    change
+        .before
+        .as_ref()
+        .or(change.after.as_ref())
+        .map(|todo| todo.id.as_str())
+        .unwrap_or_default()
+}
+
+/// Write a recorded snapshot back as a fresh mutation, returning the todo
+/// before and after. A `None` snapshot moves the todo to the trash.
+pub(crate) async fn apply_snapshot(
+    tx: &mut Transaction<'_, Sqlite>,
+    id: &str,
+    snapshot: Option<&Todo>,
+) -> Result<TodoChange, sqlx::Error> {
+    let now = Utc::now();
+    let before: Option<Todo> = sqlx::query_as("SELECT * FROM todos WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+    let Some(snapshot) = snapshot else {
+        sqlx::query("UPDATE todos SET deleted_at = ?, updated_at = ? WHERE id = ? AND deleted_at IS NULL")
+            .bind(now)
+            .bind(now)
+            .bind(id)
+            .execute(&mut **tx)
+            .await?;
+        let after = sqlx::query_as("SELECT * FROM todos WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&mut **tx)
+            .await?;
+        return Ok(TodoChange { before, after });
+    };
+
+    let mut todo = snapshot.clone();
+    todo.updated_at = now;
+
+    sqlx::query(
+        r#"
+        INSERT INTO todos (id, title, description, completed, priority, created_at, updated_at, due_date, all_day, list, tags, recurrence, reminders, list_id, assignee_id, parent_id, deleted_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT(id) DO UPDATE SET
+            title = excluded.title,
+            description = excluded.description,
+            completed = excluded.completed,
+            priority = excluded.priority,
+            updated_at = excluded.updated_at,
+            due_date = excluded.due_date,
+            all_day = excluded.all_day,
+            list = excluded.list,
+            tags = excluded.tags,
+            recurrence = excluded.recurrence,
+            reminders = excluded.reminders,
+            list_id = excluded.list_id,
+            assignee_id = excluded.assignee_id,
+            parent_id = excluded.parent_id,
+            deleted_at = excluded.deleted_at
+        "#
+    )
+    .bind(&todo.id)
+    .bind(&todo.title)
+    .bind(&todo.description)
+    .bind(todo.completed)
+    .bind(todo.priority)
+    .bind(todo.created_at)
+    .bind(todo.updated_at)
+    .bind(todo.due_date)
+    .bind(todo.all_day)
+    .bind(&todo.list)
+    .bind(&todo.tags)
+    .bind(&todo.recurrence)
+    .bind(&todo.reminders)
+    .bind(&todo.list_id)
+    .bind(&todo.assignee_id)
+    .bind(&todo.parent_id)
+    .bind(todo.deleted_at)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(TodoChange { before, after: Some(todo) })
+}
@@ -1,10 +1,19 @@
+mod attachments;
 mod commands;
 mod models;
-mod db;
+pub mod db;
+pub mod history;
+mod pinned_http;
+pub mod reminders;
+mod revisions;
+mod settings;
+#[cfg(feature = "otel")]
+mod telemetry;
 
 pub use commands::*;
 pub use models::*;
 
+use std::sync::Arc;
 use tauri::Manager;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -30,7 +39,48 @@ pub fn run() {
             let handle = app.handle().clone();
             tauri::async_runtime::block_on(async move {
                 let db_pool = db::init_db(&handle).await.expect("failed to initialize database");
-                handle.manage(AppState { db: db_pool });
+                let trash_retention = chrono::Duration::days(DEFAULT_TRASH_RETENTION_DAYS);
+                if let Err(e) = db::purge_trash(&db_pool, chrono::Utc::now() - trash_retention).await {
+                    eprintln!("failed to purge trash: {}", e);
+                }
+                let revision_retention = settings::revision_retention(&db_pool)
+                    .await
+                    .unwrap_or_else(|_| chrono::Duration::days(todo_shared::revisions::DEFAULT_RETENTION_DAYS));
+                if let Err(e) = revisions::purge(&db_pool, chrono::Utc::now() - revision_retention).await {
+                    eprintln!("failed to purge revisions: {}", e);
+                }
+                let persist_history = settings::persist_history(&db_pool).await.unwrap_or(true);
+                let history = if persist_history {
+                    history::History::load(&db_pool, history::HISTORY_LIMIT)
+                        .await
+                        .unwrap_or_else(|e| {
+                            eprintln!("failed to load undo history: {}", e);
+                            history::History::new(history::HISTORY_LIMIT)
+                        })
+                } else {
+                    history::History::new(history::HISTORY_LIMIT)
+                };
+                let notifier = Arc::new(reminders::TauriNotifier::new(handle.clone()));
+                let scheduler = Arc::new(reminders::Scheduler::new(db_pool.clone(), notifier));
+                tauri::async_runtime::spawn({
+                    let scheduler = scheduler.clone();
+                    async move { scheduler.run().await }
+                });
+                let attachments_dir = handle
+                    .path()
+                    .app_data_dir()
+                    .expect("failed to resolve app data dir")
+                    .join("attachments");
+                handle.manage(AppState {
+                    db: db_pool,
+                    attachments_dir,
+                    pinned_client: tokio::sync::Mutex::new(None),
+                    trash_retention,
+                    history: tokio::sync::Mutex::new(history),
+                    reminders: scheduler,
+                    #[cfg(feature = "otel")]
+                    telemetry: telemetry::Telemetry::from_env(),
+                });
             });
             Ok(())
         })
@@ -42,6 +92,45 @@ pub fn run() {
             delete_todo,
             sync_local,
             clear_completed,
+            list_trash,
+            list_purged,
+            restore_todo,
+            empty_trash,
+            undo,
+            redo,
+            get_todo_history,
+            revert_todo,
+            get_device_id,
+            get_revision_retention,
+            set_revision_retention,
+            get_history_persistence,
+            set_history_persistence,
+            pinned_fetch,
+            start_sync_trace,
+            finish_sync_trace,
+            export_todos,
+            import_todos,
+            snooze_reminder,
+            dismiss_reminder,
+            parse_quick_add,
+            get_timezone,
+            set_timezone,
+            get_today,
+            get_overdue,
+            get_upcoming,
+            get_view_counts,
+            get_view_todos,
+            list_views,
+            save_view,
+            delete_view,
+            get_comments,
+            list_comments,
+            add_comment,
+            edit_comment,
+            delete_comment,
+            list_attachments,
+            cached_attachment,
+            cache_attachment,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
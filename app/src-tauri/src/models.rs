@@ -1,7 +1,13 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
+use sqlx::types::Json;
 use sqlx::FromRow;
+use todo_shared::formats::TodoRecord;
+use todo_shared::quick_add::QuickAdd;
+use todo_shared::reminders::Reminder;
+
+pub use todo_shared::Priority;
 
 /// A Todo item
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -14,6 +20,33 @@ pub struct Todo {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub due_date: Option<DateTime<Utc>>,
+    /// Whether the due date is a whole day rather than a point in time
+    #[serde(default)]
+    pub all_day: bool,
+    /// Name of the list the todo belongs to
+    #[serde(default)]
+    pub list: Option<String>,
+    /// Tags, stored as a JSON array
+    #[serde(default)]
+    pub tags: Json<Vec<String>>,
+    /// Recurrence rule in iCalendar RRULE syntax, e.g. `FREQ=WEEKLY;BYDAY=MO`
+    #[serde(default)]
+    pub recurrence: Option<String>,
+    /// Reminders, stored as a JSON array
+    #[serde(default)]
+    pub reminders: Json<Vec<Reminder>>,
+    /// Shared list on the server the todo belongs to, if any
+    #[serde(default)]
+    pub list_id: Option<String>,
+    /// Member of the todo's shared list the todo is assigned to
+    #[serde(default)]
+    pub assignee_id: Option<String>,
+    /// The todo this one is a subtask of
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    /// Set when the todo has been moved to the trash
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 impl Todo {
@@ -28,33 +61,98 @@ impl Todo {
             created_at: now,
             updated_at: now,
             due_date: None,
+            all_day: false,
+            list: None,
+            tags: Json(Vec::new()),
+            recurrence: None,
+            reminders: Json(Vec::new()),
+            list_id: None,
+            assignee_id: None,
+            parent_id: None,
+            deleted_at: None,
         }
     }
 }
 
-/// Priority level for a todo item
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, sqlx::Type)]
-#[serde(rename_all = "lowercase")]
-#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
-pub enum Priority {
-    Low,
-    Medium,
-    High,
+impl From<TodoRecord> for Todo {
+    fn from(record: TodoRecord) -> Self {
+        Self {
+            id: record.id,
+            title: record.title,
+            description: record.description,
+            completed: record.completed,
+            priority: record.priority,
+            created_at: record.created_at,
+            updated_at: record.updated_at,
+            due_date: record.due_date,
+            all_day: record.all_day,
+            list: record.list,
+            tags: Json(record.tags),
+            recurrence: record.recurrence,
+            reminders: Json(Vec::new()),
+            list_id: None,
+            assignee_id: None,
+            parent_id: record.parent_id,
+            deleted_at: None,
+        }
+    }
 }
 
-impl Default for Priority {
-    fn default() -> Self {
-        Priority::Medium
+impl From<Todo> for TodoRecord {
+    fn from(todo: Todo) -> Self {
+        Self {
+            id: todo.id,
+            title: todo.title,
+            description: todo.description,
+            completed: todo.completed,
+            priority: todo.priority,
+            created_at: todo.created_at,
+            updated_at: todo.updated_at,
+            due_date: todo.due_date,
+            all_day: todo.all_day,
+            list: todo.list,
+            tags: todo.tags.0,
+            recurrence: todo.recurrence,
+            parent_id: todo.parent_id,
+        }
     }
 }
 
 /// Request to create a new todo
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct CreateTodoRequest {
     pub title: String,
     pub description: Option<String>,
     pub priority: Option<Priority>,
     pub due_date: Option<DateTime<Utc>>,
+    pub all_day: Option<bool>,
+    pub list: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub recurrence: Option<String>,
+    pub reminders: Option<Vec<Reminder>>,
+    pub list_id: Option<String>,
+    pub assignee_id: Option<String>,
+    /// Todo to make this one a subtask of
+    pub parent_id: Option<String>,
+}
+
+impl From<QuickAdd> for CreateTodoRequest {
+    fn from(parsed: QuickAdd) -> Self {
+        Self {
+            title: parsed.title,
+            description: None,
+            priority: parsed.priority,
+            due_date: parsed.due_date,
+            all_day: Some(parsed.all_day),
+            list: parsed.list,
+            tags: (!parsed.tags.is_empty()).then_some(parsed.tags),
+            recurrence: parsed.recurrence,
+            reminders: None,
+            list_id: None,
+            assignee_id: None,
+            parent_id: None,
+        }
+    }
 }
 
 /// Request to update a todo
@@ -65,6 +163,17 @@ pub struct UpdateTodoRequest {
     pub completed: Option<bool>,
     pub priority: Option<Priority>,
     pub due_date: Option<DateTime<Utc>>,
+    pub all_day: Option<bool>,
+    pub list: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub recurrence: Option<String>,
+    pub reminders: Option<Vec<Reminder>>,
+    /// Shared list to move the todo to; an empty string moves it out of its list
+    pub list_id: Option<String>,
+    /// Member to assign the todo to; an empty string unassigns it
+    pub assignee_id: Option<String>,
+    /// Todo to make this one a subtask of; an empty string makes it a top-level todo
+    pub parent_id: Option<String>,
 }
 
 /// Sync status for the app
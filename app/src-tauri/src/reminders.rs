@@ -0,0 +1,180 @@
+use crate::models::Todo;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter};
+use tokio::sync::Notify;
+
+/// Event emitted when a reminder comes due
+pub const REMINDER_DUE_EVENT: &str = "reminder://due";
+
+/// Event emitted when a reminder that was due is no longer, for example
+/// because it was dismissed on another device
+pub const REMINDER_CLEARED_EVENT: &str = "reminder://cleared";
+
+/// Longest the scheduler sleeps before looking at the database again
+const MAX_SLEEP: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Payload of the reminder events
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReminderEvent {
+    pub todo_id: String,
+    pub reminder_id: String,
+    pub title: String,
+    pub fire_at: DateTime<Utc>,
+}
+
+/// Receives reminders as they come due and are cleared
+pub trait Notifier: Send + Sync {
+    fn reminder_due(&self, event: &ReminderEvent);
+    fn reminder_cleared(&self, event: &ReminderEvent);
+}
+
+/// Emits reminder events to the frontend
+pub struct TauriNotifier {
+    app: AppHandle,
+}
+
+impl TauriNotifier {
+    pub fn new(app: AppHandle) -> Self {
+        Self { app }
+    }
+}
+
+impl Notifier for TauriNotifier {
+    fn reminder_due(&self, event: &ReminderEvent) {
+        if let Err(e) = self.app.emit(REMINDER_DUE_EVENT, event) {
+            eprintln!("failed to emit reminder: {}", e);
+        }
+    }
+
+    fn reminder_cleared(&self, event: &ReminderEvent) {
+        if let Err(e) = self.app.emit(REMINDER_CLEARED_EVENT, event) {
+            eprintln!("failed to emit reminder: {}", e);
+        }
+    }
+}
+
+/// Records reminder events in memory, for tests
+#[derive(Default)]
+pub struct TestNotifier {
+    due: Mutex<Vec<ReminderEvent>>,
+    cleared: Mutex<Vec<ReminderEvent>>,
+}
+
+impl TestNotifier {
+    pub fn due(&self) -> Vec<ReminderEvent> {
+        self.due.lock().unwrap().clone()
+    }
+
+    pub fn cleared(&self) -> Vec<ReminderEvent> {
+        self.cleared.lock().unwrap().clone()
+    }
+}
+
+impl Notifier for TestNotifier {
+    fn reminder_due(&self, event: &ReminderEvent) {
+        self.due.lock().unwrap().push(event.clone());
+    }
+
+    fn reminder_cleared(&self, event: &ReminderEvent) {
+        self.cleared.lock().unwrap().push(event.clone());
+    }
+}
+
+/// Fires reminders of open todos when they come due.
+///
+/// Reminders stay due until they are snoozed or dismissed, or their todo is
+/// completed or trashed; each one is announced once per app run.
+pub struct Scheduler {
+    db: SqlitePool,
+    notifier: Arc<dyn Notifier>,
+    wake: Notify,
+    /// Reminders announced as due, by todo and reminder id
+    active: Mutex<HashMap<(String, String), ReminderEvent>>,
+}
+
+impl Scheduler {
+    pub fn new(db: SqlitePool, notifier: Arc<dyn Notifier>) -> Self {
+        Self {
+            db,
+            notifier,
+            wake: Notify::new(),
+            active: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Re-read reminders after todos changed
+    pub fn reschedule(&self) {
+        self.wake.notify_one();
+    }
+
+    /// Sleep until the next reminder is due, fire it, and repeat
+    pub async fn run(&self) {
+        loop {
+            let next = self.tick(Utc::now()).await.unwrap_or_else(|e| {
+                eprintln!("failed to load reminders: {}", e);
+                None
+            });
+            let sleep = next
+                .and_then(|next| (next - Utc::now()).to_std().ok())
+                .map_or(MAX_SLEEP, |sleep| sleep.min(MAX_SLEEP));
+
+            tokio::select! {
+                _ = tokio::time::sleep(sleep) => {}
+                _ = self.wake.notified() => {}
+            }
+        }
+    }
+
+    /// Announce reminders due at `now` and clear those no longer due.
+    /// Returns when the next reminder comes due.
+    pub async fn tick(&self, now: DateTime<Utc>) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+        let todos = sqlx::query_as::<_, Todo>(
+            "SELECT * FROM todos WHERE deleted_at IS NULL AND completed = 0 AND reminders != '[]'"
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        let mut due = Vec::new();
+        let mut next: Option<DateTime<Utc>> = None;
+        for todo in &todos {
+            for reminder in todo.reminders.iter() {
+                let Some(fire_at) = reminder.fire_at(todo.due_date) else {
+                    continue;
+                };
+                if fire_at <= now {
+                    due.push(ReminderEvent {
+                        todo_id: todo.id.clone(),
+                        reminder_id: reminder.id.clone(),
+                        title: todo.title.clone(),
+                        fire_at,
+                    });
+                } else if next.is_none_or(|next| fire_at < next) {
+                    next = Some(fire_at);
+                }
+            }
+        }
+        due.sort_by_key(|event| event.fire_at);
+
+        let mut active = self.active.lock().unwrap();
+        let mut still_due = HashMap::with_capacity(due.len());
+        for event in due {
+            let key = (event.todo_id.clone(), event.reminder_id.clone());
+            if !active.contains_key(&key) {
+                self.notifier.reminder_due(&event);
+            }
+            still_due.insert(key, event);
+        }
+        for (key, event) in active.iter() {
+            if !still_due.contains_key(key) {
+                self.notifier.reminder_cleared(event);
+            }
+        }
+        *active = still_due;
+
+        Ok(next)
+    }
+}
@@ -0,0 +1,85 @@
+use crate::history::TodoChange;
+use crate::models::Todo;
+use chrono::{DateTime, Utc};
+use sqlx::types::Json;
+use sqlx::{SqliteConnection, SqlitePool};
+use todo_shared::revisions;
+
+pub use todo_shared::revisions::Revision;
+
+/// Record the revisions of a local mutation on the connection that made it,
+/// so that both are saved or neither is. Changes that leave every tracked
+/// field as it was are skipped. Local edits have no actor; the server
+/// records who made them once they sync.
+pub async fn record(
+    conn: &mut SqliteConnection,
+    device_id: &str,
+    action: &str,
+    changes: &[TodoChange],
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    for change in changes {
+        let field_changes = revisions::diff(change.before.as_ref(), change.after.as_ref());
+        if field_changes.is_empty() {
+            continue;
+        }
+        let Some(todo_id) = change.before.as_ref().or(change.after.as_ref()).map(|todo| &todo.id) else {
+            continue;
+        };
+        let snapshot = change
+            .after
+            .as_ref()
+            .map(|todo| serde_json::to_value(todo).map(Json))
+            .transpose()
+            .map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+        
+        sqlx::query(
+            r#"
+            INSERT INTO revisions (todo_id, device_id, action, changes, snapshot, created_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#
+        )
+        .bind(todo_id)
+        .bind(device_id)
+        .bind(action)
+        .bind(Json(field_changes))
+        .bind(snapshot)
+        .bind(now)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// The revisions of a todo, newest first
+pub async fn history(pool: &SqlitePool, todo_id: &str) -> Result<Vec<Revision>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM revisions WHERE todo_id = ? ORDER BY id DESC")
+        .bind(todo_id)
+        .fetch_all(pool)
+        .await
+}
+
+/// The todo as it was right after a revision, if it still existed then
+pub async fn snapshot(pool: &SqlitePool, todo_id: &str, revision_id: i64) -> Result<Option<Todo>, String> {
+    let revision: Revision = sqlx::query_as("SELECT * FROM revisions WHERE id = ? AND todo_id = ?")
+        .bind(revision_id)
+        .bind(todo_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Revision not found: {}", revision_id))?;
+    revision
+        .snapshot
+        .map(|snapshot| serde_json::from_value(snapshot.0))
+        .transpose()
+        .map_err(|e| e.to_string())
+}
+
+/// Delete revisions recorded before `cutoff`
+pub async fn purge(pool: &SqlitePool, cutoff: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM revisions WHERE created_at < ?")
+        .bind(cutoff)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
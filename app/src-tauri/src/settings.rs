@@ -0,0 +1,81 @@
+use chrono::Duration;
+use chrono_tz::Tz;
+use sqlx::{SqliteConnection, SqliteExecutor, SqlitePool};
+use todo_shared::due;
+use todo_shared::revisions;
+use uuid::Uuid;
+
+/// Key of the user's IANA time zone
+const TIMEZONE: &str = "timezone";
+/// Key of the id this installation sends as `X-Device-Id`
+const DEVICE_ID: &str = "device_id";
+/// Key of the number of days revisions are kept
+const REVISION_RETENTION_DAYS: &str = "revision_retention_days";
+/// Key of whether the undo history is kept across restarts
+const PERSIST_HISTORY: &str = "persist_history";
+
+/// Read a setting
+pub async fn get<'e>(executor: impl SqliteExecutor<'e>, key: &str) -> Result<Option<String>, sqlx::Error> {
+    let value: Option<(String,)> = sqlx::query_as("SELECT value FROM settings WHERE key = ?")
+        .bind(key)
+        .fetch_optional(executor)
+        .await?;
+    Ok(value.map(|(value,)| value))
+}
+
+/// Write a setting
+pub async fn set<'e>(executor: impl SqliteExecutor<'e>, key: &str, value: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO settings (key, value) VALUES (?, ?) ON CONFLICT(key) DO UPDATE SET value = excluded.value")
+        .bind(key)
+        .bind(value)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+/// The time zone the user has chosen, or UTC
+pub async fn timezone(pool: &SqlitePool) -> Result<Tz, sqlx::Error> {
+    let name = get(pool, TIMEZONE).await?;
+    Ok(name
+        .and_then(|name| due::parse_timezone(&name).ok())
+        .unwrap_or(due::DEFAULT_TIMEZONE))
+}
+
+pub async fn set_timezone(pool: &SqlitePool, timezone: Tz) -> Result<(), sqlx::Error> {
+    set(pool, TIMEZONE, timezone.name()).await
+}
+
+/// The id of this device, generated on first use
+pub async fn device_id(conn: &mut SqliteConnection) -> Result<String, sqlx::Error> {
+    if let Some(id) = get(&mut *conn, DEVICE_ID).await? {
+        return Ok(id);
+    }
+    let id = Uuid::new_v4().to_string();
+    set(&mut *conn, DEVICE_ID, &id).await?;
+    Ok(id)
+}
+
+/// How long revisions are kept before being purged
+pub async fn revision_retention(pool: &SqlitePool) -> Result<Duration, sqlx::Error> {
+    let days = get(pool, REVISION_RETENTION_DAYS).await?;
+    // Values out of range can only come from older versions; ignore them
+    let retention = days
+        .and_then(|days| days.parse().ok())
+        .filter(|days| (1..=revisions::MAX_RETENTION_DAYS).contains(days))
+        .and_then(Duration::try_days);
+    Ok(retention.unwrap_or(Duration::days(revisions::DEFAULT_RETENTION_DAYS)))
+}
+
+pub async fn set_revision_retention(pool: &SqlitePool, days: i64) -> Result<(), sqlx::Error> {
+    set(pool, REVISION_RETENTION_DAYS, &days.to_string()).await
+}
+
+/// Whether the undo history is kept across restarts, which it is by default
+pub async fn persist_history(pool: &SqlitePool) -> Result<bool, sqlx::Error> {
+    let persist = get(pool, PERSIST_HISTORY).await?;
+    Ok(persist.as_deref() != Some("false"))
+}
+
+pub async fn set_persist_history(pool: &SqlitePool, persist: bool) -> Result<(), sqlx::Error> {
+    set(pool, PERSIST_HISTORY, &persist.to_string()).await
+}
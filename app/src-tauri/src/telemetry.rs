@@ -0,0 +1,99 @@
+//! OpenTelemetry trace export for syncs, built with the `otel` feature.
+//!
+//! When `OTEL_EXPORTER_OTLP_ENDPOINT` is set, each sync is traced: a root span
+//! covers the whole sync, its W3C `traceparent` is sent with the request so
+//! the server's spans join the same trace, and applying the server's answer
+//! locally is a child span of it.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::{Status, TraceContextExt, Tracer, TracerProvider};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider, Span};
+use opentelemetry_sdk::Resource;
+
+/// Service name spans are exported under, unless `OTEL_SERVICE_NAME` is set
+const DEFAULT_SERVICE_NAME: &str = "todo-app";
+
+pub struct Telemetry {
+    provider: SdkTracerProvider,
+    tracer: SdkTracer,
+    /// The sync being traced, if one is in progress
+    sync: Mutex<Option<Context>>,
+}
+
+impl Telemetry {
+    /// Export to the collector named by the environment, if there is one
+    pub fn from_env() -> Option<Self> {
+        let endpoint = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok().filter(|e| !e.is_empty())?;
+        let service_name = std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| DEFAULT_SERVICE_NAME.to_string());
+        let exporter = match SpanExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+            .build()
+        {
+            Ok(exporter) => exporter,
+            Err(e) => {
+                eprintln!("failed to set up trace export: {}", e);
+                return None;
+            }
+        };
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(
+                Resource::builder()
+                    .with_service_name(service_name)
+                    .with_attribute(KeyValue::new("service.version", env!("CARGO_PKG_VERSION")))
+                    .build(),
+            )
+            .build();
+        let tracer = provider.tracer("todo-app");
+        Some(Self {
+            provider,
+            tracer,
+            sync: Mutex::new(None),
+        })
+    }
+
+    /// Start tracing a sync, returning the `traceparent` header to send with it
+    pub fn start_sync(&self) -> String {
+        let span = self.tracer.start("sync");
+        let cx = Context::new().with_span(span);
+        let mut headers = HashMap::new();
+        TraceContextPropagator::new().inject_context(&cx, &mut headers);
+        // A sync that was never finished is ended by being dropped
+        *self.sync.lock().unwrap() = Some(cx);
+        headers.remove("traceparent").unwrap_or_default()
+    }
+
+    /// A span for part of the sync in progress, ended when dropped
+    pub fn sync_step(&self, name: &'static str) -> Option<Span> {
+        let sync = self.sync.lock().unwrap();
+        let cx = sync.as_ref()?;
+        Some(self.tracer.start_with_context(name, cx))
+    }
+
+    /// End the sync in progress, as failed if there is an `error`, and export it
+    pub fn finish_sync(&self, error: Option<String>) {
+        let Some(cx) = self.sync.lock().unwrap().take() else {
+            return;
+        };
+        let span = cx.span();
+        if let Some(error) = error {
+            span.set_status(Status::error(error));
+        }
+        span.end();
+
+        // Flushing blocks until the collector answers
+        let provider = self.provider.clone();
+        tauri::async_runtime::spawn_blocking(move || {
+            if let Err(e) = provider.force_flush() {
+                eprintln!("failed to export sync trace: {}", e);
+            }
+        });
+    }
+}
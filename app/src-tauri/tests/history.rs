@@ -0,0 +1,263 @@
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+use tauri_todo_app_lib::db;
+use tauri_todo_app_lib::history::{History, HistoryEntry, TodoChange};
+use tauri_todo_app_lib::{Priority, Todo};
+
+async fn setup() -> SqlitePool {
+    // A single connection, so that every query sees the same in-memory database
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    db::migrate(&pool).await.unwrap();
+    pool
+}
+
+async fn titles(pool: &SqlitePool) -> Vec<String> {
+    sqlx::query_scalar("SELECT title FROM todos WHERE deleted_at IS NULL")
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn failed_undo_keeps_the_entry() {
+    let pool = setup().await;
+    let mut history = History::new(10);
+    let todo = Todo::new("Milk".to_string(), None, Priority::Medium);
+    let deletion = TodoChange { before: Some(todo), after: None };
+    history.record(&pool, HistoryEntry::new("delete", vec![deletion])).await.unwrap();
+
+    // The undo cannot write while the table is missing
+    sqlx::query("ALTER TABLE todos RENAME TO todos_away").execute(&pool).await.unwrap();
+    assert!(history.undo(&pool).await.is_err());
+    assert!(history.redo(&pool).await.unwrap().is_empty());
+
+    sqlx::query("ALTER TABLE todos_away RENAME TO todos").execute(&pool).await.unwrap();
+    assert_eq!(history.undo(&pool).await.unwrap().len(), 1);
+    assert_eq!(titles(&pool).await, vec!["Milk"]);
+
+    // The same holds for redo
+    sqlx::query("ALTER TABLE todos RENAME TO todos_away").execute(&pool).await.unwrap();
+    assert!(history.redo(&pool).await.is_err());
+    sqlx::query("ALTER TABLE todos_away RENAME TO todos").execute(&pool).await.unwrap();
+    assert_eq!(history.redo(&pool).await.unwrap().len(), 1);
+    assert!(titles(&pool).await.is_empty());
+}
+
+/// Write a todo as a command would have left it
+async fn save(pool: &SqlitePool, todo: &Todo) {
+    sqlx::query(
+        r#"
+        INSERT OR REPLACE INTO todos (id, title, description, completed, priority, created_at, updated_at, deleted_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#
+    )
+    .bind(&todo.id)
+    .bind(&todo.title)
+    .bind(&todo.description)
+    .bind(todo.completed)
+    .bind(todo.priority)
+    .bind(todo.created_at)
+    .bind(todo.updated_at)
+    .bind(todo.deleted_at)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+async fn load(pool: &SqlitePool, id: &str) -> Todo {
+    sqlx::query_as("SELECT * FROM todos WHERE id = ?")
+        .bind(id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+/// Make a change to a todo and record it in the history
+async fn change(pool: &SqlitePool, history: &mut History, action: &str, before: &Todo, edit: impl FnOnce(&mut Todo)) -> Todo {
+    let mut after = before.clone();
+    edit(&mut after);
+    save(pool, &after).await;
+    let change = TodoChange { before: Some(before.clone()), after: Some(after.clone()) };
+    history.record(pool, HistoryEntry::new(action, vec![change])).await.unwrap();
+    after
+}
+
+#[tokio::test]
+async fn undo_and_redo_create() {
+    let pool = setup().await;
+    let mut history = History::new(10);
+    let todo = Todo::new("Milk".to_string(), None, Priority::Medium);
+    save(&pool, &todo).await;
+    let creation = TodoChange { before: None, after: Some(todo.clone()) };
+    history.record(&pool, HistoryEntry::new("create", vec![creation])).await.unwrap();
+
+    // Undoing a create moves the todo to the trash, so the undo syncs
+    history.undo(&pool).await.unwrap();
+    assert!(titles(&pool).await.is_empty());
+    assert!(load(&pool, &todo.id).await.deleted_at.is_some());
+
+    history.redo(&pool).await.unwrap();
+    assert_eq!(titles(&pool).await, vec!["Milk"]);
+}
+
+#[tokio::test]
+async fn undo_and_redo_update() {
+    let pool = setup().await;
+    let mut history = History::new(10);
+    let todo = Todo::new("Milk".to_string(), None, Priority::Medium);
+    save(&pool, &todo).await;
+    let updated = change(&pool, &mut history, "update", &todo, |todo| {
+        todo.title = "Oat milk".to_string();
+        todo.priority = Priority::High;
+    })
+    .await;
+
+    history.undo(&pool).await.unwrap();
+    let undone = load(&pool, &todo.id).await;
+    assert_eq!(undone.title, "Milk");
+    assert_eq!(undone.priority, Priority::Medium);
+    // Undone changes are fresh changes, so they win over the synced copy
+    assert!(undone.updated_at >= updated.updated_at);
+
+    history.redo(&pool).await.unwrap();
+    let redone = load(&pool, &todo.id).await;
+    assert_eq!(redone.title, "Oat milk");
+    assert_eq!(redone.priority, Priority::High);
+}
+
+#[tokio::test]
+async fn undo_and_redo_toggle() {
+    let pool = setup().await;
+    let mut history = History::new(10);
+    let todo = Todo::new("Milk".to_string(), None, Priority::Medium);
+    save(&pool, &todo).await;
+    change(&pool, &mut history, "toggle", &todo, |todo| todo.completed = true).await;
+
+    history.undo(&pool).await.unwrap();
+    assert!(!load(&pool, &todo.id).await.completed);
+    history.redo(&pool).await.unwrap();
+    assert!(load(&pool, &todo.id).await.completed);
+}
+
+#[tokio::test]
+async fn undo_and_redo_delete() {
+    let pool = setup().await;
+    let mut history = History::new(10);
+    let todo = Todo::new("Milk".to_string(), None, Priority::Medium);
+    save(&pool, &todo).await;
+    change(&pool, &mut history, "delete", &todo, |todo| todo.deleted_at = Some(chrono::Utc::now())).await;
+    assert!(titles(&pool).await.is_empty());
+
+    history.undo(&pool).await.unwrap();
+    assert_eq!(titles(&pool).await, vec!["Milk"]);
+    history.redo(&pool).await.unwrap();
+    assert!(titles(&pool).await.is_empty());
+}
+
+#[tokio::test]
+async fn clear_completed_is_undone_as_one_action() {
+    let pool = setup().await;
+    let mut history = History::new(10);
+    let now = chrono::Utc::now();
+    let mut changes = Vec::new();
+    for title in ["Milk", "Eggs"] {
+        let mut todo = Todo::new(title.to_string(), None, Priority::Medium);
+        todo.completed = true;
+        let mut cleared = todo.clone();
+        cleared.deleted_at = Some(now);
+        save(&pool, &cleared).await;
+        changes.push(TodoChange { before: Some(todo), after: Some(cleared) });
+    }
+    history.record(&pool, HistoryEntry::new("clear_completed", changes)).await.unwrap();
+    assert!(titles(&pool).await.is_empty());
+
+    assert_eq!(history.undo(&pool).await.unwrap().len(), 2);
+    let mut restored = titles(&pool).await;
+    restored.sort();
+    assert_eq!(restored, vec!["Eggs", "Milk"]);
+
+    assert_eq!(history.redo(&pool).await.unwrap().len(), 2);
+    assert!(titles(&pool).await.is_empty());
+}
+
+#[tokio::test]
+async fn new_actions_clear_the_redo_stack() {
+    let pool = setup().await;
+    let mut history = History::new(10);
+    let todo = Todo::new("Milk".to_string(), None, Priority::Medium);
+    save(&pool, &todo).await;
+    let renamed = change(&pool, &mut history, "update", &todo, |todo| todo.title = "Oat milk".to_string()).await;
+
+    history.undo(&pool).await.unwrap();
+    let undone = load(&pool, &renamed.id).await;
+    change(&pool, &mut history, "toggle", &undone, |todo| todo.completed = true).await;
+
+    // The rename can no longer be redone over the toggle
+    assert!(history.redo(&pool).await.unwrap().is_empty());
+    assert_eq!(load(&pool, &todo.id).await.title, "Milk");
+
+    // Undo walks back through the toggle first
+    history.undo(&pool).await.unwrap();
+    let todo = load(&pool, &todo.id).await;
+    assert!(!todo.completed);
+    assert_eq!(todo.title, "Milk");
+}
+
+async fn stacks(pool: &SqlitePool) -> Vec<(String, String)> {
+    sqlx::query_as("SELECT stack, json_extract(entry, '$.action') FROM history ORDER BY id")
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn persisted_history_is_restored_with_its_limit() {
+    let pool = setup().await;
+    let mut history = History::load(&pool, 2).await.unwrap();
+    let todo = Todo::new("Milk".to_string(), None, Priority::Medium);
+    save(&pool, &todo).await;
+    let todo = change(&pool, &mut history, "rename", &todo, |todo| todo.title = "Oat milk".to_string()).await;
+    let todo = change(&pool, &mut history, "toggle", &todo, |todo| todo.completed = true).await;
+    change(&pool, &mut history, "priority", &todo, |todo| todo.priority = Priority::High).await;
+    history.undo(&pool).await.unwrap();
+
+    let expected = vec![
+        ("undo".to_string(), "toggle".to_string()),
+        ("redo".to_string(), "priority".to_string()),
+    ];
+    assert_eq!(stacks(&pool).await, expected);
+
+    // The restored history picks up where the last one stopped
+    let mut restored = History::load(&pool, 2).await.unwrap();
+    restored.redo(&pool).await.unwrap();
+    assert_eq!(load(&pool, &todo.id).await.priority, Priority::High);
+    restored.undo(&pool).await.unwrap();
+    restored.undo(&pool).await.unwrap();
+    assert_eq!(load(&pool, &todo.id).await.title, "Oat milk");
+    assert!(!load(&pool, &todo.id).await.completed);
+}
+
+#[tokio::test]
+async fn history_is_only_written_while_persisted() {
+    let pool = setup().await;
+    let mut history = History::new(10);
+    let todo = Todo::new("Milk".to_string(), None, Priority::Medium);
+    save(&pool, &todo).await;
+    let todo = change(&pool, &mut history, "rename", &todo, |todo| todo.title = "Oat milk".to_string()).await;
+    assert!(stacks(&pool).await.is_empty());
+
+    history.set_persisted(&pool, true).await.unwrap();
+    assert_eq!(stacks(&pool).await, vec![("undo".to_string(), "rename".to_string())]);
+    change(&pool, &mut history, "toggle", &todo, |todo| todo.completed = true).await;
+    assert_eq!(stacks(&pool).await.len(), 2);
+
+    // Turning persistence off forgets what was kept, but not the session's stacks
+    history.set_persisted(&pool, false).await.unwrap();
+    assert!(stacks(&pool).await.is_empty());
+    assert_eq!(history.undo(&pool).await.unwrap().len(), 1);
+    assert!(stacks(&pool).await.is_empty());
+}
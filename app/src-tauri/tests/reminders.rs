@@ -0,0 +1,128 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+use tauri_todo_app_lib::db;
+use tauri_todo_app_lib::reminders::{Scheduler, TestNotifier};
+use todo_shared::reminders::{Reminder, ReminderTrigger};
+
+fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 3, 1, hour, minute, 0).unwrap()
+}
+
+async fn setup() -> (SqlitePool, Arc<TestNotifier>, Scheduler) {
+    // A single connection, so that every query sees the same in-memory database
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    db::migrate(&pool).await.unwrap();
+
+    let notifier = Arc::new(TestNotifier::default());
+    let scheduler = Scheduler::new(pool.clone(), notifier.clone());
+    (pool, notifier, scheduler)
+}
+
+async fn insert_todo(pool: &SqlitePool, id: &str, due_date: Option<DateTime<Utc>>, reminders: Vec<Reminder>) {
+    sqlx::query(
+        "INSERT INTO todos (id, title, completed, priority, created_at, updated_at, due_date, reminders) VALUES (?, ?, 0, 'medium', ?, ?, ?, ?)"
+    )
+    .bind(id)
+    .bind(format!("Todo {}", id))
+    .bind(at(0, 0))
+    .bind(at(0, 0))
+    .bind(due_date)
+    .bind(serde_json::to_string(&reminders).unwrap())
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+async fn set_reminders(pool: &SqlitePool, id: &str, reminders: Vec<Reminder>) {
+    sqlx::query("UPDATE todos SET reminders = ? WHERE id = ?")
+        .bind(serde_json::to_string(&reminders).unwrap())
+        .bind(id)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn fires_due_reminders_once_and_reports_the_next() {
+    let (pool, notifier, scheduler) = setup().await;
+    let early = Reminder::new(ReminderTrigger::At { at: at(9, 0) });
+    let relative = Reminder::new(ReminderTrigger::BeforeDue { minutes: 30 });
+    insert_todo(&pool, "a", None, vec![early.clone()]).await;
+    insert_todo(&pool, "b", Some(at(12, 0)), vec![relative.clone()]).await;
+
+    let next = scheduler.tick(at(10, 0)).await.unwrap();
+    assert_eq!(next, Some(at(11, 30)));
+    assert_eq!(notifier.due().len(), 1);
+    assert_eq!(notifier.due()[0].reminder_id, early.id);
+
+    scheduler.tick(at(10, 5)).await.unwrap();
+    assert_eq!(notifier.due().len(), 1);
+
+    let next = scheduler.tick(at(11, 30)).await.unwrap();
+    assert_eq!(next, None);
+    assert_eq!(notifier.due().len(), 2);
+    assert_eq!(notifier.due()[1].reminder_id, relative.id);
+}
+
+#[tokio::test]
+async fn skips_completed_and_trashed_todos() {
+    let (pool, notifier, scheduler) = setup().await;
+    let reminder = Reminder::new(ReminderTrigger::At { at: at(9, 0) });
+    insert_todo(&pool, "done", None, vec![reminder.clone()]).await;
+    insert_todo(&pool, "trashed", None, vec![reminder]).await;
+    sqlx::query("UPDATE todos SET completed = 1 WHERE id = 'done'").execute(&pool).await.unwrap();
+    sqlx::query("UPDATE todos SET deleted_at = ? WHERE id = 'trashed'")
+        .bind(at(8, 0))
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    scheduler.tick(at(10, 0)).await.unwrap();
+
+    assert!(notifier.due().is_empty());
+}
+
+#[tokio::test]
+async fn snoozed_reminders_clear_and_fire_again() {
+    let (pool, notifier, scheduler) = setup().await;
+    let mut reminder = Reminder::new(ReminderTrigger::At { at: at(9, 0) });
+    insert_todo(&pool, "a", None, vec![reminder.clone()]).await;
+    scheduler.tick(at(9, 0)).await.unwrap();
+
+    reminder.snooze(at(9, 0) + Duration::minutes(10));
+    set_reminders(&pool, "a", vec![reminder]).await;
+
+    let next = scheduler.tick(at(9, 1)).await.unwrap();
+    assert_eq!(next, Some(at(9, 10)));
+    assert_eq!(notifier.cleared().len(), 1);
+
+    scheduler.tick(at(9, 10)).await.unwrap();
+    assert_eq!(notifier.due().len(), 2);
+    assert_eq!(notifier.due()[1].fire_at, at(9, 10));
+}
+
+#[tokio::test]
+async fn dismissal_from_another_device_clears_the_reminder() {
+    let (pool, notifier, scheduler) = setup().await;
+    let mut reminder = Reminder::new(ReminderTrigger::At { at: at(9, 0) });
+    insert_todo(&pool, "a", None, vec![reminder.clone()]).await;
+    scheduler.tick(at(9, 0)).await.unwrap();
+
+    // A sync brings in the todo as dismissed elsewhere
+    reminder.dismiss(at(9, 5));
+    set_reminders(&pool, "a", vec![reminder.clone()]).await;
+    scheduler.tick(at(9, 6)).await.unwrap();
+
+    assert_eq!(notifier.cleared().len(), 1);
+    assert_eq!(notifier.cleared()[0].reminder_id, reminder.id);
+
+    scheduler.tick(at(9, 7)).await.unwrap();
+    assert_eq!(notifier.due().len(), 1);
+}
@@ -0,0 +1,71 @@
+use chrono::{Duration, Utc};
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+use tauri_todo_app_lib::db;
+
+async fn setup() -> SqlitePool {
+    // A single connection, so that every query sees the same in-memory database
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    db::migrate(&pool).await.unwrap();
+    pool
+}
+
+async fn count(pool: &SqlitePool, table: &str) -> i64 {
+    sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn purging_the_trash_drops_related_records_and_keeps_a_tombstone() {
+    let pool = setup().await;
+    let now = Utc::now();
+    let trashed_at = now - Duration::days(40);
+    sqlx::query(
+        r#"
+        INSERT INTO todos (id, title, priority, created_at, updated_at, deleted_at)
+        VALUES ('old', 'Old', 'medium', ?, ?, ?), ('fresh', 'Fresh', 'medium', ?, ?, ?)
+        "#
+    )
+    .bind(trashed_at)
+    .bind(trashed_at)
+    .bind(trashed_at)
+    .bind(now)
+    .bind(now)
+    .bind(now)
+    .execute(&pool)
+    .await
+    .unwrap();
+    for todo_id in ["old", "fresh"] {
+        sqlx::query("INSERT INTO comments (id, todo_id, author_id, body, created_at, updated_at) VALUES (?, ?, 'me', 'Hi', ?, ?)")
+            .bind(format!("comment-{}", todo_id))
+            .bind(todo_id)
+            .bind(now)
+            .bind(now)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO revisions (todo_id, action, changes, created_at) VALUES (?, 'delete', '[]', ?)")
+            .bind(todo_id)
+            .bind(now)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(db::purge_trash(&pool, now - Duration::days(30)).await.unwrap(), 1);
+
+    assert_eq!(count(&pool, "todos").await, 1);
+    assert_eq!(count(&pool, "comments").await, 1);
+    assert_eq!(count(&pool, "revisions").await, 1);
+    let purged: Vec<String> = sqlx::query_scalar("SELECT id FROM purged_todos")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(purged, vec!["old"]);
+}
@@ -1,6 +1,21 @@
 import { fetch } from '@tauri-apps/plugin-http';
 import { settingsStore } from './stores/settings.svelte';
-import type { Todo, CreateTodoRequest, UpdateTodoRequest } from './types';
+import type {
+  Todo,
+  CreateTodoRequest,
+  UpdateTodoRequest,
+  SavedView,
+  ViewCount,
+  ViewFilter,
+  SharedList,
+  ListMember,
+  ListInvitation,
+  ListRole,
+  Comment,
+  FeedEvent,
+  Revision,
+  Attachment
+} from './types';
 
 // API Response wrapper from server
 interface ApiResponse<T> {
@@ -22,6 +37,57 @@ async function getUserId() {
   return settingsStore.userId || 'default-user';
 }
 
+let deviceId: string | null = null;
+
+// Identifies this device in the revision history of todos
+async function getDeviceId() {
+  if (deviceId) return deviceId;
+  if (isTauri) {
+    const { invoke } = await import('@tauri-apps/api/core');
+    deviceId = await invoke<string>('get_device_id');
+  } else {
+    deviceId = localStorage.getItem('device_id');
+    if (!deviceId) {
+      deviceId = crypto.randomUUID();
+      localStorage.setItem('device_id', deviceId);
+    }
+  }
+  return deviceId;
+}
+
+interface PinnedResponse {
+  status: number;
+  headers: [string, string][];
+  body: number[];
+}
+
+// The webview cannot check certificate fingerprints, so when the server's
+// certificate is pinned the request is made by the Rust side instead
+async function httpFetch(url: string, init: RequestInit = {}): Promise<Response> {
+  const pin = settingsStore.certificatePin;
+  if (isTauri && pin) {
+    const { invoke } = await import('@tauri-apps/api/core');
+    const body = init.body == null
+      ? null
+      : Array.from(new Uint8Array(await new Response(init.body).arrayBuffer()));
+    const response = await invoke<PinnedResponse>('pinned_fetch', {
+      request: {
+        method: init.method || 'GET',
+        url,
+        headers: Array.from(new Headers(init.headers).entries()),
+        body
+      },
+      pin
+    });
+    const hasBody = response.status !== 204 && response.status !== 304;
+    return new Response(hasBody ? new Uint8Array(response.body) : null, {
+      status: response.status,
+      headers: response.headers
+    });
+  }
+  return isTauri ? fetch(url, init) : window.fetch(url, init);
+}
+
 async function apiRequest<T>(path: string, options: RequestInit = {}): Promise<T | null> {
   const baseUrl = await getBaseUrl();
   if (!baseUrl) {
@@ -31,48 +97,43 @@ async function apiRequest<T>(path: string, options: RequestInit = {}): Promise<T
 
   const url = `${baseUrl}${path}`;
   const userId = await getUserId();
+  const deviceId = await getDeviceId();
+  // Logged here and by the server, to find one request in both logs
+  const requestId = crypto.randomUUID();
   
   const headers = {
-    ...options.headers,
     'Content-Type': 'application/json',
-    'X-User-ID': userId
+    ...options.headers,
+    'X-User-ID': userId,
+    'X-Device-Id': deviceId,
+    'X-Request-Id': requestId
   };
 
-  console.log(`[API] ${options.method || 'GET'} ${url}`);
+  console.log(`[API] ${options.method || 'GET'} ${url} (request ${requestId})`);
 
   try {
-    let response;
-    if (isTauri) {
-      console.log('[API] Using Tauri HTTP plugin');
-      response = await fetch(url, {
-        ...options,
-        headers
-      });
-    } else {
-      console.log('[API] Using browser fetch');
-      response = await window.fetch(url, {
-        ...options,
-        headers
-      });
-    }
+    const response = await httpFetch(url, {
+      ...options,
+      headers
+    });
 
     console.log(`[API] Response: ${response.status} ${response.statusText}`);
 
     if (!response.ok) {
       const errorText = await response.text();
-      console.error(`[API] Error (${response.status}): ${errorText}`);
+      console.error(`[API] Error (${response.status}) for request ${requestId}: ${errorText}`);
       return null;
     }
 
     const apiResponse = await response.json() as ApiResponse<T>;
     if (!apiResponse.success) {
-      console.error(`[API] API Error for ${path}:`, apiResponse.error);
+      console.error(`[API] API Error for ${path} (request ${requestId}):`, apiResponse.error);
       return null;
     }
     console.log(`[API] Success:`, apiResponse.data);
     return apiResponse.data as T;
   } catch (error) {
-    console.error(`[API] Request failed for ${path}:`, error);
+    console.error(`[API] Request failed for ${path} (request ${requestId}):`, error);
     return null;
   }
 }
@@ -84,7 +145,7 @@ export const backendApi = {
     
     try {
       const url = `${baseUrl}/health`;
-      const response = await (isTauri ? fetch(url) : window.fetch(url));
+      const response = await httpFetch(url);
       console.log(`Health check to ${url}: ${response.status} ${response.ok}`);
       return response.ok;
     } catch (e) {
@@ -122,13 +183,247 @@ export const backendApi = {
     return result !== null;
   },
 
-  async syncTodos(todos: Todo[], lastSync?: string): Promise<{ todos: Todo[], sync_time: string } | null> {
+  async listTrash(): Promise<Todo[] | null> {
+    const userId = await getUserId();
+    return apiRequest<Todo[]>(`/api/users/${userId}/trash`);
+  },
+
+  async restoreTodo(id: string): Promise<Todo | null> {
+    const userId = await getUserId();
+    return apiRequest<Todo>(`/api/users/${userId}/trash/${id}/restore`, {
+      method: 'POST'
+    });
+  },
+
+  async emptyTrash(): Promise<boolean> {
+    const userId = await getUserId();
+    const result = await apiRequest<number>(`/api/users/${userId}/trash`, {
+      method: 'DELETE'
+    });
+    return result !== null;
+  },
+
+  async updateSettings(settings: {
+    timezone?: string;
+    username?: string;
+    email?: string;
+  }): Promise<{ timezone: string; username?: string; email?: string } | null> {
+    const userId = await getUserId();
+    return apiRequest<{ timezone: string; username?: string; email?: string }>(`/api/users/${userId}/settings`, {
+      method: 'PUT',
+      body: JSON.stringify(settings)
+    });
+  },
+
+  async parseQuickAdd(text: string, timezone: string): Promise<CreateTodoRequest | null> {
+    const userId = await getUserId();
+    return apiRequest<CreateTodoRequest>(`/api/users/${userId}/quick-add`, {
+      method: 'POST',
+      body: JSON.stringify({ text, timezone })
+    });
+  },
+
+  async getViewCounts(): Promise<ViewCount[] | null> {
+    const userId = await getUserId();
+    return apiRequest<ViewCount[]>(`/api/users/${userId}/views`);
+  },
+
+  async createView(id: string | undefined, name: string, filter: ViewFilter): Promise<SavedView | null> {
+    const userId = await getUserId();
+    return apiRequest<SavedView>(`/api/users/${userId}/views`, {
+      method: 'POST',
+      body: JSON.stringify({ id, name, filter })
+    });
+  },
+
+  async updateView(id: string, name: string, filter: ViewFilter): Promise<SavedView | null> {
+    const userId = await getUserId();
+    return apiRequest<SavedView>(`/api/users/${userId}/views/${id}`, {
+      method: 'PUT',
+      body: JSON.stringify({ name, filter })
+    });
+  },
+
+  async deleteView(id: string): Promise<boolean> {
+    const userId = await getUserId();
+    const result = await apiRequest<any>(`/api/users/${userId}/views/${id}`, {
+      method: 'DELETE'
+    });
+    return result !== null;
+  },
+
+  async getLists(): Promise<SharedList[] | null> {
+    const userId = await getUserId();
+    return apiRequest<SharedList[]>(`/api/users/${userId}/lists`);
+  },
+
+  async createList(name: string): Promise<SharedList | null> {
+    const userId = await getUserId();
+    return apiRequest<SharedList>(`/api/users/${userId}/lists`, {
+      method: 'POST',
+      body: JSON.stringify({ name })
+    });
+  },
+
+  async deleteList(listId: string): Promise<boolean> {
+    const userId = await getUserId();
+    const result = await apiRequest<any>(`/api/users/${userId}/lists/${listId}`, {
+      method: 'DELETE'
+    });
+    return result !== null;
+  },
+
+  async getListMembers(listId: string): Promise<ListMember[] | null> {
+    const userId = await getUserId();
+    return apiRequest<ListMember[]>(`/api/users/${userId}/lists/${listId}/members`);
+  },
+
+  async updateListMember(listId: string, memberId: string, role: ListRole): Promise<ListMember | null> {
+    const userId = await getUserId();
+    return apiRequest<ListMember>(`/api/users/${userId}/lists/${listId}/members/${memberId}`, {
+      method: 'PUT',
+      body: JSON.stringify({ role })
+    });
+  },
+
+  async removeListMember(listId: string, memberId: string): Promise<boolean> {
+    const userId = await getUserId();
+    const result = await apiRequest<any>(`/api/users/${userId}/lists/${listId}/members/${memberId}`, {
+      method: 'DELETE'
+    });
+    return result !== null;
+  },
+
+  async inviteToList(listId: string, invitee: string, role: ListRole = 'editor'): Promise<ListInvitation | null> {
+    const userId = await getUserId();
+    return apiRequest<ListInvitation>(`/api/users/${userId}/lists/${listId}/invitations`, {
+      method: 'POST',
+      body: JSON.stringify({ invitee, role })
+    });
+  },
+
+  async getInvitations(): Promise<ListInvitation[] | null> {
+    const userId = await getUserId();
+    return apiRequest<ListInvitation[]>(`/api/users/${userId}/invitations`);
+  },
+
+  async respondToInvitation(invitationId: string, accept: boolean): Promise<boolean> {
+    const userId = await getUserId();
+    const action = accept ? 'accept' : 'decline';
+    const result = await apiRequest<any>(`/api/users/${userId}/invitations/${invitationId}/${action}`, {
+      method: 'POST'
+    });
+    return result !== null;
+  },
+
+  async getComments(todoId: string): Promise<Comment[] | null> {
+    const userId = await getUserId();
+    return apiRequest<Comment[]>(`/api/users/${userId}/todos/${todoId}/comments`);
+  },
+
+  async createComment(todoId: string, body: string, id?: string): Promise<Comment | null> {
+    const userId = await getUserId();
+    return apiRequest<Comment>(`/api/users/${userId}/todos/${todoId}/comments`, {
+      method: 'POST',
+      body: JSON.stringify({ id, body })
+    });
+  },
+
+  async updateComment(todoId: string, commentId: string, body: string): Promise<Comment | null> {
+    const userId = await getUserId();
+    return apiRequest<Comment>(`/api/users/${userId}/todos/${todoId}/comments/${commentId}`, {
+      method: 'PUT',
+      body: JSON.stringify({ body })
+    });
+  },
+
+  async deleteComment(todoId: string, commentId: string): Promise<boolean> {
+    const userId = await getUserId();
+    const result = await apiRequest<any>(`/api/users/${userId}/todos/${todoId}/comments/${commentId}`, {
+      method: 'DELETE'
+    });
+    return result !== null;
+  },
+
+  async getAttachments(todoId: string): Promise<Attachment[] | null> {
+    const userId = await getUserId();
+    return apiRequest<Attachment[]>(`/api/users/${userId}/todos/${todoId}/attachments`);
+  },
+
+  async uploadAttachment(todoId: string, file: File): Promise<Attachment | null> {
+    const userId = await getUserId();
+    const name = encodeURIComponent(file.name);
+    return apiRequest<Attachment>(`/api/users/${userId}/todos/${todoId}/attachments?name=${name}`, {
+      method: 'POST',
+      headers: { 'Content-Type': file.type || 'application/octet-stream' },
+      body: await file.arrayBuffer()
+    });
+  },
+
+  // Contents are not wrapped in an ApiResponse, so this bypasses apiRequest
+  async downloadAttachment(attachment: Attachment): Promise<Uint8Array | null> {
+    const baseUrl = await getBaseUrl();
+    if (!baseUrl) return null;
+    const userId = await getUserId();
+    const url = `${baseUrl}/api/users/${userId}/todos/${attachment.todo_id}/attachments/${attachment.id}`;
+    try {
+      const response = await httpFetch(url);
+      if (!response.ok) {
+        console.error(`[API] Attachment download failed (${response.status})`);
+        return null;
+      }
+      return new Uint8Array(await response.arrayBuffer());
+    } catch (error) {
+      console.error('[API] Attachment download failed:', error);
+      return null;
+    }
+  },
+
+  async deleteAttachment(todoId: string, attachmentId: string): Promise<boolean> {
+    const userId = await getUserId();
+    const result = await apiRequest<any>(`/api/users/${userId}/todos/${todoId}/attachments/${attachmentId}`, {
+      method: 'DELETE'
+    });
+    return result !== null;
+  },
+
+  async getTodoHistory(todoId: string): Promise<Revision[] | null> {
+    const userId = await getUserId();
+    return apiRequest<Revision[]>(`/api/users/${userId}/todos/${todoId}/history`);
+  },
+
+  async revertTodo(todoId: string, revisionId: number): Promise<Todo | null> {
+    const userId = await getUserId();
+    return apiRequest<Todo>(`/api/users/${userId}/todos/${todoId}/history/${revisionId}/revert`, {
+      method: 'POST'
+    });
+  },
+
+  async getFeed(after?: number): Promise<FeedEvent[] | null> {
+    const userId = await getUserId();
+    const query = after !== undefined ? `?after=${after}` : '';
+    return apiRequest<FeedEvent[]>(`/api/users/${userId}/feed${query}`);
+  },
+
+  async syncTodos(
+    todos: Todo[],
+    lastSync?: string,
+    views: SavedView[] = [],
+    comments: Comment[] = [],
+    purged: string[] = [],
+    traceparent?: string
+  ): Promise<{ todos: Todo[], views: SavedView[], comments: Comment[], lists: SharedList[], attachments: Attachment[], purged: string[], sync_time: string } | null> {
     const userId = await getUserId();
-    return apiRequest<{ todos: Todo[], sync_time: string }>(`/api/users/${userId}/sync`, {
+    return apiRequest<{ todos: Todo[], views: SavedView[], comments: Comment[], lists: SharedList[], attachments: Attachment[], purged: string[], sync_time: string }>(`/api/users/${userId}/sync`, {
       method: 'POST',
+      // Lets the server's spans join the app's trace of this sync
+      headers: traceparent ? { traceparent } : undefined,
       body: JSON.stringify({
         last_sync: lastSync,
-        todos: todos
+        todos: todos,
+        views: views,
+        comments: comments,
+        purged: purged
       })
     });
   }
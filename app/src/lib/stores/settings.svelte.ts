@@ -6,7 +6,9 @@ const STORAGE_KEY = 'todo_app_settings';
 const defaultSettings: AppSettings = {
   backendUrl: '',
   userId: 'default-user',
-  isConnected: false
+  isConnected: false,
+  timezone: Intl.DateTimeFormat().resolvedOptions().timeZone,
+  certificatePin: ''
 };
 
 function loadSettings(): AppSettings {
@@ -14,7 +16,7 @@ function loadSettings(): AppSettings {
   const stored = localStorage.getItem(STORAGE_KEY);
   if (stored) {
     try {
-      return JSON.parse(stored);
+      return { ...defaultSettings, ...JSON.parse(stored) };
     } catch (e) {
       console.error('Failed to parse settings', e);
     }
@@ -34,10 +36,27 @@ if (typeof window !== 'undefined' && settings.backendUrl) {
   }, 1000);
 }
 
+// Share the time zone with the Rust side and the server, which work out due days
+async function pushTimezone(timezone: string) {
+  try {
+    if (typeof window !== 'undefined' && '__TAURI_INTERNALS__' in window) {
+      const { invoke } = await import('@tauri-apps/api/core');
+      await invoke('set_timezone', { timezone });
+    }
+    if (settings.backendUrl) {
+      await backendApi.updateSettings({ timezone });
+    }
+  } catch (e) {
+    console.error('Failed to save time zone', e);
+  }
+}
+
 export const settingsStore = {
   get backendUrl() { return settings.backendUrl; },
   get userId() { return settings.userId; },
   get isConnected() { return settings.isConnected; },
+  get timezone() { return settings.timezone; },
+  get certificatePin() { return settings.certificatePin; },
   get isConfigured() { return settings.backendUrl.length > 0; },
   
   async checkConnection() {
@@ -59,8 +78,11 @@ export const settingsStore = {
       localStorage.setItem(STORAGE_KEY, JSON.stringify(settings));
     }
     // Auto-check connection after update
-    if (newSettings.backendUrl !== undefined) {
+    if (newSettings.backendUrl !== undefined || newSettings.certificatePin !== undefined) {
       this.checkConnection();
     }
+    if (newSettings.timezone !== undefined) {
+      pushTimezone(newSettings.timezone);
+    }
   }
 };
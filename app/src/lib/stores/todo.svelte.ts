@@ -1,4 +1,4 @@
-import type { Todo, CreateTodoRequest, UpdateTodoRequest, FilterType, SortType } from '$types';
+import type { Todo, CreateTodoRequest, UpdateTodoRequest, FilterType, SortType, SavedView, ViewCount, ViewFilter, SharedList, Comment, Revision, Attachment } from '$types';
 import { backendApi } from '../backend';
 import { settingsStore } from './settings.svelte';
 
@@ -12,6 +12,8 @@ let sortBy = $state<SortType>('created');
 let searchQuery = $state('');
 let isLoading = $state(false);
 let isSyncing = $state(false);
+let viewCounts = $state<ViewCount[]>([]);
+let sharedLists = $state<SharedList[]>([]);
 
 // Tauri invoke helper
 async function invoke<T>(cmd: string, args?: Record<string, unknown>): Promise<T> {
@@ -110,22 +112,45 @@ async function syncWithBackend(): Promise<void> {
   if (!settingsStore.isConfigured) return;
   
   isSyncing = true;
+  let syncError: string | null = 'No response from the server';
   try {
-    const syncResult = await backendApi.syncTodos(todos);
+    // Trashed todos, deleted views and comments are sent too, so that deletions reach other devices
+    const localTodos = isTauri ? await invoke<Todo[]>('get_todos', { includeDeleted: true }) : todos;
+    const views = isTauri ? await invoke<SavedView[]>('list_views', { includeDeleted: true }) : [];
+    const comments = isTauri ? await invoke<Comment[]>('list_comments', { includeDeleted: true }) : [];
+    // Purged todos are gone locally, so only their ids are left to send
+    const purged = isTauri ? await invoke<string[]>('list_purged') : [];
+    // Traced end to end when the app exports traces
+    const traceparent = isTauri ? await invoke<string | null>('start_sync_trace') : null;
+    const syncResult = await backendApi.syncTodos(localTodos, undefined, views, comments, purged, traceparent ?? undefined);
     if (syncResult) {
-      todos = syncResult.todos;
+      // Trashed todos are kept locally so they can be restored, but not shown
+      todos = syncResult.todos.filter((t) => !t.deleted_at);
+      sharedLists = syncResult.lists;
       
       // Update local storage/state
       if (isTauri) {
-        await invoke('sync_local', { remoteTodos: todos });
+        await invoke('sync_local', {
+          remoteTodos: syncResult.todos,
+          remoteViews: syncResult.views,
+          remoteComments: syncResult.comments,
+          remoteAttachments: syncResult.attachments,
+          remotePurged: syncResult.purged,
+          sentPurged: purged
+        });
       } else {
         saveTodosToLocalStorage();
       }
+      syncError = null;
     }
   } catch (error) {
     console.error('Failed to sync with backend:', error);
+    syncError = String(error);
   } finally {
     isSyncing = false;
+    if (isTauri) {
+      invoke('finish_sync_trace', { error: syncError }).catch(() => {});
+    }
   }
 }
 
@@ -142,7 +167,12 @@ async function createTodo(request: CreateTodoRequest): Promise<Todo | undefined>
       priority: request.priority || 'medium',
       created_at: new Date().toISOString(),
       updated_at: new Date().toISOString(),
-      due_date: request.due_date
+      due_date: request.due_date,
+      all_day: request.all_day ?? false,
+      list: request.list,
+      tags: request.tags ?? [],
+      recurrence: request.recurrence,
+      reminders: request.reminders ?? []
     };
     todos = [newTodo, ...todos];
     saveTodosToLocalStorage();
@@ -282,6 +312,238 @@ async function clearCompleted(): Promise<void> {
   }
 }
 
+async function snoozeReminder(todoId: string, reminderId: string, minutes: number): Promise<void> {
+  if (!isTauri) return;
+  try {
+    const updated = await invoke<Todo>('snooze_reminder', { todoId, reminderId, minutes });
+    await applyReminderChange(updated);
+  } catch (error) {
+    console.error('Failed to snooze reminder:', error);
+  }
+}
+
+async function dismissReminder(todoId: string, reminderId: string): Promise<void> {
+  if (!isTauri) return;
+  try {
+    const updated = await invoke<Todo>('dismiss_reminder', { todoId, reminderId });
+    await applyReminderChange(updated);
+  } catch (error) {
+    console.error('Failed to dismiss reminder:', error);
+  }
+}
+
+// Push reminder state right away so that other devices stop showing it
+async function applyReminderChange(updated: Todo): Promise<void> {
+  const index = todos.findIndex((t) => t.id === updated.id);
+  if (index !== -1) {
+    todos[index] = updated;
+    todos = [...todos];
+  }
+  if (settingsStore.isConfigured) {
+    await backendApi.updateTodo(updated.id, { reminders: updated.reminders });
+  }
+}
+
+// The app lists attachments known from the last sync and downloads their
+// contents the first time they are opened
+async function loadAttachments(todoId: string): Promise<Attachment[]> {
+  try {
+    if (isTauri) {
+      return await invoke<Attachment[]>('list_attachments', { todoId });
+    }
+    if (settingsStore.isConfigured) {
+      return (await backendApi.getAttachments(todoId)) ?? [];
+    }
+  } catch (error) {
+    console.error('Failed to load attachments:', error);
+  }
+  return [];
+}
+
+async function uploadAttachment(todoId: string, file: File): Promise<Attachment | undefined> {
+  if (!settingsStore.isConfigured) return undefined;
+  try {
+    const attachment = await backendApi.uploadAttachment(todoId, file);
+    if (!attachment) return undefined;
+    if (isTauri) {
+      const contents = Array.from(new Uint8Array(await file.arrayBuffer()));
+      await invoke<string>('cache_attachment', { attachment, contents });
+    }
+    return attachment;
+  } catch (error) {
+    console.error('Failed to upload attachment:', error);
+  }
+}
+
+/** A local file path in the app, or an object URL on the web */
+async function openAttachment(attachment: Attachment): Promise<string | undefined> {
+  try {
+    if (isTauri) {
+      const cached = await invoke<string | null>('cached_attachment', { id: attachment.id });
+      if (cached) return cached;
+    }
+    const contents = await backendApi.downloadAttachment(attachment);
+    if (!contents) return undefined;
+    if (isTauri) {
+      return await invoke<string>('cache_attachment', { attachment, contents: Array.from(contents) });
+    }
+    return URL.createObjectURL(new Blob([contents], { type: attachment.content_type }));
+  } catch (error) {
+    console.error('Failed to open attachment:', error);
+  }
+}
+
+async function deleteAttachment(attachment: Attachment): Promise<boolean> {
+  if (!settingsStore.isConfigured) return false;
+  return backendApi.deleteAttachment(attachment.todo_id, attachment.id);
+}
+
+// In the app the local history is shown; it includes edits not yet synced
+async function loadHistory(todoId: string): Promise<Revision[]> {
+  try {
+    if (isTauri) {
+      return await invoke<Revision[]>('get_todo_history', { id: todoId });
+    }
+    if (settingsStore.isConfigured) {
+      return (await backendApi.getTodoHistory(todoId)) ?? [];
+    }
+  } catch (error) {
+    console.error('Failed to load history:', error);
+  }
+  return [];
+}
+
+async function revertTodo(todoId: string, revisionId: number): Promise<Todo | undefined> {
+  try {
+    const reverted = isTauri
+      ? await invoke<Todo>('revert_todo', { id: todoId, revisionId })
+      : await backendApi.revertTodo(todoId, revisionId);
+    if (!reverted) return undefined;
+    const index = todos.findIndex((t) => t.id === reverted.id);
+    if (index !== -1) {
+      todos[index] = reverted;
+      todos = [...todos];
+    }
+    return reverted;
+  } catch (error) {
+    console.error('Failed to revert todo:', error);
+  }
+}
+
+// Comments are written locally in the app and reach the server on the next sync
+async function loadComments(todoId: string): Promise<Comment[]> {
+  try {
+    if (isTauri) {
+      return await invoke<Comment[]>('get_comments', { todoId });
+    }
+    if (settingsStore.isConfigured) {
+      return (await backendApi.getComments(todoId)) ?? [];
+    }
+  } catch (error) {
+    console.error('Failed to load comments:', error);
+  }
+  return [];
+}
+
+async function addComment(todoId: string, body: string): Promise<Comment | undefined> {
+  try {
+    if (isTauri) {
+      return await invoke<Comment>('add_comment', { todoId, authorId: settingsStore.userId, body });
+    }
+    if (settingsStore.isConfigured) {
+      return (await backendApi.createComment(todoId, body)) ?? undefined;
+    }
+  } catch (error) {
+    console.error('Failed to add comment:', error);
+  }
+}
+
+async function editComment(comment: Comment, body: string): Promise<Comment | undefined> {
+  try {
+    if (isTauri) {
+      return await invoke<Comment>('edit_comment', { id: comment.id, body });
+    }
+    if (settingsStore.isConfigured) {
+      return (await backendApi.updateComment(comment.todo_id, comment.id, body)) ?? undefined;
+    }
+  } catch (error) {
+    console.error('Failed to edit comment:', error);
+  }
+}
+
+async function deleteComment(comment: Comment): Promise<void> {
+  try {
+    if (isTauri) {
+      await invoke('delete_comment', { id: comment.id });
+    } else if (settingsStore.isConfigured) {
+      await backendApi.deleteComment(comment.todo_id, comment.id);
+    }
+  } catch (error) {
+    console.error('Failed to delete comment:', error);
+  }
+}
+
+// Badge counts of the smart views and saved views
+async function loadViewCounts(): Promise<void> {
+  try {
+    if (isTauri) {
+      viewCounts = await invoke<ViewCount[]>('get_view_counts');
+    } else if (settingsStore.isConfigured) {
+      viewCounts = (await backendApi.getViewCounts()) ?? [];
+    }
+  } catch (error) {
+    console.error('Failed to load view counts:', error);
+  }
+}
+
+async function saveView(name: string, filter: ViewFilter, id?: string): Promise<SavedView | undefined> {
+  try {
+    let view: SavedView | null = null;
+    if (isTauri) {
+      view = await invoke<SavedView>('save_view', { id, name, filter });
+    }
+    if (settingsStore.isConfigured) {
+      const remote = id ? await backendApi.updateView(id, name, filter) : await backendApi.createView(view?.id, name, filter);
+      view = view ?? remote;
+    }
+    await loadViewCounts();
+    return view ?? undefined;
+  } catch (error) {
+    console.error('Failed to save view:', error);
+  }
+}
+
+async function deleteView(id: string): Promise<void> {
+  try {
+    if (isTauri) {
+      await invoke('delete_view', { id });
+    }
+    if (settingsStore.isConfigured) {
+      await backendApi.deleteView(id);
+    }
+    await loadViewCounts();
+  } catch (error) {
+    console.error('Failed to delete view:', error);
+  }
+}
+
+// Turn a line like "Pay rent every month on the 1st !high tomorrow 9am" into a new todo
+async function parseQuickAdd(text: string): Promise<CreateTodoRequest> {
+  const timezone = settingsStore.timezone;
+  try {
+    if (isTauri) {
+      return await invoke<CreateTodoRequest>('parse_quick_add', { input: text, timezone });
+    }
+    if (settingsStore.isConfigured) {
+      const parsed = await backendApi.parseQuickAdd(text, timezone);
+      if (parsed) return parsed;
+    }
+  } catch (error) {
+    console.error('Failed to parse quick add:', error);
+  }
+  return { title: text.trim() };
+}
+
 function setFilter(newFilter: FilterType): void {
   filter = newFilter;
 }
@@ -329,6 +591,12 @@ export const todoStore = {
   get isTauri() {
     return isTauri;
   },
+  get viewCounts() {
+    return viewCounts;
+  },
+  get sharedLists() {
+    return sharedLists;
+  },
   loadTodos,
   syncWithBackend,
   createTodo,
@@ -336,6 +604,22 @@ export const todoStore = {
   toggleTodo,
   deleteTodo,
   clearCompleted,
+  snoozeReminder,
+  dismissReminder,
+  parseQuickAdd,
+  loadViewCounts,
+  loadAttachments,
+  uploadAttachment,
+  openAttachment,
+  deleteAttachment,
+  loadHistory,
+  revertTodo,
+  loadComments,
+  addComment,
+  editComment,
+  deleteComment,
+  saveView,
+  deleteView,
   setFilter,
   setSortBy,
   setSearchQuery
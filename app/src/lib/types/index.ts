@@ -1,5 +1,23 @@
 export type Priority = 'low' | 'medium' | 'high';
 
+export type ReminderTrigger =
+  | { type: 'at'; at: string }
+  | { type: 'before_due'; minutes: number };
+
+export interface Reminder {
+  id?: string;
+  trigger: ReminderTrigger;
+  snoozed_until?: string;
+  dismissed_at?: string;
+}
+
+export interface ReminderEvent {
+  todo_id: string;
+  reminder_id: string;
+  title: string;
+  fire_at: string;
+}
+
 export interface Todo {
   id: string;
   title: string;
@@ -9,6 +27,19 @@ export interface Todo {
   created_at: string;
   updated_at: string;
   due_date?: string;
+  /** Due on the whole day; `due_date` is then midnight UTC of that day */
+  all_day: boolean;
+  list?: string;
+  tags: string[];
+  recurrence?: string;
+  reminders: Reminder[];
+  /** Shared list on the server the todo belongs to */
+  list_id?: string;
+  /** Member of the shared list the todo is assigned to */
+  assignee_id?: string;
+  /** The todo this one is a subtask of */
+  parent_id?: string;
+  deleted_at?: string;
 }
 
 export interface CreateTodoRequest {
@@ -17,6 +48,14 @@ export interface CreateTodoRequest {
   description?: string;
   priority?: Priority;
   due_date?: string;
+  all_day?: boolean;
+  list?: string;
+  tags?: string[];
+  recurrence?: string;
+  reminders?: Reminder[];
+  list_id?: string;
+  assignee_id?: string;
+  parent_id?: string;
 }
 
 export interface UpdateTodoRequest {
@@ -25,6 +64,140 @@ export interface UpdateTodoRequest {
   completed?: boolean;
   priority?: Priority;
   due_date?: string;
+  all_day?: boolean;
+  list?: string;
+  tags?: string[];
+  recurrence?: string;
+  reminders?: Reminder[];
+  /** An empty string moves the todo out of its shared list */
+  list_id?: string;
+  /** An empty string unassigns the todo */
+  assignee_id?: string;
+  /** An empty string makes the todo a top-level todo */
+  parent_id?: string;
+}
+
+export type ListRole = 'viewer' | 'editor' | 'owner';
+
+/** A list shared between users, with the current user's role in it */
+export interface SharedList {
+  id: string;
+  name: string;
+  role: ListRole;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface ListMember {
+  user_id: string;
+  role: ListRole;
+  created_at: string;
+}
+
+export interface ListInvitation {
+  id: string;
+  list_id: string;
+  list_name: string;
+  /** User id, username or email address the invitation was sent to */
+  invitee: string;
+  role: ListRole;
+  invited_by: string;
+  status: 'pending' | 'accepted' | 'declined';
+  created_at: string;
+  responded_at?: string;
+}
+
+/** A comment on a todo */
+export interface Comment {
+  id: string;
+  todo_id: string;
+  author_id: string;
+  /** Markdown text */
+  body: string;
+  created_at: string;
+  updated_at: string;
+  deleted_at?: string;
+}
+
+/** Metadata of a file attached to a todo */
+export interface Attachment {
+  id: string;
+  todo_id: string;
+  name: string;
+  content_type: string;
+  /** Size in bytes */
+  size: number;
+  /** Hex SHA-256 of the contents */
+  hash: string;
+  uploaded_by: string;
+  created_at: string;
+  updated_at: string;
+  deleted_at?: string;
+}
+
+/** An event in the change feed, about a change someone else made */
+export interface FeedEvent {
+  id: number;
+  kind: 'comment_added' | 'comment_edited' | 'comment_deleted';
+  todo_id: string;
+  comment_id?: string;
+  actor_id: string;
+  created_at: string;
+}
+
+/** One field of a todo before and after a change */
+export interface FieldChange {
+  field: string;
+  before: unknown;
+  after: unknown;
+}
+
+/** One change in the revision history of a todo */
+export interface Revision {
+  id: number;
+  todo_id: string;
+  /** Missing for changes made locally and not yet synced */
+  actor_id?: string;
+  device_id?: string;
+  action: string;
+  changes: FieldChange[];
+  /** The todo right after the change, missing once it was deleted for good */
+  snapshot?: Todo;
+  created_at: string;
+}
+
+export type DueFilter =
+  | { type: 'overdue' }
+  | { type: 'today' }
+  | { type: 'within'; days: number }
+  | { type: 'none' }
+  | { type: 'any' };
+
+/** Conditions a todo must all meet to be in a view */
+export interface ViewFilter {
+  completed?: boolean;
+  completed_within_days?: number;
+  priority?: Priority;
+  list?: string;
+  tags?: string[];
+  due?: DueFilter;
+  search?: string;
+}
+
+export interface SavedView {
+  id: string;
+  name: string;
+  filter: ViewFilter;
+  created_at: string;
+  updated_at: string;
+  deleted_at?: string;
+}
+
+/** Number of todos in a built-in view (e.g. `today`) or a saved view */
+export interface ViewCount {
+  id: string;
+  name: string;
+  count: number;
 }
 
 export interface SyncStatus {
@@ -37,6 +210,10 @@ export interface AppSettings {
   backendUrl: string;
   userId: string;
   isConnected: boolean;
+  /** IANA time zone, used to work out which day todos are due on */
+  timezone: string;
+  /** SHA-256 fingerprint of the server's certificate, to trust a self-signed one */
+  certificatePin: string;
 }
 
 export type FilterType = 'all' | 'active' | 'completed';
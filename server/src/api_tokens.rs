@@ -0,0 +1,230 @@
+//! Personal API tokens for scripts and other automation.
+//!
+//! A token is sent as `Authorization: Bearer <token>` and only works for the
+//! user that created it, on routes its scopes allow. Tokens are stored hashed,
+//! like calendar feed tokens.
+//!
+//! The server does not authenticate users, so requests without a bearer
+//! token act for the user in their path with no scope checks at all: a
+//! token limits what a script holding it can do, not what anyone else can.
+//! With `api_tokens.required_once_created` set, users who have created a
+//! token must send one on every route tokens may use. Routes closed to
+//! tokens, such as managing tokens themselves, stay open without one, so
+//! deployments relying on this must protect those routes some other way.
+
+use std::collections::HashMap;
+
+use axum::{
+    extract::{FromRequestParts, MatchedPath, Path, Request, State},
+    http::{header, request::Parts, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json as SqlJson;
+use sqlx::FromRow;
+
+use crate::db::DbPool;
+use crate::models::ApiResponse;
+use crate::tokens;
+
+/// Prefix of every API token, so that leaked tokens are easy to spot
+pub const TOKEN_PREFIX: &str = "tdo_";
+
+/// Longest accepted token name
+pub const MAX_NAME_LENGTH: usize = 100;
+
+/// What a token is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scope {
+    /// Read todos, comments, attachments, views, lists and settings
+    #[serde(rename = "todos:read")]
+    TodosRead,
+    /// Create, change and delete todos and everything attached to them, and sync
+    #[serde(rename = "todos:write")]
+    TodosWrite,
+    /// Create and change lists, their members and invitations
+    #[serde(rename = "lists:admin")]
+    ListsAdmin,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::TodosRead => "todos:read",
+            Scope::TodosWrite => "todos:write",
+            Scope::ListsAdmin => "lists:admin",
+        }
+    }
+}
+
+/// A personal API token. The token itself is only shown when it is created.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ApiToken {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    #[sqlx(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    pub scopes: SqlJson<Vec<Scope>>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Generate a new token string
+pub fn generate() -> String {
+    format!("{}{}", TOKEN_PREFIX, tokens::generate_token())
+}
+
+/// The scope a route needs when called with an API token, or `None` if API
+/// tokens may not use it at all.
+///
+/// Managing tokens, webhooks, settings and the calendar feed token is left to
+/// interactive clients, so that a leaked token cannot be used to mint more
+/// access. Reads need `todos:read`; changes to lists, their members and
+/// invitations need `lists:admin`; any other change needs `todos:write`.
+pub fn required_scope(method: &Method, route: &str) -> Option<Scope> {
+    let route = route.strip_prefix("/api/users/{user_id}")?;
+    let reading = matches!(*method, Method::GET | Method::HEAD);
+
+    if route.starts_with("/tokens") || route.starts_with("/webhooks") || route == "/calendar/token" {
+        return None;
+    }
+    if route == "/settings" && !reading {
+        return None;
+    }
+    if reading || route == "/quick-add" {
+        return Some(Scope::TodosRead);
+    }
+    if route.starts_with("/lists") || route.starts_with("/invitations") {
+        return Some(Scope::ListsAdmin);
+    }
+    Some(Scope::TodosWrite)
+}
+
+/// State for the scope middleware
+#[derive(Clone)]
+pub struct ScopeState {
+    pub pool: DbPool,
+    /// Refuse requests without a token for users who have created one
+    pub required_once_created: bool,
+}
+
+/// Check bearer tokens against the user and scope of the route they are used on
+pub async fn scope_layer(State(state): State<ScopeState>, request: Request, next: Next) -> Response {
+    let Some(route) = request.extensions().get::<MatchedPath>().map(|path| path.as_str().to_string()) else {
+        return next.run(request).await;
+    };
+    if !route.starts_with("/api/users/{user_id}") {
+        return next.run(request).await;
+    }
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string());
+    let Some(token) = token else {
+        return without_token(&state, &route, request, next).await;
+    };
+
+    let api_token = match find_token(&state.pool, &token).await {
+        Ok(Some(api_token)) => api_token,
+        Ok(None) => return unauthorized(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    let (mut parts, body) = request.into_parts();
+    let user_id = path_user_id(&mut parts).await;
+    if user_id.as_deref() != Some(api_token.user_id.as_str()) {
+        return error_response(StatusCode::FORBIDDEN, "Token does not belong to this user");
+    }
+    match required_scope(&parts.method, &route) {
+        Some(scope) if api_token.scopes.contains(&scope) => {}
+        Some(scope) => {
+            return error_response(
+                StatusCode::FORBIDDEN,
+                &format!("Token is missing the {} scope", scope.as_str()),
+            )
+        }
+        None => return error_response(StatusCode::FORBIDDEN, "This route is not available to API tokens"),
+    }
+
+    if mark_used(&state.pool, &api_token.id).await.is_err() {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    next.run(Request::from_parts(parts, body)).await
+}
+
+/// Let a request without a bearer token through, unless tokens are required
+/// and its user has one
+async fn without_token(state: &ScopeState, route: &str, request: Request, next: Next) -> Response {
+    // The calendar feed carries its own token, and routes closed to tokens
+    // could not be reached at all
+    let exempt = route.ends_with("/calendar.ics") || required_scope(request.method(), route).is_none();
+    if !state.required_once_created || exempt {
+        return next.run(request).await;
+    }
+
+    let (mut parts, body) = request.into_parts();
+    let user_id = path_user_id(&mut parts).await.unwrap_or_default();
+    match has_tokens(&state.pool, &user_id).await {
+        Ok(false) => next.run(Request::from_parts(parts, body)).await,
+        Ok(true) => {
+            let mut response = error_response(StatusCode::UNAUTHORIZED, "This user's requests need an API token");
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            response
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// The user in the request's path
+async fn path_user_id(parts: &mut Parts) -> Option<String> {
+    Path::<HashMap<String, String>>::from_request_parts(parts, &())
+        .await
+        .ok()
+        .and_then(|Path(mut params)| params.remove("user_id"))
+}
+
+/// Whether the user has a token that is not revoked
+async fn has_tokens(pool: &DbPool, user_id: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM api_tokens WHERE user_id = ? AND revoked_at IS NULL)")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+}
+
+/// Look up an unrevoked token
+async fn find_token(pool: &DbPool, token: &str) -> Result<Option<ApiToken>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM api_tokens WHERE token_hash = ? AND revoked_at IS NULL")
+        .bind(tokens::hash_token(token))
+        .fetch_optional(pool)
+        .await
+}
+
+async fn mark_used(pool: &DbPool, id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE api_tokens SET last_used_at = ? WHERE id = ?")
+        .bind(Utc::now())
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+fn unauthorized() -> Response {
+    let mut response = error_response(StatusCode::UNAUTHORIZED, "Invalid or revoked API token");
+    response
+        .headers_mut()
+        .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    response
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response {
+    (status, Json(ApiResponse::<()>::error(message.to_string()))).into_response()
+}
@@ -0,0 +1,305 @@
+//! Storage for attachment contents.
+//!
+//! Blobs are keyed by the SHA-256 of their contents, so a file attached to
+//! several todos is stored once. The `blobs` table records which blobs exist;
+//! blobs no attachment refers to any more are collected periodically.
+//!
+//! Two stores are built in: files in a local directory, and any S3-compatible
+//! service through the `ObjectStorage` trait. `LocalObjectStorage` is an
+//! in-memory stand-in for such a service, for tests and local development.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use axum::body::Bytes;
+use chrono::{DateTime, Duration, Utc};
+use todo_shared::attachments;
+
+use crate::config::{BlobConfig, BlobStoreKind};
+use crate::db::DbPool;
+use crate::shutdown::Shutdown;
+
+/// Future returned by blob and object stores
+pub type BlobFuture<'a, T> = Pin<Box<dyn Future<Output = io::Result<T>> + Send + 'a>>;
+
+/// A content-addressed store of attachment contents
+pub trait BlobStore: Send + Sync {
+    /// Store `contents` under `hash`. Storing the same hash twice is harmless.
+    fn put<'a>(&'a self, hash: &'a str, contents: Bytes) -> BlobFuture<'a, ()>;
+
+    /// The contents stored under `hash`, if any
+    fn get<'a>(&'a self, hash: &'a str) -> BlobFuture<'a, Option<Bytes>>;
+
+    /// Remove the contents stored under `hash`, if any
+    fn delete<'a>(&'a self, hash: &'a str) -> BlobFuture<'a, ()>;
+}
+
+/// The blob store shared by all handlers
+pub type Blobs = Arc<dyn BlobStore>;
+
+/// How long an unreferenced blob is kept, so that an upload of the same
+/// contents running at the same time can still claim it
+const GARBAGE_GRACE: Duration = Duration::hours(1);
+
+/// Build the configured blob store
+pub fn from_config(config: &BlobConfig) -> Blobs {
+    match config.store {
+        BlobStoreKind::Fs => Arc::new(FsBlobStore::new(config.dir.clone())),
+        BlobStoreKind::MemoryS3 => Arc::new(S3BlobStore::new(
+            LocalObjectStorage::default(),
+            config.bucket.clone(),
+            "blobs/",
+        )),
+    }
+}
+
+fn check_hash(hash: &str) -> io::Result<()> {
+    if attachments::is_valid_hash(hash) {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Not a blob hash: {}", hash)))
+    }
+}
+
+/// Blobs as files in a directory, fanned out by the first two hex digits
+pub struct FsBlobStore {
+    root: PathBuf,
+}
+
+impl FsBlobStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, hash: &str) -> io::Result<PathBuf> {
+        check_hash(hash)?;
+        Ok(self.root.join(&hash[..2]).join(hash))
+    }
+}
+
+impl BlobStore for FsBlobStore {
+    fn put<'a>(&'a self, hash: &'a str, contents: Bytes) -> BlobFuture<'a, ()> {
+        Box::pin(async move {
+            let path = self.path(hash)?;
+            if tokio::fs::try_exists(&path).await? {
+                return Ok(());
+            }
+            if let Some(dir) = path.parent() {
+                tokio::fs::create_dir_all(dir).await?;
+            }
+            // Write to a temporary file first, so readers never see half a blob
+            let partial = path.with_extension(format!("{}.partial", uuid::Uuid::new_v4()));
+            tokio::fs::write(&partial, &contents).await?;
+            tokio::fs::rename(&partial, &path).await
+        })
+    }
+
+    fn get<'a>(&'a self, hash: &'a str) -> BlobFuture<'a, Option<Bytes>> {
+        Box::pin(async move {
+            match tokio::fs::read(self.path(hash)?).await {
+                Ok(contents) => Ok(Some(Bytes::from(contents))),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            }
+        })
+    }
+
+    fn delete<'a>(&'a self, hash: &'a str) -> BlobFuture<'a, ()> {
+        Box::pin(async move {
+            match tokio::fs::remove_file(self.path(hash)?).await {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            }
+        })
+    }
+}
+
+/// The part of the S3 API blob storage needs. Implement it on top of an S3
+/// client to keep blobs in S3, MinIO or any other S3-compatible service.
+pub trait ObjectStorage: Send + Sync {
+    fn put_object<'a>(&'a self, bucket: &'a str, key: &'a str, body: Bytes) -> BlobFuture<'a, ()>;
+
+    /// `None` when there is no such key, like a `NoSuchKey` error
+    fn get_object<'a>(&'a self, bucket: &'a str, key: &'a str) -> BlobFuture<'a, Option<Bytes>>;
+
+    /// Deleting a missing key succeeds, as in S3
+    fn delete_object<'a>(&'a self, bucket: &'a str, key: &'a str) -> BlobFuture<'a, ()>;
+}
+
+/// Blobs as objects in a bucket, under a key prefix
+pub struct S3BlobStore<C> {
+    client: C,
+    bucket: String,
+    prefix: String,
+}
+
+impl<C: ObjectStorage> S3BlobStore<C> {
+    pub fn new(client: C, bucket: impl Into<String>, prefix: impl Into<String>) -> Self {
+        Self {
+            client,
+            bucket: bucket.into(),
+            prefix: prefix.into(),
+        }
+    }
+
+    fn key(&self, hash: &str) -> io::Result<String> {
+        check_hash(hash)?;
+        Ok(format!("{}{}", self.prefix, hash))
+    }
+}
+
+impl<C: ObjectStorage> BlobStore for S3BlobStore<C> {
+    fn put<'a>(&'a self, hash: &'a str, contents: Bytes) -> BlobFuture<'a, ()> {
+        Box::pin(async move {
+            let key = self.key(hash)?;
+            self.client.put_object(&self.bucket, &key, contents).await
+        })
+    }
+
+    fn get<'a>(&'a self, hash: &'a str) -> BlobFuture<'a, Option<Bytes>> {
+        Box::pin(async move {
+            let key = self.key(hash)?;
+            self.client.get_object(&self.bucket, &key).await
+        })
+    }
+
+    fn delete<'a>(&'a self, hash: &'a str) -> BlobFuture<'a, ()> {
+        Box::pin(async move {
+            let key = self.key(hash)?;
+            self.client.delete_object(&self.bucket, &key).await
+        })
+    }
+}
+
+/// An in-memory S3-compatible object store, standing in for MinIO
+#[derive(Default)]
+pub struct LocalObjectStorage {
+    objects: Mutex<HashMap<(String, String), Bytes>>,
+}
+
+impl LocalObjectStorage {
+    /// Number of objects stored across all buckets
+    pub fn len(&self) -> usize {
+        self.objects.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl ObjectStorage for LocalObjectStorage {
+    fn put_object<'a>(&'a self, bucket: &'a str, key: &'a str, body: Bytes) -> BlobFuture<'a, ()> {
+        let mut objects = self.objects.lock().unwrap();
+        objects.insert((bucket.to_string(), key.to_string()), body);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn get_object<'a>(&'a self, bucket: &'a str, key: &'a str) -> BlobFuture<'a, Option<Bytes>> {
+        let objects = self.objects.lock().unwrap();
+        let object = objects.get(&(bucket.to_string(), key.to_string())).cloned();
+        Box::pin(async { Ok(object) })
+    }
+
+    fn delete_object<'a>(&'a self, bucket: &'a str, key: &'a str) -> BlobFuture<'a, ()> {
+        let mut objects = self.objects.lock().unwrap();
+        objects.remove(&(bucket.to_string(), key.to_string()));
+        Box::pin(async { Ok(()) })
+    }
+}
+
+impl<T: ObjectStorage + ?Sized> ObjectStorage for Arc<T> {
+    fn put_object<'a>(&'a self, bucket: &'a str, key: &'a str, body: Bytes) -> BlobFuture<'a, ()> {
+        (**self).put_object(bucket, key, body)
+    }
+
+    fn get_object<'a>(&'a self, bucket: &'a str, key: &'a str) -> BlobFuture<'a, Option<Bytes>> {
+        (**self).get_object(bucket, key)
+    }
+
+    fn delete_object<'a>(&'a self, bucket: &'a str, key: &'a str) -> BlobFuture<'a, ()> {
+        (**self).delete_object(bucket, key)
+    }
+}
+
+/// Store contents unless a blob with the same hash is already stored, and
+/// mark the blob as just used
+pub async fn store(pool: &DbPool, blobs: &Blobs, hash: &str, contents: Bytes) -> io::Result<()> {
+    let known: Option<(String,)> = sqlx::query_as("SELECT hash FROM blobs WHERE hash = ?")
+        .bind(hash)
+        .fetch_optional(pool)
+        .await
+        .map_err(io::Error::other)?;
+    if known.is_none() {
+        blobs.put(hash, contents).await?;
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO blobs (hash, created_at, last_used_at) VALUES (?, ?, ?)
+        ON CONFLICT(hash) DO UPDATE SET last_used_at = excluded.last_used_at
+        "#,
+    )
+    .bind(hash)
+    .bind(Utc::now())
+    .bind(Utc::now())
+    .execute(pool)
+    .await
+    .map_err(io::Error::other)?;
+    Ok(())
+}
+
+/// Drop attachments removed before `cutoff` or whose todo is gone, then the
+/// blobs no attachment refers to. Returns the number of blobs deleted.
+pub async fn collect_garbage(pool: &DbPool, blobs: &Blobs, cutoff: DateTime<Utc>) -> io::Result<u64> {
+    sqlx::query(
+        "DELETE FROM attachments WHERE (deleted_at IS NOT NULL AND deleted_at < ?) OR todo_id NOT IN (SELECT id FROM todos)",
+    )
+    .bind(cutoff)
+    .execute(pool)
+    .await
+    .map_err(io::Error::other)?;
+
+    let orphans: Vec<(String,)> = sqlx::query_as(
+        r#"
+        SELECT hash FROM blobs
+        WHERE last_used_at < ?
+        AND hash NOT IN (SELECT hash FROM attachments WHERE deleted_at IS NULL)
+        "#,
+    )
+    .bind(Utc::now() - GARBAGE_GRACE)
+    .fetch_all(pool)
+    .await
+    .map_err(io::Error::other)?;
+
+    for (hash,) in &orphans {
+        blobs.delete(hash).await?;
+        sqlx::query("DELETE FROM blobs WHERE hash = ?")
+            .bind(hash)
+            .execute(pool)
+            .await
+            .map_err(io::Error::other)?;
+    }
+    Ok(orphans.len() as u64)
+}
+
+/// Collect garbage once an hour, dropping removed attachments after `retention`,
+/// until shutdown
+pub async fn collect_garbage_periodically(pool: DbPool, blobs: Blobs, retention: Duration, shutdown: Shutdown) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.triggered() => return,
+        }
+        match collect_garbage(&pool, &blobs, Utc::now() - retention).await {
+            Ok(0) => {}
+            Ok(deleted) => tracing::info!("Deleted {} unused blobs", deleted),
+            Err(e) => tracing::error!("Failed to collect unused blobs: {}", e),
+        }
+    }
+}
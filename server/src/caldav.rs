@@ -0,0 +1,814 @@
+//! Minimal CalDAV (RFC 4791) access to todos.
+//!
+//! Everything lives under `/dav`:
+//! - `/dav/{user_id}/` is both the principal and the calendar home
+//! - `/dav/{user_id}/todos/` is a calendar with all of the user's todos
+//! - `/dav/{user_id}/lists/{list}/` is a calendar per list
+//! - `{calendar}/{todo_id}.ics` is a single VTODO resource
+//!
+//! Supported methods are OPTIONS, PROPFIND, REPORT (`calendar-query` and
+//! `calendar-multiget`), GET, PUT and DELETE. ETags are derived from
+//! `updated_at`, so any change through the API or sync shows up as a new ETag.
+
+use axum::{
+    body::{self, Body},
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use chrono::Utc;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use roxmltree::{Document, Node};
+use sha2::{Digest, Sha256};
+use sqlx::SqliteConnection;
+use todo_shared::formats::{ical, TodoRecord};
+
+use crate::config::Features;
+use crate::db::DbPool;
+use crate::models::Todo;
+use crate::revisions::{self, DeviceId};
+
+const DAV_NS: &str = "DAV:";
+const CALDAV_NS: &str = "urn:ietf:params:xml:ns:caldav";
+const CALENDARSERVER_NS: &str = "http://calendarserver.org/ns/";
+
+const ALLOWED_METHODS: &str = "OPTIONS, PROPFIND, REPORT, GET, HEAD, PUT, DELETE";
+const CALENDAR_CONTENT_TYPE: &str = "text/calendar; charset=utf-8";
+const TODO_CONTENT_TYPE: &str = "text/calendar; charset=utf-8; component=vtodo";
+const XML_CONTENT_TYPE: &str = "application/xml; charset=utf-8";
+
+/// Largest request body accepted, for both XML requests and iCalendar uploads
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// Characters left unescaped in path segments of generated hrefs
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_').remove(b'.').remove(b'~');
+
+/// A calendar collection
+#[derive(Debug, Clone, PartialEq)]
+enum Calendar {
+    /// Every todo of the user
+    All,
+    /// Todos in one list
+    List(String),
+}
+
+impl Calendar {
+    fn contains(&self, todo: &Todo) -> bool {
+        match self {
+            Calendar::All => true,
+            Calendar::List(list) => todo.list.as_deref() == Some(list.as_str()),
+        }
+    }
+}
+
+/// What a request path points at
+#[derive(Debug, Clone, PartialEq)]
+enum Target {
+    Home,
+    /// The collection holding one calendar per list
+    Lists,
+    Calendar(Calendar),
+    Todo(Calendar, String),
+}
+
+/// Split `/dav/{user_id}/...` into the user and the target
+fn parse_path(path: &str) -> Option<(String, Target)> {
+    let rest = path.strip_prefix("/dav/")?;
+    let segments: Vec<String> = rest
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| percent_decode_str(segment).decode_utf8().map(|s| s.into_owned()))
+        .collect::<Result<_, _>>()
+        .ok()?;
+
+    let resource = |name: &str| name.strip_suffix(".ics").filter(|id| !id.is_empty()).map(str::to_string);
+    let target = match segments.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        [_] => Target::Home,
+        [_, "lists"] => Target::Lists,
+        [_, "todos"] => Target::Calendar(Calendar::All),
+        [_, "todos", name] => Target::Todo(Calendar::All, resource(name)?),
+        [_, "lists", list] => Target::Calendar(Calendar::List(list.to_string())),
+        [_, "lists", list, name] => Target::Todo(Calendar::List(list.to_string()), resource(name)?),
+        _ => return None,
+    };
+
+    Some((segments[0].clone(), target))
+}
+
+fn encode_segment(segment: &str) -> String {
+    utf8_percent_encode(segment, PATH_SEGMENT).to_string()
+}
+
+fn home_href(user_id: &str) -> String {
+    format!("/dav/{}/", encode_segment(user_id))
+}
+
+fn lists_href(user_id: &str) -> String {
+    format!("{}lists/", home_href(user_id))
+}
+
+fn calendar_href(user_id: &str, calendar: &Calendar) -> String {
+    match calendar {
+        Calendar::All => format!("{}todos/", home_href(user_id)),
+        Calendar::List(list) => format!("{}{}/", lists_href(user_id), encode_segment(list)),
+    }
+}
+
+fn todo_href(user_id: &str, calendar: &Calendar, id: &str) -> String {
+    format!("{}{}.ics", calendar_href(user_id, calendar), encode_segment(id))
+}
+
+/// Entry point for every request under `/dav`
+pub async fn handle(
+    State(pool): State<DbPool>,
+    Extension(features): Extension<Features>,
+    request: Request,
+) -> Result<Response, StatusCode> {
+    let (user_id, target) = parse_path(request.uri().path()).ok_or(StatusCode::NOT_FOUND)?;
+    let method = request.method().as_str().to_string();
+    let headers = request.headers().clone();
+    let body = body::to_bytes(request.into_body(), MAX_BODY_BYTES)
+        .await
+        .map_err(|_| StatusCode::PAYLOAD_TOO_LARGE)?;
+    let body = String::from_utf8(body.to_vec()).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    match method.as_str() {
+        "OPTIONS" => Ok(options()),
+        "PROPFIND" => propfind(&pool, &user_id, &target, &headers, &body).await,
+        "REPORT" => report(&pool, &user_id, &target, &body).await,
+        "GET" | "HEAD" => get(&pool, &user_id, &target).await,
+        "PUT" => put(&pool, &user_id, &target, &headers, &body, features.webhooks).await,
+        "DELETE" => delete(&pool, &user_id, &target, &headers, features.webhooks).await,
+        _ => Ok((
+            StatusCode::METHOD_NOT_ALLOWED,
+            [(header::ALLOW, ALLOWED_METHODS)],
+        )
+            .into_response()),
+    }
+}
+
+fn options() -> Response {
+    (
+        StatusCode::OK,
+        [
+            (header::ALLOW, ALLOWED_METHODS),
+            (header::HeaderName::from_static("dav"), "1, 3, calendar-access"),
+        ],
+    )
+        .into_response()
+}
+
+async fn propfind(
+    pool: &DbPool,
+    user_id: &str,
+    target: &Target,
+    headers: &HeaderMap,
+    body: &str,
+) -> Result<Response, StatusCode> {
+    let request = if body.trim().is_empty() {
+        PropRequest::All
+    } else {
+        let document = parse_xml(body)?;
+        let root = document.root_element();
+        if !is(root, DAV_NS, "propfind") {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        match child(root, DAV_NS, "prop") {
+            Some(prop) => PropRequest::Props(prop_names(prop)),
+            None => PropRequest::All,
+        }
+    };
+    // Infinite depth is not supported and is served as depth 1
+    let depth_one = headers
+        .get("depth")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim() != "0")
+        .unwrap_or(true);
+
+    let mut entries = Vec::new();
+    match target {
+        Target::Home => {
+            entries.push(Entry::new(home_href(user_id), Resource::Home));
+            if depth_one {
+                entries.push(calendar_entry(pool, user_id, Calendar::All).await?);
+                entries.push(Entry::new(lists_href(user_id), Resource::Lists));
+            }
+        }
+        Target::Lists => {
+            entries.push(Entry::new(lists_href(user_id), Resource::Lists));
+            if depth_one {
+                for list in fetch_lists(pool, user_id).await? {
+                    entries.push(calendar_entry(pool, user_id, Calendar::List(list)).await?);
+                }
+            }
+        }
+        Target::Calendar(calendar) => {
+            entries.push(calendar_entry(pool, user_id, calendar.clone()).await?);
+            if depth_one {
+                for todo in fetch_todos(pool, user_id, calendar).await? {
+                    entries.push(Entry::todo(user_id, calendar, todo));
+                }
+            }
+        }
+        Target::Todo(calendar, id) => {
+            let todo = fetch_todo(pool, user_id, calendar, id).await?.ok_or(StatusCode::NOT_FOUND)?;
+            entries.push(Entry::todo(user_id, calendar, todo));
+        }
+    }
+
+    let mut multistatus = Multistatus::new();
+    for entry in &entries {
+        multistatus.entry(user_id, entry, &request);
+    }
+    Ok(multistatus.into_response())
+}
+
+async fn report(pool: &DbPool, user_id: &str, target: &Target, body: &str) -> Result<Response, StatusCode> {
+    let Target::Calendar(calendar) = target else {
+        return Err(StatusCode::FORBIDDEN);
+    };
+
+    let document = parse_xml(body)?;
+    let root = document.root_element();
+    let request = match child(root, DAV_NS, "prop") {
+        Some(prop) => PropRequest::Props(prop_names(prop)),
+        None => PropRequest::All,
+    };
+
+    let mut multistatus = Multistatus::new();
+    if is(root, CALDAV_NS, "calendar-query") {
+        let filter = child(root, CALDAV_NS, "filter");
+        for todo in fetch_todos(pool, user_id, calendar).await? {
+            if filter.is_none_or(|filter| matches_filter(filter, &todo)) {
+                multistatus.entry(user_id, &Entry::todo(user_id, calendar, todo), &request);
+            }
+        }
+    } else if is(root, CALDAV_NS, "calendar-multiget") {
+        let hrefs = root.children().filter(|node| is(*node, DAV_NS, "href"));
+        for href in hrefs {
+            let href = href.text().unwrap_or_default().trim();
+            let todo = match parse_path(href) {
+                Some((owner, Target::Todo(href_calendar, id))) if owner == user_id && href_calendar == *calendar => {
+                    fetch_todo(pool, user_id, calendar, &id).await?
+                }
+                _ => None,
+            };
+            match todo {
+                Some(todo) => multistatus.entry(user_id, &Entry::new(href.to_string(), Resource::Todo(Box::new(todo))), &request),
+                None => multistatus.missing(href),
+            }
+        }
+    } else {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    Ok(multistatus.into_response())
+}
+
+async fn get(pool: &DbPool, user_id: &str, target: &Target) -> Result<Response, StatusCode> {
+    match target {
+        Target::Calendar(calendar) => {
+            let records: Vec<TodoRecord> = fetch_todos(pool, user_id, calendar)
+                .await?
+                .into_iter()
+                .map(TodoRecord::from)
+                .collect();
+            Ok((
+                [(header::CONTENT_TYPE, CALENDAR_CONTENT_TYPE)],
+                ical::write_calendar(&records, Some(&display_name(calendar))),
+            )
+                .into_response())
+        }
+        Target::Todo(calendar, id) => {
+            let todo = fetch_todo(pool, user_id, calendar, id).await?.ok_or(StatusCode::NOT_FOUND)?;
+            let etag = etag(&todo);
+            Ok((
+                [(header::CONTENT_TYPE, TODO_CONTENT_TYPE.to_string()), (header::ETAG, etag)],
+                calendar_data(todo),
+            )
+                .into_response())
+        }
+        _ => Err(StatusCode::METHOD_NOT_ALLOWED),
+    }
+}
+
+/// Create or replace a todo from a single VTODO.
+/// The resource name must be the todo's UID.
+async fn put(
+    pool: &DbPool,
+    user_id: &str,
+    target: &Target,
+    headers: &HeaderMap,
+    body: &str,
+    raise_webhooks: bool,
+) -> Result<Response, StatusCode> {
+    let Target::Todo(calendar, id) = target else {
+        return Err(StatusCode::METHOD_NOT_ALLOWED);
+    };
+
+    let mut records = ical::parse_calendar(body).map_err(|_| StatusCode::BAD_REQUEST)?;
+    if records.len() != 1 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let record = records.remove(0);
+    if record.id != *id {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let existing: Option<Todo> = sqlx::query_as("SELECT * FROM todos WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if let Some(existing) = &existing {
+        let in_other_calendar = existing.deleted_at.is_none() && !calendar.contains(existing);
+        if existing.user_id != user_id || in_other_calendar {
+            return Err(StatusCode::CONFLICT);
+        }
+    }
+    let current = existing.as_ref().filter(|todo| todo.deleted_at.is_none());
+    check_preconditions(headers, current)?;
+
+    let mut todo = Todo::from_record(user_id.to_string(), record);
+    match calendar {
+        Calendar::List(list) => todo.list = Some(list.clone()),
+        Calendar::All if todo.list.is_none() => todo.list = existing.as_ref().and_then(|e| e.list.clone()),
+        Calendar::All => {}
+    }
+    if let Some(existing) = &existing {
+        todo.created_at = existing.created_at;
+    }
+    todo.updated_at = Utc::now();
+    // Subtasks can only belong to another of the user's todos
+    if let Some(parent_id) = &todo.parent_id {
+        let owned: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM todos WHERE id = ? AND user_id = ? AND id != ?)")
+            .bind(parent_id)
+            .bind(user_id)
+            .bind(&todo.id)
+            .fetch_one(pool)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if !owned {
+            todo.parent_id = None;
+        }
+    }
+
+    let mut tx = pool.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query(
+        r#"
+        INSERT INTO todos (id, user_id, title, description, completed, priority, created_at, updated_at, due_date, all_day, list, tags, recurrence, parent_id, deleted_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, NULL)
+        ON CONFLICT(id) DO UPDATE SET
+            title = excluded.title,
+            description = excluded.description,
+            completed = excluded.completed,
+            priority = excluded.priority,
+            updated_at = excluded.updated_at,
+            due_date = excluded.due_date,
+            all_day = excluded.all_day,
+            list = excluded.list,
+            tags = excluded.tags,
+            recurrence = excluded.recurrence,
+            parent_id = excluded.parent_id,
+            deleted_at = NULL
+        "#
+    )
+    .bind(&todo.id)
+    .bind(&todo.user_id)
+    .bind(&todo.title)
+    .bind(&todo.description)
+    .bind(todo.completed)
+    .bind(todo.priority)
+    .bind(todo.created_at)
+    .bind(todo.updated_at)
+    .bind(todo.due_date)
+    .bind(todo.all_day)
+    .bind(&todo.list)
+    .bind(&todo.tags)
+    .bind(&todo.recurrence)
+    .bind(&todo.parent_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    record_revision(&mut tx, id, existing.as_ref(), user_id, raise_webhooks).await?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let status = if current.is_some() { StatusCode::NO_CONTENT } else { StatusCode::CREATED };
+    Ok((status, [(header::ETAG, etag(&todo))]).into_response())
+}
+
+/// Move a todo to the trash
+async fn delete(
+    pool: &DbPool,
+    user_id: &str,
+    target: &Target,
+    headers: &HeaderMap,
+    raise_webhooks: bool,
+) -> Result<Response, StatusCode> {
+    let Target::Todo(calendar, id) = target else {
+        return Err(StatusCode::FORBIDDEN);
+    };
+
+    let todo = fetch_todo(pool, user_id, calendar, id).await?.ok_or(StatusCode::NOT_FOUND)?;
+    check_preconditions(headers, Some(&todo))?;
+
+    let now = Utc::now();
+    let mut tx = pool.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query("UPDATE todos SET deleted_at = ?, updated_at = ? WHERE id = ? AND user_id = ?")
+        .bind(now)
+        .bind(now)
+        .bind(id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    record_revision(&mut tx, id, Some(&todo), user_id, raise_webhooks).await?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Record a change made by a CalDAV client, which has no device id to send
+async fn record_revision(
+    conn: &mut SqliteConnection,
+    id: &str,
+    before: Option<&Todo>,
+    user_id: &str,
+    raise_webhooks: bool,
+) -> Result<(), StatusCode> {
+    revisions::record(conn, id, before, user_id, &DeviceId::default(), "caldav", raise_webhooks)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Evaluate `If-Match` and `If-None-Match` against the current version of a resource
+fn check_preconditions(headers: &HeaderMap, current: Option<&Todo>) -> Result<(), StatusCode> {
+    let current_etag = current.map(etag);
+    let matches = |value: &HeaderValue| {
+        value.to_str().unwrap_or_default().split(',').map(str::trim).any(|tag| match &current_etag {
+            Some(current) => tag == "*" || tag == current,
+            None => false,
+        })
+    };
+
+    if headers.get(header::IF_MATCH).is_some_and(|value| !matches(value)) {
+        return Err(StatusCode::PRECONDITION_FAILED);
+    }
+    if headers.get(header::IF_NONE_MATCH).is_some_and(matches) {
+        return Err(StatusCode::PRECONDITION_FAILED);
+    }
+    Ok(())
+}
+
+fn etag(todo: &Todo) -> String {
+    format!("\"{}\"", todo.updated_at.timestamp_micros())
+}
+
+fn calendar_data(todo: Todo) -> String {
+    ical::write_calendar(&[TodoRecord::from(todo)], None)
+}
+
+fn display_name(calendar: &Calendar) -> String {
+    match calendar {
+        Calendar::All => "Todos".to_string(),
+        Calendar::List(list) => list.clone(),
+    }
+}
+
+async fn fetch_todos(pool: &DbPool, user_id: &str, calendar: &Calendar) -> Result<Vec<Todo>, StatusCode> {
+    let todos = match calendar {
+        Calendar::All => {
+            sqlx::query_as::<_, Todo>(
+                "SELECT * FROM todos WHERE user_id = ? AND deleted_at IS NULL ORDER BY created_at"
+            )
+            .bind(user_id)
+            .fetch_all(pool)
+            .await
+        }
+        Calendar::List(list) => {
+            sqlx::query_as::<_, Todo>(
+                "SELECT * FROM todos WHERE user_id = ? AND list = ? AND deleted_at IS NULL ORDER BY created_at"
+            )
+            .bind(user_id)
+            .bind(list)
+            .fetch_all(pool)
+            .await
+        }
+    };
+
+    todos.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn fetch_todo(pool: &DbPool, user_id: &str, calendar: &Calendar, id: &str) -> Result<Option<Todo>, StatusCode> {
+    let todo: Option<Todo> = sqlx::query_as(
+        "SELECT * FROM todos WHERE id = ? AND user_id = ? AND deleted_at IS NULL"
+    )
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(todo.filter(|todo| calendar.contains(todo)))
+}
+
+async fn fetch_lists(pool: &DbPool, user_id: &str) -> Result<Vec<String>, StatusCode> {
+    let lists: Vec<(String,)> = sqlx::query_as(
+        "SELECT DISTINCT list FROM todos WHERE user_id = ? AND list IS NOT NULL AND deleted_at IS NULL ORDER BY list"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(lists.into_iter().map(|(list,)| list).collect())
+}
+
+async fn calendar_entry(pool: &DbPool, user_id: &str, calendar: Calendar) -> Result<Entry, StatusCode> {
+    // Trashed todos are included so that deletions change the tag as well
+    let (latest, count): (Option<String>, i64) = match &calendar {
+        Calendar::All => {
+            sqlx::query_as("SELECT MAX(updated_at), COUNT(*) FROM todos WHERE user_id = ?")
+                .bind(user_id)
+                .fetch_one(pool)
+                .await
+        }
+        Calendar::List(list) => {
+            sqlx::query_as("SELECT MAX(updated_at), COUNT(*) FROM todos WHERE user_id = ? AND list = ?")
+                .bind(user_id)
+                .bind(list)
+                .fetch_one(pool)
+                .await
+        }
+    }
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(latest.unwrap_or_default().as_bytes());
+    hasher.update(count.to_be_bytes());
+    let ctag = format!("\"{}\"", &hex::encode(hasher.finalize())[..16]);
+
+    Ok(Entry::new(calendar_href(user_id, &calendar), Resource::Calendar { calendar, ctag }))
+}
+
+/// Whether a todo passes a `calendar-query` filter.
+/// Time ranges are not supported and always match.
+fn matches_filter(filter: Node, todo: &Todo) -> bool {
+    let Some(calendar) = child(filter, CALDAV_NS, "comp-filter") else {
+        return true;
+    };
+    if !has_name(calendar, "VCALENDAR") {
+        return false;
+    }
+
+    calendar
+        .children()
+        .filter(|node| is(*node, CALDAV_NS, "comp-filter"))
+        .all(|component| {
+            let defined = has_name(component, "VTODO");
+            if child(component, CALDAV_NS, "is-not-defined").is_some() {
+                return !defined;
+            }
+            defined
+                && component
+                    .children()
+                    .filter(|node| is(*node, CALDAV_NS, "prop-filter"))
+                    .all(|prop_filter| matches_prop_filter(prop_filter, todo))
+        })
+}
+
+fn matches_prop_filter(filter: Node, todo: &Todo) -> bool {
+    let name = filter.attribute("name").unwrap_or_default().to_ascii_uppercase();
+    let value = property_text(todo, &name);
+
+    if child(filter, CALDAV_NS, "is-not-defined").is_some() {
+        return value.is_none();
+    }
+    let Some(value) = value else {
+        return false;
+    };
+    match child(filter, CALDAV_NS, "text-match") {
+        Some(text_match) => {
+            let needle = text_match.text().unwrap_or_default().to_lowercase();
+            let negate = text_match.attribute("negate-condition") == Some("yes");
+            value.to_lowercase().contains(&needle) != negate
+        }
+        None => true,
+    }
+}
+
+/// Text of a VTODO property as written by the iCalendar export, or `None` when it is not set
+fn property_text(todo: &Todo, name: &str) -> Option<String> {
+    let date_time = |value: chrono::DateTime<Utc>| value.format("%Y%m%dT%H%M%SZ").to_string();
+    match name {
+        "UID" => Some(todo.id.clone()),
+        "SUMMARY" => Some(todo.title.clone()),
+        "DESCRIPTION" => todo.description.clone(),
+        "STATUS" => Some(if todo.completed { "COMPLETED" } else { "NEEDS-ACTION" }.to_string()),
+        "PRIORITY" => Some(todo.priority.to_string()),
+        "DTSTAMP" | "LAST-MODIFIED" => Some(date_time(todo.updated_at)),
+        "CREATED" => Some(date_time(todo.created_at)),
+        "DUE" => todo.due_date.map(date_time),
+        "COMPLETED" => todo.completed.then(|| date_time(todo.updated_at)),
+        "CATEGORIES" => (!todo.tags.is_empty()).then(|| todo.tags.join(",")),
+        "RRULE" => todo.recurrence.clone(),
+        _ => None,
+    }
+}
+
+/// A resource listed in a multistatus response
+enum Resource {
+    Home,
+    Lists,
+    Calendar { calendar: Calendar, ctag: String },
+    Todo(Box<Todo>),
+}
+
+struct Entry {
+    href: String,
+    resource: Resource,
+}
+
+impl Entry {
+    fn new(href: String, resource: Resource) -> Self {
+        Self { href, resource }
+    }
+
+    fn todo(user_id: &str, calendar: &Calendar, todo: Todo) -> Self {
+        Self::new(todo_href(user_id, calendar, &todo.id), Resource::Todo(Box::new(todo)))
+    }
+}
+
+/// A property name qualified by its XML namespace
+#[derive(Debug, Clone, PartialEq)]
+struct PropName {
+    namespace: String,
+    name: String,
+}
+
+impl PropName {
+    fn new(namespace: &str, name: &str) -> Self {
+        Self {
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+        }
+    }
+}
+
+/// The properties asked for by a PROPFIND or REPORT
+enum PropRequest {
+    All,
+    Props(Vec<PropName>),
+}
+
+/// Properties returned for `allprop` requests and requests without a body
+fn default_props() -> Vec<PropName> {
+    vec![
+        PropName::new(DAV_NS, "resourcetype"),
+        PropName::new(DAV_NS, "displayname"),
+        PropName::new(DAV_NS, "getetag"),
+        PropName::new(DAV_NS, "getcontenttype"),
+        PropName::new(CALENDARSERVER_NS, "getctag"),
+        PropName::new(CALDAV_NS, "supported-calendar-component-set"),
+    ]
+}
+
+/// The XML content of a property, or `None` if the resource does not have it
+fn prop_value(user_id: &str, resource: &Resource, prop: &PropName) -> Option<String> {
+    let href = |href: String| format!("<d:href>{}</d:href>", escape_xml(&href));
+
+    match (prop.namespace.as_str(), prop.name.as_str(), resource) {
+        (DAV_NS, "resourcetype", Resource::Todo(_)) => Some(String::new()),
+        (DAV_NS, "resourcetype", Resource::Calendar { .. }) => Some("<d:collection/><c:calendar/>".to_string()),
+        (DAV_NS, "resourcetype", _) => Some("<d:collection/>".to_string()),
+        (DAV_NS, "displayname", Resource::Home) => Some(escape_xml(user_id)),
+        (DAV_NS, "displayname", Resource::Lists) => Some("Lists".to_string()),
+        (DAV_NS, "displayname", Resource::Calendar { calendar, .. }) => Some(escape_xml(&display_name(calendar))),
+        (DAV_NS, "current-user-principal" | "principal-URL", _) => Some(href(home_href(user_id))),
+        (CALDAV_NS, "calendar-home-set", _) => Some(href(home_href(user_id))),
+        (CALDAV_NS, "supported-calendar-component-set", Resource::Calendar { .. }) => {
+            Some("<c:comp name=\"VTODO\"/>".to_string())
+        }
+        (CALENDARSERVER_NS, "getctag", Resource::Calendar { ctag, .. }) => Some(escape_xml(ctag)),
+        (DAV_NS, "getetag", Resource::Todo(todo)) => Some(escape_xml(&etag(todo))),
+        (DAV_NS, "getcontenttype", Resource::Todo(_)) => Some(TODO_CONTENT_TYPE.to_string()),
+        (CALDAV_NS, "calendar-data", Resource::Todo(todo)) => Some(escape_xml(&calendar_data(todo.as_ref().clone()))),
+        _ => None,
+    }
+}
+
+/// Builder for a `207 Multi-Status` response
+struct Multistatus {
+    body: String,
+}
+
+impl Multistatus {
+    fn new() -> Self {
+        Self {
+            body: format!(
+                r#"<?xml version="1.0" encoding="utf-8"?><d:multistatus xmlns:d="{}" xmlns:c="{}" xmlns:cs="{}">"#,
+                DAV_NS, CALDAV_NS, CALENDARSERVER_NS
+            ),
+        }
+    }
+
+    fn entry(&mut self, user_id: &str, entry: &Entry, request: &PropRequest) {
+        let (props, report_missing) = match request {
+            PropRequest::All => (default_props(), false),
+            PropRequest::Props(props) => (props.clone(), true),
+        };
+
+        let mut found = String::new();
+        let mut missing = String::new();
+        for prop in &props {
+            match prop_value(user_id, &entry.resource, prop) {
+                Some(value) => found.push_str(&prop_element(prop, &value)),
+                None if report_missing => missing.push_str(&prop_element(prop, "")),
+                None => {}
+            }
+        }
+
+        self.body.push_str("<d:response>");
+        self.body.push_str(&format!("<d:href>{}</d:href>", escape_xml(&entry.href)));
+        for (props, status) in [(found, "200 OK"), (missing, "404 Not Found")] {
+            if !props.is_empty() {
+                self.body.push_str(&format!(
+                    "<d:propstat><d:prop>{}</d:prop><d:status>HTTP/1.1 {}</d:status></d:propstat>",
+                    props, status
+                ));
+            }
+        }
+        self.body.push_str("</d:response>");
+    }
+
+    fn missing(&mut self, href: &str) {
+        self.body.push_str(&format!(
+            "<d:response><d:href>{}</d:href><d:status>HTTP/1.1 404 Not Found</d:status></d:response>",
+            escape_xml(href)
+        ));
+    }
+
+    fn into_response(mut self) -> Response {
+        self.body.push_str("</d:multistatus>");
+        let mut response = Response::new(Body::from(self.body));
+        *response.status_mut() = StatusCode::MULTI_STATUS;
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(XML_CONTENT_TYPE));
+        response
+    }
+}
+
+fn prop_element(prop: &PropName, value: &str) -> String {
+    let (prefix, declaration) = match prop.namespace.as_str() {
+        DAV_NS => ("d", String::new()),
+        CALDAV_NS => ("c", String::new()),
+        CALENDARSERVER_NS => ("cs", String::new()),
+        other => ("x", format!(" xmlns:x=\"{}\"", escape_xml(other))),
+    };
+
+    if value.is_empty() {
+        format!("<{}:{}{}/>", prefix, prop.name, declaration)
+    } else {
+        format!("<{0}:{1}{2}>{3}</{0}:{1}>", prefix, prop.name, declaration, value)
+    }
+}
+
+fn parse_xml(body: &str) -> Result<Document<'_>, StatusCode> {
+    Document::parse(body).map_err(|_| StatusCode::BAD_REQUEST)
+}
+
+fn is(node: Node, namespace: &str, name: &str) -> bool {
+    node.is_element() && node.tag_name().namespace() == Some(namespace) && node.tag_name().name() == name
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, namespace: &str, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| is(*child, namespace, name))
+}
+
+fn has_name(node: Node, name: &str) -> bool {
+    node.attribute("name").is_some_and(|value| value.eq_ignore_ascii_case(name))
+}
+
+/// The property names inside a `<d:prop>` element
+fn prop_names(prop: Node) -> Vec<PropName> {
+    prop.children()
+        .filter(|node| node.is_element())
+        .map(|node| PropName::new(node.tag_name().namespace().unwrap_or_default(), node.tag_name().name()))
+        .collect()
+}
+
+fn escape_xml(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
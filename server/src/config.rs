@@ -0,0 +1,498 @@
+//! Server configuration, read from a TOML file with environment overrides.
+//!
+//! The file is `server.toml` in the working directory, or the path in
+//! `SERVER_CONFIG`; every setting has a default, so the file is optional.
+//! Environment variables override the file, and the result is validated
+//! before the server starts. See `server.example.toml` for all settings.
+
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use axum::http::HeaderValue;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use todo_shared::revisions::MAX_RETENTION_DAYS;
+use tracing_subscriber::EnvFilter;
+
+use crate::limits::Limits;
+
+/// Config file read when `SERVER_CONFIG` is not set
+pub const DEFAULT_CONFIG_PATH: &str = "server.toml";
+
+/// Why the configuration could not be loaded
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("could not read config file {path}: {source}")]
+    Read { path: PathBuf, source: std::io::Error },
+    #[error("invalid config file {path}: {source}")]
+    Parse { path: PathBuf, source: toml::de::Error },
+    #[error("invalid value {value:?} for {name}: {message}")]
+    Env { name: String, value: String, message: String },
+    #[error("invalid configuration:\n{}", format_problems(.0))]
+    Invalid(Vec<String>),
+}
+
+fn format_problems(problems: &[String]) -> String {
+    problems
+        .iter()
+        .map(|problem| format!("  - {}", problem))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The whole server configuration
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub tls: TlsConfig,
+    pub database: DatabaseConfig,
+    pub log: LogConfig,
+    pub retention: RetentionConfig,
+    pub limits: Limits,
+    pub blobs: BlobConfig,
+    pub webhooks: WebhookConfig,
+    pub api_tokens: ApiTokenConfig,
+    pub health: HealthConfig,
+    pub telemetry: TelemetryConfig,
+    pub features: Features,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Address to listen on
+    pub bind: String,
+    /// Origins browsers may call the API from, or `["*"]` for any
+    pub allowed_origins: Vec<String>,
+    /// How long requests in flight get to finish when shutting down
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: "0.0.0.0:3001".to_string(),
+            allowed_origins: vec!["*".to_string()],
+            shutdown_timeout_secs: 30,
+        }
+    }
+}
+
+/// HTTPS serving; on when both a certificate and a key are given
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM file with the certificate chain
+    pub cert_path: Option<PathBuf>,
+    /// PEM file with the private key
+    pub key_path: Option<PathBuf>,
+    /// Generate a self-signed certificate at the paths above if there is none, for development
+    pub self_signed: bool,
+    /// Host names and IP addresses the self-signed certificate is valid for
+    pub self_signed_names: Vec<String>,
+    /// Seconds between checks for a changed certificate or key
+    pub reload_secs: u64,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            cert_path: None,
+            key_path: None,
+            self_signed: false,
+            self_signed_names: vec!["localhost".to_string(), "127.0.0.1".to_string()],
+            reload_secs: 60,
+        }
+    }
+}
+
+impl TlsConfig {
+    pub fn enabled(&self) -> bool {
+        self.cert_path.is_some() && self.key_path.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// SQLite connection URL
+    pub url: String,
+    /// Size of the connection pool
+    pub max_connections: u32,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: "sqlite:./todos.db?mode=rwc".to_string(),
+            max_connections: 5,
+        }
+    }
+}
+
+/// How log lines are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines
+    #[default]
+    Pretty,
+    /// One JSON object per line
+    Json,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    /// Which events to log, in `RUST_LOG` syntax
+    pub filter: String,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Pretty,
+            filter: "todo_server=debug,tower_http=debug".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    /// Days todos stay in the trash; removed attachments are kept as long
+    pub trash_days: i64,
+    /// Days of revision history kept
+    pub revision_days: i64,
+    /// Seconds an idempotency key and its response are kept
+    pub idempotency_secs: i64,
+    /// Days finished webhook deliveries, delivered or dead, are kept
+    pub webhook_delivery_days: i64,
+    /// Days change feed events are kept
+    pub feed_days: i64,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            trash_days: 30,
+            revision_days: todo_shared::revisions::DEFAULT_RETENTION_DAYS,
+            idempotency_secs: 24 * 60 * 60,
+            webhook_delivery_days: 30,
+            feed_days: 30,
+        }
+    }
+}
+
+/// Where attachment contents are kept
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BlobStoreKind {
+    /// Files in `dir`
+    #[default]
+    Fs,
+    /// An in-memory stand-in for an S3-compatible service
+    MemoryS3,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlobConfig {
+    pub store: BlobStoreKind,
+    pub dir: PathBuf,
+    pub bucket: String,
+}
+
+impl Default for BlobConfig {
+    fn default() -> Self {
+        Self {
+            store: BlobStoreKind::Fs,
+            dir: PathBuf::from("./blobs"),
+            bucket: "attachments".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookConfig {
+    /// Attempts before a delivery becomes a dead letter
+    pub max_attempts: i64,
+    /// Let webhooks target loopback, private and link-local addresses
+    pub allow_private_targets: bool,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 8,
+            allow_private_targets: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiTokenConfig {
+    /// Refuse requests without a token for users who have created one, on
+    /// every route tokens may use
+    pub required_once_created: bool,
+}
+
+/// Limits for the readiness check
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    /// How long the database gets to answer before the server is not ready
+    pub db_timeout_ms: u64,
+    /// Free space needed next to the database file
+    pub min_free_disk_bytes: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            db_timeout_ms: 2_000,
+            min_free_disk_bytes: 100 * 1024 * 1024,
+        }
+    }
+}
+
+/// OpenTelemetry trace export, for servers built with the `otel` feature
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    /// Collector to send spans to over OTLP/HTTP, such as `http://localhost:4318`;
+    /// nothing is exported without one
+    pub otlp_endpoint: Option<String>,
+    /// `service.name` of the exported spans
+    pub service_name: String,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: "todo-server".to_string(),
+        }
+    }
+}
+
+/// Optional parts of the server that can be switched off
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Features {
+    /// The CalDAV endpoint under `/dav`
+    pub caldav: bool,
+    /// The iCalendar feed and its tokens
+    pub calendar_feed: bool,
+    /// Webhook management and delivery; no events are queued while off
+    pub webhooks: bool,
+    /// Prometheus metrics at `/metrics`
+    pub metrics: bool,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Self {
+            caldav: true,
+            calendar_feed: true,
+            webhooks: true,
+            metrics: true,
+        }
+    }
+}
+
+impl Config {
+    /// Load the config file, apply environment overrides and validate the result
+    pub fn load() -> Result<Self, ConfigError> {
+        let path = std::env::var("SERVER_CONFIG").ok();
+        let mut config = match &path {
+            Some(path) => Self::from_file(Path::new(path))?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => Self::from_file(Path::new(DEFAULT_CONFIG_PATH))?,
+            None => Self::default(),
+        };
+        config.apply_env(|name| std::env::var(name).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Read a config file, without environment overrides or validation
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        toml::from_str(&text).map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    /// Override settings with the environment variables `var` returns
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        let var = &var;
+        override_with(var, "SERVER_ADDR", &mut self.server.bind, parse)?;
+        override_with(var, "CORS_ALLOWED_ORIGINS", &mut self.server.allowed_origins, |value| {
+            Ok::<_, String>(value.split(',').map(|origin| origin.trim().to_string()).collect())
+        })?;
+        override_with(var, "SHUTDOWN_TIMEOUT_SECS", &mut self.server.shutdown_timeout_secs, parse)?;
+        override_with(var, "TLS_CERT_PATH", &mut self.tls.cert_path, |value| parse(value).map(Some))?;
+        override_with(var, "TLS_KEY_PATH", &mut self.tls.key_path, |value| parse(value).map(Some))?;
+        override_with(var, "TLS_SELF_SIGNED", &mut self.tls.self_signed, parse)?;
+        override_with(var, "DATABASE_URL", &mut self.database.url, parse)?;
+        override_with(var, "DATABASE_MAX_CONNECTIONS", &mut self.database.max_connections, parse)?;
+        override_with(var, "LOG_FORMAT", &mut self.log.format, parse_enum)?;
+        override_with(var, "RUST_LOG", &mut self.log.filter, parse)?;
+        override_with(var, "TRASH_RETENTION_DAYS", &mut self.retention.trash_days, parse)?;
+        override_with(var, "REVISION_RETENTION_DAYS", &mut self.retention.revision_days, parse)?;
+        override_with(var, "IDEMPOTENCY_WINDOW_SECS", &mut self.retention.idempotency_secs, parse)?;
+        override_with(var, "WEBHOOK_DELIVERY_RETENTION_DAYS", &mut self.retention.webhook_delivery_days, parse)?;
+        override_with(var, "FEED_RETENTION_DAYS", &mut self.retention.feed_days, parse)?;
+        self.limits.apply_env(var)?;
+        override_with(var, "BLOB_STORE", &mut self.blobs.store, parse_enum)?;
+        override_with(var, "BLOB_DIR", &mut self.blobs.dir, parse)?;
+        override_with(var, "BLOB_BUCKET", &mut self.blobs.bucket, parse)?;
+        override_with(var, "WEBHOOK_MAX_ATTEMPTS", &mut self.webhooks.max_attempts, parse)?;
+        override_with(var, "WEBHOOK_ALLOW_PRIVATE_TARGETS", &mut self.webhooks.allow_private_targets, parse)?;
+        override_with(var, "API_TOKENS_REQUIRED", &mut self.api_tokens.required_once_created, parse)?;
+        override_with(var, "HEALTH_DB_TIMEOUT_MS", &mut self.health.db_timeout_ms, parse)?;
+        override_with(var, "HEALTH_MIN_FREE_DISK_BYTES", &mut self.health.min_free_disk_bytes, parse)?;
+        override_with(var, "OTEL_EXPORTER_OTLP_ENDPOINT", &mut self.telemetry.otlp_endpoint, |value| parse(value).map(Some))?;
+        override_with(var, "OTEL_SERVICE_NAME", &mut self.telemetry.service_name, parse)?;
+        override_with(var, "FEATURE_CALDAV", &mut self.features.caldav, parse)?;
+        override_with(var, "FEATURE_CALENDAR_FEED", &mut self.features.calendar_feed, parse)?;
+        override_with(var, "FEATURE_WEBHOOKS", &mut self.features.webhooks, parse)?;
+        override_with(var, "FEATURE_METRICS", &mut self.features.metrics, parse)?;
+        Ok(())
+    }
+
+    /// Check the settings, reporting every problem at once
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+
+        if self.server.bind.parse::<SocketAddr>().is_err() {
+            problems.push(format!("server.bind: {:?} is not an address like 0.0.0.0:3001", self.server.bind));
+        }
+        if let Err(problem) = allowed_origins(&self.server.allowed_origins) {
+            problems.push(format!("server.allowed_origins: {}", problem));
+        }
+        if self.server.shutdown_timeout_secs == 0 {
+            problems.push("server.shutdown_timeout_secs: must be at least 1".to_string());
+        }
+        if self.tls.cert_path.is_some() != self.tls.key_path.is_some() {
+            problems.push("tls: cert_path and key_path must be given together".to_string());
+        }
+        if self.tls.self_signed && !self.tls.enabled() {
+            problems.push("tls.self_signed: needs cert_path and key_path to write the certificate to".to_string());
+        }
+        if self.tls.self_signed && self.tls.self_signed_names.is_empty() {
+            problems.push("tls.self_signed_names: must name at least one host".to_string());
+        }
+        if self.tls.reload_secs == 0 {
+            problems.push("tls.reload_secs: must be at least 1".to_string());
+        }
+        if !self.database.url.starts_with("sqlite:") {
+            problems.push(format!("database.url: {:?} is not a sqlite: URL", self.database.url));
+        }
+        if self.database.max_connections == 0 {
+            problems.push("database.max_connections: must be at least 1".to_string());
+        }
+        if let Err(error) = EnvFilter::try_new(&self.log.filter) {
+            problems.push(format!("log.filter: {}", error));
+        }
+        for (name, value) in [
+            ("retention.trash_days", self.retention.trash_days),
+            ("retention.revision_days", self.retention.revision_days),
+            ("retention.idempotency_secs", self.retention.idempotency_secs),
+            ("retention.webhook_delivery_days", self.retention.webhook_delivery_days),
+            ("retention.feed_days", self.retention.feed_days),
+            ("webhooks.max_attempts", self.webhooks.max_attempts),
+        ] {
+            if value < 1 {
+                problems.push(format!("{}: must be at least 1", name));
+            }
+        }
+        for (name, value, max) in [
+            ("retention.trash_days", self.retention.trash_days, MAX_RETENTION_DAYS),
+            ("retention.revision_days", self.retention.revision_days, MAX_RETENTION_DAYS),
+            ("retention.idempotency_secs", self.retention.idempotency_secs, MAX_RETENTION_DAYS * 24 * 60 * 60),
+            ("retention.webhook_delivery_days", self.retention.webhook_delivery_days, MAX_RETENTION_DAYS),
+            ("retention.feed_days", self.retention.feed_days, MAX_RETENTION_DAYS),
+        ] {
+            if value > max {
+                problems.push(format!("{}: must be at most {}", name, max));
+            }
+        }
+        if self.telemetry.otlp_endpoint.is_some() && !cfg!(feature = "otel") {
+            problems.push("telemetry.otlp_endpoint: the server was built without the otel feature".to_string());
+        }
+        if self.health.db_timeout_ms == 0 {
+            problems.push("health.db_timeout_ms: must be at least 1".to_string());
+        }
+        problems.extend(self.limits.problems());
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+}
+
+/// Origins browsers may call the API from; `None` allows any origin
+pub fn allowed_origins(origins: &[String]) -> Result<Option<Vec<HeaderValue>>, String> {
+    if origins.iter().any(|origin| origin == "*") {
+        if origins.len() > 1 {
+            return Err("\"*\" cannot be combined with other origins".to_string());
+        }
+        return Ok(None);
+    }
+
+    origins
+        .iter()
+        .map(|origin| {
+            let is_origin = reqwest::Url::parse(origin).is_ok_and(|url| {
+                matches!(url.scheme(), "http" | "https")
+                    && url.has_host()
+                    && url.path() == "/"
+                    && !origin.ends_with('/')
+            });
+            match HeaderValue::from_str(origin) {
+                Ok(value) if is_origin => Ok(value),
+                _ => Err(format!("{:?} is not an origin like https://todo.example.com", origin)),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+}
+
+/// Replace `target` with the parsed value of the environment variable `name`, if set
+pub(crate) fn override_with<T, E: Display>(
+    var: &impl Fn(&str) -> Option<String>,
+    name: &str,
+    target: &mut T,
+    parse: impl Fn(&str) -> Result<T, E>,
+) -> Result<(), ConfigError> {
+    let Some(value) = var(name) else {
+        return Ok(());
+    };
+    *target = parse(&value).map_err(|error| ConfigError::Env {
+        name: name.to_string(),
+        value: value.clone(),
+        message: error.to_string(),
+    })?;
+    Ok(())
+}
+
+pub(crate) fn parse<T: FromStr>(value: &str) -> Result<T, T::Err> {
+    value.parse()
+}
+
+fn parse_enum<T: DeserializeOwned>(value: &str) -> Result<T, toml::de::Error> {
+    T::deserialize(toml::Value::String(value.to_string()))
+}
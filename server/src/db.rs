@@ -1,18 +1,32 @@
-use sqlx::{Pool, Sqlite, sqlite::SqlitePoolOptions};
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use sqlx::{Pool, Sqlite, SqliteConnection, sqlite::SqlitePoolOptions};
 use std::path::Path;
+use todo_shared::due;
+
+use crate::shutdown::Shutdown;
 
 pub type DbPool = Pool<Sqlite>;
 
-/// Initialize the database connection pool
-pub async fn init_db() -> Result<DbPool, sqlx::Error> {
-    let db_path = std::env::var("DATABASE_URL")
-        .unwrap_or_else(|_| "sqlite:./todos.db?mode=rwc".to_string());
-    
+/// Pool size used by `connect`
+pub const DEFAULT_MAX_CONNECTIONS: u32 = 5;
+
+/// Stored as the database's `user_version` once migrations have run; bump it
+/// when changing them
+pub const SCHEMA_VERSION: i64 = 1;
+
+/// Open the database at `db_path`, creating it and running migrations as needed
+pub async fn connect(db_path: &str) -> Result<DbPool, sqlx::Error> {
+    connect_with(db_path, DEFAULT_MAX_CONNECTIONS).await
+}
+
+/// Open the database at `db_path` with a pool of up to `max_connections`
+pub async fn connect_with(db_path: &str, max_connections: u32) -> Result<DbPool, sqlx::Error> {
     // Create database file if it doesn't exist
     if !db_path.contains(":memory:") {
         let path = db_path
             .strip_prefix("sqlite:")
-            .unwrap_or(&db_path)
+            .unwrap_or(db_path)
             .split('?')
             .next()
             .unwrap_or("./todos.db");
@@ -23,8 +37,8 @@ pub async fn init_db() -> Result<DbPool, sqlx::Error> {
     }
     
     let pool = SqlitePoolOptions::new()
-        .max_connections(5)
-        .connect(&db_path)
+        .max_connections(max_connections)
+        .connect(db_path)
         .await?;
     
     // Run migrations
@@ -39,15 +53,334 @@ pub async fn init_db() -> Result<DbPool, sqlx::Error> {
             priority TEXT NOT NULL DEFAULT 'medium',
             created_at TEXT NOT NULL,
             updated_at TEXT NOT NULL,
-            due_date TEXT
+            due_date TEXT,
+            all_day BOOLEAN NOT NULL DEFAULT 0,
+            list TEXT,
+            tags TEXT NOT NULL DEFAULT '[]',
+            recurrence TEXT,
+            reminders TEXT NOT NULL DEFAULT '[]',
+            list_id TEXT,
+            assignee_id TEXT,
+            parent_id TEXT,
+            deleted_at TEXT
         );
         
         CREATE INDEX IF NOT EXISTS idx_todos_user_id ON todos(user_id);
         CREATE INDEX IF NOT EXISTS idx_todos_updated_at ON todos(updated_at);
+        
+        CREATE TABLE IF NOT EXISTS idempotency_keys (
+            user_id TEXT NOT NULL,
+            key TEXT NOT NULL,
+            fingerprint TEXT NOT NULL,
+            status INTEGER,
+            content_type TEXT,
+            body BLOB,
+            created_at TEXT NOT NULL,
+            PRIMARY KEY (user_id, key)
+        );
+        
+        CREATE INDEX IF NOT EXISTS idx_idempotency_keys_created_at ON idempotency_keys(created_at);
+        
+        CREATE TABLE IF NOT EXISTS calendar_feeds (
+            user_id TEXT PRIMARY KEY NOT NULL,
+            token_hash TEXT NOT NULL UNIQUE,
+            created_at TEXT NOT NULL
+        );
+        
+        CREATE TABLE IF NOT EXISTS views (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            filter TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            deleted_at TEXT
+        );
+        
+        CREATE INDEX IF NOT EXISTS idx_views_user_id ON views(user_id);
+        
+        CREATE TABLE IF NOT EXISTS user_settings (
+            user_id TEXT PRIMARY KEY NOT NULL,
+            timezone TEXT NOT NULL,
+            username TEXT,
+            email TEXT,
+            updated_at TEXT NOT NULL
+        );
+        
+        CREATE TABLE IF NOT EXISTS shared_lists (
+            id TEXT PRIMARY KEY NOT NULL,
+            name TEXT NOT NULL,
+            created_by TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        
+        CREATE TABLE IF NOT EXISTS list_members (
+            list_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            role TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            PRIMARY KEY (list_id, user_id)
+        );
+        
+        CREATE INDEX IF NOT EXISTS idx_list_members_user_id ON list_members(user_id);
+        
+        CREATE TABLE IF NOT EXISTS list_invitations (
+            id TEXT PRIMARY KEY NOT NULL,
+            list_id TEXT NOT NULL,
+            invitee TEXT NOT NULL,
+            role TEXT NOT NULL,
+            invited_by TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending',
+            created_at TEXT NOT NULL,
+            responded_at TEXT
+        );
+        
+        CREATE INDEX IF NOT EXISTS idx_list_invitations_invitee ON list_invitations(invitee);
+        
+        CREATE TABLE IF NOT EXISTS comments (
+            id TEXT PRIMARY KEY NOT NULL,
+            todo_id TEXT NOT NULL,
+            author_id TEXT NOT NULL,
+            body TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            deleted_at TEXT
+        );
+        
+        CREATE INDEX IF NOT EXISTS idx_comments_todo_id ON comments(todo_id);
+        
+        CREATE TABLE IF NOT EXISTS feed (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            todo_id TEXT NOT NULL,
+            comment_id TEXT,
+            actor_id TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        );
+        
+        CREATE INDEX IF NOT EXISTS idx_feed_user_id ON feed(user_id, id);
+        CREATE INDEX IF NOT EXISTS idx_feed_created_at ON feed(created_at);
+        
+        CREATE TABLE IF NOT EXISTS todo_revisions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            todo_id TEXT NOT NULL,
+            actor_id TEXT NOT NULL,
+            device_id TEXT,
+            action TEXT NOT NULL,
+            changes TEXT NOT NULL,
+            snapshot TEXT,
+            created_at TEXT NOT NULL
+        );
+        
+        CREATE INDEX IF NOT EXISTS idx_todo_revisions_todo_id ON todo_revisions(todo_id);
+        CREATE INDEX IF NOT EXISTS idx_todo_revisions_created_at ON todo_revisions(created_at);
+        
+        CREATE TABLE IF NOT EXISTS attachments (
+            id TEXT PRIMARY KEY,
+            todo_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            content_type TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            hash TEXT NOT NULL,
+            uploaded_by TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            deleted_at TEXT
+        );
+        
+        CREATE INDEX IF NOT EXISTS idx_attachments_todo_id ON attachments(todo_id);
+        CREATE INDEX IF NOT EXISTS idx_attachments_hash ON attachments(hash);
+        
+        CREATE TABLE IF NOT EXISTS webhooks (
+            id TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            url TEXT NOT NULL,
+            secret TEXT NOT NULL,
+            events TEXT NOT NULL,
+            active BOOLEAN NOT NULL DEFAULT 1,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        
+        CREATE INDEX IF NOT EXISTS idx_webhooks_user_id ON webhooks(user_id);
+        
+        CREATE TABLE IF NOT EXISTS webhook_deliveries (
+            id TEXT PRIMARY KEY,
+            webhook_id TEXT NOT NULL,
+            event TEXT NOT NULL,
+            payload TEXT NOT NULL,
+            status TEXT NOT NULL,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            next_attempt_at TEXT NOT NULL,
+            last_status INTEGER,
+            last_error TEXT,
+            created_at TEXT NOT NULL,
+            delivered_at TEXT
+        );
+        
+        CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_due ON webhook_deliveries(status, next_attempt_at);
+        CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_webhook_id ON webhook_deliveries(webhook_id, created_at);
+        
+        CREATE TABLE IF NOT EXISTS blobs (
+            hash TEXT PRIMARY KEY,
+            created_at TEXT NOT NULL,
+            last_used_at TEXT NOT NULL
+        );
+        
+        CREATE TABLE IF NOT EXISTS api_tokens (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            token_hash TEXT NOT NULL UNIQUE,
+            scopes TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            last_used_at TEXT,
+            revoked_at TEXT
+        );
+        
+        CREATE INDEX IF NOT EXISTS idx_api_tokens_user_id ON api_tokens(user_id);
+        
+        CREATE TABLE IF NOT EXISTS purged_todos (
+            id TEXT PRIMARY KEY NOT NULL,
+            user_id TEXT NOT NULL,
+            list_id TEXT,
+            purged_at TEXT NOT NULL
+        );
+        
+        CREATE INDEX IF NOT EXISTS idx_purged_todos_purged_at ON purged_todos(purged_at);
+        "#
+    )
+    .execute(&pool)
+    .await?;
+    
+    add_column_if_missing(&pool, "todos", "deleted_at", "TEXT").await?;
+    add_column_if_missing(&pool, "todos", "list", "TEXT").await?;
+    add_column_if_missing(&pool, "todos", "tags", "TEXT NOT NULL DEFAULT '[]'").await?;
+    add_column_if_missing(&pool, "todos", "recurrence", "TEXT").await?;
+    add_column_if_missing(&pool, "todos", "reminders", "TEXT NOT NULL DEFAULT '[]'").await?;
+    add_column_if_missing(&pool, "todos", "all_day", "BOOLEAN NOT NULL DEFAULT 0").await?;
+    add_column_if_missing(&pool, "todos", "list_id", "TEXT").await?;
+    add_column_if_missing(&pool, "todos", "assignee_id", "TEXT").await?;
+    add_column_if_missing(&pool, "todos", "parent_id", "TEXT").await?;
+    add_column_if_missing(&pool, "user_settings", "username", "TEXT").await?;
+    add_column_if_missing(&pool, "user_settings", "email", "TEXT").await?;
+    
+    // Indexes on added columns can only be created once the columns exist
+    sqlx::query(
+        r#"
+        CREATE INDEX IF NOT EXISTS idx_todos_list_id ON todos(list_id);
+        CREATE INDEX IF NOT EXISTS idx_todos_parent_id ON todos(parent_id);
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_user_settings_username ON user_settings(username);
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_user_settings_email ON user_settings(email);
         "#
     )
     .execute(&pool)
     .await?;
     
+    // PRAGMA does not take bound parameters
+    sqlx::query(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))
+        .execute(&pool)
+        .await?;
+    
     Ok(pool)
 }
+
+/// Add a column to an existing table, for databases created before the column existed
+async fn add_column_if_missing(
+    pool: &DbPool,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), sqlx::Error> {
+    let exists: Option<(String,)> = sqlx::query_as(
+        "SELECT name FROM pragma_table_info(?) WHERE name = ?"
+    )
+    .bind(table)
+    .bind(column)
+    .fetch_optional(pool)
+    .await?;
+    
+    if exists.is_none() {
+        sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+            .execute(pool)
+            .await?;
+    }
+    
+    Ok(())
+}
+
+/// The time zone a user has chosen, or UTC
+pub async fn user_timezone(pool: &DbPool, user_id: &str) -> Result<Tz, sqlx::Error> {
+    let timezone: Option<(String,)> = sqlx::query_as("SELECT timezone FROM user_settings WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+    
+    Ok(timezone
+        .and_then(|(name,)| due::parse_timezone(&name).ok())
+        .unwrap_or(due::DEFAULT_TIMEZONE))
+}
+
+/// Permanently delete todos that have been in the trash since before `cutoff`,
+/// along with their comments, attachments and revisions
+pub async fn purge_trash(pool: &DbPool, cutoff: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let ids: Vec<String> = sqlx::query_scalar("SELECT id FROM todos WHERE deleted_at IS NOT NULL AND deleted_at < ?")
+        .bind(cutoff)
+        .fetch_all(&mut *tx)
+        .await?;
+    purge_todos(&mut tx, &ids).await?;
+    tx.commit().await?;
+    
+    Ok(ids.len() as u64)
+}
+
+/// Delete todos for good, with their comments, attachments and revisions.
+/// Each leaves a tombstone in `purged_todos`, so that sync neither brings it
+/// back from a device that still has it nor leaves it on other devices.
+pub async fn purge_todos(conn: &mut SqliteConnection, todo_ids: &[String]) -> Result<(), sqlx::Error> {
+    if todo_ids.is_empty() {
+        return Ok(());
+    }
+    let ids = serde_json::to_string(todo_ids).map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+    sqlx::query(
+        r#"
+        INSERT OR REPLACE INTO purged_todos (id, user_id, list_id, purged_at)
+        SELECT id, user_id, list_id, ? FROM todos WHERE id IN (SELECT value FROM json_each(?))
+        "#
+    )
+    .bind(Utc::now())
+    .bind(&ids)
+    .execute(&mut *conn)
+    .await?;
+    for table in ["comments", "attachments", "todo_revisions", "feed"] {
+        sqlx::query(&format!("DELETE FROM {} WHERE todo_id IN (SELECT value FROM json_each(?))", table))
+            .bind(&ids)
+            .execute(&mut *conn)
+            .await?;
+    }
+    sqlx::query("DELETE FROM todos WHERE id IN (SELECT value FROM json_each(?))")
+        .bind(&ids)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Purge the trash once an hour, dropping todos deleted more than `retention` ago,
+/// until shutdown
+pub async fn purge_trash_periodically(pool: DbPool, retention: Duration, shutdown: Shutdown) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.triggered() => return,
+        }
+        match purge_trash(&pool, Utc::now() - retention).await {
+            Ok(0) => {}
+            Ok(purged) => tracing::info!("Purged {} todos from the trash", purged),
+            Err(e) => tracing::error!("Failed to purge trash: {}", e),
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! The change feed: events telling users about changes others made to todos
+//! they take part in. Clients poll it with the id of the last event they saw.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, SqliteConnection};
+
+use crate::db::DbPool;
+use crate::models::{ListRole, Todo};
+use crate::sharing;
+use crate::shutdown::Shutdown;
+
+/// Most events returned by one read of the feed
+pub const PAGE_SIZE: i64 = 100;
+
+/// What happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+pub enum FeedKind {
+    CommentAdded,
+    CommentEdited,
+    CommentDeleted,
+}
+
+/// An event in a user's change feed
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct FeedEvent {
+    pub id: i64,
+    pub kind: FeedKind,
+    pub todo_id: String,
+    pub comment_id: Option<String>,
+    /// The user who made the change
+    pub actor_id: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The users taking part in a todo's discussion, other than `actor_id`: its
+/// creator, its assignee and everyone who has commented on it, as long as
+/// they can still see it
+pub async fn participants(pool: &DbPool, todo: &Todo, actor_id: &str) -> Result<Vec<String>, sqlx::Error> {
+    let commenters: Vec<(String,)> =
+        sqlx::query_as("SELECT DISTINCT author_id FROM comments WHERE todo_id = ? AND deleted_at IS NULL")
+            .bind(&todo.id)
+            .fetch_all(pool)
+            .await?;
+
+    let mut candidates = vec![todo.user_id.clone()];
+    candidates.extend(todo.assignee_id.clone());
+    candidates.extend(commenters.into_iter().map(|(author_id,)| author_id));
+
+    let mut participants: Vec<String> = Vec::new();
+    for user_id in candidates {
+        if user_id == actor_id || participants.contains(&user_id) {
+            continue;
+        }
+        if sharing::todo_role(pool, todo, &user_id).await? >= Some(ListRole::Viewer) {
+            participants.push(user_id);
+        }
+    }
+    Ok(participants)
+}
+
+/// Add an event to the feed of every recipient
+pub async fn notify(
+    conn: &mut SqliteConnection,
+    recipients: &[String],
+    kind: FeedKind,
+    todo_id: &str,
+    comment_id: Option<&str>,
+    actor_id: &str,
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    for user_id in recipients {
+        sqlx::query(
+            "INSERT INTO feed (user_id, kind, todo_id, comment_id, actor_id, created_at) VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(user_id)
+        .bind(kind)
+        .bind(todo_id)
+        .bind(comment_id)
+        .bind(actor_id)
+        .bind(now)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// A user's events after `after`, oldest first
+pub async fn events(pool: &DbPool, user_id: &str, after: i64) -> Result<Vec<FeedEvent>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT id, kind, todo_id, comment_id, actor_id, created_at FROM feed
+        WHERE user_id = ? AND id > ?
+        ORDER BY id
+        LIMIT ?
+        "#,
+    )
+    .bind(user_id)
+    .bind(after)
+    .bind(PAGE_SIZE)
+    .fetch_all(pool)
+    .await
+}
+
+/// Drop events created before `cutoff`, returning how many were dropped
+pub async fn purge(pool: &DbPool, cutoff: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM feed WHERE created_at < ?")
+        .bind(cutoff)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// Purge the feed once an hour, dropping events older than `retention`,
+/// until shutdown
+pub async fn purge_periodically(pool: DbPool, retention: Duration, shutdown: Shutdown) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.triggered() => return,
+        }
+        match purge(&pool, Utc::now() - retention).await {
+            Ok(0) => {}
+            Ok(purged) => tracing::info!("Purged {} feed events", purged),
+            Err(e) => tracing::error!("Failed to purge feed events: {}", e),
+        }
+    }
+}
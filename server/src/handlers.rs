@@ -1,26 +1,53 @@
+use std::collections::HashSet;
+use std::time::Instant;
+
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    Json,
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
 };
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use sqlx::types::Json as SqlJson;
+use sqlx::SqliteConnection;
+use todo_shared::attachments;
+use todo_shared::comments;
+use todo_shared::due;
+use todo_shared::formats::{self, ical, ExportFormat, TodoRecord};
+use todo_shared::import::{self, ImportReport};
+use todo_shared::quick_add;
+use todo_shared::recurrence;
+use todo_shared::reminders::Reminder;
+use todo_shared::views::{self, SmartView, ViewCount};
 
-use crate::db::DbPool;
+use crate::api_tokens;
+use crate::blobs::{self, Blobs};
+use crate::config::{Features, WebhookConfig};
+use crate::db::{self, DbPool};
+use crate::feed::{self, FeedEvent, FeedKind};
+use crate::metrics::{Metrics, SyncStats};
 use crate::models::*;
+use crate::revisions::{self, DeviceId, Revision};
+use crate::sharing::{self, EDITABLE_TODOS, VISIBLE_TODOS};
+use crate::tokens;
+use crate::webhooks;
 
 /// Health check endpoint
 pub async fn health_check() -> Json<ApiResponse<&'static str>> {
     Json(ApiResponse::success("OK"))
 }
 
-/// Get all todos for a user
+/// Get all todos for a user, including those in lists shared with them
 pub async fn get_todos(
     State(pool): State<DbPool>,
     Path(user_id): Path<String>,
 ) -> Result<Json<ApiResponse<Vec<Todo>>>, StatusCode> {
-    let todos = sqlx::query_as::<_, Todo>(
-        "SELECT * FROM todos WHERE user_id = ? ORDER BY created_at DESC"
-    )
+    let todos = sqlx::query_as::<_, Todo>(&format!(
+        "SELECT * FROM todos WHERE {} AND deleted_at IS NULL ORDER BY created_at DESC",
+        VISIBLE_TODOS
+    ))
+    .bind(&user_id)
     .bind(&user_id)
     .fetch_all(&pool)
     .await
@@ -33,8 +60,33 @@ pub async fn get_todos(
 pub async fn create_todo(
     State(pool): State<DbPool>,
     Path(user_id): Path<String>,
+    device: DeviceId,
+    Extension(features): Extension<Features>,
     Json(request): Json<CreateTodoRequest>,
 ) -> Result<Json<ApiResponse<Todo>>, StatusCode> {
+    if let Some(recurrence) = &request.recurrence {
+        if !recurrence::is_valid_rrule(recurrence) {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+    if request.reminders.as_ref().is_some_and(|reminders| !reminders.iter().all(Reminder::is_valid)) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    
+    let list_id = request.list_id.as_deref();
+    if let Some(list_id) = list_id {
+        sharing::require_role(&pool, list_id, &user_id, ListRole::Editor).await?;
+    }
+    let can_assign = sharing::can_assign(&pool, list_id, &user_id, request.assignee_id.as_deref())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !can_assign {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if let Some(parent_id) = &request.parent_id {
+        check_parent(&pool, &user_id, None, parent_id).await?;
+    }
+    
     let mut todo = Todo::new(
         user_id,
         request.title,
@@ -45,11 +97,24 @@ pub async fn create_todo(
         todo.id = id;
     }
     todo.due_date = request.due_date;
+    todo.all_day = request.all_day.unwrap_or(false);
+    todo.list = request.list;
+    if let Some(tags) = request.tags {
+        todo.tags = SqlJson(tags);
+    }
+    todo.recurrence = request.recurrence;
+    if let Some(reminders) = request.reminders {
+        todo.reminders = SqlJson(reminders);
+    }
+    todo.list_id = request.list_id;
+    todo.assignee_id = request.assignee_id;
+    todo.parent_id = request.parent_id;
     
+    let mut tx = pool.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     sqlx::query(
         r#"
-        INSERT INTO todos (id, user_id, title, description, completed, priority, created_at, updated_at, due_date)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        INSERT INTO todos (id, user_id, title, description, completed, priority, created_at, updated_at, due_date, all_day, list, tags, recurrence, reminders, list_id, assignee_id, parent_id)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         "#
     )
     .bind(&todo.id)
@@ -57,33 +122,132 @@ pub async fn create_todo(
     .bind(&todo.title)
     .bind(&todo.description)
     .bind(todo.completed)
-    .bind(&todo.priority)
-    .bind(&todo.created_at)
-    .bind(&todo.updated_at)
-    .bind(&todo.due_date)
-    .execute(&pool)
+    .bind(todo.priority)
+    .bind(todo.created_at)
+    .bind(todo.updated_at)
+    .bind(todo.due_date)
+    .bind(todo.all_day)
+    .bind(&todo.list)
+    .bind(&todo.tags)
+    .bind(&todo.recurrence)
+    .bind(&todo.reminders)
+    .bind(&todo.list_id)
+    .bind(&todo.assignee_id)
+    .bind(&todo.parent_id)
+    .execute(&mut *tx)
     .await
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    record_revision(&mut tx, &todo.id, None, &todo.user_id, &device, "create", features.webhooks).await?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     
     Ok(Json(ApiResponse::success(todo)))
 }
 
+/// Record a revision of a todo changed by a handler, in the transaction that changed it
+async fn record_revision(
+    conn: &mut SqliteConnection,
+    todo_id: &str,
+    before: Option<&Todo>,
+    user_id: &str,
+    device: &DeviceId,
+    action: &str,
+    raise_webhooks: bool,
+) -> Result<(), StatusCode> {
+    revisions::record(conn, todo_id, before, user_id, device, action, raise_webhooks)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Check that `parent_id` can be the parent of the todo `todo_id` (`None` for
+/// a new todo): a todo the user can edit that is not a subtask itself. Subtasks
+/// are one level deep, so a todo that has subtasks cannot become one.
+async fn check_parent(pool: &DbPool, user_id: &str, todo_id: Option<&str>, parent_id: &str) -> Result<(), StatusCode> {
+    if todo_id == Some(parent_id) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let parent = accessible_todo(pool, user_id, parent_id, false, true)
+        .await
+        .map_err(|status| if status == StatusCode::NOT_FOUND { StatusCode::BAD_REQUEST } else { status })?;
+    if parent.parent_id.is_some() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if let Some(todo_id) = todo_id {
+        let has_subtasks: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM todos WHERE parent_id = ? AND deleted_at IS NULL)"
+        )
+        .bind(todo_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if has_subtasks {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+    Ok(())
+}
+
+/// Fetch a todo the user can see, or 404.
+/// Returns 403 if `edit` is set and the user can only view it.
+async fn accessible_todo(
+    pool: &DbPool,
+    user_id: &str,
+    todo_id: &str,
+    trashed: bool,
+    edit: bool,
+) -> Result<Todo, StatusCode> {
+    let trash = if trashed { "deleted_at IS NOT NULL" } else { "deleted_at IS NULL" };
+    visible_todo(pool, user_id, todo_id, trash, edit).await
+}
+
+/// Like `accessible_todo`, for todos matching the `trash` condition
+async fn visible_todo(
+    pool: &DbPool,
+    user_id: &str,
+    todo_id: &str,
+    trash: &str,
+    edit: bool,
+) -> Result<Todo, StatusCode> {
+    let todo: Todo = sqlx::query_as(&format!("SELECT * FROM todos WHERE id = ? AND {} AND {}", VISIBLE_TODOS, trash))
+        .bind(todo_id)
+        .bind(user_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    
+    if edit {
+        let role = sharing::todo_role(pool, &todo, user_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if role < Some(ListRole::Editor) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+    
+    Ok(todo)
+}
+
 /// Update a todo
 pub async fn update_todo(
     State(pool): State<DbPool>,
     Path((user_id, todo_id)): Path<(String, String)>,
+    device: DeviceId,
+    Extension(features): Extension<Features>,
     Json(request): Json<UpdateTodoRequest>,
 ) -> Result<Json<ApiResponse<Todo>>, StatusCode> {
+    if let Some(recurrence) = &request.recurrence {
+        if !recurrence::is_valid_rrule(recurrence) {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+    if request.reminders.as_ref().is_some_and(|reminders| !reminders.iter().all(Reminder::is_valid)) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    
     // First fetch the existing todo
-    let mut todo: Todo = sqlx::query_as(
-        "SELECT * FROM todos WHERE id = ? AND user_id = ?"
-    )
-    .bind(&todo_id)
-    .bind(&user_id)
-    .fetch_optional(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    .ok_or(StatusCode::NOT_FOUND)?;
+    let mut todo = accessible_todo(&pool, &user_id, &todo_id, false, true).await?;
+    let before = todo.clone();
     
     // Apply updates
     if let Some(title) = request.title {
@@ -101,142 +265,2070 @@ pub async fn update_todo(
     if let Some(due_date) = request.due_date {
         todo.due_date = Some(due_date);
     }
+    if let Some(all_day) = request.all_day {
+        todo.all_day = all_day;
+    }
+    if let Some(list) = request.list {
+        todo.list = Some(list);
+    }
+    if let Some(tags) = request.tags {
+        todo.tags = SqlJson(tags);
+    }
+    if let Some(recurrence) = request.recurrence {
+        todo.recurrence = Some(recurrence);
+    }
+    if let Some(reminders) = request.reminders {
+        todo.reminders = SqlJson(reminders);
+    }
+    if let Some(list_id) = request.list_id {
+        let list_id = (!list_id.is_empty()).then_some(list_id);
+        if list_id != todo.list_id {
+            if let Some(list_id) = &list_id {
+                sharing::require_role(&pool, list_id, &user_id, ListRole::Editor).await?;
+            }
+            todo.list_id = list_id;
+        }
+    }
+    if let Some(assignee_id) = request.assignee_id {
+        todo.assignee_id = (!assignee_id.is_empty()).then_some(assignee_id);
+    }
+    if let Some(parent_id) = request.parent_id {
+        let parent_id = (!parent_id.is_empty()).then_some(parent_id);
+        if parent_id != todo.parent_id {
+            if let Some(parent_id) = &parent_id {
+                check_parent(&pool, &user_id, Some(&todo_id), parent_id).await?;
+            }
+            todo.parent_id = parent_id;
+        }
+    }
+    let can_assign = sharing::can_assign(&pool, todo.list_id.as_deref(), &user_id, todo.assignee_id.as_deref())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !can_assign {
+        return Err(StatusCode::BAD_REQUEST);
+    }
     todo.updated_at = Utc::now();
     
     // Save updates
+    let mut tx = pool.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     sqlx::query(
         r#"
         UPDATE todos 
-        SET title = ?, description = ?, completed = ?, priority = ?, updated_at = ?, due_date = ?
-        WHERE id = ? AND user_id = ?
+        SET title = ?, description = ?, completed = ?, priority = ?, updated_at = ?, due_date = ?, all_day = ?, list = ?, tags = ?, recurrence = ?, reminders = ?, list_id = ?, assignee_id = ?, parent_id = ?
+        WHERE id = ?
         "#
     )
     .bind(&todo.title)
     .bind(&todo.description)
     .bind(todo.completed)
-    .bind(&todo.priority)
-    .bind(&todo.updated_at)
-    .bind(&todo.due_date)
+    .bind(todo.priority)
+    .bind(todo.updated_at)
+    .bind(todo.due_date)
+    .bind(todo.all_day)
+    .bind(&todo.list)
+    .bind(&todo.tags)
+    .bind(&todo.recurrence)
+    .bind(&todo.reminders)
+    .bind(&todo.list_id)
+    .bind(&todo.assignee_id)
+    .bind(&todo.parent_id)
     .bind(&todo_id)
-    .bind(&user_id)
-    .execute(&pool)
+    .execute(&mut *tx)
     .await
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    record_revision(&mut tx, &todo_id, Some(&before), &user_id, &device, "update", features.webhooks).await?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     
     Ok(Json(ApiResponse::success(todo)))
 }
 
-/// Delete a todo
+/// Move a todo to the trash
 pub async fn delete_todo(
     State(pool): State<DbPool>,
     Path((user_id, todo_id)): Path<(String, String)>,
+    device: DeviceId,
+    Extension(features): Extension<Features>,
 ) -> Result<Json<ApiResponse<()>>, StatusCode> {
-    let result = sqlx::query(
-        "DELETE FROM todos WHERE id = ? AND user_id = ?"
-    )
-    .bind(&todo_id)
+    let before = accessible_todo(&pool, &user_id, &todo_id, false, true).await?;
+    
+    let now = Utc::now();
+    let mut tx = pool.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query("UPDATE todos SET deleted_at = ?, updated_at = ? WHERE id = ? AND deleted_at IS NULL")
+        .bind(now)
+        .bind(now)
+        .bind(&todo_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    record_revision(&mut tx, &todo_id, Some(&before), &user_id, &device, "delete", features.webhooks).await?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(())))
+}
+
+/// List the todos in a user's trash, including trashed todos of lists shared with them
+pub async fn list_trash(
+    State(pool): State<DbPool>,
+    Path(user_id): Path<String>,
+) -> Result<Json<ApiResponse<Vec<Todo>>>, StatusCode> {
+    let todos = sqlx::query_as::<_, Todo>(&format!(
+        "SELECT * FROM todos WHERE {} AND deleted_at IS NOT NULL ORDER BY deleted_at DESC",
+        VISIBLE_TODOS
+    ))
     .bind(&user_id)
-    .execute(&pool)
+    .bind(&user_id)
+    .fetch_all(&pool)
     .await
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     
-    if result.rows_affected() == 0 {
-        return Err(StatusCode::NOT_FOUND);
-    }
+    Ok(Json(ApiResponse::success(todos)))
+}
+
+/// Restore a todo from the trash
+pub async fn restore_todo(
+    State(pool): State<DbPool>,
+    Path((user_id, todo_id)): Path<(String, String)>,
+    device: DeviceId,
+    Extension(features): Extension<Features>,
+) -> Result<Json<ApiResponse<Todo>>, StatusCode> {
+    let mut todo = accessible_todo(&pool, &user_id, &todo_id, true, true).await?;
+    let before = todo.clone();
     
-    Ok(Json(ApiResponse::success(())))
+    // Bumping updated_at lets the restore win over trashed copies on other devices
+    todo.deleted_at = None;
+    todo.updated_at = Utc::now();
+    
+    let mut tx = pool.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query("UPDATE todos SET deleted_at = NULL, updated_at = ? WHERE id = ?")
+        .bind(todo.updated_at)
+        .bind(&todo_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    record_revision(&mut tx, &todo_id, Some(&before), &user_id, &device, "restore", features.webhooks).await?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(todo)))
 }
 
-/// Sync todos between client and server
-pub async fn sync_todos(
+/// Permanently delete everything in a user's trash that they can edit
+pub async fn empty_trash(
     State(pool): State<DbPool>,
     Path(user_id): Path<String>,
-    Json(request): Json<SyncRequest>,
-) -> Result<Json<ApiResponse<SyncResponse>>, StatusCode> {
-    let now = Utc::now();
-    
-    // Process incoming todos from client
-    for todo in request.todos {
-        // Check if todo exists
-        let existing: Option<Todo> = sqlx::query_as(
-            "SELECT * FROM todos WHERE id = ? AND user_id = ?"
-        )
-        .bind(&todo.id)
+    device: DeviceId,
+    Extension(features): Extension<Features>,
+) -> Result<Json<ApiResponse<u64>>, StatusCode> {
+    let mut tx = pool.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let trashed = sqlx::query_as::<_, Todo>(&format!("SELECT * FROM todos WHERE {} AND deleted_at IS NOT NULL", EDITABLE_TODOS))
         .bind(&user_id)
-        .fetch_optional(&pool)
+        .bind(&user_id)
+        .fetch_all(&mut *tx)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        
-        match existing {
-            Some(existing_todo) => {
-                // Update if client version is newer
-                if todo.updated_at > existing_todo.updated_at {
-                    sqlx::query(
-                        r#"
-                        UPDATE todos 
-                        SET title = ?, description = ?, completed = ?, priority = ?, updated_at = ?, due_date = ?
-                        WHERE id = ? AND user_id = ?
-                        "#
-                    )
-                    .bind(&todo.title)
-                    .bind(&todo.description)
-                    .bind(todo.completed)
-                    .bind(&todo.priority)
-                    .bind(&todo.updated_at)
-                    .bind(&todo.due_date)
-                    .bind(&todo.id)
-                    .bind(&user_id)
-                    .execute(&pool)
-                    .await
-                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-                }
-            }
-            None => {
-                // Insert new todo
-                sqlx::query(
-                    r#"
-                    INSERT INTO todos (id, user_id, title, description, completed, priority, created_at, updated_at, due_date)
-                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
-                    "#
-                )
-                .bind(&todo.id)
-                .bind(&user_id)
-                .bind(&todo.title)
-                .bind(&todo.description)
-                .bind(todo.completed)
-                .bind(&todo.priority)
-                .bind(&todo.created_at)
-                .bind(&todo.updated_at)
-                .bind(&todo.due_date)
-                .execute(&pool)
-                .await
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-            }
+    let ids: Vec<String> = trashed.iter().map(|todo| todo.id.clone()).collect();
+    db::purge_todos(&mut tx, &ids)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    for todo in &trashed {
+        // Only the purge itself stays in the todo's history
+        revisions::record(&mut tx, &todo.id, Some(todo), &user_id, &device, "purge", features.webhooks)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(trashed.len() as u64)))
+}
+
+/// Get the revision history of a todo, newest first, whether or not it is in the trash
+pub async fn get_todo_history(
+    State(pool): State<DbPool>,
+    Path((user_id, todo_id)): Path<(String, String)>,
+) -> Result<Json<ApiResponse<Vec<Revision>>>, StatusCode> {
+    visible_todo(&pool, &user_id, &todo_id, "1 = 1", false).await?;
+    
+    let history = revisions::history(&pool, &todo_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(history)))
+}
+
+/// Put a todo back the way it was after an earlier revision.
+/// The revert itself is recorded as a new revision.
+pub async fn revert_todo(
+    State(pool): State<DbPool>,
+    Path((user_id, todo_id, revision_id)): Path<(String, String, i64)>,
+    device: DeviceId,
+    Extension(features): Extension<Features>,
+) -> Result<Json<ApiResponse<Todo>>, StatusCode> {
+    let before = visible_todo(&pool, &user_id, &todo_id, "1 = 1", true).await?;
+    let revision = revisions::find(&pool, &todo_id, revision_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    // Revisions that deleted the todo for good have nothing to go back to
+    let snapshot = revision.snapshot.ok_or(StatusCode::CONFLICT)?;
+    
+    let mut todo: Todo = serde_json::from_value(snapshot.0).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    todo.user_id = before.user_id.clone();
+    todo.created_at = before.created_at;
+    todo.updated_at = Utc::now();
+    if todo.list_id != before.list_id {
+        let allowed = sharing::can_write_to(&pool, todo.list_id.as_deref(), &user_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if !allowed {
+            return Err(StatusCode::FORBIDDEN);
         }
     }
+    // The assignee may have left the list since
+    let can_assign = sharing::can_assign(&pool, todo.list_id.as_deref(), &user_id, todo.assignee_id.as_deref())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !can_assign {
+        todo.assignee_id = None;
+    }
     
-    // Get all todos updated since last sync (or all if first sync)
-    let todos = if let Some(last_sync) = request.last_sync {
-        sqlx::query_as::<_, Todo>(
-            "SELECT * FROM todos WHERE user_id = ? AND updated_at > ? ORDER BY updated_at DESC"
-        )
+    let mut tx = pool.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query(
+        r#"
+        UPDATE todos 
+        SET title = ?, description = ?, completed = ?, priority = ?, updated_at = ?, due_date = ?, all_day = ?, list = ?, tags = ?, recurrence = ?, reminders = ?, list_id = ?, assignee_id = ?, parent_id = ?, deleted_at = ?
+        WHERE id = ?
+        "#
+    )
+    .bind(&todo.title)
+    .bind(&todo.description)
+    .bind(todo.completed)
+    .bind(todo.priority)
+    .bind(todo.updated_at)
+    .bind(todo.due_date)
+    .bind(todo.all_day)
+    .bind(&todo.list)
+    .bind(&todo.tags)
+    .bind(&todo.recurrence)
+    .bind(&todo.reminders)
+    .bind(&todo.list_id)
+    .bind(&todo.assignee_id)
+    .bind(&todo.parent_id)
+    .bind(todo.deleted_at)
+    .bind(&todo_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    record_revision(&mut tx, &todo_id, Some(&before), &user_id, &device, "revert", features.webhooks).await?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(todo)))
+}
+
+/// Export a user's todos as a downloadable file
+pub async fn export_todos(
+    State(pool): State<DbPool>,
+    Path(user_id): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, StatusCode> {
+    let format = query.format.unwrap_or(ExportFormat::Json);
+    let todos = sqlx::query_as::<_, Todo>(&format!(
+        "SELECT * FROM todos WHERE {} AND deleted_at IS NULL ORDER BY created_at",
+        VISIBLE_TODOS
+    ))
+    .bind(&user_id)
+    .bind(&user_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    let records: Vec<TodoRecord> = todos.into_iter().map(TodoRecord::from).collect();
+    let body = formats::export_todos(&records, format)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let disposition = format!("attachment; filename=\"todos.{}\"", format.extension());
+    
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// Import todos from an uploaded file, skipping duplicates
+pub async fn import_todos(
+    State(pool): State<DbPool>,
+    Path(user_id): Path<String>,
+    Query(query): Query<ImportQuery>,
+    device: DeviceId,
+    Extension(features): Extension<Features>,
+    body: String,
+) -> Result<Json<ApiResponse<ImportReport>>, StatusCode> {
+    let format = query.format.unwrap_or(ExportFormat::Json);
+    let incoming = formats::parse_todos(&body, format).map_err(|_| StatusCode::BAD_REQUEST)?;
+    
+    // Trashed todos count too, so that ids never collide
+    let existing = sqlx::query_as::<_, Todo>("SELECT * FROM todos WHERE user_id = ?")
         .bind(&user_id)
-        .bind(last_sync)
         .fetch_all(&pool)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    } else {
-        sqlx::query_as::<_, Todo>(
-            "SELECT * FROM todos WHERE user_id = ? ORDER BY updated_at DESC"
-        )
-        .bind(&user_id)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let existing: Vec<TodoRecord> = existing.into_iter().map(TodoRecord::from).collect();
+    
+    let mut report = import::plan_import(&existing, incoming, query.dry_run);
+    
+    // Ids are unique across users, so ids another user has are replaced
+    let ids: Vec<&str> = report.imported.iter().map(|todo| todo.id.as_str()).collect();
+    let taken: HashSet<String> = sqlx::query_scalar("SELECT id FROM todos WHERE id IN (SELECT value FROM json_each(?))")
+        .bind(serde_json::to_string(&ids).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?)
         .fetch_all(&pool)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .collect();
+    report.reassign_ids(&taken);
+    check_imported(&pool, &user_id, &report.imported).await?;
+    if report.dry_run {
+        return Ok(Json(ApiResponse::success(report)));
+    }
+    
+    // Imported todos count as changed now, so incremental syncs pick them up
+    let now = Utc::now();
+    let mut tx = pool.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    for record in &report.imported {
+        let mut todo = Todo::from_record(user_id.clone(), record.clone());
+        todo.updated_at = now;
+        sqlx::query(
+            r#"
+            INSERT INTO todos (id, user_id, title, description, completed, priority, created_at, updated_at, due_date, all_day, list, tags, recurrence, parent_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#
+        )
+        .bind(&todo.id)
+        .bind(&todo.user_id)
+        .bind(&todo.title)
+        .bind(&todo.description)
+        .bind(todo.completed)
+        .bind(todo.priority)
+        .bind(todo.created_at)
+        .bind(todo.updated_at)
+        .bind(todo.due_date)
+        .bind(todo.all_day)
+        .bind(&todo.list)
+        .bind(&todo.tags)
+        .bind(&todo.recurrence)
+        .bind(&todo.parent_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        revisions::record(&mut tx, &todo.id, None, &user_id, &device, "import", features.webhooks)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(report)))
+}
+
+/// Check imported todos the way `create_todo` checks new ones, including
+/// that parents outside the import are todos the user can edit
+async fn check_imported(pool: &DbPool, user_id: &str, todos: &[TodoRecord]) -> Result<(), StatusCode> {
+    let parents = import::check_imported(todos).map_err(|_| StatusCode::BAD_REQUEST)?;
+    for parent_id in parents {
+        check_parent(pool, user_id, None, parent_id).await?;
+    }
+    Ok(())
+}
+
+/// Parse a natural-language quick-add line into a todo to create.
+/// Relative dates are resolved in the user's time zone unless the request names one.
+pub async fn parse_quick_add(
+    State(pool): State<DbPool>,
+    Path(user_id): Path<String>,
+    Json(request): Json<QuickAddRequest>,
+) -> Result<Json<ApiResponse<CreateTodoRequest>>, StatusCode> {
+    let timezone = match request.timezone {
+        Some(timezone) => due::parse_timezone(&timezone).map_err(|_| StatusCode::BAD_REQUEST)?,
+        None => db::user_timezone(&pool, &user_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
     };
+    let parsed = quick_add::parse(&request.text, &Utc::now().with_timezone(&timezone));
     
-    Ok(Json(ApiResponse::success(SyncResponse {
-        todos,
-        sync_time: now,
-    })))
+    Ok(Json(ApiResponse::success(parsed.into())))
+}
+
+/// Get a user's settings
+pub async fn get_settings(
+    State(pool): State<DbPool>,
+    Path(user_id): Path<String>,
+) -> Result<Json<ApiResponse<UserSettings>>, StatusCode> {
+    let settings = load_settings(&pool, &user_id).await?;
+    
+    Ok(Json(ApiResponse::success(settings)))
+}
+
+/// Update a user's settings. Usernames and email addresses must be unique.
+pub async fn update_settings(
+    State(pool): State<DbPool>,
+    Path(user_id): Path<String>,
+    Json(request): Json<UpdateSettingsRequest>,
+) -> Result<Json<ApiResponse<UserSettings>>, StatusCode> {
+    let mut settings = load_settings(&pool, &user_id).await?;
+    if let Some(name) = request.timezone {
+        settings.timezone = due::parse_timezone(&name).map_err(|_| StatusCode::BAD_REQUEST)?.name().to_string();
+    }
+    if let Some(username) = request.username {
+        let username = username.trim().to_string();
+        settings.username = (!username.is_empty()).then_some(username);
+    }
+    if let Some(email) = request.email {
+        let email = sharing::normalize_invitee(&email);
+        if !email.is_empty() && !email.contains('@') {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        settings.email = (!email.is_empty()).then_some(email);
+    }
+    
+    sqlx::query(
+        r#"
+        INSERT INTO user_settings (user_id, timezone, username, email, updated_at)
+        VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT(user_id) DO UPDATE SET
+            timezone = excluded.timezone,
+            username = excluded.username,
+            email = excluded.email,
+            updated_at = excluded.updated_at
+        "#
+    )
+    .bind(&user_id)
+    .bind(&settings.timezone)
+    .bind(&settings.username)
+    .bind(&settings.email)
+    .bind(Utc::now())
+    .execute(&pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(e) if e.is_unique_violation() => StatusCode::CONFLICT,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    })?;
+    
+    Ok(Json(ApiResponse::success(settings)))
+}
+
+/// A user's settings, with defaults for users who have not saved any
+async fn load_settings(pool: &DbPool, user_id: &str) -> Result<UserSettings, StatusCode> {
+    let timezone = db::user_timezone(pool, user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let profile: Option<(Option<String>, Option<String>)> =
+        sqlx::query_as("SELECT username, email FROM user_settings WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let (username, email) = profile.unwrap_or_default();
+    
+    Ok(UserSettings {
+        timezone: timezone.name().to_string(),
+        username,
+        email,
+    })
+}
+
+/// Count the todos in every smart view and saved view, for badges
+pub async fn get_views(
+    State(pool): State<DbPool>,
+    Path(user_id): Path<String>,
+) -> Result<Json<ApiResponse<Vec<ViewCount>>>, StatusCode> {
+    let todos = open_or_recent_todos(&pool, &user_id).await?;
+    let saved = saved_views(&pool, &user_id).await?;
+    let timezone = db::user_timezone(&pool, &user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    let records: Vec<TodoRecord> = todos.into_iter().map(TodoRecord::from).collect();
+    let counts = views::count_views(&records, &saved, &Utc::now().with_timezone(&timezone));
+    
+    Ok(Json(ApiResponse::success(counts)))
+}
+
+/// List the todos in a smart view or saved view
+pub async fn get_view_todos(
+    State(pool): State<DbPool>,
+    Path((user_id, view_id)): Path<(String, String)>,
+) -> Result<Json<ApiResponse<Vec<Todo>>>, StatusCode> {
+    let saved = saved_views(&pool, &user_id).await?;
+    let filter = views::find_filter(&view_id, &saved).ok_or(StatusCode::NOT_FOUND)?;
+    let timezone = db::user_timezone(&pool, &user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let now = Utc::now().with_timezone(&timezone);
+    
+    let todos = open_or_recent_todos(&pool, &user_id)
+        .await?
+        .into_iter()
+        .filter(|todo| filter.matches(&TodoRecord::from(todo.clone()), &now))
+        .collect();
+    
+    Ok(Json(ApiResponse::success(todos)))
+}
+
+/// Save a new custom view
+pub async fn create_view(
+    State(pool): State<DbPool>,
+    Path(user_id): Path<String>,
+    Json(request): Json<SaveViewRequest>,
+) -> Result<Json<ApiResponse<SavedView>>, StatusCode> {
+    let mut view = SavedView::new(request.name, request.filter);
+    if let Some(id) = request.id {
+        view.id = id;
+    }
+    // Saved views must not shadow the built-in ones
+    if SmartView::from_id(&view.id).is_some() || !view.filter.is_valid() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    
+    let result = sqlx::query(
+        r#"
+        INSERT INTO views (id, user_id, name, filter, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?)
+        ON CONFLICT(id) DO NOTHING
+        "#
+    )
+    .bind(&view.id)
+    .bind(&user_id)
+    .bind(&view.name)
+    .bind(&view.filter)
+    .bind(view.created_at)
+    .bind(view.updated_at)
+    .execute(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::CONFLICT);
+    }
+    
+    Ok(Json(ApiResponse::success(view)))
+}
+
+/// Rename a custom view or change its filter
+pub async fn update_view(
+    State(pool): State<DbPool>,
+    Path((user_id, view_id)): Path<(String, String)>,
+    Json(request): Json<SaveViewRequest>,
+) -> Result<Json<ApiResponse<SavedView>>, StatusCode> {
+    let mut view: SavedView = sqlx::query_as(
+        "SELECT * FROM views WHERE id = ? AND user_id = ? AND deleted_at IS NULL"
+    )
+    .bind(&view_id)
+    .bind(&user_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    
+    if !request.filter.is_valid() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    view.name = request.name;
+    view.filter = SqlJson(request.filter);
+    view.updated_at = Utc::now();
+    
+    sqlx::query("UPDATE views SET name = ?, filter = ?, updated_at = ? WHERE id = ? AND user_id = ?")
+        .bind(&view.name)
+        .bind(&view.filter)
+        .bind(view.updated_at)
+        .bind(&view_id)
+        .bind(&user_id)
+        .execute(&pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(view)))
+}
+
+/// Delete a custom view. The deletion is kept so that it syncs to other devices.
+pub async fn delete_view(
+    State(pool): State<DbPool>,
+    Path((user_id, view_id)): Path<(String, String)>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let now = Utc::now();
+    let result = sqlx::query(
+        "UPDATE views SET deleted_at = ?, updated_at = ? WHERE id = ? AND user_id = ? AND deleted_at IS NULL"
+    )
+    .bind(now)
+    .bind(now)
+    .bind(&view_id)
+    .bind(&user_id)
+    .execute(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    
+    Ok(Json(ApiResponse::success(())))
+}
+
+/// Todos any view can contain: everything outside the trash, soonest due first
+async fn open_or_recent_todos(pool: &DbPool, user_id: &str) -> Result<Vec<Todo>, StatusCode> {
+    sqlx::query_as::<_, Todo>(&format!(
+        "SELECT * FROM todos WHERE {} AND deleted_at IS NULL ORDER BY due_date IS NULL, due_date, created_at",
+        VISIBLE_TODOS
+    ))
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// A user's saved views, without deleted ones
+async fn saved_views(pool: &DbPool, user_id: &str) -> Result<Vec<SavedView>, StatusCode> {
+    sqlx::query_as::<_, SavedView>(
+        "SELECT * FROM views WHERE user_id = ? AND deleted_at IS NULL ORDER BY created_at"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// List the comments on a todo, oldest first
+pub async fn get_comments(
+    State(pool): State<DbPool>,
+    Path((user_id, todo_id)): Path<(String, String)>,
+) -> Result<Json<ApiResponse<Vec<Comment>>>, StatusCode> {
+    accessible_todo(&pool, &user_id, &todo_id, false, false).await?;
+    
+    let comments = sqlx::query_as::<_, Comment>(
+        "SELECT * FROM comments WHERE todo_id = ? AND deleted_at IS NULL ORDER BY created_at"
+    )
+    .bind(&todo_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(comments)))
+}
+
+/// Comment on a todo. Everyone who can see a todo can comment on it.
+pub async fn create_comment(
+    State(pool): State<DbPool>,
+    Path((user_id, todo_id)): Path<(String, String)>,
+    Json(request): Json<CommentRequest>,
+) -> Result<Json<ApiResponse<Comment>>, StatusCode> {
+    let todo = accessible_todo(&pool, &user_id, &todo_id, false, false).await?;
+    comments::validate_body(&request.body).map_err(|_| StatusCode::BAD_REQUEST)?;
+    
+    let mut comment = Comment::new(todo_id, user_id, request.body);
+    if let Some(id) = request.id {
+        comment.id = id;
+    }
+    
+    let recipients = feed::participants(&pool, &todo, &comment.author_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut tx = pool.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    insert_comment(&mut tx, &comment).await.map_err(|e| match e {
+        sqlx::Error::Database(e) if e.is_unique_violation() => StatusCode::CONFLICT,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    })?;
+    feed::notify(&mut tx, &recipients, FeedKind::CommentAdded, &todo.id, Some(&comment.id), &comment.author_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(comment)))
+}
+
+/// Edit a comment. Only its author can.
+pub async fn update_comment(
+    State(pool): State<DbPool>,
+    Path((user_id, todo_id, comment_id)): Path<(String, String, String)>,
+    Json(request): Json<CommentRequest>,
+) -> Result<Json<ApiResponse<Comment>>, StatusCode> {
+    let todo = accessible_todo(&pool, &user_id, &todo_id, false, false).await?;
+    let mut comment = find_comment(&pool, &todo_id, &comment_id).await?;
+    if comment.author_id != user_id {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    comments::validate_body(&request.body).map_err(|_| StatusCode::BAD_REQUEST)?;
+    comment.body = request.body;
+    comment.updated_at = Utc::now();
+    
+    let recipients = feed::participants(&pool, &todo, &user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut tx = pool.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query("UPDATE comments SET body = ?, updated_at = ? WHERE id = ?")
+        .bind(&comment.body)
+        .bind(comment.updated_at)
+        .bind(&comment.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    feed::notify(&mut tx, &recipients, FeedKind::CommentEdited, &todo.id, Some(&comment.id), &user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(comment)))
+}
+
+/// Delete a comment. Its author can, and so can owners of the todo's list.
+pub async fn delete_comment(
+    State(pool): State<DbPool>,
+    Path((user_id, todo_id, comment_id)): Path<(String, String, String)>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let todo = accessible_todo(&pool, &user_id, &todo_id, false, false).await?;
+    let comment = find_comment(&pool, &todo_id, &comment_id).await?;
+    if comment.author_id != user_id {
+        let role = sharing::todo_role(&pool, &todo, &user_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if role < Some(ListRole::Owner) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+    
+    let recipients = feed::participants(&pool, &todo, &user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let now = Utc::now();
+    let mut tx = pool.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query("UPDATE comments SET deleted_at = ?, updated_at = ? WHERE id = ?")
+        .bind(now)
+        .bind(now)
+        .bind(&comment.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    feed::notify(&mut tx, &recipients, FeedKind::CommentDeleted, &todo.id, Some(&comment.id), &user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(())))
+}
+
+/// List the files attached to a todo
+pub async fn get_attachments(
+    State(pool): State<DbPool>,
+    Path((user_id, todo_id)): Path<(String, String)>,
+) -> Result<Json<ApiResponse<Vec<Attachment>>>, StatusCode> {
+    accessible_todo(&pool, &user_id, &todo_id, false, false).await?;
+    
+    let attachments = sqlx::query_as::<_, Attachment>(
+        "SELECT * FROM attachments WHERE todo_id = ? AND deleted_at IS NULL ORDER BY created_at"
+    )
+    .bind(&todo_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(attachments)))
+}
+
+/// Attach a file to a todo. The request body is the file itself, its name
+/// comes from the query string and its type from `Content-Type`.
+pub async fn upload_attachment(
+    State(pool): State<DbPool>,
+    Extension(blobs): Extension<Blobs>,
+    Path((user_id, todo_id)): Path<(String, String)>,
+    Query(query): Query<AttachmentQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<ApiResponse<Attachment>>, StatusCode> {
+    accessible_todo(&pool, &user_id, &todo_id, false, true).await?;
+    let name = attachments::clean_name(&query.name).map_err(|_| StatusCode::BAD_REQUEST)?;
+    attachments::validate_size(body.len()).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+        .unwrap_or(attachments::DEFAULT_CONTENT_TYPE)
+        .to_string();
+    
+    let attachment = Attachment::new(todo_id, user_id, name, content_type, &body);
+    blobs::store(&pool, &blobs, &attachment.hash, body)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    sqlx::query(
+        r#"
+        INSERT INTO attachments (id, todo_id, name, content_type, size, hash, uploaded_by, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#
+    )
+    .bind(&attachment.id)
+    .bind(&attachment.todo_id)
+    .bind(&attachment.name)
+    .bind(&attachment.content_type)
+    .bind(attachment.size)
+    .bind(&attachment.hash)
+    .bind(&attachment.uploaded_by)
+    .bind(attachment.created_at)
+    .bind(attachment.updated_at)
+    .execute(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(attachment)))
+}
+
+/// Download the contents of an attachment
+pub async fn download_attachment(
+    State(pool): State<DbPool>,
+    Extension(blobs): Extension<Blobs>,
+    Path((user_id, todo_id, attachment_id)): Path<(String, String, String)>,
+) -> Result<Response, StatusCode> {
+    accessible_todo(&pool, &user_id, &todo_id, false, false).await?;
+    let attachment = find_attachment(&pool, &todo_id, &attachment_id).await?;
+    
+    let contents = blobs
+        .get(&attachment.hash)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let disposition = format!("attachment; filename=\"{}\"", attachment.name.replace(['"', '\\'], "_"));
+    
+    Ok((
+        [
+            (header::CONTENT_TYPE, attachment.content_type),
+            (header::CONTENT_DISPOSITION, disposition),
+            (header::ETAG, format!("\"{}\"", attachment.hash)),
+        ],
+        contents,
+    )
+        .into_response())
+}
+
+/// Remove an attachment. Its contents are deleted once no other attachment uses them.
+pub async fn delete_attachment(
+    State(pool): State<DbPool>,
+    Path((user_id, todo_id, attachment_id)): Path<(String, String, String)>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    accessible_todo(&pool, &user_id, &todo_id, false, true).await?;
+    find_attachment(&pool, &todo_id, &attachment_id).await?;
+    
+    let now = Utc::now();
+    sqlx::query("UPDATE attachments SET deleted_at = ?, updated_at = ? WHERE id = ?")
+        .bind(now)
+        .bind(now)
+        .bind(&attachment_id)
+        .execute(&pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(())))
+}
+
+async fn find_attachment(pool: &DbPool, todo_id: &str, attachment_id: &str) -> Result<Attachment, StatusCode> {
+    sqlx::query_as("SELECT * FROM attachments WHERE id = ? AND todo_id = ? AND deleted_at IS NULL")
+        .bind(attachment_id)
+        .bind(todo_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Read a user's change feed, oldest first, after the event with id `after`
+pub async fn get_feed(
+    State(pool): State<DbPool>,
+    Path(user_id): Path<String>,
+    Query(query): Query<FeedQuery>,
+) -> Result<Json<ApiResponse<Vec<FeedEvent>>>, StatusCode> {
+    let events = feed::events(&pool, &user_id, query.after.unwrap_or(0))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(events)))
+}
+
+/// A comment on a todo that has not been deleted, or 404
+async fn find_comment(pool: &DbPool, todo_id: &str, comment_id: &str) -> Result<Comment, StatusCode> {
+    sqlx::query_as("SELECT * FROM comments WHERE id = ? AND todo_id = ? AND deleted_at IS NULL")
+        .bind(comment_id)
+        .bind(todo_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn insert_comment(tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>, comment: &Comment) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO comments (id, todo_id, author_id, body, created_at, updated_at, deleted_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#
+    )
+    .bind(&comment.id)
+    .bind(&comment.todo_id)
+    .bind(&comment.author_id)
+    .bind(&comment.body)
+    .bind(comment.created_at)
+    .bind(comment.updated_at)
+    .bind(comment.deleted_at)
+    .execute(&mut **tx)
+    .await?;
+    
+    Ok(())
+}
+
+/// List the shared lists a user is a member of
+pub async fn get_lists(
+    State(pool): State<DbPool>,
+    Path(user_id): Path<String>,
+) -> Result<Json<ApiResponse<Vec<SharedList>>>, StatusCode> {
+    let lists = member_lists(&pool, &user_id).await?;
+    
+    Ok(Json(ApiResponse::success(lists)))
+}
+
+/// Create a shared list, with the user as its owner
+pub async fn create_list(
+    State(pool): State<DbPool>,
+    Path(user_id): Path<String>,
+    Json(request): Json<CreateListRequest>,
+) -> Result<Json<ApiResponse<SharedList>>, StatusCode> {
+    let now = Utc::now();
+    let list = SharedList {
+        id: request.id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+        name: request.name,
+        role: ListRole::Owner,
+        created_at: now,
+        updated_at: now,
+    };
+    
+    let mut tx = pool.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query("INSERT INTO shared_lists (id, name, created_by, created_at, updated_at) VALUES (?, ?, ?, ?, ?)")
+        .bind(&list.id)
+        .bind(&list.name)
+        .bind(&user_id)
+        .bind(list.created_at)
+        .bind(list.updated_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(e) if e.is_unique_violation() => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        })?;
+    add_member(&mut tx, &list.id, &user_id, ListRole::Owner, now).await?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(list)))
+}
+
+/// Rename a shared list. Only owners can.
+pub async fn update_list(
+    State(pool): State<DbPool>,
+    Path((user_id, list_id)): Path<(String, String)>,
+    Json(request): Json<UpdateListRequest>,
+) -> Result<Json<ApiResponse<SharedList>>, StatusCode> {
+    sharing::require_role(&pool, &list_id, &user_id, ListRole::Owner).await?;
+    
+    sqlx::query("UPDATE shared_lists SET name = ?, updated_at = ? WHERE id = ?")
+        .bind(&request.name)
+        .bind(Utc::now())
+        .bind(&list_id)
+        .execute(&pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    let list = member_lists(&pool, &user_id)
+        .await?
+        .into_iter()
+        .find(|list| list.id == list_id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(ApiResponse::success(list)))
+}
+
+/// Delete a shared list. Only owners can. Its todos go back to the members
+/// who created them, as personal todos.
+pub async fn delete_list(
+    State(pool): State<DbPool>,
+    Path((user_id, list_id)): Path<(String, String)>,
+    device: DeviceId,
+    Extension(features): Extension<Features>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    sharing::require_role(&pool, &list_id, &user_id, ListRole::Owner).await?;
+    
+    // Bumping updated_at lets the detached todos reach their creators on the next sync
+    let mut tx = pool.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let detached = sqlx::query_as::<_, Todo>("SELECT * FROM todos WHERE list_id = ?")
+        .bind(&list_id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query("UPDATE todos SET list_id = NULL, assignee_id = NULL, updated_at = ? WHERE list_id = ?")
+        .bind(Utc::now())
+        .bind(&list_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    for todo in &detached {
+        revisions::record(&mut tx, &todo.id, Some(todo), &user_id, &device, "delete_list", features.webhooks)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+    for table in ["list_invitations", "list_members"] {
+        sqlx::query(&format!("DELETE FROM {} WHERE list_id = ?", table))
+            .bind(&list_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+    sqlx::query("DELETE FROM shared_lists WHERE id = ?")
+        .bind(&list_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(())))
+}
+
+/// List the members of a shared list
+pub async fn get_list_members(
+    State(pool): State<DbPool>,
+    Path((user_id, list_id)): Path<(String, String)>,
+) -> Result<Json<ApiResponse<Vec<ListMember>>>, StatusCode> {
+    sharing::require_role(&pool, &list_id, &user_id, ListRole::Viewer).await?;
+    
+    let members = sqlx::query_as::<_, ListMember>(
+        "SELECT user_id, role, created_at FROM list_members WHERE list_id = ? ORDER BY created_at"
+    )
+    .bind(&list_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(members)))
+}
+
+/// Change a member's role. Only owners can, and a list always keeps an owner.
+pub async fn update_list_member(
+    State(pool): State<DbPool>,
+    Path((user_id, list_id, member_id)): Path<(String, String, String)>,
+    Json(request): Json<UpdateMemberRequest>,
+) -> Result<Json<ApiResponse<ListMember>>, StatusCode> {
+    sharing::require_role(&pool, &list_id, &user_id, ListRole::Owner).await?;
+    let role = sharing::list_role(&pool, &list_id, &member_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if role == ListRole::Owner && request.role != ListRole::Owner {
+        ensure_other_owner(&pool, &list_id, &member_id).await?;
+    }
+    
+    let member: ListMember = sqlx::query_as(
+        "UPDATE list_members SET role = ? WHERE list_id = ? AND user_id = ? RETURNING user_id, role, created_at"
+    )
+    .bind(request.role)
+    .bind(&list_id)
+    .bind(&member_id)
+    .fetch_one(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(member)))
+}
+
+/// Remove a member from a shared list. Owners can remove anyone, and every
+/// member can leave, except the last owner.
+pub async fn remove_list_member(
+    State(pool): State<DbPool>,
+    Path((user_id, list_id, member_id)): Path<(String, String, String)>,
+    device: DeviceId,
+    Extension(features): Extension<Features>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let required = if member_id == user_id { ListRole::Viewer } else { ListRole::Owner };
+    sharing::require_role(&pool, &list_id, &user_id, required).await?;
+    let role = sharing::list_role(&pool, &list_id, &member_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if role == ListRole::Owner {
+        ensure_other_owner(&pool, &list_id, &member_id).await?;
+    }
+    
+    let mut tx = pool.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query("DELETE FROM list_members WHERE list_id = ? AND user_id = ?")
+        .bind(&list_id)
+        .bind(&member_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // Former members can no longer be assigned todos of the list
+    let unassigned = sqlx::query_as::<_, Todo>("SELECT * FROM todos WHERE list_id = ? AND assignee_id = ?")
+        .bind(&list_id)
+        .bind(&member_id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query("UPDATE todos SET assignee_id = NULL, updated_at = ? WHERE list_id = ? AND assignee_id = ?")
+        .bind(Utc::now())
+        .bind(&list_id)
+        .bind(&member_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    for todo in &unassigned {
+        revisions::record(&mut tx, &todo.id, Some(todo), &user_id, &device, "remove_member", features.webhooks)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(())))
+}
+
+/// Invite someone to a shared list by user id, username or email address.
+/// Only owners can. The invitee need not have an account yet.
+pub async fn invite_to_list(
+    State(pool): State<DbPool>,
+    Path((user_id, list_id)): Path<(String, String)>,
+    Json(request): Json<InviteRequest>,
+) -> Result<Json<ApiResponse<ListInvitation>>, StatusCode> {
+    sharing::require_role(&pool, &list_id, &user_id, ListRole::Owner).await?;
+    let invitee = sharing::normalize_invitee(&request.invitee);
+    if invitee.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    
+    // Inviting an existing member would only ever change their role
+    let member: Option<(String,)> = sqlx::query_as(
+        r#"
+        SELECT m.user_id FROM list_members m
+        LEFT JOIN user_settings s ON s.user_id = m.user_id
+        WHERE m.list_id = ? AND (m.user_id = ? OR s.username = ? OR s.email = ?)
+        "#
+    )
+    .bind(&list_id)
+    .bind(&invitee)
+    .bind(&invitee)
+    .bind(&invitee)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if member.is_some() {
+        return Err(StatusCode::CONFLICT);
+    }
+    
+    let id = uuid::Uuid::new_v4().to_string();
+    sqlx::query(
+        r#"
+        INSERT INTO list_invitations (id, list_id, invitee, role, invited_by, status, created_at)
+        VALUES (?, ?, ?, ?, ?, 'pending', ?)
+        "#
+    )
+    .bind(&id)
+    .bind(&list_id)
+    .bind(&invitee)
+    .bind(request.role.unwrap_or(ListRole::Editor))
+    .bind(&user_id)
+    .bind(Utc::now())
+    .execute(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    let invitation = find_invitation(&pool, &id).await?.ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(ApiResponse::success(invitation)))
+}
+
+/// List the pending invitations sent to a user's id, username or email address
+pub async fn get_invitations(
+    State(pool): State<DbPool>,
+    Path(user_id): Path<String>,
+) -> Result<Json<ApiResponse<Vec<ListInvitation>>>, StatusCode> {
+    let names = sharing::invitee_names(&pool, &user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    let mut invitations = Vec::new();
+    for name in names {
+        let sent: Vec<ListInvitation> = sqlx::query_as(&format!(
+            "{} WHERE i.invitee = ? AND i.status = 'pending' ORDER BY i.created_at",
+            INVITATIONS
+        ))
+        .bind(&name)
+        .fetch_all(&pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        invitations.extend(sent);
+    }
+    
+    Ok(Json(ApiResponse::success(invitations)))
+}
+
+/// Accept an invitation, joining its list
+pub async fn accept_invitation(
+    State(pool): State<DbPool>,
+    Path((user_id, invitation_id)): Path<(String, String)>,
+) -> Result<Json<ApiResponse<SharedList>>, StatusCode> {
+    let invitation = pending_invitation(&pool, &user_id, &invitation_id).await?;
+    // Joining again, e.g. through a second invitation, keeps the current role
+    let joined = sharing::list_role(&pool, &invitation.list_id, &user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .is_some();
+    let now = Utc::now();
+    
+    let mut tx = pool.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !joined {
+        add_member(&mut tx, &invitation.list_id, &user_id, invitation.role, now).await?;
+    }
+    respond_to_invitation(&mut tx, &invitation.id, InvitationStatus::Accepted, now).await?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    let list = member_lists(&pool, &user_id)
+        .await?
+        .into_iter()
+        .find(|list| list.id == invitation.list_id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(ApiResponse::success(list)))
+}
+
+/// Decline an invitation
+pub async fn decline_invitation(
+    State(pool): State<DbPool>,
+    Path((user_id, invitation_id)): Path<(String, String)>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let invitation = pending_invitation(&pool, &user_id, &invitation_id).await?;
+    
+    let mut tx = pool.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    respond_to_invitation(&mut tx, &invitation.id, InvitationStatus::Declined, Utc::now()).await?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(())))
+}
+
+/// Select invitations together with the name of their list
+const INVITATIONS: &str = r#"
+    SELECT i.id, i.list_id, l.name AS list_name, i.invitee, i.role, i.invited_by, i.status, i.created_at, i.responded_at
+    FROM list_invitations i JOIN shared_lists l ON l.id = i.list_id
+"#;
+
+async fn find_invitation(pool: &DbPool, invitation_id: &str) -> Result<Option<ListInvitation>, StatusCode> {
+    sqlx::query_as(&format!("{} WHERE i.id = ?", INVITATIONS))
+        .bind(invitation_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// A pending invitation sent to the user, or 404
+async fn pending_invitation(pool: &DbPool, user_id: &str, invitation_id: &str) -> Result<ListInvitation, StatusCode> {
+    let invitation = find_invitation(pool, invitation_id).await?.ok_or(StatusCode::NOT_FOUND)?;
+    let names = sharing::invitee_names(pool, user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !names.contains(&invitation.invitee) || invitation.status != InvitationStatus::Pending {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    
+    Ok(invitation)
+}
+
+async fn respond_to_invitation(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    invitation_id: &str,
+    status: InvitationStatus,
+    now: DateTime<Utc>,
+) -> Result<(), StatusCode> {
+    sqlx::query("UPDATE list_invitations SET status = ?, responded_at = ? WHERE id = ?")
+        .bind(status)
+        .bind(now)
+        .bind(invitation_id)
+        .execute(&mut **tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(())
+}
+
+async fn add_member(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    list_id: &str,
+    user_id: &str,
+    role: ListRole,
+    now: DateTime<Utc>,
+) -> Result<(), StatusCode> {
+    sqlx::query("INSERT INTO list_members (list_id, user_id, role, created_at) VALUES (?, ?, ?, ?)")
+        .bind(list_id)
+        .bind(user_id)
+        .bind(role)
+        .bind(now)
+        .execute(&mut **tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(())
+}
+
+/// Refuse with 409 unless the list has an owner other than `member_id`
+async fn ensure_other_owner(pool: &DbPool, list_id: &str, member_id: &str) -> Result<(), StatusCode> {
+    let (owners,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM list_members WHERE list_id = ? AND role = 'owner' AND user_id != ?"
+    )
+    .bind(list_id)
+    .bind(member_id)
+    .fetch_one(pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    if owners == 0 {
+        return Err(StatusCode::CONFLICT);
+    }
+    Ok(())
+}
+
+/// The shared lists a user is a member of, with their role in each
+async fn member_lists(pool: &DbPool, user_id: &str) -> Result<Vec<SharedList>, StatusCode> {
+    sqlx::query_as::<_, SharedList>(
+        r#"
+        SELECT l.id, l.name, m.role, l.created_at, l.updated_at
+        FROM shared_lists l JOIN list_members m ON m.list_id = l.id
+        WHERE m.user_id = ?
+        ORDER BY l.name
+        "#
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// List a user's webhooks
+pub async fn get_webhooks(
+    State(pool): State<DbPool>,
+    Path(user_id): Path<String>,
+) -> Result<Json<ApiResponse<Vec<Webhook>>>, StatusCode> {
+    let webhooks = sqlx::query_as::<_, Webhook>("SELECT * FROM webhooks WHERE user_id = ? ORDER BY created_at")
+        .bind(&user_id)
+        .fetch_all(&pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    let webhooks = webhooks
+        .into_iter()
+        .map(|webhook| Webhook { secret: None, ..webhook })
+        .collect();
+    Ok(Json(ApiResponse::success(webhooks)))
+}
+
+/// Register a webhook. The response holds the signing secret, which is not shown again.
+pub async fn create_webhook(
+    State(pool): State<DbPool>,
+    Path(user_id): Path<String>,
+    Extension(config): Extension<WebhookConfig>,
+    Json(request): Json<CreateWebhookRequest>,
+) -> Result<Json<ApiResponse<Webhook>>, StatusCode> {
+    if request.events.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    check_webhook_url(&request.url, &config).await?;
+    
+    let now = Utc::now();
+    let webhook = Webhook {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id,
+        url: request.url,
+        secret: Some(tokens::generate_token()),
+        events: SqlJson(request.events),
+        active: true,
+        created_at: now,
+        updated_at: now,
+    };
+    
+    sqlx::query(
+        r#"
+        INSERT INTO webhooks (id, user_id, url, secret, events, active, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#
+    )
+    .bind(&webhook.id)
+    .bind(&webhook.user_id)
+    .bind(&webhook.url)
+    .bind(&webhook.secret)
+    .bind(&webhook.events)
+    .bind(webhook.active)
+    .bind(webhook.created_at)
+    .bind(webhook.updated_at)
+    .execute(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(webhook)))
+}
+
+/// Change a webhook's URL, events or whether it is active
+pub async fn update_webhook(
+    State(pool): State<DbPool>,
+    Path((user_id, webhook_id)): Path<(String, String)>,
+    Extension(config): Extension<WebhookConfig>,
+    Json(request): Json<UpdateWebhookRequest>,
+) -> Result<Json<ApiResponse<Webhook>>, StatusCode> {
+    let mut webhook = find_webhook(&pool, &user_id, &webhook_id).await?;
+    
+    if let Some(url) = request.url {
+        check_webhook_url(&url, &config).await?;
+        webhook.url = url;
+    }
+    if let Some(events) = request.events {
+        if events.is_empty() {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        webhook.events = SqlJson(events);
+    }
+    if let Some(active) = request.active {
+        webhook.active = active;
+    }
+    webhook.updated_at = Utc::now();
+    
+    sqlx::query("UPDATE webhooks SET url = ?, events = ?, active = ?, updated_at = ? WHERE id = ?")
+        .bind(&webhook.url)
+        .bind(&webhook.events)
+        .bind(webhook.active)
+        .bind(webhook.updated_at)
+        .bind(&webhook_id)
+        .execute(&pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    webhook.secret = None;
+    Ok(Json(ApiResponse::success(webhook)))
+}
+
+/// Remove a webhook and its queued deliveries
+pub async fn delete_webhook(
+    State(pool): State<DbPool>,
+    Path((user_id, webhook_id)): Path<(String, String)>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    find_webhook(&pool, &user_id, &webhook_id).await?;
+    
+    let mut tx = pool.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query("DELETE FROM webhook_deliveries WHERE webhook_id = ?")
+        .bind(&webhook_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    sqlx::query("DELETE FROM webhooks WHERE id = ?")
+        .bind(&webhook_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(())))
+}
+
+/// List a webhook's deliveries, newest first. `?status=dead` lists the dead letters.
+pub async fn get_webhook_deliveries(
+    State(pool): State<DbPool>,
+    Path((user_id, webhook_id)): Path<(String, String)>,
+    Query(query): Query<DeliveryQuery>,
+) -> Result<Json<ApiResponse<Vec<Delivery>>>, StatusCode> {
+    find_webhook(&pool, &user_id, &webhook_id).await?;
+    
+    let deliveries = sqlx::query_as::<_, Delivery>(
+        r#"
+        SELECT * FROM webhook_deliveries
+        WHERE webhook_id = ? AND (? IS NULL OR status = ?)
+        ORDER BY created_at DESC
+        LIMIT 100
+        "#
+    )
+    .bind(&webhook_id)
+    .bind(query.status)
+    .bind(query.status)
+    .fetch_all(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(deliveries)))
+}
+
+/// Queue a delivery again, typically a dead letter, with a fresh set of retries
+pub async fn redeliver_webhook(
+    State(pool): State<DbPool>,
+    Path((user_id, webhook_id, delivery_id)): Path<(String, String, String)>,
+) -> Result<Json<ApiResponse<Delivery>>, StatusCode> {
+    find_webhook(&pool, &user_id, &webhook_id).await?;
+    
+    let delivery: Delivery = sqlx::query_as(
+        r#"
+        UPDATE webhook_deliveries
+        SET status = ?, attempts = 0, next_attempt_at = ?, delivered_at = NULL
+        WHERE id = ? AND webhook_id = ?
+        RETURNING *
+        "#
+    )
+    .bind(DeliveryStatus::Pending)
+    .bind(Utc::now())
+    .bind(&delivery_id)
+    .bind(&webhook_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    
+    Ok(Json(ApiResponse::success(delivery)))
+}
+
+async fn find_webhook(pool: &DbPool, user_id: &str, webhook_id: &str) -> Result<Webhook, StatusCode> {
+    sqlx::query_as("SELECT * FROM webhooks WHERE id = ? AND user_id = ?")
+        .bind(webhook_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Reject URLs webhooks may not be delivered to
+async fn check_webhook_url(url: &str, config: &WebhookConfig) -> Result<(), StatusCode> {
+    webhooks::check_target(url, config.allow_private_targets).await.map_err(|error| {
+        tracing::warn!(error = %error, "Rejected webhook URL");
+        StatusCode::BAD_REQUEST
+    })
+}
+
+/// List a user's personal API tokens, without the tokens themselves
+pub async fn get_api_tokens(
+    State(pool): State<DbPool>,
+    Path(user_id): Path<String>,
+) -> Result<Json<ApiResponse<Vec<ApiToken>>>, StatusCode> {
+    let tokens = sqlx::query_as::<_, ApiToken>(
+        "SELECT * FROM api_tokens WHERE user_id = ? AND revoked_at IS NULL ORDER BY created_at"
+    )
+    .bind(&user_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(tokens)))
+}
+
+/// Create a personal API token. The response holds the token, which is not shown again.
+pub async fn create_api_token(
+    State(pool): State<DbPool>,
+    Path(user_id): Path<String>,
+    Json(request): Json<CreateApiTokenRequest>,
+) -> Result<Json<ApiResponse<ApiToken>>, StatusCode> {
+    let name = request.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > api_tokens::MAX_NAME_LENGTH || request.scopes.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let mut scopes: Vec<Scope> = Vec::new();
+    for scope in request.scopes {
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    
+    let token = api_tokens::generate();
+    let api_token = ApiToken {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id,
+        name,
+        token: Some(token.clone()),
+        scopes: SqlJson(scopes),
+        created_at: Utc::now(),
+        last_used_at: None,
+    };
+    
+    sqlx::query(
+        r#"
+        INSERT INTO api_tokens (id, user_id, name, token_hash, scopes, created_at)
+        VALUES (?, ?, ?, ?, ?, ?)
+        "#
+    )
+    .bind(&api_token.id)
+    .bind(&api_token.user_id)
+    .bind(&api_token.name)
+    .bind(tokens::hash_token(&token))
+    .bind(&api_token.scopes)
+    .bind(api_token.created_at)
+    .execute(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(ApiResponse::success(api_token)))
+}
+
+/// Revoke a personal API token; requests using it are refused from then on
+pub async fn revoke_api_token(
+    State(pool): State<DbPool>,
+    Path((user_id, token_id)): Path<(String, String)>,
+) -> Result<Json<ApiResponse<()>>, StatusCode> {
+    let result = sqlx::query(
+        "UPDATE api_tokens SET revoked_at = ? WHERE id = ? AND user_id = ? AND revoked_at IS NULL"
+    )
+    .bind(Utc::now())
+    .bind(&token_id)
+    .bind(&user_id)
+    .execute(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    
+    Ok(Json(ApiResponse::success(())))
+}
+
+/// Issue a new calendar feed token, replacing any previous one
+pub async fn create_calendar_token(
+    State(pool): State<DbPool>,
+    Path(user_id): Path<String>,
+) -> Result<Json<ApiResponse<CalendarFeedToken>>, StatusCode> {
+    let token = tokens::generate_token();
+    
+    sqlx::query(
+        r#"
+        INSERT INTO calendar_feeds (user_id, token_hash, created_at)
+        VALUES (?, ?, ?)
+        ON CONFLICT(user_id) DO UPDATE SET token_hash = excluded.token_hash, created_at = excluded.created_at
+        "#
+    )
+    .bind(&user_id)
+    .bind(tokens::hash_token(&token))
+    .bind(Utc::now())
+    .execute(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    let feed_path = format!("/api/users/{}/calendar.ics?token={}", user_id, token);
+    Ok(Json(ApiResponse::success(CalendarFeedToken { token, feed_path })))
+}
+
+/// Serve a user's todos with a due date as an iCalendar feed
+pub async fn calendar_feed(
+    State(pool): State<DbPool>,
+    Path(user_id): Path<String>,
+    Query(query): Query<CalendarFeedQuery>,
+) -> Result<Response, StatusCode> {
+    let token = query.token.ok_or(StatusCode::UNAUTHORIZED)?;
+    let feed: Option<(String,)> = sqlx::query_as(
+        "SELECT user_id FROM calendar_feeds WHERE user_id = ? AND token_hash = ?"
+    )
+    .bind(&user_id)
+    .bind(tokens::hash_token(&token))
+    .fetch_optional(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if feed.is_none() {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    
+    let todos = sqlx::query_as::<_, Todo>(&format!(
+        "SELECT * FROM todos WHERE {} AND deleted_at IS NULL AND due_date IS NOT NULL ORDER BY due_date",
+        VISIBLE_TODOS
+    ))
+    .bind(&user_id)
+    .bind(&user_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    let records: Vec<TodoRecord> = todos.into_iter().map(TodoRecord::from).collect();
+    let body = ical::write_calendar(&records, Some("Todos"));
+    
+    Ok((
+        [(header::CONTENT_TYPE, ExportFormat::ICalendar.content_type())],
+        body,
+    )
+        .into_response())
+}
+
+/// Sync todos between client and server
+pub async fn sync_todos(
+    State(pool): State<DbPool>,
+    Path(user_id): Path<String>,
+    device: DeviceId,
+    Extension(features): Extension<Features>,
+    Extension(metrics): Extension<Metrics>,
+    Json(request): Json<SyncRequest>,
+) -> Result<Json<ApiResponse<SyncResponse>>, StatusCode> {
+    let now = Utc::now();
+    let started = Instant::now();
+    let mut stats = SyncStats::default();
+    
+    // Purges made on the device. Only todos still in the trash are purged,
+    // so a restore made elsewhere wins.
+    let mut purged = Vec::new();
+    for id in &request.purged {
+        let trashed: Option<Todo> = sqlx::query_as(&format!(
+            "SELECT * FROM todos WHERE id = ? AND {} AND deleted_at IS NOT NULL",
+            EDITABLE_TODOS
+        ))
+        .bind(id)
+        .bind(&user_id)
+        .bind(&user_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(sync_failed)?;
+        purged.extend(trashed);
+    }
+    if !purged.is_empty() {
+        let ids: Vec<String> = purged.iter().map(|todo| todo.id.clone()).collect();
+        let mut tx = pool.begin().await.map_err(sync_failed)?;
+        db::purge_todos(&mut tx, &ids).await.map_err(sync_failed)?;
+        for todo in &purged {
+            revisions::record(&mut tx, &todo.id, Some(todo), &user_id, &device, "purge", features.webhooks)
+                .await
+                .map_err(sync_failed)?;
+        }
+        tx.commit().await.map_err(sync_failed)?;
+    }
+    
+    // Process incoming todos from client. Changes the user is not allowed to
+    // make are skipped, and the server's copy comes back in the response.
+    // Top-level todos go first, so that subtasks created along with their
+    // parent find it.
+    let mut incoming = request.todos;
+    incoming.sort_by_key(|todo| todo.parent_id.is_some());
+    for todo in incoming {
+        // Check if todo exists, whoever it belongs to
+        let existing: Option<Todo> = sqlx::query_as("SELECT * FROM todos WHERE id = ?")
+            .bind(&todo.id)
+            .fetch_optional(&pool)
+            .await
+            .map_err(sync_failed)?;
+        
+        // A device that missed a purge must not bring the todo back
+        if existing.is_none() {
+            let was_purged: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM purged_todos WHERE id = ?)")
+                .bind(&todo.id)
+                .fetch_one(&pool)
+                .await
+                .map_err(sync_failed)?;
+            if was_purged {
+                continue;
+            }
+        }
+        
+        let allowed = may_sync(&pool, &user_id, existing.as_ref(), &todo)
+            .await
+            .map_err(sync_failed)?;
+        if !allowed {
+            tracing::warn!(todo_id = %todo.id, "Sync skipped a change the user may not make");
+            continue;
+        }
+        if !todo.reminders.iter().all(Reminder::is_valid) {
+            tracing::warn!(todo_id = %todo.id, "Skipping todo with a reminder out of range");
+            continue;
+        }
+        if todo.recurrence.as_deref().is_some_and(|rule| !recurrence::is_valid_rrule(rule)) {
+            tracing::warn!(todo_id = %todo.id, "Skipping todo with an invalid recurrence rule");
+            continue;
+        }
+        // Subtasks are checked like in `update_todo`, when they get a new parent
+        let reparented = existing.as_ref().is_none_or(|existing| existing.parent_id != todo.parent_id);
+        if let Some(parent_id) = todo.parent_id.as_deref().filter(|_| reparented) {
+            match check_parent(&pool, &user_id, Some(&todo.id), parent_id).await {
+                Ok(()) => {}
+                Err(StatusCode::INTERNAL_SERVER_ERROR) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+                Err(_) => {
+                    tracing::warn!(todo_id = %todo.id, "Skipping subtask of a todo it cannot be under");
+                    continue;
+                }
+            }
+        }
+        
+        match &existing {
+            Some(existing_todo) => {
+                // Update if client version is newer
+                if todo.updated_at <= existing_todo.updated_at {
+                    stats.conflicts += 1;
+                } else {
+                    let mut tx = pool.begin().await.map_err(sync_failed)?;
+                    sqlx::query(
+                        r#"
+                        UPDATE todos 
+                        SET title = ?, description = ?, completed = ?, priority = ?, updated_at = ?, due_date = ?, all_day = ?, list = ?, tags = ?, recurrence = ?, reminders = ?, list_id = ?, assignee_id = ?, parent_id = ?, deleted_at = ?
+                        WHERE id = ?
+                        "#
+                    )
+                    .bind(&todo.title)
+                    .bind(&todo.description)
+                    .bind(todo.completed)
+                    .bind(todo.priority)
+                    .bind(todo.updated_at)
+                    .bind(todo.due_date)
+                    .bind(todo.all_day)
+                    .bind(&todo.list)
+                    .bind(&todo.tags)
+                    .bind(&todo.recurrence)
+                    .bind(&todo.reminders)
+                    .bind(&todo.list_id)
+                    .bind(&todo.assignee_id)
+                    .bind(&todo.parent_id)
+                    .bind(todo.deleted_at)
+                    .bind(&todo.id)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(sync_failed)?;
+                    record_revision(&mut tx, &todo.id, existing.as_ref(), &user_id, &device, "sync", features.webhooks).await?;
+                    tx.commit().await.map_err(sync_failed)?;
+                    stats.pushed += 1;
+                }
+            }
+            None => {
+                // Insert new todo
+                let mut tx = pool.begin().await.map_err(sync_failed)?;
+                sqlx::query(
+                    r#"
+                    INSERT INTO todos (id, user_id, title, description, completed, priority, created_at, updated_at, due_date, all_day, list, tags, recurrence, reminders, list_id, assignee_id, parent_id, deleted_at)
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                    "#
+                )
+                .bind(&todo.id)
+                .bind(&user_id)
+                .bind(&todo.title)
+                .bind(&todo.description)
+                .bind(todo.completed)
+                .bind(todo.priority)
+                .bind(todo.created_at)
+                .bind(todo.updated_at)
+                .bind(todo.due_date)
+                .bind(todo.all_day)
+                .bind(&todo.list)
+                .bind(&todo.tags)
+                .bind(&todo.recurrence)
+                .bind(&todo.reminders)
+                .bind(&todo.list_id)
+                .bind(&todo.assignee_id)
+                .bind(&todo.parent_id)
+                .bind(todo.deleted_at)
+                .execute(&mut *tx)
+                .await
+                .map_err(sync_failed)?;
+                record_revision(&mut tx, &todo.id, None, &user_id, &device, "sync", features.webhooks).await?;
+                tx.commit().await.map_err(sync_failed)?;
+                stats.pushed += 1;
+            }
+        }
+    }
+    
+    // Saved views: the newer copy wins, as with todos
+    for view in request.views {
+        if !view.filter.is_valid() {
+            tracing::warn!(view_id = %view.id, "Skipping view with a filter out of range");
+            continue;
+        }
+        sqlx::query(
+            r#"
+            INSERT INTO views (id, user_id, name, filter, created_at, updated_at, deleted_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name,
+                filter = excluded.filter,
+                updated_at = excluded.updated_at,
+                deleted_at = excluded.deleted_at
+            WHERE views.user_id = excluded.user_id AND excluded.updated_at > views.updated_at
+            "#
+        )
+        .bind(&view.id)
+        .bind(&user_id)
+        .bind(&view.name)
+        .bind(&view.filter)
+        .bind(view.created_at)
+        .bind(view.updated_at)
+        .bind(view.deleted_at)
+        .execute(&pool)
+        .await
+        .map_err(sync_failed)?;
+    }
+    
+    // Comments: only their authors can change them, and new ones can only be
+    // added to todos the user can see. Others taking part hear of the change.
+    for comment in request.comments {
+        sync_comment(&pool, &user_id, comment)
+            .await
+            .map_err(sync_failed)?;
+    }
+    
+    // Get all todos updated since last sync (or all if first sync), and every
+    // todo of lists the user has joined since, however old
+    let last_sync = request.last_sync.unwrap_or(DateTime::<Utc>::MIN_UTC);
+    let todos = sqlx::query_as::<_, Todo>(&format!(
+        r#"
+        SELECT * FROM todos WHERE {} AND (
+            updated_at > ?
+            OR list_id IN (SELECT list_id FROM list_members WHERE user_id = ? AND created_at > ?)
+        )
+        ORDER BY updated_at DESC
+        "#,
+        VISIBLE_TODOS
+    ))
+    .bind(&user_id)
+    .bind(&user_id)
+    .bind(last_sync)
+    .bind(&user_id)
+    .bind(last_sync)
+    .fetch_all(&pool)
+    .await
+    .map_err(sync_failed)?;
+    
+    let views = sqlx::query_as::<_, SavedView>(
+        "SELECT * FROM views WHERE user_id = ? AND updated_at > ? ORDER BY created_at"
+    )
+    .bind(&user_id)
+    .bind(last_sync)
+    .fetch_all(&pool)
+    .await
+    .map_err(sync_failed)?;
+    
+    let comments = sqlx::query_as::<_, Comment>(&format!(
+        r#"
+        SELECT * FROM comments WHERE todo_id IN (SELECT id FROM todos WHERE {}) AND (
+            updated_at > ?
+            OR todo_id IN (SELECT id FROM todos WHERE list_id IN (SELECT list_id FROM list_members WHERE user_id = ? AND created_at > ?))
+        )
+        ORDER BY created_at
+        "#,
+        VISIBLE_TODOS
+    ))
+    .bind(&user_id)
+    .bind(&user_id)
+    .bind(last_sync)
+    .bind(&user_id)
+    .bind(last_sync)
+    .fetch_all(&pool)
+    .await
+    .map_err(sync_failed)?;
+    
+    let attachments = sqlx::query_as::<_, Attachment>(&format!(
+        r#"
+        SELECT * FROM attachments WHERE todo_id IN (SELECT id FROM todos WHERE {}) AND (
+            updated_at > ?
+            OR todo_id IN (SELECT id FROM todos WHERE list_id IN (SELECT list_id FROM list_members WHERE user_id = ? AND created_at > ?))
+        )
+        ORDER BY created_at
+        "#,
+        VISIBLE_TODOS
+    ))
+    .bind(&user_id)
+    .bind(&user_id)
+    .bind(last_sync)
+    .bind(&user_id)
+    .bind(last_sync)
+    .fetch_all(&pool)
+    .await
+    .map_err(sync_failed)?;
+    
+    let purged: Vec<String> = sqlx::query_scalar(&format!(
+        "SELECT id FROM purged_todos WHERE {} AND purged_at > ?",
+        VISIBLE_TODOS
+    ))
+    .bind(&user_id)
+    .bind(&user_id)
+    .bind(last_sync)
+    .fetch_all(&pool)
+    .await
+    .map_err(sync_failed)?;
+    
+    let lists = member_lists(&pool, &user_id).await?;
+    
+    stats.pulled = todos.len() as u64;
+    stats.duration = started.elapsed();
+    metrics.record_sync(stats);
+    tracing::info!(
+        pushed = stats.pushed,
+        pulled = stats.pulled,
+        conflicts = stats.conflicts,
+        duration_ms = stats.duration.as_millis() as u64,
+        "Sync completed"
+    );
+    
+    Ok(Json(ApiResponse::success(SyncResponse {
+        todos,
+        views,
+        comments,
+        lists,
+        attachments,
+        purged,
+        sync_time: now,
+    })))
+}
+
+/// Log why a sync failed; the request's span tells whose sync it was
+fn sync_failed(error: sqlx::Error) -> StatusCode {
+    tracing::error!(error = %error, "Sync failed");
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Apply a comment from a sync, newer copy winning, and notify the todo's participants
+async fn sync_comment(pool: &DbPool, user_id: &str, comment: Comment) -> Result<(), sqlx::Error> {
+    let todo: Option<Todo> = sqlx::query_as(&format!("SELECT * FROM todos WHERE id = ? AND {}", VISIBLE_TODOS))
+        .bind(&comment.todo_id)
+        .bind(user_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+    let Some(todo) = todo else {
+        return Ok(());
+    };
+    if comments::validate_body(&comment.body).is_err() {
+        return Ok(());
+    }
+    let existing: Option<Comment> = sqlx::query_as("SELECT * FROM comments WHERE id = ?")
+        .bind(&comment.id)
+        .fetch_optional(pool)
+        .await?;
+    
+    let kind = match &existing {
+        None => FeedKind::CommentAdded,
+        Some(existing) if existing.author_id != user_id || comment.updated_at <= existing.updated_at => {
+            return Ok(());
+        }
+        Some(_) if comment.deleted_at.is_some() => FeedKind::CommentDeleted,
+        Some(_) => FeedKind::CommentEdited,
+    };
+    
+    let recipients = feed::participants(pool, &todo, user_id).await?;
+    let mut tx = pool.begin().await?;
+    if existing.is_some() {
+        sqlx::query("UPDATE comments SET body = ?, updated_at = ?, deleted_at = ? WHERE id = ?")
+            .bind(&comment.body)
+            .bind(comment.updated_at)
+            .bind(comment.deleted_at)
+            .bind(&comment.id)
+            .execute(&mut *tx)
+            .await?;
+    } else {
+        // Comments are always by whoever syncs them
+        let comment = Comment {
+            author_id: user_id.to_string(),
+            ..comment.clone()
+        };
+        insert_comment(&mut tx, &comment).await?;
+    }
+    // A comment written and deleted offline never reached anyone
+    if existing.is_some() || comment.deleted_at.is_none() {
+        feed::notify(&mut tx, &recipients, kind, &todo.id, Some(&comment.id), user_id).await?;
+    }
+    tx.commit().await
+}
+
+/// Whether a synced todo may be written: the user must be able to edit the
+/// server's copy, if any, and to put the todo in its list with its assignee
+async fn may_sync(pool: &DbPool, user_id: &str, existing: Option<&Todo>, todo: &Todo) -> Result<bool, sqlx::Error> {
+    if let Some(existing) = existing {
+        if sharing::todo_role(pool, existing, user_id).await? < Some(ListRole::Editor) {
+            return Ok(false);
+        }
+    }
+    let list_id = todo.list_id.as_deref();
+    Ok(sharing::can_write_to(pool, list_id, user_id).await?
+        && sharing::can_assign(pool, list_id, user_id, todo.assignee_id.as_deref()).await?)
 }
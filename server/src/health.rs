@@ -0,0 +1,192 @@
+//! Liveness and readiness checks.
+//!
+//! `/health/live` answers as long as the server can handle requests at all.
+//! `/health/ready` also checks what requests depend on: that the database
+//! answers in time, that its migrations have run, and that the disk it is on
+//! has room to grow. Each check is reported separately, and the server is
+//! ready only if all of them pass.
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    Extension, Json,
+};
+use serde::Serialize;
+
+use crate::config::HealthConfig;
+use crate::db::{self, DbPool};
+use crate::models::ApiResponse;
+
+/// What was built and how
+#[derive(Debug, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    /// Commit the server was built from, when `GIT_COMMIT` was set at build time
+    pub commit: Option<&'static str>,
+    pub profile: &'static str,
+}
+
+impl BuildInfo {
+    pub fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            commit: option_env!("GIT_COMMIT"),
+            profile: if cfg!(debug_assertions) { "debug" } else { "release" },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Ok,
+    Failed,
+    /// Not applicable, such as disk space for an in-memory database
+    Skipped,
+}
+
+/// The outcome of checking one dependency
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub status: CheckStatus,
+    pub detail: String,
+    pub duration_ms: u64,
+}
+
+/// The checks made for readiness
+#[derive(Debug, Serialize)]
+pub struct Checks {
+    pub database: Check,
+    pub migrations: Check,
+    pub disk: Check,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    /// `live`, `ready` or `not_ready`
+    pub status: &'static str,
+    pub build: BuildInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checks: Option<Checks>,
+}
+
+/// Liveness: the server is up
+pub async fn live() -> Json<ApiResponse<HealthReport>> {
+    Json(ApiResponse::success(HealthReport {
+        status: "live",
+        build: BuildInfo::current(),
+        checks: None,
+    }))
+}
+
+/// Readiness: the server can serve requests; 503 if any check fails
+pub async fn ready(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<HealthConfig>,
+) -> (StatusCode, Json<ApiResponse<HealthReport>>) {
+    let started = Instant::now();
+    let timeout = Duration::from_millis(config.db_timeout_ms);
+    let schema_version = tokio::time::timeout(
+        timeout,
+        sqlx::query_scalar::<_, i64>("PRAGMA user_version").fetch_one(&pool),
+    )
+    .await;
+    let elapsed = started.elapsed();
+
+    let (database, migrations) = match schema_version {
+        Ok(Ok(version)) => (
+            check(CheckStatus::Ok, "answering".to_string(), elapsed),
+            check_schema(version),
+        ),
+        Ok(Err(e)) => (
+            check(CheckStatus::Failed, e.to_string(), elapsed),
+            check(CheckStatus::Skipped, "database unavailable".to_string(), Duration::ZERO),
+        ),
+        Err(_) => (
+            check(
+                CheckStatus::Failed,
+                format!("no answer within {}ms", config.db_timeout_ms),
+                elapsed,
+            ),
+            check(CheckStatus::Skipped, "database unavailable".to_string(), Duration::ZERO),
+        ),
+    };
+    let checks = Checks {
+        database,
+        migrations,
+        disk: check_disk(pool.connect_options().get_filename(), config.min_free_disk_bytes),
+    };
+
+    let failed: Vec<&str> = [
+        ("database", &checks.database),
+        ("migrations", &checks.migrations),
+        ("disk", &checks.disk),
+    ]
+    .into_iter()
+    .filter(|(_, check)| check.status == CheckStatus::Failed)
+    .map(|(name, _)| name)
+    .collect();
+    let ready = failed.is_empty();
+    if !ready {
+        tracing::warn!("Not ready, failed checks: {}", failed.join(", "));
+    }
+
+    let report = HealthReport {
+        status: if ready { "ready" } else { "not_ready" },
+        build: BuildInfo::current(),
+        checks: Some(checks),
+    };
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (
+        status,
+        Json(ApiResponse {
+            success: ready,
+            data: Some(report),
+            error: (!ready).then(|| format!("Failed checks: {}", failed.join(", "))),
+        }),
+    )
+}
+
+fn check(status: CheckStatus, detail: String, duration: Duration) -> Check {
+    Check {
+        status,
+        detail,
+        duration_ms: duration.as_millis() as u64,
+    }
+}
+
+fn check_schema(version: i64) -> Check {
+    if version == db::SCHEMA_VERSION {
+        check(CheckStatus::Ok, format!("schema version {}", version), Duration::ZERO)
+    } else {
+        check(
+            CheckStatus::Failed,
+            format!("schema version {}, expected {}", version, db::SCHEMA_VERSION),
+            Duration::ZERO,
+        )
+    }
+}
+
+fn check_disk(database: &Path, min_free: u64) -> Check {
+    let started = Instant::now();
+    if database.as_os_str().is_empty() || database.as_os_str() == ":memory:" {
+        return check(CheckStatus::Skipped, "in-memory database".to_string(), Duration::ZERO);
+    }
+    // The file itself may not exist yet, but its directory does
+    let dir = match database.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match fs4::available_space(dir) {
+        Ok(free) if free >= min_free => check(CheckStatus::Ok, format!("{} bytes free", free), started.elapsed()),
+        Ok(free) => check(
+            CheckStatus::Failed,
+            format!("{} bytes free, need {}", free, min_free),
+            started.elapsed(),
+        ),
+        Err(e) => check(CheckStatus::Failed, format!("{}: {}", dir.display(), e), started.elapsed()),
+    }
+}
@@ -0,0 +1,385 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::{
+    body::{self, Body, Bytes},
+    extract::{FromRequestParts, MatchedPath, Path, Request, State},
+    http::{header, request::Parts, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use futures_util::{stream, StreamExt};
+use sha2::{Digest, Sha256};
+use sqlx::FromRow;
+use tokio::task::JoinHandle;
+
+use crate::db::DbPool;
+use crate::models::ApiResponse;
+
+/// Header carrying the client-chosen idempotency key
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Header set on responses replayed from the cache
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// Largest response kept for replay. Larger ones are passed through, and
+/// retries are told the request already ran.
+const MAX_STORED_RESPONSE_BYTES: usize = 8 * 1024 * 1024;
+
+/// How long a claim on a key lasts unless renewed. Requests renew it for as
+/// long as they run, so it only runs out for requests that were abandoned,
+/// such as by a server that stopped while handling them.
+const IN_PROGRESS_LEASE_SECS: i64 = 60;
+
+/// How often a running request renews its claim
+const LEASE_RENEWAL_SECS: u64 = IN_PROGRESS_LEASE_SECS as u64 / 3;
+
+/// Longest accepted idempotency key
+const MAX_KEY_LEN: usize = 255;
+
+/// State for the idempotency middleware
+#[derive(Clone)]
+pub struct IdempotencyState {
+    pub pool: DbPool,
+    /// How long a key and its cached response are kept
+    pub window: Duration,
+    /// Largest request body the layer will buffer for most routes
+    pub max_body_bytes: usize,
+    /// Routes accepting larger bodies, by path, and the largest body each accepts
+    pub route_body_bytes: Arc<HashMap<&'static str, usize>>,
+}
+
+/// A stored key; `status` is `None` while the original request is still
+/// running, and `body` is `None` if its response was too large to keep
+#[derive(Debug, FromRow)]
+struct StoredKey {
+    fingerprint: String,
+    status: Option<i64>,
+    content_type: Option<String>,
+    body: Option<Vec<u8>>,
+    created_at: DateTime<Utc>,
+}
+
+/// A key claimed by the request being handled. Dropped before the request
+/// finished, such as when the client disconnects, it releases the key so
+/// that the client can retry.
+struct ClaimedKey {
+    pool: DbPool,
+    user_id: String,
+    key: String,
+    settled: bool,
+    /// Task renewing the claim until the request is settled
+    renewal: JoinHandle<()>,
+}
+
+impl ClaimedKey {
+    fn new(pool: DbPool, user_id: String, key: String) -> Self {
+        let renewal = tokio::spawn(renew_lease(pool.clone(), user_id.clone(), key.clone()));
+        Self {
+            pool,
+            user_id,
+            key,
+            settled: false,
+            renewal,
+        }
+    }
+
+    /// Release the key so that the request can be retried
+    async fn release(mut self) {
+        self.settled = true;
+        self.renewal.abort();
+        let _ = release_key(&self.pool, &self.user_id, &self.key).await;
+    }
+
+    /// Keep the key with the response it was used for
+    async fn store(
+        mut self,
+        status: StatusCode,
+        content_type: Option<&str>,
+        body: Option<&[u8]>,
+    ) {
+        self.settled = true;
+        self.renewal.abort();
+        let stored = store_response(&self.pool, &self.user_id, &self.key, status, content_type, body).await;
+        if stored.is_err() {
+            let _ = release_key(&self.pool, &self.user_id, &self.key).await;
+        }
+    }
+}
+
+impl Drop for ClaimedKey {
+    fn drop(&mut self) {
+        self.renewal.abort();
+        if self.settled {
+            return;
+        }
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let (pool, user_id, key) = (self.pool.clone(), self.user_id.clone(), self.key.clone());
+            runtime.spawn(async move {
+                let _ = release_key(&pool, &user_id, &key).await;
+            });
+        }
+    }
+}
+
+/// Replay cached responses for mutating requests that carry an `Idempotency-Key`
+pub async fn idempotency_layer(
+    State(state): State<IdempotencyState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !is_mutating(request.method()) {
+        return next.run(request).await;
+    }
+
+    let key = match request.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(value) => match value.to_str() {
+            Ok(key) if !key.is_empty() && key.len() <= MAX_KEY_LEN => key.to_string(),
+            _ => return error_response(StatusCode::BAD_REQUEST, "Invalid Idempotency-Key header"),
+        },
+        None => return next.run(request).await,
+    };
+
+    let (mut parts, body) = request.into_parts();
+    let max_body_bytes = parts
+        .extensions
+        .get::<MatchedPath>()
+        .and_then(|path| state.route_body_bytes.get(path.as_str()))
+        .copied()
+        .unwrap_or(state.max_body_bytes);
+    let body = match body::to_bytes(body, max_body_bytes).await {
+        Ok(body) => body,
+        Err(_) => return error_response(StatusCode::PAYLOAD_TOO_LARGE, "Request body too large"),
+    };
+    let user_id = user_id(&mut parts).await;
+    let fingerprint = fingerprint(&parts, &body);
+
+    match claim_key(&state, &user_id, &key, &fingerprint).await {
+        Ok(Claim::Claimed) => {}
+        Ok(Claim::Replay(stored)) => return replay(stored),
+        Ok(Claim::Mismatch) => {
+            return error_response(
+                StatusCode::CONFLICT,
+                "Idempotency-Key was already used with a different request",
+            )
+        }
+        Ok(Claim::InProgress) => {
+            return error_response(
+                StatusCode::CONFLICT,
+                "A request with this Idempotency-Key is still in progress",
+            )
+        }
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+    let claimed = ClaimedKey::new(state.pool.clone(), user_id, key);
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+
+    // Server errors are not cached so that the client can retry them
+    if response.status().is_server_error() {
+        claimed.release().await;
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let content_type = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    // The request has taken effect by now, so the key is kept whatever
+    // happens to the response: retries must not run it again
+    let mut chunks = body.into_data_stream();
+    let mut buffered = Vec::new();
+    while let Some(chunk) = chunks.next().await {
+        match chunk {
+            Ok(chunk) if buffered.len() + chunk.len() <= MAX_STORED_RESPONSE_BYTES => {
+                buffered.extend_from_slice(&chunk);
+            }
+            chunk => {
+                claimed.store(parts.status, content_type.as_deref(), None).await;
+                let sent = stream::iter([Ok(Bytes::from(buffered)), chunk]);
+                return Response::from_parts(parts, Body::from_stream(sent.chain(chunks)));
+            }
+        }
+    }
+
+    claimed.store(parts.status, content_type.as_deref(), Some(&buffered)).await;
+    Response::from_parts(parts, Body::from(buffered))
+}
+
+/// The user a request acts for, from its path, or `""` for routes outside a user
+async fn user_id(parts: &mut Parts) -> String {
+    Path::<HashMap<String, String>>::from_request_parts(parts, &())
+        .await
+        .ok()
+        .and_then(|Path(mut params)| params.remove("user_id"))
+        .unwrap_or_default()
+}
+
+enum Claim {
+    Claimed,
+    Replay(StoredKey),
+    Mismatch,
+    InProgress,
+}
+
+/// Reserve the key for this request, or report what is already stored under it
+async fn claim_key(
+    state: &IdempotencyState,
+    user_id: &str,
+    key: &str,
+    fingerprint: &str,
+) -> Result<Claim, sqlx::Error> {
+    let now = Utc::now();
+    purge_expired(&state.pool, now - state.window).await?;
+
+    let inserted = sqlx::query(
+        "INSERT OR IGNORE INTO idempotency_keys (user_id, key, fingerprint, created_at) VALUES (?, ?, ?, ?)"
+    )
+    .bind(user_id)
+    .bind(key)
+    .bind(fingerprint)
+    .bind(now)
+    .execute(&state.pool)
+    .await?;
+
+    if inserted.rows_affected() == 1 {
+        return Ok(Claim::Claimed);
+    }
+
+    let stored: Option<StoredKey> = sqlx::query_as(
+        "SELECT fingerprint, status, content_type, body, created_at FROM idempotency_keys WHERE user_id = ? AND key = ?"
+    )
+    .bind(user_id)
+    .bind(key)
+    .fetch_optional(&state.pool)
+    .await?;
+
+    Ok(match stored {
+        // Purged between the insert and the select; treat it as still taken
+        None => Claim::InProgress,
+        Some(stored) if stored.status.is_none() && stored.created_at < now - Duration::seconds(IN_PROGRESS_LEASE_SECS) => {
+            // Abandoned; take it over unless another retry just did
+            let taken = sqlx::query(
+                "UPDATE idempotency_keys SET fingerprint = ?, created_at = ? WHERE user_id = ? AND key = ? AND status IS NULL AND created_at = ?"
+            )
+            .bind(fingerprint)
+            .bind(now)
+            .bind(user_id)
+            .bind(key)
+            .bind(stored.created_at)
+            .execute(&state.pool)
+            .await?;
+            if taken.rows_affected() == 1 {
+                Claim::Claimed
+            } else {
+                Claim::InProgress
+            }
+        }
+        Some(stored) if stored.status.is_none() => Claim::InProgress,
+        Some(stored) if stored.fingerprint != fingerprint => Claim::Mismatch,
+        Some(stored) => Claim::Replay(stored),
+    })
+}
+
+/// Keep a claimed key from being taken over while its request runs
+async fn renew_lease(pool: DbPool, user_id: String, key: String) {
+    let mut renewals = tokio::time::interval(std::time::Duration::from_secs(LEASE_RENEWAL_SECS));
+    // The first tick completes immediately, and the claim is fresh
+    renewals.tick().await;
+    loop {
+        renewals.tick().await;
+        let _ = sqlx::query("UPDATE idempotency_keys SET created_at = ? WHERE user_id = ? AND key = ? AND status IS NULL")
+            .bind(Utc::now())
+            .bind(&user_id)
+            .bind(&key)
+            .execute(&pool)
+            .await;
+    }
+}
+
+async fn store_response(
+    pool: &DbPool,
+    user_id: &str,
+    key: &str,
+    status: StatusCode,
+    content_type: Option<&str>,
+    body: Option<&[u8]>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE idempotency_keys SET status = ?, content_type = ?, body = ? WHERE user_id = ? AND key = ?")
+        .bind(i64::from(status.as_u16()))
+        .bind(content_type)
+        .bind(body)
+        .bind(user_id)
+        .bind(key)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+async fn release_key(pool: &DbPool, user_id: &str, key: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM idempotency_keys WHERE user_id = ? AND key = ?")
+        .bind(user_id)
+        .bind(key)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+async fn purge_expired(pool: &DbPool, cutoff: DateTime<Utc>) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM idempotency_keys WHERE created_at < ?")
+        .bind(cutoff)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+fn replay(stored: StoredKey) -> Response {
+    let status = stored
+        .status
+        .and_then(|status| u16::try_from(status).ok())
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .unwrap_or(StatusCode::OK);
+    let Some(body) = stored.body else {
+        return error_response(
+            StatusCode::CONFLICT,
+            &format!(
+                "The request with this Idempotency-Key completed with status {}, but its response was too large to keep",
+                status.as_u16()
+            ),
+        );
+    };
+
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    let headers = response.headers_mut();
+    if let Some(content_type) = stored.content_type.and_then(|value| HeaderValue::from_str(&value).ok()) {
+        headers.insert(header::CONTENT_TYPE, content_type);
+    }
+    headers.insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+    response
+}
+
+fn is_mutating(method: &Method) -> bool {
+    matches!(*method, Method::POST | Method::PUT | Method::PATCH | Method::DELETE)
+}
+
+/// Hash of everything that identifies a request: method, path, query and body
+fn fingerprint(parts: &Parts, body: &[u8]) -> String {
+    let path = parts.uri.path_and_query().map_or(parts.uri.path(), |path| path.as_str());
+    let mut hasher = Sha256::new();
+    hasher.update(parts.method.as_str().as_bytes());
+    hasher.update([0]);
+    hasher.update(path.as_bytes());
+    hasher.update([0]);
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response {
+    (status, Json(ApiResponse::<()>::error(message.to_string()))).into_response()
+}
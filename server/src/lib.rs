@@ -0,0 +1,190 @@
+pub mod api_tokens;
+pub mod blobs;
+pub mod caldav;
+pub mod config;
+pub mod db;
+pub mod feed;
+pub mod handlers;
+pub mod health;
+pub mod idempotency;
+pub mod limits;
+pub mod logging;
+pub mod metrics;
+pub mod models;
+pub mod revisions;
+pub mod sharing;
+pub mod shutdown;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+pub mod tls;
+pub mod tokens;
+pub mod webhooks;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::{
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{any, delete, get, post, put},
+    Extension, Router,
+};
+use todo_shared::attachments;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tower_http::trace::{DefaultOnResponse, TraceLayer};
+use tower_http::LatencyUnit;
+use tracing::Level;
+
+use crate::blobs::Blobs;
+use crate::config::Config;
+use crate::db::DbPool;
+use crate::limits::LimitState;
+use crate::metrics::Metrics;
+
+/// Routes whose bodies may exceed `limits.body_bytes`
+const ATTACHMENTS_ROUTE: &str = "/api/users/{user_id}/todos/{todo_id}/attachments";
+const IMPORT_ROUTE: &str = "/api/users/{user_id}/import";
+const SYNC_ROUTE: &str = "/api/users/{user_id}/sync";
+
+/// Build the application router with all routes and middleware, keeping
+/// attachment contents in `blobs`. `config` must have been validated.
+pub fn app(pool: DbPool, blobs: Blobs, config: &Config) -> Router {
+    // CORS configuration
+    let origins = config::allowed_origins(&config.server.allowed_origins)
+        .expect("allowed origins are validated with the config");
+    let cors = CorsLayer::new()
+        .allow_origin(origins.map_or(AllowOrigin::any(), AllowOrigin::list))
+        .allow_methods(Any)
+        .allow_headers(Any);
+    
+    // Idempotency-Key support for retried mutating requests, buffering no
+    // more of a body than its route accepts
+    let idempotency = idempotency::IdempotencyState {
+        pool: pool.clone(),
+        window: chrono::Duration::seconds(config.retention.idempotency_secs),
+        max_body_bytes: config.limits.body_bytes,
+        route_body_bytes: Arc::new(HashMap::from([
+            (ATTACHMENTS_ROUTE, attachments::MAX_SIZE),
+            (IMPORT_ROUTE, config.limits.sync_body_bytes),
+            (SYNC_ROUTE, config.limits.sync_body_bytes),
+        ])),
+    };
+    
+    let scopes = api_tokens::ScopeState {
+        pool: pool.clone(),
+        required_once_created: config.api_tokens.required_once_created,
+    };
+    
+    // Sync and import carry whole collections, so they get a larger body limit
+    let body_limit = DefaultBodyLimit::max(config.limits.body_bytes);
+    let sync_body_limit = DefaultBodyLimit::max(config.limits.sync_body_bytes);
+    let limits = LimitState::new(config.limits.clone());
+    
+    let mut api = Router::new()
+        .route("/health", get(handlers::health_check))
+        .route("/api/users/{user_id}/todos", get(handlers::get_todos))
+        .route("/api/users/{user_id}/todos", post(handlers::create_todo))
+        .route("/api/users/{user_id}/todos/{todo_id}", put(handlers::update_todo))
+        .route("/api/users/{user_id}/todos/{todo_id}", delete(handlers::delete_todo))
+        .route("/api/users/{user_id}/todos/{todo_id}/history", get(handlers::get_todo_history))
+        .route("/api/users/{user_id}/todos/{todo_id}/history/{revision_id}/revert", post(handlers::revert_todo))
+        .route(ATTACHMENTS_ROUTE, get(handlers::get_attachments))
+        .route(
+            ATTACHMENTS_ROUTE,
+            post(handlers::upload_attachment).layer(DefaultBodyLimit::max(attachments::MAX_SIZE)),
+        )
+        .route("/api/users/{user_id}/todos/{todo_id}/attachments/{attachment_id}", get(handlers::download_attachment))
+        .route("/api/users/{user_id}/todos/{todo_id}/attachments/{attachment_id}", delete(handlers::delete_attachment))
+        .route("/api/users/{user_id}/todos/{todo_id}/comments", get(handlers::get_comments))
+        .route("/api/users/{user_id}/todos/{todo_id}/comments", post(handlers::create_comment))
+        .route("/api/users/{user_id}/todos/{todo_id}/comments/{comment_id}", put(handlers::update_comment))
+        .route("/api/users/{user_id}/todos/{todo_id}/comments/{comment_id}", delete(handlers::delete_comment))
+        .route("/api/users/{user_id}/feed", get(handlers::get_feed))
+        .route("/api/users/{user_id}/trash", get(handlers::list_trash))
+        .route("/api/users/{user_id}/trash", delete(handlers::empty_trash))
+        .route("/api/users/{user_id}/trash/{todo_id}/restore", post(handlers::restore_todo))
+        .route("/api/users/{user_id}/export", get(handlers::export_todos))
+        .route(IMPORT_ROUTE, post(handlers::import_todos).layer(sync_body_limit))
+        .route("/api/users/{user_id}/views", get(handlers::get_views))
+        .route("/api/users/{user_id}/views", post(handlers::create_view))
+        .route("/api/users/{user_id}/views/{view_id}", put(handlers::update_view))
+        .route("/api/users/{user_id}/views/{view_id}", delete(handlers::delete_view))
+        .route("/api/users/{user_id}/views/{view_id}/todos", get(handlers::get_view_todos))
+        .route("/api/users/{user_id}/settings", get(handlers::get_settings))
+        .route("/api/users/{user_id}/settings", put(handlers::update_settings))
+        .route("/api/users/{user_id}/quick-add", post(handlers::parse_quick_add))
+        .route("/api/users/{user_id}/lists", get(handlers::get_lists))
+        .route("/api/users/{user_id}/lists", post(handlers::create_list))
+        .route("/api/users/{user_id}/lists/{list_id}", put(handlers::update_list))
+        .route("/api/users/{user_id}/lists/{list_id}", delete(handlers::delete_list))
+        .route("/api/users/{user_id}/lists/{list_id}/members", get(handlers::get_list_members))
+        .route("/api/users/{user_id}/lists/{list_id}/members/{member_id}", put(handlers::update_list_member))
+        .route("/api/users/{user_id}/lists/{list_id}/members/{member_id}", delete(handlers::remove_list_member))
+        .route("/api/users/{user_id}/lists/{list_id}/invitations", post(handlers::invite_to_list))
+        .route("/api/users/{user_id}/invitations", get(handlers::get_invitations))
+        .route("/api/users/{user_id}/invitations/{invitation_id}/accept", post(handlers::accept_invitation))
+        .route("/api/users/{user_id}/invitations/{invitation_id}/decline", post(handlers::decline_invitation))
+        .route("/api/users/{user_id}/tokens", get(handlers::get_api_tokens))
+        .route("/api/users/{user_id}/tokens", post(handlers::create_api_token))
+        .route("/api/users/{user_id}/tokens/{token_id}", delete(handlers::revoke_api_token))
+        .route(
+            SYNC_ROUTE,
+            post(handlers::sync_todos)
+                .route_layer(middleware::from_fn_with_state(limits.clone(), limits::sync_size_layer))
+                .layer(sync_body_limit),
+        );
+    if config.features.webhooks {
+        api = api
+            .route("/api/users/{user_id}/webhooks", get(handlers::get_webhooks))
+            .route("/api/users/{user_id}/webhooks", post(handlers::create_webhook))
+            .route("/api/users/{user_id}/webhooks/{webhook_id}", put(handlers::update_webhook))
+            .route("/api/users/{user_id}/webhooks/{webhook_id}", delete(handlers::delete_webhook))
+            .route("/api/users/{user_id}/webhooks/{webhook_id}/deliveries", get(handlers::get_webhook_deliveries))
+            .route(
+                "/api/users/{user_id}/webhooks/{webhook_id}/deliveries/{delivery_id}/redeliver",
+                post(handlers::redeliver_webhook),
+            );
+    }
+    if config.features.calendar_feed {
+        api = api
+            .route("/api/users/{user_id}/calendar/token", post(handlers::create_calendar_token))
+            .route("/api/users/{user_id}/calendar.ics", get(handlers::calendar_feed));
+    }
+    
+    let mut app = api
+        .layer(body_limit)
+        .layer(middleware::from_fn_with_state(idempotency, idempotency::idempotency_layer))
+        // Outside the idempotency layer, so that rejected tokens are not cached
+        .layer(middleware::from_fn_with_state(scopes, api_tokens::scope_layer))
+        .layer(middleware::from_fn_with_state(limits, limits::rate_limit_layer))
+        .layer(middleware::from_fn(limits::envelope_layer))
+        .layer(cors)
+        // Probes from load balancers and orchestrators are never rate limited
+        .route("/health/live", get(health::live))
+        .route("/health/ready", get(health::ready));
+    if config.features.caldav {
+        // Added after the CORS layer, which would otherwise answer every OPTIONS
+        // request as a preflight and hide the DAV capabilities from clients
+        app = app.route("/dav/{*path}", any(caldav::handle));
+    }
+    if config.features.metrics {
+        // Scraped by Prometheus rather than browsers, and never rate limited
+        app = app.route("/metrics", get(metrics::get_metrics));
+    }
+    
+    let metrics = Metrics::new();
+    app.layer(middleware::from_fn_with_state(metrics.clone(), metrics::track_layer))
+        .layer(Extension(blobs))
+        .layer(Extension(metrics))
+        .layer(Extension(config.health.clone()))
+        .layer(Extension(config.webhooks.clone()))
+        .layer(Extension(config.features.clone()))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(logging::make_span)
+                .on_response(DefaultOnResponse::new().level(Level::INFO).latency_unit(LatencyUnit::Millis)),
+        )
+        // Outermost, so the id is known when the request's span is made
+        .layer(middleware::from_fn(logging::request_id_layer))
+        .with_state(pool)
+}
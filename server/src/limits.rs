@@ -0,0 +1,328 @@
+//! Rate limiting and request size limits.
+//!
+//! Every request takes a token from a bucket for the client's IP address
+//! and, on user routes, one for the user. Buckets refill at a steady rate up
+//! to their burst size; a request finding its bucket empty is answered with
+//! 429 and a `Retry-After` header. Request bodies are capped per route, and
+//! a sync request may carry only so many todos.
+//!
+//! Requests with a bearer token are limited per token. Other requests are
+//! limited per user named in the path, which the server does not
+//! authenticate: anyone can use up a user's bucket, and the per-IP limit is
+//! what keeps a single client from doing so for long.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    body::{self, Body},
+    extract::{ConnectInfo, FromRequestParts, Path, Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Deserializer};
+
+use crate::config::{override_with, parse, ConfigError};
+use crate::models::ApiResponse;
+use crate::tokens;
+
+/// Most buckets kept; past this the least recently used one is dropped
+const MAX_BUCKETS: usize = 10_000;
+
+/// How often buckets that have refilled completely are dropped
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A token bucket's size and refill rate
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Quota {
+    /// Requests that can be made at once
+    pub burst: u32,
+    /// Requests regained per second
+    pub per_second: f64,
+}
+
+/// Configured limits
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Rate limit per user, `None` to disable; a burst of 0 disables it in the config file
+    #[serde(deserialize_with = "quota_or_off")]
+    pub per_user: Option<Quota>,
+    /// Rate limit per client IP address, `None` to disable
+    #[serde(deserialize_with = "quota_or_off")]
+    pub per_ip: Option<Quota>,
+    /// Largest request body on most routes
+    pub body_bytes: usize,
+    /// Largest request body for sync and import
+    pub sync_body_bytes: usize,
+    /// Most todos a single sync request may carry
+    pub max_sync_todos: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            per_user: Some(Quota { burst: 120, per_second: 2.0 }),
+            per_ip: Some(Quota { burst: 300, per_second: 5.0 }),
+            body_bytes: 64 * 1024,
+            sync_body_bytes: 8 * 1024 * 1024,
+            max_sync_todos: 5_000,
+        }
+    }
+}
+
+impl Limits {
+    /// Override the limits with `RATE_LIMIT_USER_BURST`, `RATE_LIMIT_USER_PER_SEC`,
+    /// `RATE_LIMIT_IP_BURST`, `RATE_LIMIT_IP_PER_SEC`, `MAX_BODY_BYTES`,
+    /// `MAX_SYNC_BODY_BYTES` and `MAX_SYNC_TODOS`. A burst of 0 disables that
+    /// rate limit.
+    pub fn apply_env(&mut self, var: &impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        override_quota(var, "RATE_LIMIT_USER", &mut self.per_user)?;
+        override_quota(var, "RATE_LIMIT_IP", &mut self.per_ip)?;
+        override_with(var, "MAX_BODY_BYTES", &mut self.body_bytes, parse)?;
+        override_with(var, "MAX_SYNC_BODY_BYTES", &mut self.sync_body_bytes, parse)?;
+        override_with(var, "MAX_SYNC_TODOS", &mut self.max_sync_todos, parse)?;
+        Ok(())
+    }
+
+    /// Describe what is wrong with the limits, if anything
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (name, quota) in [("limits.per_user", self.per_user), ("limits.per_ip", self.per_ip)] {
+            if quota.is_some_and(|quota| !quota.per_second.is_finite() || quota.per_second <= 0.0) {
+                problems.push(format!("{}.per_second: must be greater than 0", name));
+            }
+        }
+        for (name, value) in [
+            ("limits.body_bytes", self.body_bytes),
+            ("limits.sync_body_bytes", self.sync_body_bytes),
+            ("limits.max_sync_todos", self.max_sync_todos),
+        ] {
+            if value == 0 {
+                problems.push(format!("{}: must be at least 1", name));
+            }
+        }
+        problems
+    }
+}
+
+fn quota_or_off<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Quota>, D::Error> {
+    let quota = Quota::deserialize(deserializer)?;
+    Ok((quota.burst > 0).then_some(quota))
+}
+
+fn override_quota(
+    var: &impl Fn(&str) -> Option<String>,
+    prefix: &str,
+    quota: &mut Option<Quota>,
+) -> Result<(), ConfigError> {
+    let mut value = quota.unwrap_or(Quota { burst: 0, per_second: 0.0 });
+    override_with(var, &format!("{}_BURST", prefix), &mut value.burst, parse)?;
+    override_with(var, &format!("{}_PER_SEC", prefix), &mut value.per_second, parse)?;
+    *quota = (value.burst > 0).then_some(value);
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// The quota the bucket was last used with, which it refills by
+    quota: Quota,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.quota.per_second).min(f64::from(self.quota.burst));
+        self.updated = now;
+    }
+
+    /// Whether the bucket is as it would be if it were new, so it can be dropped
+    fn is_full(&self, now: Instant) -> bool {
+        let mut bucket = *self;
+        bucket.refill(now);
+        bucket.tokens >= f64::from(bucket.quota.burst)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    by_key: HashMap<String, Bucket>,
+    swept: Option<Instant>,
+}
+
+/// Token buckets keyed by client, at most `MAX_BUCKETS` of them
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiter {
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+impl RateLimiter {
+    /// Take a token from `key`'s bucket, or report how long until one is available
+    pub fn take(&self, key: &str, quota: Quota, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.swept.is_none_or(|swept| now.saturating_duration_since(swept) >= SWEEP_INTERVAL) {
+            buckets.by_key.retain(|_, bucket| !bucket.is_full(now));
+            buckets.swept = Some(now);
+        }
+        if buckets.by_key.len() >= MAX_BUCKETS && !buckets.by_key.contains_key(key) {
+            let least_recent = buckets
+                .by_key
+                .iter()
+                .min_by_key(|(_, bucket)| bucket.updated)
+                .map(|(key, _)| key.clone());
+            if let Some(least_recent) = least_recent {
+                buckets.by_key.remove(&least_recent);
+            }
+        }
+
+        let bucket = buckets.by_key.entry(key.to_string()).or_insert(Bucket {
+            tokens: f64::from(quota.burst),
+            updated: now,
+            quota,
+        });
+        bucket.refill(now);
+        bucket.quota = quota;
+        bucket.tokens = bucket.tokens.min(f64::from(quota.burst));
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        if quota.per_second <= 0.0 {
+            return Err(Duration::from_secs(u64::MAX / 2));
+        }
+        Err(Duration::from_secs_f64((1.0 - bucket.tokens) / quota.per_second))
+    }
+
+    /// The number of buckets being kept
+    pub fn len(&self) -> usize {
+        self.buckets.lock().unwrap().by_key.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// State for the limit middleware
+#[derive(Debug, Clone)]
+pub struct LimitState {
+    pub limits: Limits,
+    pub limiter: RateLimiter,
+}
+
+impl LimitState {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            limiter: RateLimiter::default(),
+        }
+    }
+}
+
+/// Reject clients that have used up their rate limit
+pub async fn rate_limit_layer(State(state): State<LimitState>, request: Request, next: Next) -> Response {
+    let now = Instant::now();
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let (mut parts, body) = request.into_parts();
+    let user_id = Path::<HashMap<String, String>>::from_request_parts(&mut parts, &())
+        .await
+        .ok()
+        .and_then(|Path(params)| params.get("user_id").cloned());
+    let bearer = parts
+        .headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim);
+
+    let mut checks = Vec::new();
+    if let (Some(quota), Some(ip)) = (state.limits.per_ip, ip) {
+        checks.push((format!("ip:{}", ip), quota));
+    }
+    if let (Some(quota), Some(user_id)) = (state.limits.per_user, user_id) {
+        // The token is what identifies the caller; the path names anyone
+        let key = match bearer {
+            Some(token) => format!("token:{}", tokens::hash_token(token)),
+            None => format!("user:{}", user_id),
+        };
+        checks.push((key, quota));
+    }
+    for (key, quota) in checks {
+        if let Err(wait) = state.limiter.take(&key, quota, now) {
+            return too_many_requests(wait);
+        }
+    }
+
+    next.run(Request::from_parts(parts, body)).await
+}
+
+/// Reject sync requests carrying more todos than allowed
+pub async fn sync_size_layer(State(state): State<LimitState>, request: Request, next: Next) -> Response {
+    #[derive(Deserialize)]
+    struct SyncTodos {
+        #[serde(default)]
+        todos: Vec<IgnoredAny>,
+    }
+
+    let (parts, body) = request.into_parts();
+    let body = match body::to_bytes(body, state.limits.sync_body_bytes).await {
+        Ok(body) => body,
+        Err(_) => return payload_too_large("Request body too large"),
+    };
+    // Malformed bodies are left for the handler to reject
+    if let Ok(sync) = serde_json::from_slice::<SyncTodos>(&body) {
+        if sync.todos.len() > state.limits.max_sync_todos {
+            return payload_too_large(&format!(
+                "A sync may carry at most {} todos",
+                state.limits.max_sync_todos
+            ));
+        }
+    }
+
+    next.run(Request::from_parts(parts, Body::from(body))).await
+}
+
+/// Wrap the plain-text 413 responses of axum's extractors in the `ApiResponse` envelope
+pub async fn envelope_layer(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+    if response.status() != StatusCode::PAYLOAD_TOO_LARGE || is_json {
+        return response;
+    }
+    payload_too_large("Request body too large")
+}
+
+fn too_many_requests(wait: Duration) -> Response {
+    let seconds = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+    let mut response = (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(ApiResponse::<()>::error("Too many requests".to_string())),
+    )
+        .into_response();
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(seconds.max(1)));
+    response
+}
+
+fn payload_too_large(message: &str) -> Response {
+    (
+        StatusCode::PAYLOAD_TOO_LARGE,
+        Json(ApiResponse::<()>::error(message.to_string())),
+    )
+        .into_response()
+}
@@ -0,0 +1,87 @@
+//! Request ids and the context requests are logged in.
+//!
+//! Every request gets an id, taken from its `X-Request-Id` header when the
+//! client sent a usable one and generated otherwise, and the id is echoed in
+//! the response. Each request is handled in a span carrying that id with the
+//! route, user and device, so everything logged along the way, such as the
+//! outcome of a sync, can be found by any of them.
+
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use percent_encoding::percent_decode_str;
+use tracing::Span;
+
+use crate::revisions::DEVICE_ID_HEADER;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest request id accepted from clients
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// The id of the request being handled
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// Give every request an id and return it in the `X-Request-Id` header
+pub async fn request_id_layer(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    request.extensions_mut().insert(RequestId(id.clone()));
+
+    let mut response = next.run(request).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// Ids from clients end up in logs, so only short printable ones are kept
+fn is_valid(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LENGTH && id.bytes().all(|byte| byte.is_ascii_graphic())
+}
+
+/// The span a request is handled in
+pub fn make_span(request: &Request) -> Span {
+    let route = request.extensions().get::<MatchedPath>().map(MatchedPath::as_str);
+    let request_id = request.extensions().get::<RequestId>().map(|RequestId(id)| id.as_str());
+    let user_id = route.and_then(|route| path_param(route, request.uri().path(), "user_id"));
+    let device_id = request
+        .headers()
+        .get(DEVICE_ID_HEADER)
+        .and_then(|value| value.to_str().ok());
+
+    let span = tracing::info_span!(
+        "request",
+        request_id,
+        method = %request.method(),
+        route,
+        path = request.uri().path(),
+        user_id = user_id.as_deref(),
+        device_id,
+        // Filled in for the OpenTelemetry layer when traces are exported
+        otel.name = tracing::field::Empty,
+        otel.kind = tracing::field::Empty,
+    );
+    #[cfg(feature = "otel")]
+    crate::telemetry::continue_trace(&span, request);
+    span
+}
+
+/// The value of the `{name}` segment of `route` in `path`
+fn path_param(route: &str, path: &str, name: &str) -> Option<String> {
+    let pattern = format!("{{{}}}", name);
+    route
+        .split('/')
+        .zip(path.split('/'))
+        .find(|(segment, _)| *segment == pattern)
+        .map(|(_, value)| percent_decode_str(value).decode_utf8_lossy().into_owned())
+}
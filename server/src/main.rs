@@ -1,54 +1,128 @@
-mod db;
-mod handlers;
-mod models;
+use std::time::Duration;
 
-use axum::{
-    routing::{get, post, put, delete},
-    Router,
-};
-use tower_http::cors::{Any, CorsLayer};
-use tower_http::trace::TraceLayer;
+use axum::serve::ListenerExt;
+use todo_server::config::{Config, LogFormat};
+use todo_server::shutdown::{self, Shutdown};
+use todo_server::{blobs, db, feed, revisions, tls, webhooks};
+use tokio::task::JoinSet;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    // Load and validate the configuration before anything else
+    let config = Config::load()?;
+    
     // Initialize tracing
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "todo_server=debug,tower_http=debug".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    let registry = tracing_subscriber::registry()
+        .with(tracing_subscriber::EnvFilter::new(&config.log.filter));
+    // With the otel feature, spans are also exported when a collector is configured
+    #[cfg(feature = "otel")]
+    let tracer_provider = todo_server::telemetry::tracer_provider(&config.telemetry)?;
+    #[cfg(feature = "otel")]
+    let registry = registry.with(tracer_provider.as_ref().map(todo_server::telemetry::layer));
+    match config.log.format {
+        LogFormat::Pretty => registry.with(tracing_subscriber::fmt::layer()).init(),
+        LogFormat::Json => registry.with(tracing_subscriber::fmt::layer().json()).init(),
+    }
     
     // Initialize database
-    let pool = db::init_db().await?;
+    let pool = db::connect_with(&config.database.url, config.database.max_connections).await?;
     tracing::info!("Database initialized");
     
-    // CORS configuration
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
+    // SIGINT or SIGTERM stops the server and, after it, the background tasks
+    let shutdown = Shutdown::new();
+    tokio::spawn(shutdown.clone().trigger_on_signal());
+    let mut tasks = JoinSet::new();
+    
+    // Purge todos that have been in the trash longer than the retention period
+    let trash_retention = chrono::Duration::days(config.retention.trash_days);
+    tasks.spawn(db::purge_trash_periodically(pool.clone(), trash_retention, shutdown.clone()));
+    
+    // Removed attachments are kept as long as trashed todos
+    let blobs = blobs::from_config(&config.blobs);
+    tasks.spawn(blobs::collect_garbage_periodically(
+        pool.clone(),
+        blobs.clone(),
+        trash_retention,
+        shutdown.clone(),
+    ));
+    
+    // Drop revision history older than the retention period
+    tasks.spawn(revisions::purge_periodically(
+        pool.clone(),
+        chrono::Duration::days(config.retention.revision_days),
+        shutdown.clone(),
+    ));
+    
+    // Deliver queued webhook events
+    if config.features.webhooks {
+        let policy = webhooks::RetryPolicy {
+            max_attempts: config.webhooks.max_attempts,
+            allow_private_targets: config.webhooks.allow_private_targets,
+            ..webhooks::RetryPolicy::default()
+        };
+        tasks.spawn(webhooks::Dispatcher::new(pool.clone(), policy).run(shutdown.clone()));
+    }
+    
+    // Drop finished webhook deliveries older than the retention period
+    tasks.spawn(webhooks::purge_periodically(
+        pool.clone(),
+        chrono::Duration::days(config.retention.webhook_delivery_days),
+        shutdown.clone(),
+    ));
+    
+    // Drop change feed events older than the retention period
+    tasks.spawn(feed::purge_periodically(
+        pool.clone(),
+        chrono::Duration::days(config.retention.feed_days),
+        shutdown.clone(),
+    ));
     
     // Build router
-    let app = Router::new()
-        .route("/health", get(handlers::health_check))
-        .route("/api/users/{user_id}/todos", get(handlers::get_todos))
-        .route("/api/users/{user_id}/todos", post(handlers::create_todo))
-        .route("/api/users/{user_id}/todos/{todo_id}", put(handlers::update_todo))
-        .route("/api/users/{user_id}/todos/{todo_id}", delete(handlers::delete_todo))
-        .route("/api/users/{user_id}/sync", post(handlers::sync_todos))
-        .layer(TraceLayer::new_for_http())
-        .layer(cors)
-        .with_state(pool);
+    let app = todo_server::app(pool.clone(), blobs, &config);
     
     // Start server
-    let addr = std::env::var("SERVER_ADDR").unwrap_or_else(|_| "0.0.0.0:3001".to_string());
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    let listener = tokio::net::TcpListener::bind(&config.server.bind).await?;
+    let drain_timeout = Duration::from_secs(config.server.shutdown_timeout_secs);
+    
+    if config.tls.enabled() {
+        let tls = tls::Tls::load(&config.tls)?;
+        tasks.spawn(tls.clone().reload_periodically(
+            Duration::from_secs(config.tls.reload_secs),
+            shutdown.clone(),
+        ));
+        // Handshakes already batch writes, so Nagle's algorithm only adds latency.
+        // Tapping the listener also makes client addresses available as `ConnectInfo`.
+        let listener = tls::TlsListener::new(listener, tls.acceptor())?.tap_io(|stream| {
+            let _ = stream.get_ref().0.set_nodelay(true);
+        });
+        
+        tracing::info!("Server listening on https://{}", config.server.bind);
+        tracing::info!("TLS certificate SHA-256 fingerprint: {}", tls.fingerprint());
+        shutdown::serve(listener, app, shutdown.clone(), drain_timeout).await?;
+    } else {
+        tracing::info!("Server listening on {}", config.server.bind);
+        shutdown::serve(listener, app, shutdown.clone(), drain_timeout).await?;
+    }
+    
+    // Let background tasks finish what they are doing, then close the database
+    let finished = tokio::time::timeout(drain_timeout, async {
+        while tasks.join_next().await.is_some() {}
+    })
+    .await;
+    if finished.is_err() {
+        tracing::warn!("Background tasks still running after {:?}, stopping them", drain_timeout);
+    }
+    pool.close().await;
+    tracing::info!("Shutdown complete");
     
-    tracing::info!("Server listening on {}", addr);
-    axum::serve(listener, app).await?;
+    // Export the spans still queued; this blocks until the collector answers
+    #[cfg(feature = "otel")]
+    if let Some(provider) = tracer_provider {
+        if let Err(e) = tokio::task::spawn_blocking(move || provider.shutdown()).await? {
+            eprintln!("Failed to export the remaining spans: {}", e);
+        }
+    }
     
     Ok(())
 }
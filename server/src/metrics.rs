@@ -0,0 +1,220 @@
+//! Prometheus metrics.
+//!
+//! `track_layer` counts every request and times it per route, labelled with
+//! the route pattern rather than the path so user ids do not end up in
+//! label values. The sync handler adds what each sync carried, and the
+//! database pool is sampled whenever `/metrics` is scraped.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use prometheus_client::encoding::{text, EncodeLabelSet};
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
+use prometheus_client::registry::Registry;
+
+use crate::db::DbPool;
+
+/// Content type of the OpenMetrics text format
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Route label for requests no route matched
+const UNMATCHED: &str = "unmatched";
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct RequestLabels {
+    method: String,
+    route: String,
+    status: u16,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct RouteLabels {
+    method: String,
+    route: String,
+}
+
+/// Buckets from 1ms to about 16s
+fn duration_histogram() -> Histogram {
+    Histogram::new(exponential_buckets(0.001, 2.0, 15))
+}
+
+/// What one sync request carried
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SyncStats {
+    /// Todos from the client that were applied
+    pub pushed: u64,
+    /// Todos sent back to the client
+    pub pulled: u64,
+    /// Todos from the client that lost to a newer server copy
+    pub conflicts: u64,
+    pub duration: Duration,
+}
+
+/// The server's metrics and the registry they are collected in
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Arc<Mutex<Registry>>,
+    requests: Family<RequestLabels, Counter>,
+    request_duration: Family<RouteLabels, Histogram>,
+    in_flight: Gauge,
+    syncs: Counter,
+    sync_pushed: Counter,
+    sync_pulled: Counter,
+    sync_conflicts: Counter,
+    sync_duration: Histogram,
+    pool_connections: Gauge,
+    pool_idle: Gauge,
+    pool_max: Gauge,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let metrics = Self {
+            registry: Arc::default(),
+            requests: Family::default(),
+            request_duration: Family::new_with_constructor(duration_histogram),
+            in_flight: Gauge::default(),
+            syncs: Counter::default(),
+            sync_pushed: Counter::default(),
+            sync_pulled: Counter::default(),
+            sync_conflicts: Counter::default(),
+            sync_duration: duration_histogram(),
+            pool_connections: Gauge::default(),
+            pool_idle: Gauge::default(),
+            pool_max: Gauge::default(),
+        };
+
+        let mut registry = metrics.registry.lock().unwrap();
+        registry.register(
+            "http_requests",
+            "HTTP requests answered, by method, route and status",
+            metrics.requests.clone(),
+        );
+        registry.register(
+            "http_request_duration_seconds",
+            "Time taken to answer HTTP requests, by method and route",
+            metrics.request_duration.clone(),
+        );
+        registry.register(
+            "http_requests_in_flight",
+            "HTTP requests being answered, including long-lived ones",
+            metrics.in_flight.clone(),
+        );
+        registry.register("sync_requests", "Syncs completed", metrics.syncs.clone());
+        registry.register("sync_todos_pushed", "Todos applied from clients by sync", metrics.sync_pushed.clone());
+        registry.register("sync_todos_pulled", "Todos sent to clients by sync", metrics.sync_pulled.clone());
+        registry.register(
+            "sync_conflicts_resolved",
+            "Todos from clients that lost to a newer server copy",
+            metrics.sync_conflicts.clone(),
+        );
+        registry.register(
+            "sync_duration_seconds",
+            "Time taken to complete a sync",
+            metrics.sync_duration.clone(),
+        );
+        registry.register(
+            "db_pool_connections",
+            "Open database connections",
+            metrics.pool_connections.clone(),
+        );
+        registry.register(
+            "db_pool_idle_connections",
+            "Open database connections not in use",
+            metrics.pool_idle.clone(),
+        );
+        registry.register(
+            "db_pool_max_connections",
+            "Most database connections the pool opens",
+            metrics.pool_max.clone(),
+        );
+        drop(registry);
+        metrics
+    }
+
+    /// Record a completed sync
+    pub fn record_sync(&self, stats: SyncStats) {
+        self.syncs.inc();
+        self.sync_pushed.inc_by(stats.pushed);
+        self.sync_pulled.inc_by(stats.pulled);
+        self.sync_conflicts.inc_by(stats.conflicts);
+        self.sync_duration.observe(stats.duration.as_secs_f64());
+    }
+
+    /// Sample the state of `pool`
+    pub fn observe_pool(&self, pool: &DbPool) {
+        self.pool_connections.set(i64::from(pool.size()));
+        self.pool_idle.set(pool.num_idle() as i64);
+        self.pool_max.set(i64::from(pool.options().get_max_connections()));
+    }
+
+    /// The metrics in the OpenMetrics text format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        text::encode(&mut out, &self.registry.lock().unwrap()).expect("writing to a String cannot fail");
+        out
+    }
+}
+
+/// Decrements the in-flight gauge even if the request is cancelled
+struct InFlight(Gauge);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+/// Count and time every request by route
+pub async fn track_layer(State(metrics): State<Metrics>, request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or(UNMATCHED, |path| path.as_str())
+        .to_string();
+
+    metrics.in_flight.inc();
+    let _in_flight = InFlight(metrics.in_flight.clone());
+    let response = next.run(request).await;
+
+    let status = response.status().as_u16();
+    metrics
+        .request_duration
+        .get_or_create(&RouteLabels {
+            method: method.clone(),
+            route: route.clone(),
+        })
+        .observe(started.elapsed().as_secs_f64());
+    metrics
+        .requests
+        .get_or_create(&RequestLabels { method, route, status })
+        .inc();
+    response
+}
+
+/// Serve the metrics for Prometheus to scrape
+pub async fn get_metrics(
+    State(pool): State<DbPool>,
+    Extension(metrics): Extension<Metrics>,
+) -> Response {
+    metrics.observe_pool(&pool);
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], metrics.render()).into_response()
+}
@@ -1,7 +1,20 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
+use sqlx::types::Json;
 use sqlx::FromRow;
+use todo_shared::formats::{ExportFormat, TodoRecord};
+use todo_shared::quick_add::QuickAdd;
+use todo_shared::reminders::Reminder;
+use todo_shared::views::ViewFilter;
+
+pub use todo_shared::attachments::Attachment;
+pub use todo_shared::comments::Comment;
+pub use todo_shared::views::SavedView;
+pub use todo_shared::Priority;
+
+pub use crate::api_tokens::{ApiToken, Scope};
+pub use crate::webhooks::{Delivery, DeliveryStatus, Webhook, WebhookEvent};
 
 /// A Todo item stored in the database
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -16,6 +29,34 @@ pub struct Todo {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub due_date: Option<DateTime<Utc>>,
+    /// Whether the due date is a whole day rather than a point in time
+    #[serde(default)]
+    pub all_day: bool,
+    /// Name of the list the todo belongs to
+    #[serde(default)]
+    pub list: Option<String>,
+    /// Tags, stored as a JSON array
+    #[serde(default)]
+    pub tags: Json<Vec<String>>,
+    /// Recurrence rule in iCalendar RRULE syntax, e.g. `FREQ=WEEKLY;BYDAY=MO`
+    #[serde(default)]
+    pub recurrence: Option<String>,
+    /// Reminders, stored as a JSON array
+    #[serde(default)]
+    pub reminders: Json<Vec<Reminder>>,
+    /// Shared list the todo belongs to, if any. Such todos belong to the list,
+    /// whichever member created them.
+    #[serde(default)]
+    pub list_id: Option<String>,
+    /// Member of the todo's list the todo is assigned to
+    #[serde(default)]
+    pub assignee_id: Option<String>,
+    /// The todo this one is a subtask of
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    /// Set when the todo has been moved to the trash
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 impl Todo {
@@ -31,57 +72,100 @@ impl Todo {
             created_at: now,
             updated_at: now,
             due_date: None,
+            all_day: false,
+            list: None,
+            tags: Json(Vec::new()),
+            recurrence: None,
+            reminders: Json(Vec::new()),
+            list_id: None,
+            assignee_id: None,
+            parent_id: None,
+            deleted_at: None,
         }
     }
-}
-
-/// Priority level for a todo item
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, sqlx::Type)]
-#[serde(rename_all = "lowercase")]
-#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
-pub enum Priority {
-    Low,
-    Medium,
-    High,
-}
-
-impl Default for Priority {
-    fn default() -> Self {
-        Priority::Medium
-    }
-}
-
-impl std::fmt::Display for Priority {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Priority::Low => write!(f, "low"),
-            Priority::Medium => write!(f, "medium"),
-            Priority::High => write!(f, "high"),
+    
+    /// Build a todo owned by `user_id` from an imported record
+    pub fn from_record(user_id: String, record: TodoRecord) -> Self {
+        Self {
+            id: record.id,
+            user_id,
+            title: record.title,
+            description: record.description,
+            completed: record.completed,
+            priority: record.priority,
+            created_at: record.created_at,
+            updated_at: record.updated_at,
+            due_date: record.due_date,
+            all_day: record.all_day,
+            list: record.list,
+            tags: Json(record.tags),
+            recurrence: record.recurrence,
+            reminders: Json(Vec::new()),
+            list_id: None,
+            assignee_id: None,
+            parent_id: record.parent_id,
+            deleted_at: None,
         }
     }
 }
 
-impl std::str::FromStr for Priority {
-    type Err = String;
-    
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "low" => Ok(Priority::Low),
-            "medium" => Ok(Priority::Medium),
-            "high" => Ok(Priority::High),
-            _ => Err(format!("Unknown priority: {}", s)),
+impl From<Todo> for TodoRecord {
+    fn from(todo: Todo) -> Self {
+        Self {
+            id: todo.id,
+            title: todo.title,
+            description: todo.description,
+            completed: todo.completed,
+            priority: todo.priority,
+            created_at: todo.created_at,
+            updated_at: todo.updated_at,
+            due_date: todo.due_date,
+            all_day: todo.all_day,
+            list: todo.list,
+            tags: todo.tags.0,
+            recurrence: todo.recurrence,
+            parent_id: todo.parent_id,
         }
     }
 }
 
 /// Request to create a new todo
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct CreateTodoRequest {
     pub id: Option<String>,
     pub title: String,
     pub description: Option<String>,
     pub priority: Option<Priority>,
     pub due_date: Option<DateTime<Utc>>,
+    pub all_day: Option<bool>,
+    pub list: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub recurrence: Option<String>,
+    pub reminders: Option<Vec<Reminder>>,
+    pub list_id: Option<String>,
+    pub assignee_id: Option<String>,
+    /// Todo to make this one a subtask of
+    pub parent_id: Option<String>,
+}
+
+impl From<QuickAdd> for CreateTodoRequest {
+    fn from(parsed: QuickAdd) -> Self {
+        Self {
+            id: None,
+            title: parsed.title,
+            description: None,
+            priority: parsed.priority,
+            due_date: parsed.due_date,
+            all_day: Some(parsed.all_day),
+            list: parsed.list,
+            tags: (!parsed.tags.is_empty()).then_some(parsed.tags),
+            recurrence: parsed.recurrence,
+            reminders: None,
+            list_id: None,
+            assignee_id: None,
+            parent_id: None,
+        }
+    }
 }
 
 /// Request to update a todo
@@ -92,6 +176,17 @@ pub struct UpdateTodoRequest {
     pub completed: Option<bool>,
     pub priority: Option<Priority>,
     pub due_date: Option<DateTime<Utc>>,
+    pub all_day: Option<bool>,
+    pub list: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub recurrence: Option<String>,
+    pub reminders: Option<Vec<Reminder>>,
+    /// Shared list to move the todo to; an empty string moves it out of its list
+    pub list_id: Option<String>,
+    /// Member to assign the todo to; an empty string unassigns it
+    pub assignee_id: Option<String>,
+    /// Todo to make this one a subtask of; an empty string makes it a top-level todo
+    pub parent_id: Option<String>,
 }
 
 /// Sync request from client
@@ -99,15 +194,235 @@ pub struct UpdateTodoRequest {
 pub struct SyncRequest {
     pub last_sync: Option<DateTime<Utc>>,
     pub todos: Vec<Todo>,
+    /// Saved views, including deleted ones
+    #[serde(default)]
+    pub views: Vec<SavedView>,
+    /// Comments, including deleted ones and ones written offline
+    #[serde(default)]
+    pub comments: Vec<Comment>,
+    /// Ids of todos purged from the trash on the device
+    #[serde(default)]
+    pub purged: Vec<String>,
 }
 
 /// Sync response to client
 #[derive(Debug, Serialize)]
 pub struct SyncResponse {
     pub todos: Vec<Todo>,
+    pub views: Vec<SavedView>,
+    /// Comments on the todos the user can see
+    pub comments: Vec<Comment>,
+    /// Every shared list the user is a member of
+    pub lists: Vec<SharedList>,
+    /// Metadata of files attached to the todos the user can see; contents
+    /// are downloaded separately
+    pub attachments: Vec<Attachment>,
+    /// Ids of todos the user could see that were purged for good since the last sync
+    pub purged: Vec<String>,
     pub sync_time: DateTime<Utc>,
 }
 
+/// Request to save a custom view
+#[derive(Debug, Deserialize)]
+pub struct SaveViewRequest {
+    pub id: Option<String>,
+    pub name: String,
+    pub filter: ViewFilter,
+}
+
+/// Request to add or edit a comment
+#[derive(Debug, Deserialize)]
+pub struct CommentRequest {
+    pub id: Option<String>,
+    /// Markdown text
+    pub body: String,
+}
+
+/// Query parameters for uploading an attachment
+#[derive(Debug, Deserialize)]
+pub struct AttachmentQuery {
+    /// File name of the attachment
+    pub name: String,
+}
+
+/// Request to create a personal API token
+#[derive(Debug, Deserialize)]
+pub struct CreateApiTokenRequest {
+    /// What the token is for, e.g. the script using it
+    pub name: String,
+    pub scopes: Vec<Scope>,
+}
+
+/// Request to register a webhook
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+}
+
+/// Request to change a webhook
+#[derive(Debug, Deserialize)]
+pub struct UpdateWebhookRequest {
+    pub url: Option<String>,
+    pub events: Option<Vec<WebhookEvent>>,
+    pub active: Option<bool>,
+}
+
+/// Query parameters for listing webhook deliveries
+#[derive(Debug, Deserialize)]
+pub struct DeliveryQuery {
+    /// Only list deliveries in this state, e.g. `dead` for the dead letters
+    pub status: Option<DeliveryStatus>,
+}
+
+/// Query parameters for reading the change feed
+#[derive(Debug, Deserialize)]
+pub struct FeedQuery {
+    /// Only return events after this one
+    pub after: Option<i64>,
+}
+
+/// Query parameters for exporting todos
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    pub format: Option<ExportFormat>,
+}
+
+/// Query parameters for importing todos
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+    pub format: Option<ExportFormat>,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Request to parse a natural-language quick-add line
+#[derive(Debug, Deserialize)]
+pub struct QuickAddRequest {
+    pub text: String,
+    /// IANA time zone relative dates are resolved in, the user's own if not given
+    pub timezone: Option<String>,
+}
+
+/// Per-user settings
+#[derive(Debug, Serialize)]
+pub struct UserSettings {
+    /// IANA time zone used to work out which day todos are due on
+    pub timezone: String,
+    /// Name other users can invite this user to shared lists by
+    pub username: Option<String>,
+    /// Email address other users can invite this user to shared lists by
+    pub email: Option<String>,
+}
+
+/// Request to update a user's settings
+#[derive(Debug, Deserialize)]
+pub struct UpdateSettingsRequest {
+    pub timezone: Option<String>,
+    pub username: Option<String>,
+    pub email: Option<String>,
+}
+
+/// What a member of a shared list may do. Roles are ordered, each allowing
+/// everything the ones before it do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum ListRole {
+    /// Can see the list's todos
+    Viewer,
+    /// Can also create, change and delete todos in the list
+    Editor,
+    /// Can also rename and delete the list and manage its members
+    Owner,
+}
+
+/// A shared list, as seen by one of its members
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SharedList {
+    pub id: String,
+    pub name: String,
+    /// The role of the user the list was fetched for
+    pub role: ListRole,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A member of a shared list
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ListMember {
+    pub user_id: String,
+    pub role: ListRole,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Whether an invitation has been answered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum InvitationStatus {
+    Pending,
+    Accepted,
+    Declined,
+}
+
+/// An invitation to join a shared list
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ListInvitation {
+    pub id: String,
+    pub list_id: String,
+    pub list_name: String,
+    /// User id, username or email address the invitation was sent to
+    pub invitee: String,
+    /// Role the invitee gets on accepting
+    pub role: ListRole,
+    pub invited_by: String,
+    pub status: InvitationStatus,
+    pub created_at: DateTime<Utc>,
+    pub responded_at: Option<DateTime<Utc>>,
+}
+
+/// Request to create a shared list
+#[derive(Debug, Deserialize)]
+pub struct CreateListRequest {
+    pub id: Option<String>,
+    pub name: String,
+}
+
+/// Request to rename a shared list
+#[derive(Debug, Deserialize)]
+pub struct UpdateListRequest {
+    pub name: String,
+}
+
+/// Request to change a member's role
+#[derive(Debug, Deserialize)]
+pub struct UpdateMemberRequest {
+    pub role: ListRole,
+}
+
+/// Request to invite someone to a shared list
+#[derive(Debug, Deserialize)]
+pub struct InviteRequest {
+    /// User id, username or email address
+    pub invitee: String,
+    /// Role to grant, editor if not given
+    pub role: Option<ListRole>,
+}
+
+/// Query parameters for the calendar feed
+#[derive(Debug, Deserialize)]
+pub struct CalendarFeedQuery {
+    pub token: Option<String>,
+}
+
+/// A newly issued calendar feed token. The token is only ever shown once.
+#[derive(Debug, Serialize)]
+pub struct CalendarFeedToken {
+    pub token: String,
+    pub feed_path: String,
+}
+
 /// API response wrapper
 #[derive(Debug, Serialize)]
 pub struct ApiResponse<T> {
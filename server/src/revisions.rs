@@ -0,0 +1,134 @@
+//! Recording and reading the revision history of todos.
+//!
+//! Every handler that changes a todo records a revision with the user who made
+//! the change and, when the client sends an `X-Device-Id` header, the device.
+
+use std::convert::Infallible;
+
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::types::Json;
+use sqlx::SqliteConnection;
+use todo_shared::revisions;
+
+use crate::db::DbPool;
+use crate::shutdown::Shutdown;
+use crate::models::Todo;
+use crate::webhooks;
+
+pub use todo_shared::revisions::{FieldChange, Revision};
+
+/// Header clients identify their device with
+pub const DEVICE_ID_HEADER: &str = "x-device-id";
+
+/// The device a request came from, if the client said
+#[derive(Debug, Clone, Default)]
+pub struct DeviceId(pub Option<String>);
+
+impl<S: Send + Sync> FromRequestParts<S> for DeviceId {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let device_id = parts
+            .headers
+            .get(DEVICE_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|value| !value.is_empty())
+            .map(str::to_string);
+        Ok(DeviceId(device_id))
+    }
+}
+
+/// Record a change to a todo, given its state before the change, and queue
+/// the webhook deliveries it raises if `raise_webhooks` is set. The state
+/// after is read back from the database, so call this once the change is
+/// written. Nothing is recorded if no field changed.
+pub async fn record(
+    conn: &mut SqliteConnection,
+    todo_id: &str,
+    before: Option<&Todo>,
+    actor_id: &str,
+    device: &DeviceId,
+    action: &str,
+    raise_webhooks: bool,
+) -> Result<(), sqlx::Error> {
+    let after: Option<Todo> = sqlx::query_as("SELECT * FROM todos WHERE id = ?")
+        .bind(todo_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+    let changes = revisions::diff(before, after.as_ref());
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let snapshot = after
+        .as_ref()
+        .map(|todo| serde_json::to_value(todo).map(Json))
+        .transpose()
+        .map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO todo_revisions (todo_id, actor_id, device_id, action, changes, snapshot, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(todo_id)
+    .bind(actor_id)
+    .bind(&device.0)
+    .bind(action)
+    .bind(Json(changes))
+    .bind(snapshot)
+    .bind(Utc::now())
+    .execute(&mut *conn)
+    .await?;
+
+    if raise_webhooks {
+        webhooks::enqueue(conn, before, after.as_ref(), actor_id).await?;
+    }
+    Ok(())
+}
+
+/// The revisions of a todo, newest first
+pub async fn history(pool: &DbPool, todo_id: &str) -> Result<Vec<Revision>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM todo_revisions WHERE todo_id = ? ORDER BY id DESC")
+        .bind(todo_id)
+        .fetch_all(pool)
+        .await
+}
+
+/// A revision of a todo by id
+pub async fn find(pool: &DbPool, todo_id: &str, revision_id: i64) -> Result<Option<Revision>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM todo_revisions WHERE id = ? AND todo_id = ?")
+        .bind(revision_id)
+        .bind(todo_id)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Delete revisions recorded before `cutoff`
+pub async fn purge(pool: &DbPool, cutoff: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM todo_revisions WHERE created_at < ?")
+        .bind(cutoff)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// Purge revisions once an hour, dropping those older than `retention`,
+/// until shutdown
+pub async fn purge_periodically(pool: DbPool, retention: Duration, shutdown: Shutdown) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.triggered() => return,
+        }
+        match purge(&pool, Utc::now() - retention).await {
+            Ok(0) => {}
+            Ok(purged) => tracing::info!("Purged {} todo revisions", purged),
+            Err(e) => tracing::error!("Failed to purge todo revisions: {}", e),
+        }
+    }
+}
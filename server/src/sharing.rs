@@ -0,0 +1,105 @@
+//! Access control for shared lists.
+//!
+//! A todo outside any shared list belongs to the user who created it. A todo in
+//! a shared list belongs to the list instead: every member can see it, and
+//! editors and owners can change it.
+
+use axum::http::StatusCode;
+
+use crate::db::DbPool;
+use crate::models::{ListRole, Todo};
+
+/// SQL condition matching the todos a user can see. Bind the user id twice.
+pub const VISIBLE_TODOS: &str =
+    "((list_id IS NULL AND user_id = ?) OR list_id IN (SELECT list_id FROM list_members WHERE user_id = ?))";
+
+/// SQL condition matching the todos a user can change. Bind the user id twice.
+pub const EDITABLE_TODOS: &str = "((list_id IS NULL AND user_id = ?) OR list_id IN (SELECT list_id FROM list_members WHERE user_id = ? AND role IN ('editor', 'owner')))";
+
+/// A user's role in a shared list, if they are a member
+pub async fn list_role(pool: &DbPool, list_id: &str, user_id: &str) -> Result<Option<ListRole>, sqlx::Error> {
+    let role: Option<(ListRole,)> = sqlx::query_as("SELECT role FROM list_members WHERE list_id = ? AND user_id = ?")
+        .bind(list_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(role.map(|(role,)| role))
+}
+
+/// Check that a user has at least the `required` role in a list.
+/// Non-members get 404 rather than 403, so that list ids are not confirmed to outsiders.
+pub async fn require_role(
+    pool: &DbPool,
+    list_id: &str,
+    user_id: &str,
+    required: ListRole,
+) -> Result<ListRole, StatusCode> {
+    let role = list_role(pool, list_id, user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if role < required {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(role)
+}
+
+/// A user's role on a todo: owner of their own personal todos, and their list role otherwise
+pub async fn todo_role(pool: &DbPool, todo: &Todo, user_id: &str) -> Result<Option<ListRole>, sqlx::Error> {
+    match &todo.list_id {
+        Some(list_id) => list_role(pool, list_id, user_id).await,
+        None if todo.user_id == user_id => Ok(Some(ListRole::Owner)),
+        None => Ok(None),
+    }
+}
+
+/// Whether a user may put todos in `list_id`, or outside any list when `None`
+pub async fn can_write_to(pool: &DbPool, list_id: Option<&str>, user_id: &str) -> Result<bool, sqlx::Error> {
+    match list_id {
+        Some(list_id) => Ok(list_role(pool, list_id, user_id).await? >= Some(ListRole::Editor)),
+        None => Ok(true),
+    }
+}
+
+/// Whether a todo can be assigned to `assignee_id`: any member of its list,
+/// or only the user themselves for a personal todo
+pub async fn can_assign(
+    pool: &DbPool,
+    list_id: Option<&str>,
+    user_id: &str,
+    assignee_id: Option<&str>,
+) -> Result<bool, sqlx::Error> {
+    match (list_id, assignee_id) {
+        (_, None) => Ok(true),
+        (Some(list_id), Some(assignee_id)) => Ok(list_role(pool, list_id, assignee_id).await?.is_some()),
+        (None, Some(assignee_id)) => Ok(assignee_id == user_id),
+    }
+}
+
+/// Ids, usernames and email addresses invitations to a user may have been sent to
+pub async fn invitee_names(pool: &DbPool, user_id: &str) -> Result<Vec<String>, sqlx::Error> {
+    let profile: Option<(Option<String>, Option<String>)> =
+        sqlx::query_as("SELECT username, email FROM user_settings WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?;
+
+    let mut names = vec![user_id.to_string()];
+    if let Some((username, email)) = profile {
+        names.extend(username);
+        names.extend(email);
+    }
+    Ok(names)
+}
+
+/// Normalize an invitee so that email addresses match whatever their case
+pub fn normalize_invitee(invitee: &str) -> String {
+    let invitee = invitee.trim();
+    if invitee.contains('@') {
+        invitee.to_lowercase()
+    } else {
+        invitee.to_string()
+    }
+}
@@ -0,0 +1,124 @@
+//! Graceful shutdown.
+//!
+//! On SIGINT or SIGTERM the server stops accepting connections and gives the
+//! requests in flight time to finish. Background tasks watch the same
+//! `Shutdown` handle: they finish the work at hand, the webhook dispatcher
+//! makes a last pass over its queue, and then they return so the database
+//! pool can be closed.
+
+use std::future::IntoFuture;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::connect_info::Connected;
+use axum::serve::{IncomingStream, Listener};
+use axum::Router;
+use tokio::sync::watch;
+
+/// Tells tasks that the server is shutting down
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self {
+            sender: Arc::new(watch::channel(false).0),
+        }
+    }
+
+    /// Start shutting down
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Wait until shutdown has been triggered
+    pub async fn triggered(&self) {
+        let mut receiver = self.sender.subscribe();
+        // The sender lives as long as `self`, so this cannot fail
+        let _ = receiver.wait_for(|triggered| *triggered).await;
+    }
+
+    /// Trigger shutdown on SIGINT or SIGTERM
+    pub async fn trigger_on_signal(self) {
+        tokio::select! {
+            _ = signal() => {
+                tracing::info!("Shutting down");
+                self.trigger();
+            }
+            _ = self.triggered() => {}
+        }
+    }
+}
+
+/// Wait for SIGINT (Ctrl-C) or, on Unix, SIGTERM
+pub async fn signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
+
+/// Serve `app` on `listener` until shutdown is triggered, then stop accepting
+/// connections and wait up to `drain_timeout` for open ones to finish.
+/// Returns after that even if some are still open; they are dropped with the
+/// runtime when the process exits.
+pub async fn serve<L>(listener: L, app: Router, shutdown: Shutdown, drain_timeout: Duration) -> io::Result<()>
+where
+    L: Listener,
+    L::Addr: std::fmt::Debug,
+    SocketAddr: for<'a> Connected<IncomingStream<'a, L>>,
+{
+    let service = app.into_make_service_with_connect_info::<SocketAddr>();
+    let signal = shutdown.clone();
+    let server = axum::serve(listener, service)
+        .with_graceful_shutdown(async move { signal.triggered().await })
+        .into_future();
+
+    let deadline = async {
+        shutdown.triggered().await;
+        tokio::time::sleep(drain_timeout).await;
+    };
+    tokio::select! {
+        result = server => result,
+        _ = deadline => {
+            tracing::warn!("Connections still open after {:?}, giving up on them", drain_timeout);
+            Ok(())
+        }
+    }
+}
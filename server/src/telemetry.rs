@@ -0,0 +1,80 @@
+//! OpenTelemetry trace export, built with the `otel` feature.
+//!
+//! Spans recorded through `tracing` are exported over OTLP/HTTP in batches
+//! from a thread of their own. A request carrying a W3C `traceparent` header
+//! continues the client's trace, so a sync shows up end to end: the app's
+//! span, the server's request span and what was logged while handling it.
+
+use axum::extract::{MatchedPath, Request};
+use axum::http::HeaderMap;
+use opentelemetry::propagation::{Extractor, TextMapPropagator};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{ExporterBuildError, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
+use tracing::Span;
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::config::TelemetryConfig;
+
+/// A provider exporting to the configured collector, if there is one
+pub fn tracer_provider(config: &TelemetryConfig) -> Result<Option<SdkTracerProvider>, ExporterBuildError> {
+    let Some(endpoint) = &config.otlp_endpoint else {
+        return Ok(None);
+    };
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+        .build()?;
+    Ok(Some(
+        SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(resource(config))
+            .build(),
+    ))
+}
+
+/// The attributes every exported span carries
+pub fn resource(config: &TelemetryConfig) -> Resource {
+    Resource::builder()
+        .with_service_name(config.service_name.clone())
+        .with_attribute(opentelemetry::KeyValue::new("service.version", env!("CARGO_PKG_VERSION")))
+        .build()
+}
+
+/// A layer sending `tracing` spans to `provider`
+pub fn layer<S>(provider: &SdkTracerProvider) -> OpenTelemetryLayer<S, SdkTracer>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer("todo-server"))
+}
+
+/// Name `span` after `request`'s route and continue the trace named by its
+/// `traceparent` header, if any
+pub fn continue_trace(span: &Span, request: &Request) {
+    let route = request.extensions().get::<MatchedPath>().map_or("unmatched", MatchedPath::as_str);
+    span.record("otel.name", format!("{} {}", request.method(), route));
+    span.record("otel.kind", "server");
+
+    if !request.headers().contains_key("traceparent") {
+        return;
+    }
+    let parent = TraceContextPropagator::new().extract(&HeaderExtractor(request.headers()));
+    // Fails only when no OpenTelemetry layer is installed, which is fine
+    let _ = span.set_parent(parent);
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
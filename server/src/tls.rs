@@ -0,0 +1,226 @@
+//! HTTPS serving with rustls.
+//!
+//! The certificate and key are read from PEM files and checked for changes
+//! periodically, so a renewed certificate is picked up without a restart;
+//! connections already open keep the certificate they started with. For
+//! development a self-signed certificate can be generated, which clients
+//! trust by pinning its fingerprint.
+
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use axum::serve::Listener;
+use rustls::crypto::ring;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use todo_shared::pinning;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+use crate::config::TlsConfig;
+use crate::shutdown::Shutdown;
+
+/// How long a client gets to finish the TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The certificate currently served, swapped on reload
+#[derive(Debug)]
+struct CertResolver {
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+/// A certificate and key loaded from files
+pub struct Tls {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    resolver: Arc<CertResolver>,
+    /// The file contents last loaded, to notice changes
+    loaded: Mutex<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Tls {
+    /// Load the configured certificate, first generating a self-signed one if
+    /// that is enabled and there is none yet
+    pub fn load(config: &TlsConfig) -> io::Result<Arc<Self>> {
+        let (Some(cert_path), Some(key_path)) = (&config.cert_path, &config.key_path) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "TLS needs a certificate and a key"));
+        };
+        if config.self_signed && !cert_path.exists() && !key_path.exists() {
+            generate_self_signed(&config.self_signed_names, cert_path, key_path)?;
+            tracing::info!("Generated a self-signed certificate at {}", cert_path.display());
+        }
+
+        let cert_pem = read(cert_path)?;
+        let key_pem = read(key_path)?;
+        let key = certified_key(&cert_pem, &key_pem)?;
+        Ok(Arc::new(Self {
+            cert_path: cert_path.clone(),
+            key_path: key_path.clone(),
+            resolver: Arc::new(CertResolver {
+                current: RwLock::new(Arc::new(key)),
+            }),
+            loaded: Mutex::new((cert_pem, key_pem)),
+        }))
+    }
+
+    /// SHA-256 fingerprint of the certificate being served, for clients to pin
+    pub fn fingerprint(&self) -> String {
+        let key = self.resolver.current.read().unwrap().clone();
+        pinning::fingerprint(key.end_entity_cert().map(|cert| cert.as_ref()).unwrap_or_default())
+    }
+
+    /// Load the files again if they changed. Returns whether a new
+    /// certificate is served; on error the old one stays in use.
+    pub fn reload(&self) -> io::Result<bool> {
+        let cert_pem = read(&self.cert_path)?;
+        let key_pem = read(&self.key_path)?;
+        let mut loaded = self.loaded.lock().unwrap();
+        if loaded.0 == cert_pem && loaded.1 == key_pem {
+            return Ok(false);
+        }
+
+        let key = certified_key(&cert_pem, &key_pem)?;
+        *self.resolver.current.write().unwrap() = Arc::new(key);
+        *loaded = (cert_pem, key_pem);
+        Ok(true)
+    }
+
+    /// Check for a changed certificate every `interval` until shutdown
+    pub async fn reload_periodically(self: Arc<Self>, interval: Duration, shutdown: Shutdown) {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.triggered() => return,
+            }
+            match self.reload() {
+                Ok(true) => tracing::info!("Reloaded TLS certificate, fingerprint {}", self.fingerprint()),
+                Ok(false) => {}
+                Err(e) => tracing::error!("Failed to reload TLS certificate: {}", e),
+            }
+        }
+    }
+
+    /// An acceptor serving the current certificate over HTTP/2 or HTTP/1.1
+    pub fn acceptor(&self) -> TlsAcceptor {
+        let mut config = rustls::ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .expect("the ring provider supports the default protocol versions")
+            .with_no_client_auth()
+            .with_cert_resolver(self.resolver.clone());
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        TlsAcceptor::from(Arc::new(config))
+    }
+}
+
+/// Write a new self-signed certificate for `names` and its key as PEM files
+pub fn generate_self_signed(names: &[String], cert_path: &Path, key_path: &Path) -> io::Result<()> {
+    let generated = rcgen::generate_simple_self_signed(names.to_vec())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    for path in [cert_path, key_path] {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+    std::fs::write(cert_path, generated.cert.pem())?;
+    std::fs::write(key_path, generated.key_pair.serialize_pem())?;
+    Ok(())
+}
+
+fn read(path: &Path) -> io::Result<Vec<u8>> {
+    std::fs::read(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
+
+fn certified_key(cert_pem: &[u8], key_pem: &[u8]) -> io::Result<CertifiedKey> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+    let certs = CertificateDer::pem_slice_iter(cert_pem)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid(format!("invalid certificate: {}", e)))?;
+    if certs.is_empty() {
+        return Err(invalid("no certificate found".to_string()));
+    }
+    let key = PrivateKeyDer::from_pem_slice(key_pem).map_err(|e| invalid(format!("invalid private key: {}", e)))?;
+    let signing_key = ring::sign::any_supported_type(&key).map_err(|e| invalid(format!("unusable private key: {}", e)))?;
+
+    let certified = CertifiedKey::new(certs, signing_key);
+    certified
+        .keys_match()
+        .map_err(|e| invalid(format!("certificate does not match the key: {}", e)))?;
+    Ok(certified)
+}
+
+/// Accepts TLS connections for `axum::serve`. Handshakes run in their own
+/// tasks, so a slow client cannot hold up other connections.
+pub struct TlsListener {
+    incoming: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    local_addr: SocketAddr,
+}
+
+impl TlsListener {
+    pub fn new(listener: TcpListener, acceptor: TlsAcceptor) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let (sender, incoming) = mpsc::channel(64);
+        tokio::spawn(async move {
+            loop {
+                // Stop listening as soon as the server no longer takes connections
+                let accepted = tokio::select! {
+                    accepted = listener.accept() => accepted,
+                    _ = sender.closed() => break,
+                };
+                let (stream, addr) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        tracing::error!("Failed to accept connection: {}", e);
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                };
+
+                let acceptor = acceptor.clone();
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = sender.send((stream, addr)).await;
+                        }
+                        Ok(Err(e)) => tracing::debug!("TLS handshake with {} failed: {}", addr, e),
+                        Err(_) => tracing::debug!("TLS handshake with {} timed out", addr),
+                    }
+                });
+            }
+        });
+        Ok(Self { incoming, local_addr })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.incoming.recv().await {
+            Some(accepted) => accepted,
+            // The accept loop only stops once this listener is dropped
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
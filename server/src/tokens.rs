@@ -0,0 +1,12 @@
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Generate a random secret token
+pub fn generate_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// Hash a token for storage, so that a leaked database does not leak usable tokens
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,511 @@
+//! Outgoing webhooks: HTTP callbacks to URLs users register, for events on
+//! the todos they can see.
+//!
+//! While the webhooks feature is on, events are queued in the
+//! `webhook_deliveries` table in the same transaction as the change, and a
+//! `Dispatcher` delivers them. Each payload is signed with the webhook's
+//! secret: `X-Webhook-Signature` is `sha256=` followed by the hex HMAC-SHA256
+//! of `{timestamp}.{body}`, where the timestamp is sent in
+//! `X-Webhook-Timestamp`. Failed deliveries are
+//! retried with exponential backoff and, once the retries run out, kept as
+//! dead letters until redelivered.
+//!
+//! Unless `RetryPolicy::allow_private_targets` is set, deliveries only go to
+//! public addresses: a URL whose host is, or resolves to, a loopback, private
+//! or link-local address is refused when it is registered and again at every
+//! attempt. Redirects are never followed.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::types::Json;
+use sqlx::{FromRow, SqliteConnection};
+
+use crate::db::DbPool;
+use crate::models::Todo;
+use crate::shutdown::Shutdown;
+
+/// Header carrying the payload signature
+pub const SIGNATURE_HEADER: &str = "x-webhook-signature";
+
+/// Header carrying the signing timestamp, in seconds since the epoch
+pub const TIMESTAMP_HEADER: &str = "x-webhook-timestamp";
+
+/// Header naming the event
+pub const EVENT_HEADER: &str = "x-webhook-event";
+
+/// Header carrying the delivery id, which stays the same across retries
+pub const DELIVERY_HEADER: &str = "x-webhook-delivery";
+
+/// Events a webhook can subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "TEXT")]
+pub enum WebhookEvent {
+    #[serde(rename = "todo.created")]
+    #[sqlx(rename = "todo.created")]
+    TodoCreated,
+    #[serde(rename = "todo.updated")]
+    #[sqlx(rename = "todo.updated")]
+    TodoUpdated,
+    #[serde(rename = "todo.completed")]
+    #[sqlx(rename = "todo.completed")]
+    TodoCompleted,
+    #[serde(rename = "todo.deleted")]
+    #[sqlx(rename = "todo.deleted")]
+    TodoDeleted,
+    #[serde(rename = "todo.restored")]
+    #[sqlx(rename = "todo.restored")]
+    TodoRestored,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::TodoCreated => "todo.created",
+            WebhookEvent::TodoUpdated => "todo.updated",
+            WebhookEvent::TodoCompleted => "todo.completed",
+            WebhookEvent::TodoDeleted => "todo.deleted",
+            WebhookEvent::TodoRestored => "todo.restored",
+        }
+    }
+}
+
+/// A registered webhook. The secret is only shown when the webhook is created.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Webhook {
+    pub id: String,
+    pub user_id: String,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// Events the webhook is sent
+    pub events: Json<Vec<WebhookEvent>>,
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Where a delivery stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Waiting for its first or next attempt
+    Pending,
+    Delivered,
+    /// Every attempt failed; waiting to be redelivered by hand
+    Dead,
+}
+
+/// One event queued for one webhook
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Delivery {
+    pub id: String,
+    pub webhook_id: String,
+    pub event: WebhookEvent,
+    pub payload: Json<serde_json::Value>,
+    pub status: DeliveryStatus,
+    pub attempts: i64,
+    pub next_attempt_at: DateTime<Utc>,
+    /// HTTP status of the last attempt, if the receiver answered
+    pub last_status: Option<i64>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+/// The events a change to a todo raises. `None` means the todo did not exist
+/// before, or no longer exists after.
+pub fn events_for(before: Option<&Todo>, after: Option<&Todo>) -> Vec<WebhookEvent> {
+    match (before, after) {
+        (None, Some(after)) if after.deleted_at.is_none() => vec![WebhookEvent::TodoCreated],
+        (Some(before), None) if before.deleted_at.is_none() => vec![WebhookEvent::TodoDeleted],
+        (Some(before), Some(after)) => match (before.deleted_at, after.deleted_at) {
+            (None, Some(_)) => vec![WebhookEvent::TodoDeleted],
+            (Some(_), None) => vec![WebhookEvent::TodoRestored],
+            (Some(_), Some(_)) => Vec::new(),
+            (None, None) if !before.completed && after.completed => {
+                vec![WebhookEvent::TodoUpdated, WebhookEvent::TodoCompleted]
+            }
+            (None, None) => vec![WebhookEvent::TodoUpdated],
+        },
+        _ => Vec::new(),
+    }
+}
+
+/// Queue deliveries of the events a change raises, to every active webhook
+/// subscribed to them whose owner can see the todo
+pub async fn enqueue(
+    conn: &mut SqliteConnection,
+    before: Option<&Todo>,
+    after: Option<&Todo>,
+    actor_id: &str,
+) -> Result<(), sqlx::Error> {
+    let events = events_for(before, after);
+    let Some(todo) = after.or(before) else {
+        return Ok(());
+    };
+    if events.is_empty() {
+        return Ok(());
+    }
+
+    let webhooks: Vec<Webhook> = sqlx::query_as(
+        r#"
+        SELECT * FROM webhooks WHERE active = 1 AND (
+            (? IS NULL AND user_id = ?)
+            OR user_id IN (SELECT user_id FROM list_members WHERE list_id = ?)
+        )
+        "#,
+    )
+    .bind(&todo.list_id)
+    .bind(&todo.user_id)
+    .bind(&todo.list_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let now = Utc::now();
+    for webhook in webhooks {
+        for event in events.iter().filter(|event| webhook.events.contains(event)) {
+            let id = uuid::Uuid::new_v4().to_string();
+            let payload = serde_json::json!({
+                "id": id,
+                "event": event,
+                "created_at": now,
+                "actor_id": actor_id,
+                "todo": todo,
+            });
+            sqlx::query(
+                r#"
+                INSERT INTO webhook_deliveries (id, webhook_id, event, payload, status, attempts, next_attempt_at, created_at)
+                VALUES (?, ?, ?, ?, ?, 0, ?, ?)
+                "#,
+            )
+            .bind(&id)
+            .bind(&webhook.id)
+            .bind(event)
+            .bind(Json(payload))
+            .bind(DeliveryStatus::Pending)
+            .bind(now)
+            .bind(now)
+            .execute(&mut *conn)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// The `X-Webhook-Signature` value for a payload sent at `timestamp`
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// When and how often failed deliveries are retried
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Attempts made before a delivery becomes a dead letter
+    pub max_attempts: i64,
+    /// Wait after the first failure; doubled after each further failure
+    pub base_delay: Duration,
+    /// Longest wait between attempts
+    pub max_delay: Duration,
+    /// How often the queue is checked for due deliveries
+    pub poll_interval: StdDuration,
+    /// How long a receiver has to answer
+    pub timeout: StdDuration,
+    /// Deliver to loopback, private and link-local addresses too
+    pub allow_private_targets: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 8,
+            base_delay: Duration::seconds(30),
+            max_delay: Duration::hours(6),
+            poll_interval: StdDuration::from_secs(1),
+            timeout: StdDuration::from_secs(10),
+            allow_private_targets: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The wait after `attempts` failed attempts
+    pub fn delay(&self, attempts: i64) -> Duration {
+        let exponent = (attempts - 1).clamp(0, 30) as u32;
+        let delay = self.base_delay * 2_i32.saturating_pow(exponent);
+        delay.min(self.max_delay)
+    }
+}
+
+/// Whether webhooks may be delivered to `ip`, an address reachable on the
+/// internet rather than one of the server's own or its network's
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || first == 0
+                || first >= 240
+                // Shared address space used by carrier-grade NAT
+                || (first == 100 && (64..128).contains(&second)))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ip(IpAddr::V4(ip)),
+            None => !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || ip.is_unique_local()
+                || ip.is_unicast_link_local()),
+        },
+    }
+}
+
+/// Check that webhooks may be delivered to `url`: an http or https URL whose
+/// host is not, and does not resolve to, an address that is not public. Host
+/// names that do not resolve yet are let through; every delivery checks again.
+pub async fn check_target(url: &str, allow_private: bool) -> Result<(), String> {
+    let url = reqwest::Url::parse(url).map_err(|e| e.to_string())?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("Webhooks must use http or https, not {}", url.scheme()));
+    }
+    let host = url.host_str().ok_or("Webhook URLs need a host")?;
+    if allow_private {
+        return Ok(());
+    }
+    if let Some(ip) = literal_ip(host) {
+        return check_ip(ip);
+    }
+    let port = url.port_or_known_default().unwrap_or_default();
+    if let Ok(addrs) = tokio::net::lookup_host((host, port)).await {
+        for addr in addrs {
+            check_ip(addr.ip())?;
+        }
+    }
+    Ok(())
+}
+
+/// The address a URL host names directly, if it is an IP address
+fn literal_ip(host: &str) -> Option<IpAddr> {
+    host.trim_start_matches('[').trim_end_matches(']').parse().ok()
+}
+
+fn check_ip(ip: IpAddr) -> Result<(), String> {
+    if is_public_ip(ip) {
+        Ok(())
+    } else {
+        Err(format!("Webhooks cannot be delivered to {}", ip))
+    }
+}
+
+/// Resolves receivers' host names, failing for names with any address that
+/// is not public. The check happens as the connection is made, so a name
+/// cannot be pointed elsewhere between checking and connecting.
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            for addr in &addrs {
+                check_ip(addr.ip())?;
+            }
+            let addrs: reqwest::dns::Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// Delete deliveries that were delivered or given up on, queued before `cutoff`
+pub async fn purge(pool: &DbPool, cutoff: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM webhook_deliveries WHERE status != ? AND created_at < ?")
+        .bind(DeliveryStatus::Pending)
+        .bind(cutoff)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// Purge finished deliveries once an hour, dropping those older than
+/// `retention`, until shutdown
+pub async fn purge_periodically(pool: DbPool, retention: Duration, shutdown: Shutdown) {
+    let mut interval = tokio::time::interval(StdDuration::from_secs(60 * 60));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.triggered() => return,
+        }
+        match purge(&pool, Utc::now() - retention).await {
+            Ok(0) => {}
+            Ok(purged) => tracing::info!("Purged {} webhook deliveries", purged),
+            Err(e) => tracing::error!("Failed to purge webhook deliveries: {}", e),
+        }
+    }
+}
+
+/// Delivers queued webhook events
+pub struct Dispatcher {
+    pool: DbPool,
+    client: reqwest::Client,
+    policy: RetryPolicy,
+}
+
+impl Dispatcher {
+    pub fn new(pool: DbPool, policy: RetryPolicy) -> Self {
+        // A redirect could lead anywhere, past the address checks
+        let mut client = reqwest::Client::builder()
+            .timeout(policy.timeout)
+            .redirect(reqwest::redirect::Policy::none());
+        if !policy.allow_private_targets {
+            client = client.dns_resolver(Arc::new(PublicResolver));
+        }
+        let client = client.build().expect("failed to build webhook HTTP client");
+        Self { pool, client, policy }
+    }
+
+    /// Deliver due events until shutdown, then make a last pass over the
+    /// queue so events from the final requests are not held back
+    pub async fn run(self, shutdown: Shutdown) {
+        let mut interval = tokio::time::interval(self.policy.poll_interval);
+        loop {
+            let stopping = tokio::select! {
+                _ = interval.tick() => false,
+                _ = shutdown.triggered() => true,
+            };
+            if let Err(e) = self.run_once().await {
+                tracing::error!("Failed to deliver webhooks: {}", e);
+            }
+            if stopping {
+                return;
+            }
+        }
+    }
+
+    /// Attempt every delivery that is due, returning how many were attempted
+    pub async fn run_once(&self) -> Result<usize, sqlx::Error> {
+        let due: Vec<Delivery> = sqlx::query_as(
+            "SELECT * FROM webhook_deliveries WHERE status = ? AND next_attempt_at <= ? ORDER BY next_attempt_at LIMIT 50",
+        )
+        .bind(DeliveryStatus::Pending)
+        .bind(Utc::now())
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut attempted = 0;
+        for delivery in due {
+            let webhook: Option<(String, String, bool)> =
+                sqlx::query_as("SELECT url, secret, active FROM webhooks WHERE id = ?")
+                    .bind(&delivery.webhook_id)
+                    .fetch_optional(&self.pool)
+                    .await?;
+            let (url, secret) = match webhook {
+                Some((url, secret, true)) => (url, secret),
+                // Paused webhooks get nothing; their deliveries are kept as
+                // dead letters, so they can be redelivered once it resumes
+                Some((_, _, false)) => {
+                    self.retire(&delivery).await?;
+                    continue;
+                }
+                None => {
+                    sqlx::query("DELETE FROM webhook_deliveries WHERE id = ?")
+                        .bind(&delivery.id)
+                        .execute(&self.pool)
+                        .await?;
+                    continue;
+                }
+            };
+            let outcome = self.attempt(&delivery, &url, &secret).await;
+            self.finish(&delivery, outcome).await?;
+            attempted += 1;
+        }
+        Ok(attempted)
+    }
+
+    /// Send one delivery, returning the response status if the receiver answered
+    async fn attempt(&self, delivery: &Delivery, url: &str, secret: &str) -> Result<u16, String> {
+        // Addresses written in the URL itself never reach the resolver
+        if !self.policy.allow_private_targets {
+            let host = reqwest::Url::parse(url).map_err(|e| e.to_string())?.host_str().and_then(literal_ip);
+            if let Some(ip) = host {
+                check_ip(ip)?;
+            }
+        }
+        let body = serde_json::to_vec(&delivery.payload.0).map_err(|e| e.to_string())?;
+        let timestamp = Utc::now().timestamp();
+        let response = self
+            .client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, sign(secret, timestamp, &body))
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(EVENT_HEADER, delivery.event.as_str())
+            .header(DELIVERY_HEADER, &delivery.id)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(response.status().as_u16())
+    }
+
+    /// Give up on a delivery without attempting it, because its webhook is paused
+    async fn retire(&self, delivery: &Delivery) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE webhook_deliveries SET status = ?, last_error = ? WHERE id = ?")
+            .bind(DeliveryStatus::Dead)
+            .bind("webhook is paused")
+            .bind(&delivery.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn finish(&self, delivery: &Delivery, outcome: Result<u16, String>) -> Result<(), sqlx::Error> {
+        let now = Utc::now();
+        let attempts = delivery.attempts + 1;
+        let (last_status, last_error) = match &outcome {
+            Ok(status) => (Some(i64::from(*status)), None),
+            Err(e) => (None, Some(e.clone())),
+        };
+
+        let delivered = matches!(outcome, Ok(status) if (200..300).contains(&status));
+        let status = if delivered {
+            DeliveryStatus::Delivered
+        } else if attempts >= self.policy.max_attempts {
+            tracing::warn!("Webhook delivery {} failed {} times, giving up", delivery.id, attempts);
+            DeliveryStatus::Dead
+        } else {
+            DeliveryStatus::Pending
+        };
+
+        sqlx::query(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = ?, attempts = ?, next_attempt_at = ?, last_status = ?, last_error = ?, delivered_at = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(status)
+        .bind(attempts)
+        .bind(now + self.policy.delay(attempts))
+        .bind(last_status)
+        .bind(last_error)
+        .bind(delivered.then_some(now))
+        .bind(&delivery.id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
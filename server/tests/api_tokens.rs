@@ -0,0 +1,155 @@
+mod common;
+
+use common::{send, send_json, spawn_server, spawn_server_with_config, TestServer};
+use serde_json::{json, Value};
+use todo_server::config::{ApiTokenConfig, Config};
+
+/// Send a JSON request authenticated with an API token
+async fn send_with_token(server: &TestServer, token: &str, method: &str, path: &str, body: Value) -> (u16, Value) {
+    let authorization = format!("Bearer {}", token);
+    let body = if body.is_null() { String::new() } else { body.to_string() };
+    let response = send(
+        server,
+        method,
+        path,
+        &[("Content-Type", "application/json"), ("Authorization", &authorization)],
+        &body,
+    )
+    .await;
+    let json = serde_json::from_str(&response.body).unwrap_or(Value::Null);
+    (response.status, json)
+}
+
+async fn create_token(server: &TestServer, user: &str, scopes: Value) -> (String, String) {
+    let (status, created) = send_json(
+        server,
+        "POST",
+        &format!("/api/users/{}/tokens", user),
+        json!({"name": "backup script", "scopes": scopes}),
+    )
+    .await;
+    assert_eq!(status, 200);
+    (
+        created["data"]["id"].as_str().unwrap().to_string(),
+        created["data"]["token"].as_str().unwrap().to_string(),
+    )
+}
+
+#[tokio::test]
+async fn scopes_limit_what_a_token_can_do() {
+    let server = spawn_server().await;
+    let (_, reader) = create_token(&server, "alice", json!(["todos:read"])).await;
+    assert!(reader.starts_with("tdo_"));
+
+    let (status, _) = send_with_token(&server, &reader, "GET", "/api/users/alice/todos", Value::Null).await;
+    assert_eq!(status, 200);
+    let (status, body) = send_with_token(&server, &reader, "POST", "/api/users/alice/todos", json!({"title": "Nope"})).await;
+    assert_eq!(status, 403);
+    assert_eq!(body["error"], "Token is missing the todos:write scope");
+
+    let (_, writer) = create_token(&server, "alice", json!(["todos:write"])).await;
+    let (status, _) = send_with_token(&server, &writer, "POST", "/api/users/alice/todos", json!({"title": "Yes"})).await;
+    assert_eq!(status, 200);
+    let (status, _) = send_with_token(&server, &writer, "POST", "/api/users/alice/lists", json!({"name": "Ops"})).await;
+    assert_eq!(status, 403);
+
+    let (_, admin) = create_token(&server, "alice", json!(["lists:admin"])).await;
+    let (status, _) = send_with_token(&server, &admin, "POST", "/api/users/alice/lists", json!({"name": "Ops"})).await;
+    assert_eq!(status, 200);
+
+    // Tokens cannot be used to manage tokens or webhooks
+    let (status, _) = send_with_token(
+        &server,
+        &admin,
+        "POST",
+        "/api/users/alice/tokens",
+        json!({"name": "more", "scopes": ["todos:write"]}),
+    )
+    .await;
+    assert_eq!(status, 403);
+    let (status, _) = send_with_token(&server, &reader, "GET", "/api/users/alice/webhooks", Value::Null).await;
+    assert_eq!(status, 403);
+
+    // Requests without a token are unaffected
+    let (status, _) = send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Interactive"})).await;
+    assert_eq!(status, 200);
+}
+
+#[tokio::test]
+async fn tokens_only_work_for_their_owner_until_revoked() {
+    let server = spawn_server().await;
+    let (token_id, token) = create_token(&server, "alice", json!(["todos:read", "todos:write"])).await;
+
+    let (status, _) = send_with_token(&server, &token, "GET", "/api/users/bob/todos", Value::Null).await;
+    assert_eq!(status, 403);
+    let (status, _) = send_with_token(&server, "tdo_not-a-token", "GET", "/api/users/alice/todos", Value::Null).await;
+    assert_eq!(status, 401);
+
+    let (_, tokens) = send_json(&server, "GET", "/api/users/alice/tokens", Value::Null).await;
+    assert!(tokens["data"][0]["last_used_at"].is_null());
+    assert!(tokens["data"][0].get("token").is_none());
+
+    let (status, _) = send_with_token(&server, &token, "GET", "/api/users/alice/todos", Value::Null).await;
+    assert_eq!(status, 200);
+    let (_, tokens) = send_json(&server, "GET", "/api/users/alice/tokens", Value::Null).await;
+    assert!(tokens["data"][0]["last_used_at"].is_string());
+    assert_eq!(tokens["data"][0]["scopes"], json!(["todos:read", "todos:write"]));
+
+    let (status, _) = send_json(&server, "DELETE", &format!("/api/users/bob/tokens/{}", token_id), Value::Null).await;
+    assert_eq!(status, 404);
+    let (status, _) = send_json(&server, "DELETE", &format!("/api/users/alice/tokens/{}", token_id), Value::Null).await;
+    assert_eq!(status, 200);
+
+    let response = send(
+        &server,
+        "GET",
+        "/api/users/alice/todos",
+        &[("Authorization", &format!("Bearer {}", token))],
+        "",
+    )
+    .await;
+    assert_eq!(response.status, 401);
+    assert_eq!(response.header("www-authenticate"), Some("Bearer"));
+    let (_, tokens) = send_json(&server, "GET", "/api/users/alice/tokens", Value::Null).await;
+    assert_eq!(tokens["data"], json!([]));
+}
+
+#[tokio::test]
+async fn token_requests_are_validated() {
+    let server = spawn_server().await;
+    let path = "/api/users/alice/tokens";
+    let (status, _) = send_json(&server, "POST", path, json!({"name": "ci", "scopes": []})).await;
+    assert_eq!(status, 400);
+    let (status, _) = send_json(&server, "POST", path, json!({"name": " ", "scopes": ["todos:read"]})).await;
+    assert_eq!(status, 400);
+    let (status, _) = send_json(&server, "POST", path, json!({"name": "ci", "scopes": ["admin"]})).await;
+    assert_eq!(status, 422);
+}
+
+#[tokio::test]
+async fn tokens_can_be_required_once_created() {
+    let server = spawn_server_with_config(Config {
+        api_tokens: ApiTokenConfig { required_once_created: true },
+        ..Config::default()
+    })
+    .await;
+    let (status, _) = send_json(&server, "GET", "/api/users/alice/todos", Value::Null).await;
+    assert_eq!(status, 200);
+
+    let (token_id, token) = create_token(&server, "alice", json!(["todos:read"])).await;
+    let (status, body) = send_json(&server, "GET", "/api/users/alice/todos", Value::Null).await;
+    assert_eq!(status, 401);
+    assert_eq!(body["error"], "This user's requests need an API token");
+    let (status, _) = send_with_token(&server, &token, "GET", "/api/users/alice/todos", Value::Null).await;
+    assert_eq!(status, 200);
+    // Other users and routes tokens cannot use are unaffected
+    let (status, _) = send_json(&server, "GET", "/api/users/bob/todos", Value::Null).await;
+    assert_eq!(status, 200);
+    let (status, _) = send_json(&server, "GET", "/api/users/alice/tokens", Value::Null).await;
+    assert_eq!(status, 200);
+
+    // Revoking the last token lifts the requirement
+    send_json(&server, "DELETE", &format!("/api/users/alice/tokens/{}", token_id), Value::Null).await;
+    let (status, _) = send_json(&server, "GET", "/api/users/alice/todos", Value::Null).await;
+    assert_eq!(status, 200);
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use common::{send, send_json, spawn_server, spawn_server_with, TestServer};
+use serde_json::{json, Value};
+use todo_server::blobs::{self, Blobs, LocalObjectStorage, S3BlobStore};
+use todo_shared::attachments;
+
+async fn create_todo(server: &TestServer, title: &str) -> String {
+    let (_, todo) = send_json(server, "POST", "/api/users/alice/todos", json!({"title": title})).await;
+    todo["data"]["id"].as_str().unwrap().to_string()
+}
+
+async fn upload(server: &TestServer, todo_id: &str, name: &str, contents: &str) -> (u16, Value) {
+    let path = format!("/api/users/alice/todos/{}/attachments?name={}", todo_id, name);
+    let response = send(server, "POST", &path, &[("Content-Type", "text/plain")], contents).await;
+    (response.status, serde_json::from_str(&response.body).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn attachments_upload_download_and_sync() {
+    let server = spawn_server().await;
+    let todo_id = create_todo(&server, "Expenses").await;
+
+    let (status, receipt) = upload(&server, &todo_id, "receipt.txt", "Coffee 3.50").await;
+    assert_eq!(status, 200);
+    assert_eq!(receipt["data"]["name"], "receipt.txt");
+    assert_eq!(receipt["data"]["size"], 11);
+    assert_eq!(receipt["data"]["hash"], attachments::content_hash(b"Coffee 3.50"));
+    let receipt_id = receipt["data"]["id"].as_str().unwrap();
+
+    let path = format!("/api/users/alice/todos/{}/attachments/{}", todo_id, receipt_id);
+    let download = send(&server, "GET", &path, &[], "").await;
+    assert_eq!(download.status, 200);
+    assert_eq!(download.body, "Coffee 3.50");
+    assert_eq!(download.header("content-type"), Some("text/plain"));
+    assert_eq!(download.header("content-disposition"), Some("attachment; filename=\"receipt.txt\""));
+
+    // Metadata reaches other devices on sync; contents are fetched on demand
+    let (_, synced) = send_json(&server, "POST", "/api/users/alice/sync", json!({"todos": []})).await;
+    assert_eq!(synced["data"]["attachments"][0]["id"], receipt_id);
+
+    let (status, _) = send_json(&server, "GET", &format!("/api/users/bob/todos/{}/attachments", todo_id), Value::Null).await;
+    assert_eq!(status, 404);
+    let (status, _) = send_json(&server, "DELETE", &path, Value::Null).await;
+    assert_eq!(status, 200);
+    let (_, listed) = send_json(&server, "GET", &format!("/api/users/alice/todos/{}/attachments", todo_id), Value::Null).await;
+    assert_eq!(listed["data"], json!([]));
+    assert_eq!(send(&server, "GET", &path, &[], "").await.status, 404);
+}
+
+#[tokio::test]
+async fn uploads_are_limited() {
+    let server = spawn_server().await;
+    let todo_id = create_todo(&server, "Scans").await;
+
+    let too_large = "x".repeat(attachments::MAX_SIZE + 1);
+    let (status, _) = upload(&server, &todo_id, "scan.txt", &too_large).await;
+    assert_eq!(status, 413);
+    let (status, _) = upload(&server, &todo_id, "empty.txt", "").await;
+    assert_eq!(status, 400);
+    let (status, _) = upload(&server, &todo_id, "..", "data").await;
+    assert_eq!(status, 400);
+    let (status, _) = upload(&server, "missing", "scan.txt", "data").await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn identical_contents_are_stored_once() {
+    let storage = Arc::new(LocalObjectStorage::default());
+    let blobs: Blobs = Arc::new(S3BlobStore::new(storage.clone(), "attachments", "blobs/"));
+    let server = spawn_server_with(blobs.clone()).await;
+    let first = create_todo(&server, "Flight").await;
+    let second = create_todo(&server, "Hotel").await;
+
+    let (_, a) = upload(&server, &first, "booking.txt", "Booking #42").await;
+    let (_, b) = upload(&server, &second, "copy.txt", "Booking #42").await;
+    assert_eq!(a["data"]["hash"], b["data"]["hash"]);
+    assert_eq!(storage.len(), 1);
+
+    // Still in use by the second todo
+    let path = format!("/api/users/alice/todos/{}/attachments/{}", first, a["data"]["id"].as_str().unwrap());
+    send_json(&server, "DELETE", &path, Value::Null).await;
+    sqlx::query("UPDATE blobs SET last_used_at = ?")
+        .bind(Utc::now() - Duration::days(1))
+        .execute(&server.pool)
+        .await
+        .unwrap();
+    assert_eq!(blobs::collect_garbage(&server.pool, &blobs, Utc::now()).await.unwrap(), 0);
+    assert_eq!(storage.len(), 1);
+
+    let path = format!("/api/users/alice/todos/{}/attachments/{}", second, b["data"]["id"].as_str().unwrap());
+    send_json(&server, "DELETE", &path, Value::Null).await;
+    assert_eq!(blobs::collect_garbage(&server.pool, &blobs, Utc::now()).await.unwrap(), 1);
+    assert!(storage.is_empty());
+}
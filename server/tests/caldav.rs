@@ -0,0 +1,208 @@
+mod common;
+
+use common::{send, spawn_server, HttpResponse, TestServer};
+
+fn vtodo(uid: &str, summary: &str, completed: bool) -> String {
+    let status = if completed { "COMPLETED" } else { "NEEDS-ACTION" };
+    format!(
+        "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//Test//Test//EN\r\nBEGIN:VTODO\r\nUID:{}\r\nDTSTAMP:20240301T100000Z\r\nSUMMARY:{}\r\nSTATUS:{}\r\nEND:VTODO\r\nEND:VCALENDAR\r\n",
+        uid, summary, status
+    )
+}
+
+async fn put_todo(server: &TestServer, path: &str, uid: &str, summary: &str, completed: bool) -> HttpResponse {
+    send(server, "PUT", path, &[("Content-Type", "text/calendar")], &vtodo(uid, summary, completed)).await
+}
+
+const PROPFIND_ETAGS: &str = r#"<?xml version="1.0"?>
+<d:propfind xmlns:d="DAV:" xmlns:cs="http://calendarserver.org/ns/">
+  <d:prop><d:getetag/><d:resourcetype/><cs:getctag/></d:prop>
+</d:propfind>"#;
+
+#[tokio::test]
+async fn options_advertises_calendar_access() {
+    let server = spawn_server().await;
+
+    let response = send(&server, "OPTIONS", "/dav/alice/todos/", &[], "").await;
+
+    assert_eq!(response.status, 200);
+    assert!(response.header("DAV").unwrap().contains("calendar-access"));
+    assert!(response.header("Allow").unwrap().contains("REPORT"));
+}
+
+#[tokio::test]
+async fn put_and_get_round_trip_with_etags() {
+    let server = spawn_server().await;
+
+    let created = put_todo(&server, "/dav/alice/todos/todo-1.ics", "todo-1", "Buy milk", false).await;
+    assert_eq!(created.status, 201);
+    let etag = created.header("ETag").unwrap().to_string();
+
+    let fetched = send(&server, "GET", "/dav/alice/todos/todo-1.ics", &[], "").await;
+    assert_eq!(fetched.status, 200);
+    assert_eq!(fetched.header("ETag"), Some(etag.as_str()));
+    assert!(fetched.header("Content-Type").unwrap().starts_with("text/calendar"));
+    assert!(fetched.body.contains("SUMMARY:Buy milk"));
+
+    let create_again = send(
+        &server,
+        "PUT",
+        "/dav/alice/todos/todo-1.ics",
+        &[("If-None-Match", "*")],
+        &vtodo("todo-1", "Buy oat milk", false),
+    )
+    .await;
+    assert_eq!(create_again.status, 412);
+
+    let updated = send(
+        &server,
+        "PUT",
+        "/dav/alice/todos/todo-1.ics",
+        &[("If-Match", &etag)],
+        &vtodo("todo-1", "Buy oat milk", false),
+    )
+    .await;
+    assert_eq!(updated.status, 204);
+    assert_ne!(updated.header("ETag"), Some(etag.as_str()));
+
+    let stale = send(
+        &server,
+        "PUT",
+        "/dav/alice/todos/todo-1.ics",
+        &[("If-Match", &etag)],
+        &vtodo("todo-1", "Buy soy milk", false),
+    )
+    .await;
+    assert_eq!(stale.status, 412);
+
+    let todos = send(&server, "GET", "/api/users/alice/todos", &[], "").await;
+    assert!(todos.body.contains("Buy oat milk"));
+}
+
+#[tokio::test]
+async fn put_rejects_mismatched_uid_and_foreign_todos() {
+    let server = spawn_server().await;
+
+    let mismatch = put_todo(&server, "/dav/alice/todos/todo-1.ics", "other", "Mismatch", false).await;
+    assert_eq!(mismatch.status, 400);
+
+    put_todo(&server, "/dav/alice/todos/shared-id.ics", "shared-id", "Alice's", false).await;
+    let foreign = put_todo(&server, "/dav/bob/todos/shared-id.ics", "shared-id", "Bob's", false).await;
+    assert_eq!(foreign.status, 409);
+}
+
+#[tokio::test]
+async fn propfind_lists_calendars_and_todos() {
+    let server = spawn_server().await;
+    put_todo(&server, "/dav/alice/todos/todo-1.ics", "todo-1", "Inbox item", false).await;
+    put_todo(&server, "/dav/alice/lists/Work/todo-2.ics", "todo-2", "Write report", false).await;
+
+    let home = send(&server, "PROPFIND", "/dav/alice/", &[("Depth", "1")], "").await;
+    assert_eq!(home.status, 207);
+    assert!(home.body.contains("<d:href>/dav/alice/todos/</d:href>"));
+    assert!(home.body.contains("<d:href>/dav/alice/lists/</d:href>"));
+
+    let lists = send(&server, "PROPFIND", "/dav/alice/lists/", &[("Depth", "1")], "").await;
+    assert!(lists.body.contains("<d:href>/dav/alice/lists/Work/</d:href>"));
+    assert!(lists.body.contains(r#"<c:comp name="VTODO"/>"#));
+
+    let all = send(&server, "PROPFIND", "/dav/alice/todos/", &[("Depth", "1")], PROPFIND_ETAGS).await;
+    assert!(all.body.contains("/dav/alice/todos/todo-1.ics"));
+    assert!(all.body.contains("/dav/alice/todos/todo-2.ics"));
+    assert!(all.body.contains("<c:calendar/>"));
+
+    let work = send(&server, "PROPFIND", "/dav/alice/lists/Work/", &[("Depth", "1")], PROPFIND_ETAGS).await;
+    assert!(work.body.contains("/dav/alice/lists/Work/todo-2.ics"));
+    assert!(!work.body.contains("todo-1.ics"));
+
+    let itself = send(&server, "PROPFIND", "/dav/alice/todos/", &[("Depth", "0")], PROPFIND_ETAGS).await;
+    assert!(!itself.body.contains("todo-1.ics"));
+
+    let todos = send(&server, "GET", "/api/users/alice/todos", &[], "").await;
+    assert!(todos.body.contains(r#""list":"Work""#));
+}
+
+#[tokio::test]
+async fn ctag_changes_when_a_todo_changes() {
+    let server = spawn_server().await;
+    put_todo(&server, "/dav/alice/todos/todo-1.ics", "todo-1", "First", false).await;
+
+    let ctag = |body: &str| {
+        let start = body.find("<cs:getctag>").unwrap();
+        let end = body.find("</cs:getctag>").unwrap();
+        body[start..end].to_string()
+    };
+    let before = send(&server, "PROPFIND", "/dav/alice/todos/", &[("Depth", "0")], PROPFIND_ETAGS).await;
+    send(&server, "DELETE", "/dav/alice/todos/todo-1.ics", &[], "").await;
+    let after = send(&server, "PROPFIND", "/dav/alice/todos/", &[("Depth", "0")], PROPFIND_ETAGS).await;
+
+    assert_ne!(ctag(&before.body), ctag(&after.body));
+}
+
+#[tokio::test]
+async fn calendar_query_filters_completed_todos() {
+    let server = spawn_server().await;
+    put_todo(&server, "/dav/alice/todos/open.ics", "open", "Still open", false).await;
+    put_todo(&server, "/dav/alice/todos/done.ics", "done", "Already done", true).await;
+
+    let query = r#"<?xml version="1.0"?>
+<c:calendar-query xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+  <d:prop><d:getetag/><c:calendar-data/></d:prop>
+  <c:filter>
+    <c:comp-filter name="VCALENDAR">
+      <c:comp-filter name="VTODO">
+        <c:prop-filter name="COMPLETED"><c:is-not-defined/></c:prop-filter>
+      </c:comp-filter>
+    </c:comp-filter>
+  </c:filter>
+</c:calendar-query>"#;
+    let response = send(&server, "REPORT", "/dav/alice/todos/", &[("Depth", "1")], query).await;
+
+    assert_eq!(response.status, 207);
+    assert!(response.body.contains("/dav/alice/todos/open.ics"));
+    assert!(response.body.contains("SUMMARY:Still open"));
+    assert!(!response.body.contains("done.ics"));
+
+    let events = query.replace(r#"name="VTODO""#, r#"name="VEVENT""#);
+    let response = send(&server, "REPORT", "/dav/alice/todos/", &[("Depth", "1")], &events).await;
+    assert!(!response.body.contains("open.ics"));
+}
+
+#[tokio::test]
+async fn calendar_multiget_reports_missing_resources() {
+    let server = spawn_server().await;
+    put_todo(&server, "/dav/alice/todos/todo-1.ics", "todo-1", "Present", false).await;
+
+    let multiget = r#"<?xml version="1.0"?>
+<c:calendar-multiget xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+  <d:prop><d:getetag/><c:calendar-data/></d:prop>
+  <d:href>/dav/alice/todos/todo-1.ics</d:href>
+  <d:href>/dav/alice/todos/missing.ics</d:href>
+</c:calendar-multiget>"#;
+    let response = send(&server, "REPORT", "/dav/alice/todos/", &[], multiget).await;
+
+    assert_eq!(response.status, 207);
+    assert!(response.body.contains("SUMMARY:Present"));
+    assert!(response
+        .body
+        .contains("<d:href>/dav/alice/todos/missing.ics</d:href><d:status>HTTP/1.1 404 Not Found</d:status>"));
+}
+
+#[tokio::test]
+async fn delete_moves_todo_to_trash() {
+    let server = spawn_server().await;
+    let created = put_todo(&server, "/dav/alice/todos/todo-1.ics", "todo-1", "Short lived", false).await;
+    let etag = created.header("ETag").unwrap().to_string();
+
+    let stale = send(&server, "DELETE", "/dav/alice/todos/todo-1.ics", &[("If-Match", "\"1\"")], "").await;
+    assert_eq!(stale.status, 412);
+
+    let deleted = send(&server, "DELETE", "/dav/alice/todos/todo-1.ics", &[("If-Match", &etag)], "").await;
+    assert_eq!(deleted.status, 204);
+
+    let fetched = send(&server, "GET", "/dav/alice/todos/todo-1.ics", &[], "").await;
+    assert_eq!(fetched.status, 404);
+
+    let trash = send(&server, "GET", "/api/users/alice/trash", &[], "").await;
+    assert!(trash.body.contains("Short lived"));
+}
@@ -0,0 +1,148 @@
+mod common;
+
+use common::{send_json, spawn_server, TestServer};
+use serde_json::{json, Value};
+use todo_server::feed;
+
+/// A todo of alice's in a list bob has joined as a viewer
+async fn shared_todo(server: &TestServer) -> String {
+    let (_, list) = send_json(server, "POST", "/api/users/alice/lists", json!({"name": "Home"})).await;
+    let list_id = list["data"]["id"].as_str().unwrap();
+    let invite = format!("/api/users/alice/lists/{}/invitations", list_id);
+    send_json(server, "POST", &invite, json!({"invitee": "bob", "role": "viewer"})).await;
+    let (_, invitations) = send_json(server, "GET", "/api/users/bob/invitations", Value::Null).await;
+    let accept = format!("/api/users/bob/invitations/{}/accept", invitations["data"][0]["id"].as_str().unwrap());
+    send_json(server, "POST", &accept, Value::Null).await;
+
+    let (_, todo) = send_json(server, "POST", "/api/users/alice/todos", json!({"title": "Fix sink", "list_id": list_id})).await;
+    todo["data"]["id"].as_str().unwrap().to_string()
+}
+
+fn kinds(feed: &Value) -> Vec<&str> {
+    feed["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|event| event["kind"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn comments_notify_other_participants() {
+    let server = spawn_server().await;
+    let todo_id = shared_todo(&server).await;
+    let bob_comments = format!("/api/users/bob/todos/{}/comments", todo_id);
+
+    // Viewers can join the discussion
+    let (status, comment) = send_json(&server, "POST", &bob_comments, json!({"body": "Call a **plumber**?"})).await;
+    assert_eq!(status, 200);
+    assert_eq!(comment["data"]["author_id"], "bob");
+    let comment_id = comment["data"]["id"].as_str().unwrap();
+
+    let (_, feed) = send_json(&server, "GET", "/api/users/alice/feed", Value::Null).await;
+    assert_eq!(kinds(&feed), vec!["comment_added"]);
+    assert_eq!(feed["data"][0]["actor_id"], "bob");
+    assert_eq!(feed["data"][0]["comment_id"], comment_id);
+    // Authors are not told about their own comments
+    let (_, feed) = send_json(&server, "GET", "/api/users/bob/feed", Value::Null).await;
+    assert_eq!(kinds(&feed), Vec::<&str>::new());
+
+    let alice_comment = format!("/api/users/alice/todos/{}/comments/{}", todo_id, comment_id);
+    let (status, _) = send_json(&server, "PUT", &alice_comment, json!({"body": "No"})).await;
+    assert_eq!(status, 403, "only authors edit their comments");
+
+    let (status, _) = send_json(&server, "PUT", &format!("{}/{}", bob_comments, comment_id), json!({"body": "Plumber?"})).await;
+    assert_eq!(status, 200);
+    // List owners can delete anyone's comments
+    let (status, _) = send_json(&server, "DELETE", &alice_comment, Value::Null).await;
+    assert_eq!(status, 200);
+
+    let (_, feed) = send_json(&server, "GET", "/api/users/alice/feed", Value::Null).await;
+    assert_eq!(kinds(&feed), vec!["comment_added", "comment_edited"]);
+    let last = feed["data"][1]["id"].as_i64().unwrap();
+    let (_, feed) = send_json(&server, "GET", "/api/users/bob/feed?after=0", Value::Null).await;
+    assert_eq!(kinds(&feed), vec!["comment_deleted"]);
+    let (_, feed) = send_json(&server, "GET", &format!("/api/users/alice/feed?after={}", last), Value::Null).await;
+    assert_eq!(kinds(&feed), Vec::<&str>::new());
+
+    let (_, comments) = send_json(&server, "GET", &bob_comments, Value::Null).await;
+    assert_eq!(comments["data"], json!([]));
+}
+
+#[tokio::test]
+async fn outsiders_cannot_comment() {
+    let server = spawn_server().await;
+    let todo_id = shared_todo(&server).await;
+
+    let path = format!("/api/users/carol/todos/{}/comments", todo_id);
+    let (status, _) = send_json(&server, "POST", &path, json!({"body": "Hi"})).await;
+    assert_eq!(status, 404);
+    let (status, _) = send_json(&server, "GET", &path, Value::Null).await;
+    assert_eq!(status, 404);
+
+    let path = format!("/api/users/bob/todos/{}/comments", todo_id);
+    let (status, _) = send_json(&server, "POST", &path, json!({"body": "   "})).await;
+    assert_eq!(status, 400);
+}
+
+#[tokio::test]
+async fn offline_comments_sync() {
+    let server = spawn_server().await;
+    let todo_id = shared_todo(&server).await;
+
+    // Written offline, possibly under another author id, which the server ignores
+    let comment = json!({
+        "id": "offline-1",
+        "todo_id": todo_id,
+        "author_id": "someone-else",
+        "body": "Bought a new tap",
+        "created_at": "2024-01-01T00:00:00Z",
+        "updated_at": "2024-01-01T00:00:00Z",
+    });
+    let (status, synced) = send_json(&server, "POST", "/api/users/bob/sync", json!({"todos": [], "comments": [comment]})).await;
+    assert_eq!(status, 200);
+    assert_eq!(synced["data"]["comments"][0]["author_id"], "bob");
+
+    let (_, comments) = send_json(&server, "GET", &format!("/api/users/alice/todos/{}/comments", todo_id), Value::Null).await;
+    assert_eq!(comments["data"][0]["body"], "Bought a new tap");
+    let (_, feed) = send_json(&server, "GET", "/api/users/alice/feed", Value::Null).await;
+    assert_eq!(kinds(&feed), vec!["comment_added"]);
+
+    // A deletion made offline by someone else than the author is ignored
+    let mut deleted = synced["data"]["comments"][0].clone();
+    deleted["deleted_at"] = json!("2999-01-01T00:00:00Z");
+    deleted["updated_at"] = json!("2999-01-01T00:00:00Z");
+    send_json(&server, "POST", "/api/users/alice/sync", json!({"todos": [], "comments": [deleted.clone()]})).await;
+    let (_, comments) = send_json(&server, "GET", &format!("/api/users/alice/todos/{}/comments", todo_id), Value::Null).await;
+    assert_eq!(comments["data"].as_array().unwrap().len(), 1);
+
+    // But not when the author makes it
+    send_json(&server, "POST", "/api/users/bob/sync", json!({"todos": [], "comments": [deleted]})).await;
+    let (_, comments) = send_json(&server, "GET", &format!("/api/users/alice/todos/{}/comments", todo_id), Value::Null).await;
+    assert_eq!(comments["data"], json!([]));
+    let (_, feed) = send_json(&server, "GET", "/api/users/alice/feed", Value::Null).await;
+    assert_eq!(kinds(&feed), vec!["comment_added", "comment_deleted"]);
+}
+
+#[tokio::test]
+async fn feed_events_go_with_their_todo_or_once_old() {
+    let server = spawn_server().await;
+    let purged = shared_todo(&server).await;
+    let kept = shared_todo(&server).await;
+    for todo_id in [&purged, &kept] {
+        let comments = format!("/api/users/bob/todos/{}/comments", todo_id);
+        send_json(&server, "POST", &comments, json!({"body": "Call a plumber?"})).await;
+    }
+
+    // Purging a todo drops the events about it
+    send_json(&server, "DELETE", &format!("/api/users/alice/todos/{}", purged), Value::Null).await;
+    send_json(&server, "DELETE", "/api/users/alice/trash", Value::Null).await;
+    let (_, feed) = send_json(&server, "GET", "/api/users/alice/feed", Value::Null).await;
+    assert_eq!(kinds(&feed), vec!["comment_added"]);
+    assert_eq!(feed["data"][0]["todo_id"], kept.as_str());
+
+    let cutoff = chrono::Utc::now() + chrono::Duration::minutes(1);
+    assert_eq!(feed::purge(&server.pool, cutoff).await.unwrap(), 1);
+    let (_, feed) = send_json(&server, "GET", "/api/users/alice/feed", Value::Null).await;
+    assert_eq!(kinds(&feed), Vec::<&str>::new());
+}
@@ -0,0 +1,131 @@
+//! Helpers shared by the integration tests: a server on a random port with
+//! its own database, and a minimal HTTP client.
+#![allow(dead_code)]
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use todo_server::blobs::{Blobs, FsBlobStore};
+use todo_server::db;
+use todo_server::config::Config;
+use todo_server::shutdown::{self, Shutdown};
+
+pub struct TestServer {
+    pub addr: SocketAddr,
+    pub pool: db::DbPool,
+    _dir: TempDir,
+}
+
+pub async fn spawn_server() -> TestServer {
+    spawn_server_with_config(Config::default()).await
+}
+
+/// Spawn a server keeping attachment contents in `blobs`
+pub async fn spawn_server_with(blobs: Blobs) -> TestServer {
+    spawn_server_in(tempfile::tempdir().unwrap(), blobs, Config::default()).await
+}
+
+/// Spawn a server with `config`; its database and blob settings are ignored
+pub async fn spawn_server_with_config(config: Config) -> TestServer {
+    let dir = tempfile::tempdir().unwrap();
+    let blobs = Arc::new(FsBlobStore::new(dir.path().join("blobs")));
+    spawn_server_in(dir, blobs, config).await
+}
+
+/// Spawn a server that shuts down gracefully once `shutdown` is triggered,
+/// giving open connections `drain_timeout` to finish. The returned task ends
+/// when the server has stopped.
+pub async fn spawn_server_until(shutdown: Shutdown, drain_timeout: Duration) -> (TestServer, JoinHandle<io::Result<()>>) {
+    let dir = tempfile::tempdir().unwrap();
+    let blobs = Arc::new(FsBlobStore::new(dir.path().join("blobs")));
+    start_server(dir, blobs, Config::default(), shutdown, drain_timeout).await
+}
+
+async fn spawn_server_in(dir: TempDir, blobs: Blobs, config: Config) -> TestServer {
+    let (server, _task) = start_server(dir, blobs, config, Shutdown::new(), Duration::ZERO).await;
+    server
+}
+
+async fn start_server(
+    dir: TempDir,
+    blobs: Blobs,
+    config: Config,
+    shutdown: Shutdown,
+    drain_timeout: Duration,
+) -> (TestServer, JoinHandle<io::Result<()>>) {
+    let url = format!("sqlite:{}?mode=rwc", dir.path().join("todos.db").display());
+    let pool = db::connect(&url).await.unwrap();
+
+    let server_pool = pool.clone();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let task = tokio::spawn(async move {
+        let app = todo_server::app(pool, blobs, &config);
+        shutdown::serve(listener, app, shutdown, drain_timeout).await
+    });
+
+    (TestServer { addr, pool: server_pool, _dir: dir }, task)
+}
+
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl HttpResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Send a raw HTTP/1.1 request and read the whole response
+pub async fn send(server: &TestServer, method: &str, path: &str, headers: &[(&str, &str)], body: &str) -> HttpResponse {
+    let mut request = format!(
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n",
+        method,
+        path,
+        body.len()
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    request.push_str(body);
+
+    let mut stream = TcpStream::connect(server.addr).await.unwrap();
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut raw = String::new();
+    stream.read_to_string(&mut raw).await.unwrap();
+
+    let (head, body) = raw.split_once("\r\n\r\n").unwrap();
+    let mut lines = head.split("\r\n");
+    let status = lines.next().unwrap().split(' ').nth(1).unwrap().parse().unwrap();
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+
+    HttpResponse {
+        status,
+        headers,
+        body: body.to_string(),
+    }
+}
+
+/// Send a JSON request and parse the JSON response body
+pub async fn send_json(server: &TestServer, method: &str, path: &str, body: serde_json::Value) -> (u16, serde_json::Value) {
+    let body = if body.is_null() { String::new() } else { body.to_string() };
+    let response = send(server, method, path, &[("Content-Type", "application/json")], &body).await;
+    let json = serde_json::from_str(&response.body).unwrap_or(serde_json::Value::Null);
+    (response.status, json)
+}
@@ -0,0 +1,169 @@
+mod common;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use common::{send, send_json, spawn_server_with_config};
+use serde_json::{json, Value};
+use todo_server::config::{BlobStoreKind, Config, ConfigError, LogFormat};
+use todo_server::limits::Quota;
+
+fn example() -> Config {
+    Config::from_file(&Path::new(env!("CARGO_MANIFEST_DIR")).join("server.example.toml")).unwrap()
+}
+
+fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+    let vars: HashMap<String, String> = vars
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    move |name| vars.get(name).cloned()
+}
+
+#[test]
+fn example_config_matches_the_defaults() {
+    let example = example();
+    example.validate().unwrap();
+    assert_eq!(format!("{:?}", example), format!("{:?}", Config::default()));
+}
+
+#[test]
+fn environment_overrides_the_file() {
+    let mut config: Config = toml::from_str(
+        r#"
+        [server]
+        bind = "127.0.0.1:4000"
+
+        [limits]
+        per_ip = { burst = 0, per_second = 1.0 }
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.limits.per_ip, None);
+
+    config
+        .apply_env(env(&[
+            ("SERVER_ADDR", "127.0.0.1:5000"),
+            ("CORS_ALLOWED_ORIGINS", "https://a.example, https://b.example"),
+            ("DATABASE_MAX_CONNECTIONS", "12"),
+            ("LOG_FORMAT", "json"),
+            ("RATE_LIMIT_IP_BURST", "50"),
+            ("RATE_LIMIT_IP_PER_SEC", "2.5"),
+            ("BLOB_STORE", "memory-s3"),
+            ("FEATURE_CALDAV", "false"),
+        ]))
+        .unwrap();
+    config.validate().unwrap();
+
+    assert_eq!(config.server.bind, "127.0.0.1:5000");
+    assert_eq!(config.server.allowed_origins, ["https://a.example", "https://b.example"]);
+    assert_eq!(config.database.max_connections, 12);
+    assert_eq!(config.log.format, LogFormat::Json);
+    assert_eq!(config.limits.per_ip, Some(Quota { burst: 50, per_second: 2.5 }));
+    assert_eq!(config.blobs.store, BlobStoreKind::MemoryS3);
+    assert!(!config.features.caldav);
+}
+
+#[test]
+fn bad_settings_are_reported_clearly() {
+    let mut config = Config::default();
+    let error = config.apply_env(env(&[("DATABASE_MAX_CONNECTIONS", "lots")])).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "invalid value \"lots\" for DATABASE_MAX_CONNECTIONS: invalid digit found in string"
+    );
+    let error = config.apply_env(env(&[("LOG_FORMAT", "xml")])).unwrap_err();
+    assert!(matches!(error, ConfigError::Env { ref name, .. } if name == "LOG_FORMAT"));
+
+    let error = toml::from_str::<Config>("[server]\nport = 80\n").unwrap_err();
+    assert!(error.to_string().contains("unknown field `port`"));
+
+    config.server.bind = "localhost".to_string();
+    config.server.allowed_origins = vec!["*".to_string(), "https://todo.example.com/app".to_string()];
+    config.database.max_connections = 0;
+    config.limits.max_sync_todos = 0;
+    let error = config.validate().unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        [
+            "invalid configuration:",
+            "  - server.bind: \"localhost\" is not an address like 0.0.0.0:3001",
+            "  - server.allowed_origins: \"*\" cannot be combined with other origins",
+            "  - database.max_connections: must be at least 1",
+            "  - limits.max_sync_todos: must be at least 1",
+        ]
+        .join("\n")
+    );
+
+    config.server.allowed_origins = vec!["https://todo.example.com/app".to_string()];
+    let error = config.validate().unwrap_err();
+    assert!(error.to_string().contains("\"https://todo.example.com/app\" is not an origin"));
+
+    let mut config = Config::default();
+    config
+        .apply_env(env(&[("TLS_CERT_PATH", "cert.pem"), ("TLS_SELF_SIGNED", "true"), ("SHUTDOWN_TIMEOUT_SECS", "0")]))
+        .unwrap();
+    let error = config.validate().unwrap_err();
+    assert!(error.to_string().contains("server.shutdown_timeout_secs: must be at least 1"));
+    assert!(error.to_string().contains("tls: cert_path and key_path must be given together"));
+    assert!(error.to_string().contains("tls.self_signed: needs cert_path and key_path"));
+
+    // Retention periods must fit in a date, however they are given
+    let mut config = Config::default();
+    config
+        .apply_env(env(&[("REVISION_RETENTION_DAYS", "9999999999999"), ("IDEMPOTENCY_WINDOW_SECS", "9999999999999")]))
+        .unwrap();
+    config.retention.trash_days = i64::MAX;
+    let error = config.validate().unwrap_err();
+    assert!(error.to_string().contains("retention.trash_days: must be at most 36500"));
+    assert!(error.to_string().contains("retention.revision_days: must be at most 36500"));
+    assert!(error.to_string().contains("retention.idempotency_secs: must be at most"));
+
+    // A collector can only be used by servers built to export traces
+    let mut config = Config::default();
+    config
+        .apply_env(env(&[("OTEL_EXPORTER_OTLP_ENDPOINT", "http://localhost:4318"), ("OTEL_SERVICE_NAME", "todos")]))
+        .unwrap();
+    assert_eq!(config.telemetry.service_name, "todos");
+    assert_eq!(config.validate().is_ok(), cfg!(feature = "otel"));
+}
+
+#[tokio::test]
+async fn only_allowed_origins_pass_cors() {
+    let mut config = Config::default();
+    config.server.allowed_origins = vec!["https://todo.example.com".to_string()];
+    let server = spawn_server_with_config(config).await;
+
+    for (origin, allowed) in [("https://todo.example.com", true), ("https://evil.example.com", false)] {
+        let response = send(
+            &server,
+            "OPTIONS",
+            "/api/users/alice/todos",
+            &[("Origin", origin), ("Access-Control-Request-Method", "POST")],
+            "",
+        )
+        .await;
+        assert_eq!(response.header("access-control-allow-origin").is_some(), allowed, "{}", origin);
+    }
+}
+
+#[tokio::test]
+async fn features_can_be_switched_off() {
+    let mut config = Config::default();
+    config.features.caldav = false;
+    config.features.webhooks = false;
+    let server = spawn_server_with_config(config).await;
+
+    let response = send(&server, "PROPFIND", "/dav/alice/", &[("Depth", "0")], "").await;
+    assert_eq!(response.status, 404);
+    let (status, _) = send_json(
+        &server,
+        "POST",
+        "/api/users/alice/webhooks",
+        json!({"url": "https://example.com", "events": ["todo.created"]}),
+    )
+    .await;
+    assert_eq!(status, 404);
+    let (status, _) = send_json(&server, "GET", "/api/users/alice/todos", Value::Null).await;
+    assert_eq!(status, 200);
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::{send_json, spawn_server, spawn_server_with_config};
+use serde_json::Value;
+use todo_server::config::Config;
+
+#[tokio::test]
+async fn live_and_ready_report_build_and_checks() {
+    let server = spawn_server().await;
+
+    let (status, live) = send_json(&server, "GET", "/health/live", Value::Null).await;
+    assert_eq!(status, 200);
+    assert_eq!(live["data"]["status"], "live");
+    assert_eq!(live["data"]["build"]["version"], env!("CARGO_PKG_VERSION"));
+    assert!(live["data"].get("checks").is_none());
+
+    let (status, ready) = send_json(&server, "GET", "/health/ready", Value::Null).await;
+    assert_eq!(status, 200, "{}", ready);
+    assert_eq!(ready["success"], true);
+    assert_eq!(ready["data"]["status"], "ready");
+    let checks = &ready["data"]["checks"];
+    for name in ["database", "migrations", "disk"] {
+        assert_eq!(checks[name]["status"], "ok", "{}", checks);
+    }
+    assert!(checks["disk"]["detail"].as_str().unwrap().ends_with("bytes free"));
+}
+
+#[tokio::test]
+async fn a_full_disk_or_missing_migrations_make_the_server_unready() {
+    let mut config = Config::default();
+    config.health.min_free_disk_bytes = u64::MAX;
+    let server = spawn_server_with_config(config).await;
+
+    let (status, ready) = send_json(&server, "GET", "/health/ready", Value::Null).await;
+    assert_eq!(status, 503);
+    assert_eq!(ready["success"], false);
+    assert_eq!(ready["error"], "Failed checks: disk");
+    assert_eq!(ready["data"]["status"], "not_ready");
+    assert_eq!(ready["data"]["checks"]["database"]["status"], "ok");
+    assert_eq!(ready["data"]["checks"]["disk"]["status"], "failed");
+
+    // Liveness does not depend on anything
+    let (status, _) = send_json(&server, "GET", "/health/live", Value::Null).await;
+    assert_eq!(status, 200);
+
+    sqlx::query("PRAGMA user_version = 0").execute(&server.pool).await.unwrap();
+    let (_, ready) = send_json(&server, "GET", "/health/ready", Value::Null).await;
+    assert_eq!(ready["error"], "Failed checks: migrations, disk");
+    let detail = ready["data"]["checks"]["migrations"]["detail"].as_str().unwrap();
+    assert!(detail.starts_with("schema version 0, expected"), "{}", detail);
+}
+
+#[tokio::test]
+async fn an_unresponsive_database_times_out() {
+    let mut config = Config::default();
+    config.health.db_timeout_ms = 100;
+    let server = spawn_server_with_config(config).await;
+
+    // Hold every connection so the check cannot get one
+    let mut held = Vec::new();
+    while held.len() < server.pool.options().get_max_connections() as usize {
+        held.push(server.pool.acquire().await.unwrap());
+    }
+
+    let (status, ready) = send_json(&server, "GET", "/health/ready", Value::Null).await;
+    assert_eq!(status, 503);
+    let database = &ready["data"]["checks"]["database"];
+    assert_eq!(database["status"], "failed");
+    assert_eq!(database["detail"], "no answer within 100ms");
+    assert_eq!(ready["data"]["checks"]["migrations"]["status"], "skipped");
+
+    drop(held);
+    let (status, _) = send_json(&server, "GET", "/health/ready", Value::Null).await;
+    assert_eq!(status, 200);
+}
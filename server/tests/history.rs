@@ -0,0 +1,125 @@
+mod common;
+
+use common::{send, send_json, spawn_server};
+use serde_json::{json, Value};
+
+fn fields(revision: &Value) -> Vec<&str> {
+    revision["changes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|change| change["field"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn changes_are_recorded_and_can_be_reverted() {
+    let server = spawn_server().await;
+    let (_, todo) = send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Milk"})).await;
+    let todo_id = todo["data"]["id"].as_str().unwrap();
+    let path = format!("/api/users/alice/todos/{}", todo_id);
+
+    let body = json!({"title": "Oat milk", "priority": "high"}).to_string();
+    let headers = [("Content-Type", "application/json"), ("X-Device-Id", "phone")];
+    let response = send(&server, "PUT", &path, &headers, &body).await;
+    assert_eq!(response.status, 200);
+
+    // Offline edits arrive through sync
+    let mut synced = todo["data"].clone();
+    synced["title"] = json!("Soy milk");
+    synced["updated_at"] = json!("2999-01-01T00:00:00Z");
+    send_json(&server, "POST", "/api/users/alice/sync", json!({"todos": [synced]})).await;
+
+    let (status, history) = send_json(&server, "GET", &format!("{}/history", path), Value::Null).await;
+    assert_eq!(status, 200);
+    let revisions = history["data"].as_array().unwrap();
+    let actions: Vec<&str> = revisions.iter().map(|r| r["action"].as_str().unwrap()).collect();
+    assert_eq!(actions, vec!["sync", "update", "create"]);
+
+    let update = &revisions[1];
+    assert_eq!(update["actor_id"], "alice");
+    assert_eq!(update["device_id"], "phone");
+    assert_eq!(fields(update), vec!["priority", "title"]);
+    assert_eq!(update["changes"][1]["before"], "Milk");
+    assert_eq!(update["changes"][1]["after"], "Oat milk");
+
+    // Back to how it was after the update
+    let revert = format!("{}/history/{}/revert", path, update["id"]);
+    let (status, reverted) = send_json(&server, "POST", &revert, Value::Null).await;
+    assert_eq!(status, 200);
+    assert_eq!(reverted["data"]["title"], "Oat milk");
+
+    let (_, history) = send_json(&server, "GET", &format!("{}/history", path), Value::Null).await;
+    assert_eq!(history["data"][0]["action"], "revert");
+    assert_eq!(fields(&history["data"][0]), vec!["priority", "title"]);
+
+    // Trashed todos keep their history
+    send_json(&server, "DELETE", &path, Value::Null).await;
+    let (status, history) = send_json(&server, "GET", &format!("{}/history", path), Value::Null).await;
+    assert_eq!(status, 200);
+    assert_eq!(fields(&history["data"][0]), vec!["deleted_at"]);
+}
+
+#[tokio::test]
+async fn history_follows_todo_access() {
+    let server = spawn_server().await;
+    let (_, list) = send_json(&server, "POST", "/api/users/alice/lists", json!({"name": "Home"})).await;
+    let list_id = list["data"]["id"].as_str().unwrap();
+    send_json(
+        &server,
+        "POST",
+        &format!("/api/users/alice/lists/{}/invitations", list_id),
+        json!({"invitee": "bob", "role": "viewer"}),
+    )
+    .await;
+    let (_, invitations) = send_json(&server, "GET", "/api/users/bob/invitations", Value::Null).await;
+    let accept = format!("/api/users/bob/invitations/{}/accept", invitations["data"][0]["id"].as_str().unwrap());
+    send_json(&server, "POST", &accept, Value::Null).await;
+
+    let (_, todo) = send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Fix sink", "list_id": list_id})).await;
+    let todo_id = todo["data"]["id"].as_str().unwrap();
+
+    // Viewers can read the history but not revert
+    let (status, history) = send_json(&server, "GET", &format!("/api/users/bob/todos/{}/history", todo_id), Value::Null).await;
+    assert_eq!(status, 200);
+    let revert = format!("/api/users/bob/todos/{}/history/{}/revert", todo_id, history["data"][0]["id"]);
+    let (status, _) = send_json(&server, "POST", &revert, Value::Null).await;
+    assert_eq!(status, 403);
+
+    let (status, _) = send_json(&server, "GET", &format!("/api/users/carol/todos/{}/history", todo_id), Value::Null).await;
+    assert_eq!(status, 404);
+
+    // Revisions of other todos cannot be used
+    let (_, other) = send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Other"})).await;
+    let (_, other_history) = send_json(
+        &server,
+        "GET",
+        &format!("/api/users/alice/todos/{}/history", other["data"]["id"].as_str().unwrap()),
+        Value::Null,
+    )
+    .await;
+    let revert = format!("/api/users/alice/todos/{}/history/{}/revert", todo_id, other_history["data"][0]["id"]);
+    let (status, _) = send_json(&server, "POST", &revert, Value::Null).await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn changes_are_not_saved_without_their_revision() {
+    let server = spawn_server().await;
+    let (_, todo) = send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Milk"})).await;
+    let path = format!("/api/users/alice/todos/{}", todo["data"]["id"].as_str().unwrap());
+
+    // Recording revisions now fails, so nothing else may be written either
+    sqlx::query("DROP TABLE todo_revisions").execute(&server.pool).await.unwrap();
+
+    let (status, _) = send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Eggs"})).await;
+    assert_eq!(status, 500);
+    let (status, _) = send_json(&server, "PUT", &path, json!({"title": "Oat milk"})).await;
+    assert_eq!(status, 500);
+    let (status, _) = send_json(&server, "DELETE", &path, Value::Null).await;
+    assert_eq!(status, 500);
+
+    let (_, todos) = send_json(&server, "GET", "/api/users/alice/todos", Value::Null).await;
+    let titles: Vec<&str> = todos["data"].as_array().unwrap().iter().map(|todo| todo["title"].as_str().unwrap()).collect();
+    assert_eq!(titles, vec!["Milk"]);
+}
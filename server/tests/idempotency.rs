@@ -0,0 +1,120 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use common::{send, send_json, spawn_server, spawn_server_with_config, HttpResponse, TestServer};
+use serde_json::{json, Value};
+use todo_server::config::Config;
+use todo_server::limits::Limits;
+
+async fn create(server: &TestServer, user: &str, key: &str, title: &str) -> HttpResponse {
+    let headers = [("Content-Type", "application/json"), ("Idempotency-Key", key)];
+    let body = json!({ "title": title }).to_string();
+    send(server, "POST", &format!("/api/users/{}/todos", user), &headers, &body).await
+}
+
+async fn count_todos(server: &TestServer, user: &str) -> usize {
+    let (_, todos) = send_json(server, "GET", &format!("/api/users/{}/todos", user), Value::Null).await;
+    todos["data"].as_array().unwrap().len()
+}
+
+#[tokio::test]
+async fn retries_replay_the_first_response() {
+    let server = spawn_server().await;
+
+    let first = create(&server, "alice", "key-1", "Milk").await;
+    assert_eq!(first.status, 200);
+    assert_eq!(first.header("Idempotent-Replayed"), None);
+
+    let retry = create(&server, "alice", "key-1", "Milk").await;
+    assert_eq!(retry.status, 200);
+    assert_eq!(retry.header("Idempotent-Replayed"), Some("true"));
+    assert_eq!(retry.body, first.body);
+    assert_eq!(count_todos(&server, "alice").await, 1);
+
+    // The same key with a different request is refused
+    let other = create(&server, "alice", "key-1", "Bread").await;
+    assert_eq!(other.status, 409);
+    assert_eq!(count_todos(&server, "alice").await, 1);
+}
+
+#[tokio::test]
+async fn keys_are_scoped_to_the_user_and_the_whole_request() {
+    let server = spawn_server().await;
+
+    assert_eq!(create(&server, "alice", "shared-key", "Milk").await.status, 200);
+    // Another user choosing the same key is unaffected
+    let bob = create(&server, "bob", "shared-key", "Milk").await;
+    assert_eq!(bob.status, 200);
+    assert_eq!(bob.header("Idempotent-Replayed"), None);
+    assert_eq!(count_todos(&server, "bob").await, 1);
+
+    // Requests differing only in their query are different requests
+    let headers = [("Idempotency-Key", "purge")];
+    let response = send(&server, "DELETE", "/api/users/alice/trash?before=2000-01-01T00:00:00Z", &headers, "").await;
+    let retry = send(&server, "DELETE", "/api/users/alice/trash?before=2999-01-01T00:00:00Z", &headers, "").await;
+    assert_ne!(response.status, 500);
+    assert_eq!(retry.status, 409);
+}
+
+#[tokio::test]
+async fn keys_in_progress_are_refused_until_their_lease_runs_out() {
+    let server = spawn_server().await;
+    let hold = |created_at: chrono::DateTime<Utc>| {
+        sqlx::query(
+            "INSERT OR REPLACE INTO idempotency_keys (user_id, key, fingerprint, created_at) VALUES ('alice', 'busy', 'other', ?)",
+        )
+        .bind(created_at)
+        .execute(&server.pool)
+    };
+
+    hold(Utc::now()).await.unwrap();
+    let response = create(&server, "alice", "busy", "Milk").await;
+    assert_eq!(response.status, 409);
+    assert!(response.body.contains("still in progress"), "{}", response.body);
+
+    // A request that stopped long ago without an answer gave up its key
+    hold(Utc::now() - Duration::minutes(5)).await.unwrap();
+    let response = create(&server, "alice", "busy", "Milk").await;
+    assert_eq!(response.status, 200);
+    assert_eq!(count_todos(&server, "alice").await, 1);
+    assert_eq!(create(&server, "alice", "busy", "Milk").await.header("Idempotent-Replayed"), Some("true"));
+}
+
+#[tokio::test]
+async fn responses_too_large_to_keep_are_not_run_again() {
+    let server = spawn_server().await;
+    assert_eq!(create(&server, "alice", "big", "Milk").await.status, 200);
+    // What is kept of a request whose response was passed through unstored
+    sqlx::query("UPDATE idempotency_keys SET body = NULL WHERE key = 'big'")
+        .execute(&server.pool)
+        .await
+        .unwrap();
+
+    let response = create(&server, "alice", "big", "Milk").await;
+    assert_eq!(response.status, 409);
+    assert!(response.body.contains("completed with status 200"), "{}", response.body);
+    assert_eq!(count_todos(&server, "alice").await, 1);
+}
+
+#[tokio::test]
+async fn bodies_are_limited_by_their_route() {
+    let server = spawn_server_with_config(Config {
+        limits: Limits {
+            body_bytes: 1024,
+            ..Limits::default()
+        },
+        ..Config::default()
+    })
+    .await;
+    let padding = "x".repeat(2048);
+
+    let response = create(&server, "alice", "long", &padding).await;
+    assert_eq!(response.status, 413);
+    assert_eq!(count_todos(&server, "alice").await, 0);
+
+    // Sync accepts larger bodies, with a key or without
+    let headers = [("Content-Type", "application/json"), ("Idempotency-Key", "sync")];
+    let body = json!({"last_sync": null, "todos": [], "padding": padding}).to_string();
+    let response = send(&server, "POST", "/api/users/alice/sync", &headers, &body).await;
+    assert_eq!(response.status, 200, "{}", response.body);
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use common::{send, send_json, spawn_server};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn ids_taken_by_other_users_are_reassigned() {
+    let server = spawn_server().await;
+    let path = "/api/users/alice/todos";
+    let (_, parent) = send_json(&server, "POST", path, json!({"title": "Trip"})).await;
+    let parent_id = parent["data"]["id"].as_str().unwrap().to_string();
+    send_json(&server, "POST", path, json!({"title": "Pack", "parent_id": parent_id})).await;
+    let export = send(&server, "GET", "/api/users/alice/export?format=json", &[], "").await;
+    assert_eq!(export.status, 200);
+
+    let preview = send(&server, "POST", "/api/users/bob/import?format=json&dry_run=true", &[], &export.body).await;
+    assert_eq!(preview.status, 200, "{}", preview.body);
+    let import = send(&server, "POST", "/api/users/bob/import?format=json&dry_run=false", &[], &export.body).await;
+    assert_eq!(import.status, 200, "{}", import.body);
+    let report: Value = serde_json::from_str(&import.body).unwrap();
+    let reassigned = report["data"]["reassigned_ids"].as_object().unwrap();
+    assert_eq!(reassigned.len(), 2);
+    let new_parent_id = reassigned[&parent_id].as_str().unwrap();
+
+    let (_, todos) = send_json(&server, "GET", "/api/users/bob/todos", Value::Null).await;
+    let todos = todos["data"].as_array().unwrap();
+    assert_eq!(todos.len(), 2);
+    let pack = todos.iter().find(|todo| todo["title"] == "Pack").unwrap();
+    assert_eq!(pack["parent_id"], new_parent_id);
+
+    // Alice's todos are untouched
+    let (_, todos) = send_json(&server, "GET", path, Value::Null).await;
+    assert_eq!(todos["data"].as_array().unwrap().len(), 2);
+    assert!(todos["data"].as_array().unwrap().iter().any(|todo| todo["id"] == parent_id.as_str()));
+}
+
+fn archive(todos: Value) -> String {
+    json!({"version": 4, "exported_at": "2024-01-01T00:00:00Z", "todos": todos}).to_string()
+}
+
+fn record(id: &str, title: &str, extra: Value) -> Value {
+    let mut todo = json!({
+        "id": id,
+        "title": title,
+        "completed": false,
+        "priority": "medium",
+        "created_at": "2024-01-01T00:00:00Z",
+        "updated_at": "2024-01-01T00:00:00Z",
+    });
+    todo.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+    todo
+}
+
+#[tokio::test]
+async fn imports_are_checked_like_new_todos() {
+    let server = spawn_server().await;
+    let path = "/api/users/alice/todos";
+    let (_, parent) = send_json(&server, "POST", path, json!({"title": "Trip"})).await;
+    let parent_id = parent["data"]["id"].as_str().unwrap().to_string();
+    let (_, subtask) = send_json(&server, "POST", path, json!({"title": "Pack", "parent_id": parent_id})).await;
+    let subtask_id = subtask["data"]["id"].as_str().unwrap().to_string();
+    let import = "/api/users/alice/import?format=json&dry_run=false";
+
+    let rejected = [
+        // Recurrence rules the API would not accept
+        archive(json!([record("r", "Daily", json!({"recurrence": "FREQ=DAILY\r\nX-INJECT:1"}))])),
+        // Subtasks of subtasks, within the import
+        archive(json!([
+            record("a", "Top", json!({})),
+            record("b", "Middle", json!({"parent_id": "a"})),
+            record("c", "Bottom", json!({"parent_id": "b"})),
+        ])),
+        // Or under an existing subtask
+        archive(json!([record("d", "Deeper", json!({"parent_id": subtask_id}))])),
+        // Or their own parent
+        archive(json!([record("e", "Loop", json!({"parent_id": "e"}))])),
+    ];
+    for body in rejected {
+        let response = send(&server, "POST", import, &[], &body).await;
+        assert_eq!(response.status, 400, "{}", body);
+    }
+    let (_, todos) = send_json(&server, "GET", path, Value::Null).await;
+    assert_eq!(todos["data"].as_array().unwrap().len(), 2);
+
+    // One level under an existing todo is fine
+    let body = archive(json!([record("f", "Passport", json!({"parent_id": parent_id}))]));
+    let response = send(&server, "POST", import, &[], &body).await;
+    assert_eq!(response.status, 200, "{}", response.body);
+}
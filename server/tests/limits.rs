@@ -0,0 +1,171 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{send, send_json, spawn_server_with_config};
+use serde_json::{json, Value};
+use todo_server::config::Config;
+use todo_server::limits::{Limits, Quota, RateLimiter};
+
+#[tokio::test]
+async fn users_are_rate_limited_separately() {
+    let server = spawn_server_with_config(Config {
+        limits: Limits {
+            per_user: Some(Quota { burst: 2, per_second: 0.5 }),
+            per_ip: None,
+            ..Limits::default()
+        },
+        ..Config::default()
+    })
+    .await;
+
+    for _ in 0..2 {
+        let (status, _) = send_json(&server, "GET", "/api/users/alice/todos", Value::Null).await;
+        assert_eq!(status, 200);
+    }
+    let response = send(&server, "GET", "/api/users/alice/todos", &[], "").await;
+    assert_eq!(response.status, 429);
+    assert_eq!(response.header("retry-after"), Some("2"));
+    let body: Value = serde_json::from_str(&response.body).unwrap();
+    assert_eq!(body, json!({"success": false, "data": null, "error": "Too many requests"}));
+
+    let (status, _) = send_json(&server, "GET", "/api/users/bob/todos", Value::Null).await;
+    assert_eq!(status, 200);
+}
+
+#[tokio::test]
+async fn clients_are_rate_limited_by_ip() {
+    let server = spawn_server_with_config(Config {
+        limits: Limits {
+            per_user: None,
+            per_ip: Some(Quota { burst: 3, per_second: 0.1 }),
+            ..Limits::default()
+        },
+        ..Config::default()
+    })
+    .await;
+
+    for user in ["alice", "bob", "carol"] {
+        let (status, _) = send_json(&server, "GET", &format!("/api/users/{}/todos", user), Value::Null).await;
+        assert_eq!(status, 200);
+    }
+    let response = send(&server, "GET", "/health", &[], "").await;
+    assert_eq!(response.status, 429);
+    assert_eq!(response.header("retry-after"), Some("10"));
+}
+
+#[tokio::test]
+async fn bodies_and_syncs_are_size_limited() {
+    let server = spawn_server_with_config(Config {
+        limits: Limits {
+            body_bytes: 1024,
+            max_sync_todos: 2,
+            ..Limits::default()
+        },
+        ..Config::default()
+    })
+    .await;
+    let padding = "x".repeat(2048);
+
+    let (status, body) = send_json(&server, "POST", "/api/users/alice/todos", json!({"title": padding})).await;
+    assert_eq!(status, 413);
+    assert_eq!(body["error"], "Request body too large");
+
+    // Sync has the larger limit
+    let (status, _) = send_json(
+        &server,
+        "POST",
+        "/api/users/alice/sync",
+        json!({"last_sync": null, "todos": [], "padding": padding}),
+    )
+    .await;
+    assert_eq!(status, 200);
+
+    let (status, body) = send_json(
+        &server,
+        "POST",
+        "/api/users/alice/sync",
+        json!({"last_sync": null, "todos": [{}, {}, {}]}),
+    )
+    .await;
+    assert_eq!(status, 413);
+    assert_eq!(body["error"], "A sync may carry at most 2 todos");
+}
+
+#[test]
+fn buckets_refill_over_time() {
+    let limiter = RateLimiter::default();
+    let quota = Quota { burst: 2, per_second: 4.0 };
+    let start = Instant::now();
+
+    assert!(limiter.take("alice", quota, start).is_ok());
+    assert!(limiter.take("alice", quota, start).is_ok());
+    assert_eq!(limiter.take("alice", quota, start), Err(Duration::from_millis(250)));
+    assert!(limiter.take("bob", quota, start).is_ok());
+
+    let later = start + Duration::from_millis(250);
+    assert!(limiter.take("alice", quota, later).is_ok());
+    assert!(limiter.take("alice", quota, later).is_err());
+
+    // Buckets never hold more than the burst
+    let much_later = start + Duration::from_secs(60);
+    assert!(limiter.take("alice", quota, much_later).is_ok());
+    assert!(limiter.take("alice", quota, much_later).is_ok());
+    assert!(limiter.take("alice", quota, much_later).is_err());
+}
+
+#[test]
+fn buckets_are_dropped_once_full_or_least_recently_used() {
+    let limiter = RateLimiter::default();
+    let fast = Quota { burst: 1, per_second: 1.0 };
+    let slow = Quota { burst: 1, per_second: 0.001 };
+    let start = Instant::now();
+
+    assert!(limiter.take("fast", fast, start).is_ok());
+    assert!(limiter.take("slow", slow, start).is_ok());
+    assert_eq!(limiter.len(), 2);
+
+    // Each bucket refills at its own rate, so only the fast one is full by the next sweep
+    let later = start + Duration::from_secs(120);
+    assert!(limiter.take("slow", slow, later).is_err());
+    assert_eq!(limiter.len(), 1);
+
+    // Past the cap, the least recently used bucket makes room
+    for n in 0..10_000 {
+        assert!(limiter.take(&format!("client{}", n), slow, later + Duration::from_millis(n + 1)).is_ok());
+    }
+    assert_eq!(limiter.len(), 10_000);
+    // The slow bucket went first, so it starts over full
+    assert!(limiter.take("slow", slow, later + Duration::from_secs(11)).is_ok());
+    assert_eq!(limiter.len(), 10_000);
+}
+
+#[tokio::test]
+async fn requests_with_a_token_are_limited_per_token() {
+    let server = spawn_server_with_config(Config {
+        limits: Limits {
+            per_user: Some(Quota { burst: 2, per_second: 0.01 }),
+            per_ip: None,
+            ..Limits::default()
+        },
+        ..Config::default()
+    })
+    .await;
+    let (_, token) = send_json(
+        &server,
+        "POST",
+        "/api/users/alice/tokens",
+        json!({"name": "backup", "scopes": ["todos:read"]}),
+    )
+    .await;
+    let bearer = format!("Bearer {}", token["data"]["token"].as_str().unwrap());
+
+    // Someone else uses up the bucket of the user in the path
+    let (status, _) = send_json(&server, "GET", "/api/users/alice/todos", Value::Null).await;
+    assert_eq!(status, 200);
+    let response = send(&server, "GET", "/api/users/alice/todos", &[], "").await;
+    assert_eq!(response.status, 429);
+
+    let response = send(&server, "GET", "/api/users/alice/todos", &[("authorization", &bearer)], "").await;
+    assert_eq!(response.status, 200);
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use std::io;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use common::{send, send_json, spawn_server};
+use serde_json::{json, Value};
+
+/// Log lines written by the server, as JSON
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Captured {
+    fn lines(&self) -> Vec<Value> {
+        let buffer = self.0.lock().unwrap();
+        String::from_utf8_lossy(&buffer)
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+}
+
+/// Log every test's requests in the JSON format the server uses in production
+fn captured_logs() -> &'static Captured {
+    static LOGS: OnceLock<Captured> = OnceLock::new();
+    LOGS.get_or_init(|| {
+        let logs = Captured::default();
+        let writer = logs.clone();
+        tracing_subscriber::fmt()
+            .json()
+            .with_writer(move || writer.clone())
+            .init();
+        logs
+    })
+}
+
+#[tokio::test]
+async fn request_ids_are_propagated_or_generated() {
+    let server = spawn_server().await;
+
+    let response = send(&server, "GET", "/health", &[("X-Request-Id", "client-42")], "").await;
+    assert_eq!(response.header("X-Request-Id"), Some("client-42"));
+
+    let response = send(&server, "GET", "/health", &[], "").await;
+    let generated = response.header("X-Request-Id").unwrap();
+    assert!(uuid::Uuid::parse_str(generated).is_ok(), "{}", generated);
+
+    let too_long = "x".repeat(200);
+    let response = send(&server, "GET", "/health", &[("X-Request-Id", &too_long)], "").await;
+    assert_ne!(response.header("X-Request-Id"), Some(too_long.as_str()));
+}
+
+#[tokio::test]
+async fn sync_outcomes_are_logged_with_request_context() {
+    let logs = captured_logs();
+    let server = spawn_server().await;
+    let (_, todo) = send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Milk"})).await;
+    let mut synced = todo["data"].clone();
+    synced["title"] = json!("Oat milk");
+    synced["updated_at"] = json!("2999-01-01T00:00:00Z");
+
+    let body = json!({"todos": [synced]}).to_string();
+    let headers = [
+        ("Content-Type", "application/json"),
+        ("X-Request-Id", "sync-from-phone"),
+        ("X-Device-Id", "phone"),
+    ];
+    let response = send(&server, "POST", "/api/users/alice/sync", &headers, &body).await;
+    assert_eq!(response.status, 200);
+
+    let lines = logs.lines();
+    let in_request = |line: &&Value| line["span"]["request_id"] == "sync-from-phone";
+    let completed = lines
+        .iter()
+        .filter(in_request)
+        .find(|line| line["fields"]["message"] == "Sync completed")
+        .expect("the sync's outcome is logged");
+    assert_eq!(completed["level"], "INFO");
+    assert_eq!(completed["fields"]["pushed"], 1);
+    assert_eq!(completed["fields"]["conflicts"], 0);
+    assert_eq!(completed["span"]["user_id"], "alice");
+    assert_eq!(completed["span"]["device_id"], "phone");
+    assert_eq!(completed["span"]["route"], "/api/users/{user_id}/sync");
+
+    // The response is logged in the same span, with its status
+    let finished = lines
+        .iter()
+        .filter(in_request)
+        .find(|line| line["fields"]["message"] == "finished processing request")
+        .expect("the response is logged");
+    assert_eq!(finished["fields"]["status"], 200);
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use common::{send, send_json, spawn_server, spawn_server_with_config};
+use serde_json::{json, Value};
+use todo_server::config::Config;
+
+/// The value of the sample named `sample`, labels included
+fn sample(metrics: &str, sample: &str) -> Option<f64> {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(sample)?.strip_prefix(' ')?.parse().ok())
+}
+
+#[tokio::test]
+async fn requests_and_syncs_are_recorded() {
+    let server = spawn_server().await;
+    let (_, milk) = send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Milk"})).await;
+    let (_, bread) = send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Bread"})).await;
+    send_json(&server, "GET", "/nowhere", Value::Null).await;
+
+    // One offline edit wins, the other is older than the server's copy
+    let mut newer = milk["data"].clone();
+    newer["title"] = json!("Oat milk");
+    newer["updated_at"] = json!("2999-01-01T00:00:00Z");
+    let mut older = bread["data"].clone();
+    older["title"] = json!("Rye bread");
+    older["updated_at"] = json!("2000-01-01T00:00:00Z");
+    let (status, _) = send_json(&server, "POST", "/api/users/alice/sync", json!({"todos": [newer, older]})).await;
+    assert_eq!(status, 200);
+
+    let response = send(&server, "GET", "/metrics", &[], "").await;
+    assert_eq!(response.status, 200);
+    assert!(response.header("Content-Type").unwrap().starts_with("application/openmetrics-text"));
+    let metrics = response.body;
+
+    let created = r#"http_requests_total{method="POST",route="/api/users/{user_id}/todos",status="200"}"#;
+    assert_eq!(sample(&metrics, created), Some(2.0), "{}", metrics);
+    let missing = r#"http_requests_total{method="GET",route="unmatched",status="404"}"#;
+    assert_eq!(sample(&metrics, missing), Some(1.0));
+    let timed = r#"http_request_duration_seconds_count{method="POST",route="/api/users/{user_id}/sync"}"#;
+    assert_eq!(sample(&metrics, timed), Some(1.0));
+    assert!(!metrics.contains("alice"), "user ids stay out of labels");
+
+    assert_eq!(sample(&metrics, "sync_requests_total"), Some(1.0));
+    assert_eq!(sample(&metrics, "sync_todos_pushed_total"), Some(1.0));
+    assert_eq!(sample(&metrics, "sync_conflicts_resolved_total"), Some(1.0));
+    assert_eq!(sample(&metrics, "sync_todos_pulled_total"), Some(2.0));
+    assert_eq!(sample(&metrics, "sync_duration_seconds_count"), Some(1.0));
+
+    // The scrape itself is in flight while the metrics are rendered
+    assert_eq!(sample(&metrics, "http_requests_in_flight"), Some(1.0));
+    assert_eq!(sample(&metrics, "db_pool_max_connections"), Some(5.0));
+    assert!(sample(&metrics, "db_pool_connections").unwrap() >= 1.0);
+    assert!(sample(&metrics, "db_pool_idle_connections").is_some());
+}
+
+#[tokio::test]
+async fn metrics_can_be_turned_off() {
+    let mut config = Config::default();
+    config.features.metrics = false;
+    let server = spawn_server_with_config(config).await;
+
+    let response = send(&server, "GET", "/metrics", &[], "").await;
+    assert_eq!(response.status, 404);
+}
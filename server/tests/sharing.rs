@@ -0,0 +1,190 @@
+mod common;
+
+use common::{send_json, spawn_server, TestServer};
+use serde_json::{json, Value};
+
+/// Create a list owned by alice and have bob join it with `role`
+async fn shared_list(server: &TestServer, role: &str) -> String {
+    let (status, list) = send_json(server, "POST", "/api/users/alice/lists", json!({"name": "Groceries"})).await;
+    assert_eq!(status, 200);
+    let list_id = list["data"]["id"].as_str().unwrap().to_string();
+
+    let (status, _) = send_json(
+        server,
+        "POST",
+        &format!("/api/users/alice/lists/{}/invitations", list_id),
+        json!({"invitee": "bob", "role": role}),
+    )
+    .await;
+    assert_eq!(status, 200);
+
+    let (_, invitations) = send_json(server, "GET", "/api/users/bob/invitations", Value::Null).await;
+    let invitation_id = invitations["data"][0]["id"].as_str().unwrap();
+    let path = format!("/api/users/bob/invitations/{}/accept", invitation_id);
+    let (status, joined) = send_json(server, "POST", &path, Value::Null).await;
+    assert_eq!(status, 200);
+    assert_eq!(joined["data"]["role"], role);
+
+    list_id
+}
+
+fn titles(todos: &Value) -> Vec<&str> {
+    todos
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|todo| todo["title"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn members_see_and_edit_shared_todos() {
+    let server = spawn_server().await;
+    let list_id = shared_list(&server, "editor").await;
+
+    let (status, todo) = send_json(
+        &server,
+        "POST",
+        "/api/users/alice/todos",
+        json!({"title": "Milk", "list_id": list_id, "assignee_id": "bob"}),
+    )
+    .await;
+    assert_eq!(status, 200);
+    let todo_id = todo["data"]["id"].as_str().unwrap();
+    send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Private"})).await;
+
+    let (_, todos) = send_json(&server, "GET", "/api/users/bob/todos", Value::Null).await;
+    assert_eq!(titles(&todos["data"]), vec!["Milk"]);
+    assert_eq!(todos["data"][0]["assignee_id"], "bob");
+
+    let path = format!("/api/users/bob/todos/{}", todo_id);
+    let (status, _) = send_json(&server, "PUT", &path, json!({"completed": true})).await;
+    assert_eq!(status, 200);
+
+    // Only members can be assigned
+    let (status, _) = send_json(&server, "PUT", &path, json!({"assignee_id": "carol"})).await;
+    assert_eq!(status, 400);
+
+    // Outsiders cannot tell the todo exists
+    let (status, _) = send_json(&server, "PUT", "/api/users/carol/todos/missing", json!({"completed": true})).await;
+    assert_eq!(status, 404);
+    let (status, _) = send_json(&server, "DELETE", &format!("/api/users/carol/todos/{}", todo_id), Value::Null).await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn viewers_cannot_change_shared_todos() {
+    let server = spawn_server().await;
+    let list_id = shared_list(&server, "viewer").await;
+
+    let (_, todo) = send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Milk", "list_id": list_id})).await;
+    let todo_id = todo["data"]["id"].as_str().unwrap();
+
+    let (status, _) = send_json(&server, "PUT", &format!("/api/users/bob/todos/{}", todo_id), json!({"title": "Beer"})).await;
+    assert_eq!(status, 403);
+    let (status, _) = send_json(&server, "DELETE", &format!("/api/users/bob/todos/{}", todo_id), Value::Null).await;
+    assert_eq!(status, 403);
+    let (status, _) = send_json(&server, "POST", "/api/users/bob/todos", json!({"title": "Beer", "list_id": list_id})).await;
+    assert_eq!(status, 403);
+
+    // Nor manage the list
+    let (status, _) = send_json(
+        &server,
+        "POST",
+        &format!("/api/users/bob/lists/{}/invitations", list_id),
+        json!({"invitee": "carol"}),
+    )
+    .await;
+    assert_eq!(status, 403);
+
+    // Changes a viewer makes offline are dropped on sync
+    let mut changed = todo["data"].clone();
+    changed["title"] = json!("Beer");
+    changed["updated_at"] = json!("2999-01-01T00:00:00Z");
+    let (status, synced) = send_json(&server, "POST", "/api/users/bob/sync", json!({"todos": [changed]})).await;
+    assert_eq!(status, 200);
+    assert_eq!(titles(&synced["data"]["todos"]), vec!["Milk"]);
+}
+
+#[tokio::test]
+async fn sync_delivers_todos_of_joined_lists() {
+    let server = spawn_server().await;
+    let (_, list) = send_json(&server, "POST", "/api/users/alice/lists", json!({"name": "Trip"})).await;
+    let list_id = list["data"]["id"].as_str().unwrap();
+    send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Tickets", "list_id": list_id})).await;
+
+    // Bob has synced before he joins, so the todo is older than his last sync
+    let (_, synced) = send_json(&server, "POST", "/api/users/bob/sync", json!({"todos": []})).await;
+    let last_sync = synced["data"]["sync_time"].clone();
+    assert_eq!(titles(&synced["data"]["todos"]), Vec::<&str>::new());
+
+    // Invitations by email reach whoever sets that address later
+    send_json(
+        &server,
+        "POST",
+        &format!("/api/users/alice/lists/{}/invitations", list_id),
+        json!({"invitee": "Bob@Example.com"}),
+    )
+    .await;
+    let (status, _) = send_json(&server, "PUT", "/api/users/bob/settings", json!({"email": "bob@example.com"})).await;
+    assert_eq!(status, 200);
+    let (_, invitations) = send_json(&server, "GET", "/api/users/bob/invitations", Value::Null).await;
+    assert_eq!(invitations["data"][0]["list_name"], "Trip");
+    let invitation_id = invitations["data"][0]["id"].as_str().unwrap();
+    send_json(&server, "POST", &format!("/api/users/bob/invitations/{}/accept", invitation_id), Value::Null).await;
+
+    let (_, synced) = send_json(&server, "POST", "/api/users/bob/sync", json!({"last_sync": last_sync, "todos": []})).await;
+    assert_eq!(titles(&synced["data"]["todos"]), vec!["Tickets"]);
+    assert_eq!(synced["data"]["lists"][0]["name"], "Trip");
+    assert_eq!(synced["data"]["lists"][0]["role"], "editor");
+
+    // Todos bob adds to the list through sync reach alice
+    let todo = json!({
+        "id": "passport",
+        "title": "Passport",
+        "completed": false,
+        "priority": "high",
+        "created_at": "2024-01-01T00:00:00Z",
+        "updated_at": "2024-01-01T00:00:00Z",
+        "list_id": list_id,
+    });
+    send_json(&server, "POST", "/api/users/bob/sync", json!({"todos": [todo]})).await;
+    let (_, todos) = send_json(&server, "GET", "/api/users/alice/todos", Value::Null).await;
+    assert!(titles(&todos["data"]).contains(&"Passport"));
+}
+
+#[tokio::test]
+async fn declined_invitations_and_leaving() {
+    let server = spawn_server().await;
+    let list_id = shared_list(&server, "editor").await;
+
+    let invite = format!("/api/users/alice/lists/{}/invitations", list_id);
+    let (status, _) = send_json(&server, "POST", &invite, json!({"invitee": "bob"})).await;
+    assert_eq!(status, 409, "bob is already a member");
+
+    send_json(&server, "POST", &invite, json!({"invitee": "carol"})).await;
+    let (_, invitations) = send_json(&server, "GET", "/api/users/carol/invitations", Value::Null).await;
+    let invitation_id = invitations["data"][0]["id"].as_str().unwrap();
+    let (status, _) = send_json(
+        &server,
+        "POST",
+        &format!("/api/users/carol/invitations/{}/decline", invitation_id),
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, 200);
+    let (_, invitations) = send_json(&server, "GET", "/api/users/carol/invitations", Value::Null).await;
+    assert_eq!(invitations["data"], json!([]));
+
+    // The last owner cannot leave, but other members can
+    let members = format!("/api/users/alice/lists/{}/members", list_id);
+    let (status, _) = send_json(&server, "DELETE", &format!("{}/alice", members), Value::Null).await;
+    assert_eq!(status, 409);
+    let (status, _) = send_json(&server, "DELETE", &format!("/api/users/bob/lists/{}/members/bob", list_id), Value::Null).await;
+    assert_eq!(status, 200);
+
+    let (_, remaining) = send_json(&server, "GET", &members, Value::Null).await;
+    assert_eq!(remaining["data"].as_array().unwrap().len(), 1);
+    let (_, lists) = send_json(&server, "GET", "/api/users/bob/lists", Value::Null).await;
+    assert_eq!(lists["data"], json!([]));
+}
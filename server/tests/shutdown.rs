@@ -0,0 +1,75 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{send_json, spawn_server_until};
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use todo_server::shutdown::Shutdown;
+
+#[tokio::test]
+async fn in_flight_sync_completes_during_shutdown() {
+    let shutdown = Shutdown::new();
+    let (server, stopped) = spawn_server_until(shutdown.clone(), Duration::from_secs(5)).await;
+    let (_, todo) = send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Milk"})).await;
+    let mut synced = todo["data"].clone();
+    synced["title"] = json!("Oat milk");
+    synced["updated_at"] = json!("2999-01-01T00:00:00Z");
+    let body = json!({"todos": [synced]}).to_string();
+
+    // Start a sync but hold back half of its body while the server shuts down
+    let (first, rest) = body.split_at(body.len() / 2);
+    let mut stream = TcpStream::connect(server.addr).await.unwrap();
+    let head = format!(
+        "POST /api/users/alice/sync HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await.unwrap();
+    stream.write_all(first.as_bytes()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    shutdown.trigger();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(TcpStream::connect(server.addr).await.is_err(), "new connections are refused");
+    assert!(!stopped.is_finished(), "the server waits for the sync");
+
+    stream.write_all(rest.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+
+    tokio::time::timeout(Duration::from_secs(2), stopped)
+        .await
+        .expect("the server stops once the sync is done")
+        .unwrap()
+        .unwrap();
+    let (title,): (String,) = sqlx::query_as("SELECT title FROM todos WHERE id = ?")
+        .bind(todo["data"]["id"].as_str().unwrap())
+        .fetch_one(&server.pool)
+        .await
+        .unwrap();
+    assert_eq!(title, "Oat milk");
+    server.pool.close().await;
+}
+
+#[tokio::test]
+async fn shutdown_gives_up_on_connections_after_the_timeout() {
+    let shutdown = Shutdown::new();
+    let (server, stopped) = spawn_server_until(shutdown.clone(), Duration::from_millis(200)).await;
+
+    // A request that never finishes sending its body
+    let mut stream = TcpStream::connect(server.addr).await.unwrap();
+    let head = "POST /api/users/alice/sync HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 100\r\n\r\n{";
+    stream.write_all(head.as_bytes()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    shutdown.trigger();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!stopped.is_finished(), "the server waits for the request");
+    tokio::time::timeout(Duration::from_secs(2), stopped)
+        .await
+        .expect("the server stops after the drain timeout")
+        .unwrap()
+        .unwrap();
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use common::{send, send_json, spawn_server};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn subtasks_are_one_level_deep() {
+    let server = spawn_server().await;
+    let path = "/api/users/alice/todos";
+    let (_, parent) = send_json(&server, "POST", path, json!({"title": "Trip"})).await;
+    let parent_id = parent["data"]["id"].as_str().unwrap().to_string();
+
+    let (status, child) = send_json(&server, "POST", path, json!({"title": "Pack", "parent_id": parent_id})).await;
+    assert_eq!(status, 200);
+    assert_eq!(child["data"]["parent_id"], parent_id.as_str());
+    let child_id = child["data"]["id"].as_str().unwrap().to_string();
+
+    // Subtasks cannot have subtasks, nor can todos with subtasks become one
+    let (status, _) = send_json(&server, "POST", path, json!({"title": "Socks", "parent_id": child_id})).await;
+    assert_eq!(status, 400);
+    let (_, other) = send_json(&server, "POST", path, json!({"title": "Other"})).await;
+    let other_path = format!("{}/{}", path, other["data"]["id"].as_str().unwrap());
+    let (status, _) = send_json(&server, "PUT", &format!("{}/{}", path, parent_id), json!({"parent_id": other["data"]["id"]})).await;
+    assert_eq!(status, 400);
+    let (status, _) = send_json(&server, "PUT", &other_path, json!({"parent_id": other["data"]["id"]})).await;
+    assert_eq!(status, 400);
+
+    // Parents must exist and belong to the user
+    let (status, _) = send_json(&server, "POST", path, json!({"title": "Lost", "parent_id": "missing"})).await;
+    assert_eq!(status, 400);
+    let (status, _) = send_json(&server, "POST", "/api/users/bob/todos", json!({"title": "Sneaky", "parent_id": parent_id})).await;
+    assert_eq!(status, 400);
+
+    // An empty string makes the subtask a top-level todo again
+    let (status, child) = send_json(&server, "PUT", &format!("{}/{}", path, child_id), json!({"parent_id": ""})).await;
+    assert_eq!(status, 200);
+    assert_eq!(child["data"]["parent_id"], Value::Null);
+}
+
+#[tokio::test]
+async fn exports_keep_subtasks() {
+    let server = spawn_server().await;
+    let path = "/api/users/alice/todos";
+    let (_, parent) = send_json(&server, "POST", path, json!({"title": "Trip"})).await;
+    let parent_id = parent["data"]["id"].as_str().unwrap().to_string();
+    send_json(&server, "POST", path, json!({"title": "Pack", "parent_id": parent_id})).await;
+
+    let export = send(&server, "GET", "/api/users/alice/export?format=markdown", &[], "").await;
+    assert_eq!(export.status, 200);
+    assert!(export.body.contains("\n  - [ ] Pack"), "{}", export.body);
+    let import = send(&server, "POST", "/api/users/bob/import?format=markdown&dry_run=false", &[], &export.body).await;
+    assert_eq!(import.status, 200, "{}", import.body);
+
+    let (_, todos) = send_json(&server, "GET", "/api/users/bob/todos", Value::Null).await;
+    let todos = todos["data"].as_array().unwrap();
+    let trip = todos.iter().find(|todo| todo["title"] == "Trip").unwrap();
+    let pack = todos.iter().find(|todo| todo["title"] == "Pack").unwrap();
+    assert_eq!(pack["parent_id"], trip["id"]);
+}
+
+#[tokio::test]
+async fn sync_checks_subtasks_and_recurrence() {
+    let server = spawn_server().await;
+    let (_, foreign) = send_json(&server, "POST", "/api/users/bob/todos", json!({"title": "Bob's"})).await;
+    let todo = |id: &str, parent_id: Option<&str>, recurrence: Option<&str>| {
+        json!({
+            "id": id,
+            "title": id,
+            "completed": false,
+            "priority": "medium",
+            "created_at": "2024-01-01T00:00:00Z",
+            "updated_at": "2024-01-01T00:00:00Z",
+            "parent_id": parent_id,
+            "recurrence": recurrence,
+        })
+    };
+
+    let todos = vec![
+        // Sent before the parent it was created with
+        todo("pack", Some("trip"), None),
+        todo("trip", None, None),
+        todo("socks", Some("pack"), None),
+        todo("sneaky", foreign["data"]["id"].as_str(), None),
+        todo("water", None, Some("FREQ=DAILY\r\nATTENDEE:evil")),
+        todo("laundry", None, Some("FREQ=WEEKLY")),
+    ];
+    let (status, _) = send_json(&server, "POST", "/api/users/alice/sync", json!({"todos": todos})).await;
+    assert_eq!(status, 200);
+
+    let (_, listed) = send_json(&server, "GET", "/api/users/alice/todos", Value::Null).await;
+    let mut ids: Vec<&str> = listed["data"].as_array().unwrap().iter().map(|todo| todo["id"].as_str().unwrap()).collect();
+    ids.sort();
+    assert_eq!(ids, vec!["laundry", "pack", "trip"]);
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::time::Duration;
+
+use common::{send, send_json, spawn_server};
+use opentelemetry::trace::{SpanId, SpanKind, TraceId, Tracer, TracerProvider};
+use opentelemetry::Value as AttributeValue;
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+use serde_json::json;
+use todo_server::config::TelemetryConfig;
+use todo_server::telemetry;
+use tracing_subscriber::layer::SubscriberExt;
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const PARENT_ID: &str = "00f067aa0ba902b7";
+
+fn attribute<'a>(span: &'a SpanData, key: &str) -> Option<&'a AttributeValue> {
+    span.attributes.iter().find(|kv| kv.key.as_str() == key).map(|kv| &kv.value)
+}
+
+#[tokio::test]
+async fn syncs_continue_the_clients_trace() {
+    let exporter = InMemorySpanExporter::default();
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .with_resource(telemetry::resource(&TelemetryConfig::default()))
+        .build();
+    // The test runtime runs the server on this thread, so a scoped subscriber sees it
+    let subscriber = tracing_subscriber::registry().with(telemetry::layer(&provider));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let server = spawn_server().await;
+    let (_, todo) = send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Milk"})).await;
+    let body = json!({"todos": [todo["data"]]}).to_string();
+    let traceparent = format!("00-{}-{}-01", TRACE_ID, PARENT_ID);
+    let headers = [("Content-Type", "application/json"), ("traceparent", traceparent.as_str())];
+    let response = send(&server, "POST", "/api/users/alice/sync", &headers, &body).await;
+    assert_eq!(response.status, 200);
+
+    // The request span ends once the response has been written out
+    let mut sync = None;
+    for _ in 0..50 {
+        sync = exporter
+            .get_finished_spans()
+            .unwrap()
+            .into_iter()
+            .find(|span| span.name == "POST /api/users/{user_id}/sync");
+        if sync.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let sync = sync.expect("the sync's span is exported");
+
+    assert_eq!(sync.span_context.trace_id(), TraceId::from_hex(TRACE_ID).unwrap());
+    assert_eq!(sync.parent_span_id.to_string(), PARENT_ID);
+    assert_eq!(sync.span_kind, SpanKind::Server);
+    assert_eq!(attribute(&sync, "user_id"), Some(&AttributeValue::from("alice")));
+    assert_eq!(
+        attribute(&sync, "route"),
+        Some(&AttributeValue::from("/api/users/{user_id}/sync"))
+    );
+
+    // Requests without a traceparent start traces of their own
+    let other = exporter
+        .get_finished_spans()
+        .unwrap()
+        .into_iter()
+        .find(|span| span.name == "POST /api/users/{user_id}/todos")
+        .expect("the create's span is exported");
+    assert_ne!(other.span_context.trace_id(), sync.span_context.trace_id());
+    assert_eq!(other.parent_span_id, SpanId::INVALID);
+}
+
+#[test]
+fn spans_are_posted_to_the_configured_collector() {
+    assert!(telemetry::tracer_provider(&TelemetryConfig::default()).unwrap().is_none());
+
+    // A collector stand-in answering one export
+    let collector = TcpListener::bind("127.0.0.1:0").unwrap();
+    let config = TelemetryConfig {
+        otlp_endpoint: Some(format!("http://{}/", collector.local_addr().unwrap())),
+        service_name: "todo-server-test".to_string(),
+    };
+    let received = std::thread::spawn(move || {
+        let (stream, _) = collector.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut head = Vec::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            head.push(line.trim_end().to_string());
+        }
+        let length: usize = head
+            .iter()
+            .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length: ").map(str::to_string))
+            .unwrap()
+            .parse()
+            .unwrap();
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+            .unwrap();
+        (head, body)
+    });
+
+    let provider = telemetry::tracer_provider(&config).unwrap().expect("a collector is configured");
+    drop(provider.tracer("test").start("sync"));
+    provider.force_flush().unwrap();
+
+    let (head, body) = received.join().unwrap();
+    assert_eq!(head[0], "POST /v1/traces HTTP/1.1");
+    assert!(head.iter().any(|line| line.eq_ignore_ascii_case("content-type: application/x-protobuf")), "{:?}", head);
+    // The protobuf payload carries the span and service names as plain strings
+    let payload = String::from_utf8_lossy(&body);
+    assert!(payload.contains("todo-server-test"));
+    assert!(payload.contains("sync"));
+    provider.shutdown().ok();
+}
@@ -0,0 +1,93 @@
+use std::net::SocketAddr;
+
+use axum::serve::ListenerExt;
+use todo_server::blobs::FsBlobStore;
+use todo_server::config::{Config, TlsConfig};
+use todo_server::db;
+use todo_server::tls::{self, Tls, TlsListener};
+use todo_shared::pinning;
+use tokio::net::TcpListener;
+
+/// Fingerprint of the certificate the server presents on a new connection
+async fn served_fingerprint(addr: SocketAddr) -> String {
+    let client = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .tls_info(true)
+        .build()
+        .unwrap();
+    let response = client.get(format!("https://{}/health", addr)).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    let info = response.extensions().get::<reqwest::tls::TlsInfo>().unwrap();
+    pinning::fingerprint(info.peer_certificate().unwrap())
+}
+
+#[tokio::test]
+async fn serves_https_and_reloads_changed_certificates() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = TlsConfig {
+        cert_path: Some(dir.path().join("tls/cert.pem")),
+        key_path: Some(dir.path().join("tls/key.pem")),
+        self_signed: true,
+        ..TlsConfig::default()
+    };
+    let tls = Tls::load(&config).unwrap();
+    let first = tls.fingerprint();
+
+    let pool = db::connect(&format!("sqlite:{}?mode=rwc", dir.path().join("todos.db").display()))
+        .await
+        .unwrap();
+    let app = todo_server::app(pool, std::sync::Arc::new(FsBlobStore::new(dir.path().join("blobs"))), &Config::default());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let listener = TlsListener::new(listener, tls.acceptor()).unwrap().tap_io(|_| {});
+    tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+            .await
+            .unwrap();
+    });
+
+    assert_eq!(served_fingerprint(addr).await, first);
+    assert!(!tls.reload().unwrap(), "unchanged files are not reloaded");
+
+    // A broken certificate is rejected and the old one kept
+    std::fs::write(config.cert_path.as_ref().unwrap(), "not a certificate").unwrap();
+    assert!(tls.reload().is_err());
+    assert_eq!(served_fingerprint(addr).await, first);
+
+    // A renewed certificate is served to new connections
+    tls::generate_self_signed(
+        &["localhost".to_string()],
+        config.cert_path.as_ref().unwrap(),
+        config.key_path.as_ref().unwrap(),
+    )
+    .unwrap();
+    assert!(tls.reload().unwrap());
+    let second = tls.fingerprint();
+    assert_ne!(second, first);
+    assert_eq!(served_fingerprint(addr).await, second);
+}
+
+#[test]
+fn mismatched_keys_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let names = ["localhost".to_string()];
+    tls::generate_self_signed(&names, &dir.path().join("a.pem"), &dir.path().join("a.key")).unwrap();
+    tls::generate_self_signed(&names, &dir.path().join("b.pem"), &dir.path().join("b.key")).unwrap();
+
+    let config = TlsConfig {
+        cert_path: Some(dir.path().join("a.pem")),
+        key_path: Some(dir.path().join("b.key")),
+        ..TlsConfig::default()
+    };
+    let error = Tls::load(&config).err().unwrap();
+    assert!(error.to_string().starts_with("certificate does not match the key"), "{}", error);
+
+    // Without self_signed, missing files are an error rather than generated
+    let config = TlsConfig {
+        cert_path: Some(dir.path().join("missing.pem")),
+        key_path: Some(dir.path().join("missing.key")),
+        ..TlsConfig::default()
+    };
+    assert!(Tls::load(&config).is_err());
+    assert!(!dir.path().join("missing.pem").exists());
+}
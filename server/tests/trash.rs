@@ -0,0 +1,113 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use common::{send_json, spawn_server};
+use serde_json::{json, Value};
+use todo_server::db;
+
+#[tokio::test]
+async fn todos_trashed_offline_stay_trashed_after_sync() {
+    let server = spawn_server().await;
+    let sync = "/api/users/alice/sync";
+    let (_, created) = send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Old chore"})).await;
+    let todo_id = created["data"]["id"].as_str().unwrap().to_string();
+
+    // The device pulls the todo, then trashes it while offline
+    let (_, pulled) = send_json(&server, "POST", sync, json!({"todos": []})).await;
+    let mut trashed = pulled["data"]["todos"][0].clone();
+    let later = Utc::now() + Duration::minutes(1);
+    trashed["deleted_at"] = json!(later);
+    trashed["updated_at"] = json!(later);
+
+    let (status, pushed) = send_json(&server, "POST", sync, json!({"todos": [trashed]})).await;
+    assert_eq!(status, 200);
+    assert_eq!(pushed["data"]["todos"][0]["id"], todo_id.as_str());
+    assert!(pushed["data"]["todos"][0]["deleted_at"].is_string());
+
+    // The server keeps it in the trash rather than handing it back as live
+    let (_, todos) = send_json(&server, "GET", "/api/users/alice/todos", Value::Null).await;
+    assert_eq!(todos["data"], json!([]));
+    let (_, trash) = send_json(&server, "GET", "/api/users/alice/trash", Value::Null).await;
+    assert_eq!(trash["data"][0]["id"], todo_id.as_str());
+    let (_, pulled) = send_json(&server, "POST", sync, json!({"todos": []})).await;
+    assert!(pulled["data"]["todos"][0]["deleted_at"].is_string());
+}
+
+#[tokio::test]
+async fn purging_the_trash_drops_comments_and_history() {
+    let server = spawn_server().await;
+    let (_, created) = send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Old chore"})).await;
+    let todo_id = created["data"]["id"].as_str().unwrap().to_string();
+    let comments = format!("/api/users/alice/todos/{}/comments", todo_id);
+    let (status, _) = send_json(&server, "POST", &comments, json!({"body": "Later"})).await;
+    assert_eq!(status, 200);
+    send_json(&server, "DELETE", &format!("/api/users/alice/todos/{}", todo_id), Value::Null).await;
+
+    let purged = db::purge_trash(&server.pool, Utc::now() + Duration::minutes(1)).await.unwrap();
+    assert_eq!(purged, 1);
+    for table in ["todos", "comments", "todo_revisions"] {
+        let column = if table == "todos" { "id" } else { "todo_id" };
+        let left: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {} WHERE {} = ?", table, column))
+            .bind(&todo_id)
+            .fetch_one(&server.pool)
+            .await
+            .unwrap();
+        assert_eq!(left, 0, "{} left behind", table);
+    }
+}
+
+#[tokio::test]
+async fn purges_survive_sync_both_ways() {
+    let server = spawn_server().await;
+    let sync = "/api/users/alice/sync";
+    let todos = "/api/users/alice/todos";
+    let (_, first) = send_json(&server, "POST", todos, json!({"title": "Purged here"})).await;
+    let (_, second) = send_json(&server, "POST", todos, json!({"title": "Purged on a device"})).await;
+    let first_id = first["data"]["id"].as_str().unwrap().to_string();
+    let second_id = second["data"]["id"].as_str().unwrap().to_string();
+    send_json(&server, "DELETE", &format!("{}/{}", todos, first_id), Value::Null).await;
+    send_json(&server, "DELETE", &format!("{}/{}", todos, second_id), Value::Null).await;
+
+    // A device still holds both trashed todos when the trash is emptied here
+    let (_, pulled) = send_json(&server, "POST", sync, json!({"todos": []})).await;
+    let stale = pulled["data"]["todos"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|todo| todo["id"] == first_id.as_str())
+        .unwrap()
+        .clone();
+    let (status, emptied) = send_json(&server, "DELETE", "/api/users/alice/trash", Value::Null).await;
+    assert_eq!(status, 200);
+    assert_eq!(emptied["data"], 2);
+
+    // Pushing its stale copy does not bring the todo back, and the device hears of the purge
+    let (status, synced) = send_json(&server, "POST", sync, json!({"todos": [stale]})).await;
+    assert_eq!(status, 200);
+    assert!(synced["data"]["todos"].as_array().unwrap().is_empty());
+    let purged = synced["data"]["purged"].as_array().unwrap();
+    assert!(purged.contains(&json!(first_id)));
+    let (_, trash) = send_json(&server, "GET", "/api/users/alice/trash", Value::Null).await;
+    assert_eq!(trash["data"], json!([]));
+
+    // A purge made on a device reaches the server, unless the todo was restored meanwhile
+    let (_, third) = send_json(&server, "POST", todos, json!({"title": "Restored elsewhere"})).await;
+    let third_id = third["data"]["id"].as_str().unwrap().to_string();
+    let (_, fourth) = send_json(&server, "POST", todos, json!({"title": "Purged offline"})).await;
+    let fourth_id = fourth["data"]["id"].as_str().unwrap().to_string();
+    send_json(&server, "DELETE", &format!("{}/{}", todos, fourth_id), Value::Null).await;
+    let (status, synced) = send_json(&server, "POST", sync, json!({"todos": [], "purged": [third_id, fourth_id]})).await;
+    assert_eq!(status, 200);
+    let purged = synced["data"]["purged"].as_array().unwrap();
+    assert!(purged.contains(&json!(fourth_id)));
+    assert!(!purged.contains(&json!(third_id)));
+    let (_, live) = send_json(&server, "GET", todos, Value::Null).await;
+    let titles: Vec<&str> = live["data"].as_array().unwrap().iter().map(|todo| todo["title"].as_str().unwrap()).collect();
+    assert_eq!(titles, vec!["Restored elsewhere"]);
+    let (_, trash) = send_json(&server, "GET", "/api/users/alice/trash", Value::Null).await;
+    assert_eq!(trash["data"], json!([]));
+
+    // Nobody else hears of alice's purges
+    let (_, synced) = send_json(&server, "POST", "/api/users/mallory/sync", json!({"todos": []})).await;
+    assert_eq!(synced["data"]["purged"], json!([]));
+}
@@ -0,0 +1,146 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use common::{send_json, spawn_server};
+use serde_json::{json, Value};
+
+fn count(counts: &Value, id: &str) -> u64 {
+    counts["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|view| view["id"] == id)
+        .unwrap_or_else(|| panic!("no view {}", id))["count"]
+        .as_u64()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn counts_smart_views() {
+    let server = spawn_server().await;
+    let path = "/api/users/alice/todos";
+    let today = Utc::now().date_naive();
+    let all_day = |days: i64| format!("{}T00:00:00Z", today + Duration::days(days));
+
+    send_json(&server, "POST", path, json!({"title": "late", "due_date": all_day(-2), "all_day": true})).await;
+    send_json(&server, "POST", path, json!({"title": "now", "due_date": all_day(0), "all_day": true})).await;
+    send_json(&server, "POST", path, json!({"title": "soon", "due_date": all_day(3), "all_day": true})).await;
+    send_json(&server, "POST", path, json!({"title": "someday", "priority": "high"})).await;
+    let (_, done) = send_json(&server, "POST", path, json!({"title": "done"})).await;
+    let done_id = done["data"]["id"].as_str().unwrap();
+    send_json(&server, "PUT", &format!("{}/{}", path, done_id), json!({"completed": true})).await;
+
+    let (status, counts) = send_json(&server, "GET", "/api/users/alice/views", Value::Null).await;
+    assert_eq!(status, 200);
+    assert_eq!(count(&counts, "today"), 1);
+    assert_eq!(count(&counts, "next_7_days"), 2);
+    assert_eq!(count(&counts, "overdue"), 1);
+    assert_eq!(count(&counts, "no_due_date"), 1);
+    assert_eq!(count(&counts, "high_priority"), 1);
+    assert_eq!(count(&counts, "recently_completed"), 1);
+
+    let (status, todos) = send_json(&server, "GET", "/api/users/alice/views/overdue/todos", Value::Null).await;
+    assert_eq!(status, 200);
+    assert_eq!(todos["data"][0]["title"], "late");
+
+    // Other users' todos are not counted
+    let (_, counts) = send_json(&server, "GET", "/api/users/bob/views", Value::Null).await;
+    assert_eq!(count(&counts, "today"), 0);
+}
+
+#[tokio::test]
+async fn saved_views_are_counted_and_filter_todos() {
+    let server = spawn_server().await;
+    let path = "/api/users/alice/todos";
+    send_json(&server, "POST", path, json!({"title": "Call Bob", "tags": ["work"]})).await;
+    send_json(&server, "POST", path, json!({"title": "Buy milk", "tags": ["home"]})).await;
+
+    let (status, view) = send_json(
+        &server,
+        "POST",
+        "/api/users/alice/views",
+        json!({"name": "Work", "filter": {"tags": ["work"], "completed": false}}),
+    )
+    .await;
+    assert_eq!(status, 200);
+    let view_id = view["data"]["id"].as_str().unwrap().to_string();
+
+    let (_, counts) = send_json(&server, "GET", "/api/users/alice/views", Value::Null).await;
+    assert_eq!(count(&counts, &view_id), 1);
+
+    let (_, todos) = send_json(&server, "GET", &format!("/api/users/alice/views/{}/todos", view_id), Value::Null).await;
+    assert_eq!(todos["data"].as_array().unwrap().len(), 1);
+    assert_eq!(todos["data"][0]["title"], "Call Bob");
+
+    let (status, _) = send_json(
+        &server,
+        "PUT",
+        &format!("/api/users/alice/views/{}", view_id),
+        json!({"name": "Home", "filter": {"tags": ["home"]}}),
+    )
+    .await;
+    assert_eq!(status, 200);
+    let (_, todos) = send_json(&server, "GET", &format!("/api/users/alice/views/{}/todos", view_id), Value::Null).await;
+    assert_eq!(todos["data"][0]["title"], "Buy milk");
+
+    let (status, _) = send_json(&server, "DELETE", &format!("/api/users/alice/views/{}", view_id), Value::Null).await;
+    assert_eq!(status, 200);
+    let (status, _) = send_json(&server, "GET", &format!("/api/users/alice/views/{}/todos", view_id), Value::Null).await;
+    assert_eq!(status, 404);
+
+    // Built-in ids are reserved
+    let (status, _) = send_json(&server, "POST", "/api/users/alice/views", json!({"id": "today", "name": "Mine", "filter": {}})).await;
+    assert_eq!(status, 400);
+
+    // So are day counts beyond ten years
+    let huge = json!({"name": "Forever", "filter": {"completed_within_days": 9_000_000_000_000_i64}});
+    let (status, _) = send_json(&server, "POST", "/api/users/alice/views", huge).await;
+    assert_eq!(status, 400);
+    let (_, view) = send_json(&server, "POST", "/api/users/alice/views", json!({"name": "Soon", "filter": {}})).await;
+    let within = json!({"name": "Soon", "filter": {"due": {"type": "within", "days": 3651}}});
+    let path = format!("/api/users/alice/views/{}", view["data"]["id"].as_str().unwrap());
+    let (status, _) = send_json(&server, "PUT", &path, within).await;
+    assert_eq!(status, 400);
+    let (status, _) = send_json(&server, "GET", "/api/users/alice/views", Value::Null).await;
+    assert_eq!(status, 200);
+}
+
+#[tokio::test]
+async fn saved_views_sync_between_devices() {
+    let server = spawn_server().await;
+    let sync = "/api/users/alice/sync";
+    let now = Utc::now();
+    let view = json!({
+        "id": "view-1",
+        "name": "Work",
+        "filter": {"tags": ["work"]},
+        "created_at": now,
+        "updated_at": now,
+    });
+
+    // One device pushes a view, another pulls it
+    send_json(&server, "POST", sync, json!({"todos": [], "views": [view]})).await;
+    let (_, pulled) = send_json(&server, "POST", sync, json!({"todos": []})).await;
+    assert_eq!(pulled["data"]["views"][0]["name"], "Work");
+    assert_eq!(pulled["data"]["views"][0]["filter"]["tags"][0], "work");
+
+    // A stale copy does not overwrite a newer one; a newer deletion does
+    let mut stale = view.clone();
+    stale["name"] = json!("Old name");
+    stale["updated_at"] = json!(now - Duration::hours(1));
+    let mut deleted = view.clone();
+    deleted["updated_at"] = json!(now + Duration::minutes(1));
+    deleted["deleted_at"] = json!(now + Duration::minutes(1));
+
+    let (_, pulled) = send_json(&server, "POST", sync, json!({"todos": [], "views": [stale]})).await;
+    assert_eq!(pulled["data"]["views"][0]["name"], "Work");
+    let (_, pulled) = send_json(&server, "POST", sync, json!({"todos": [], "views": [deleted]})).await;
+    assert!(pulled["data"]["views"][0]["deleted_at"].is_string());
+
+    // Another user cannot take the view over
+    let mut hijack = view.clone();
+    hijack["name"] = json!("Mallory");
+    hijack["updated_at"] = json!(now + Duration::hours(1));
+    let (_, pulled) = send_json(&server, "POST", "/api/users/mallory/sync", json!({"todos": [], "views": [hijack]})).await;
+    assert!(pulled["data"]["views"].as_array().unwrap().is_empty());
+}
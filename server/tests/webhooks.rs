@@ -0,0 +1,346 @@
+mod common;
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration as StdDuration;
+
+use chrono::Duration;
+use common::{send_json, spawn_server, spawn_server_with_config, TestServer};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use todo_server::config::Config;
+use todo_server::webhooks::{self, Dispatcher, RetryPolicy};
+
+/// A request the receiver got
+struct Received {
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Received {
+    fn header(&self, name: &str) -> &str {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .unwrap_or_default()
+    }
+}
+
+/// A local HTTP server recording webhook requests and answering each with
+/// the next scripted status, or 200 once the script runs out
+#[derive(Clone)]
+struct Receiver {
+    addr: SocketAddr,
+    received: Arc<Mutex<Vec<Received>>>,
+    statuses: Arc<Mutex<VecDeque<u16>>>,
+}
+
+impl Receiver {
+    async fn start(statuses: &[u16]) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let receiver = Receiver {
+            addr: listener.local_addr().unwrap(),
+            received: Arc::default(),
+            statuses: Arc::new(Mutex::new(statuses.iter().copied().collect())),
+        };
+        let handle = receiver.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                handle.answer(stream).await;
+            }
+        });
+        receiver
+    }
+
+    async fn answer(&self, mut stream: TcpStream) {
+        let mut raw = Vec::new();
+        let mut buffer = [0u8; 4096];
+        let (head, mut body) = loop {
+            let read = stream.read(&mut buffer).await.unwrap();
+            raw.extend_from_slice(&buffer[..read]);
+            let text = String::from_utf8_lossy(&raw).to_string();
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                break (head.to_string(), body.as_bytes().to_vec());
+            }
+        };
+        let headers: Vec<(String, String)> = head
+            .split("\r\n")
+            .skip(1)
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect();
+        let length: usize = headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+            .map(|(_, value)| value.parse().unwrap())
+            .unwrap_or(0);
+        while body.len() < length {
+            let read = stream.read(&mut buffer).await.unwrap();
+            body.extend_from_slice(&buffer[..read]);
+        }
+
+        self.received.lock().unwrap().push(Received {
+            headers,
+            body: String::from_utf8(body).unwrap(),
+        });
+        let status = self.statuses.lock().unwrap().pop_front().unwrap_or(200);
+        let response = format!("HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+        stream.write_all(response.as_bytes()).await.unwrap();
+    }
+
+    fn url(&self) -> String {
+        format!("http://{}/hook", self.addr)
+    }
+
+    fn events(&self) -> Vec<String> {
+        self.received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| request.header("x-webhook-event").to_string())
+            .collect()
+    }
+}
+
+/// A server whose webhooks may target the receivers on localhost
+async fn spawn_server_for_receivers() -> TestServer {
+    let mut config = Config::default();
+    config.webhooks.allow_private_targets = true;
+    spawn_server_with_config(config).await
+}
+
+fn dispatcher(server: &TestServer, max_attempts: i64) -> Dispatcher {
+    Dispatcher::new(
+        server.pool.clone(),
+        RetryPolicy {
+            max_attempts,
+            base_delay: Duration::zero(),
+            poll_interval: StdDuration::from_millis(10),
+            allow_private_targets: true,
+            ..RetryPolicy::default()
+        },
+    )
+}
+
+#[tokio::test]
+async fn events_are_delivered_signed() {
+    let server = spawn_server_for_receivers().await;
+    let receiver = Receiver::start(&[]).await;
+    let (status, webhook) = send_json(
+        &server,
+        "POST",
+        "/api/users/alice/webhooks",
+        json!({"url": receiver.url(), "events": ["todo.created", "todo.completed"]}),
+    )
+    .await;
+    assert_eq!(status, 200);
+    let secret = webhook["data"]["secret"].as_str().unwrap().to_string();
+
+    // Someone else's webhook hears nothing of alice's todos
+    let outsider = Receiver::start(&[]).await;
+    send_json(&server, "POST", "/api/users/carol/webhooks", json!({"url": outsider.url(), "events": ["todo.created"]})).await;
+
+    let (_, todo) = send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Deploy"})).await;
+    let path = format!("/api/users/alice/todos/{}", todo["data"]["id"].as_str().unwrap());
+    send_json(&server, "PUT", &path, json!({"title": "Deploy v2"})).await;
+    send_json(&server, "PUT", &path, json!({"completed": true})).await;
+
+    assert_eq!(dispatcher(&server, 3).run_once().await.unwrap(), 2);
+    assert_eq!(receiver.events(), vec!["todo.created", "todo.completed"]);
+    assert!(outsider.events().is_empty());
+
+    {
+        let received = receiver.received.lock().unwrap();
+        let completed = &received[1];
+        let timestamp: i64 = completed.header("x-webhook-timestamp").parse().unwrap();
+        assert_eq!(
+            completed.header("x-webhook-signature"),
+            webhooks::sign(&secret, timestamp, completed.body.as_bytes())
+        );
+        let payload: Value = serde_json::from_str(&completed.body).unwrap();
+        assert_eq!(payload["event"], "todo.completed");
+        assert_eq!(payload["actor_id"], "alice");
+        assert_eq!(payload["todo"]["title"], "Deploy v2");
+        assert_eq!(payload["id"], completed.header("x-webhook-delivery"));
+    }
+
+    // The secret is only shown once
+    let (_, webhooks) = send_json(&server, "GET", "/api/users/alice/webhooks", Value::Null).await;
+    assert!(webhooks["data"][0].get("secret").is_none());
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried_then_dead_lettered() {
+    let server = spawn_server_for_receivers().await;
+    let receiver = Receiver::start(&[500, 503, 500]).await;
+    let (_, webhook) = send_json(
+        &server,
+        "POST",
+        "/api/users/alice/webhooks",
+        json!({"url": receiver.url(), "events": ["todo.deleted"]}),
+    )
+    .await;
+    let webhook_id = webhook["data"]["id"].as_str().unwrap();
+
+    let (_, todo) = send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Old"})).await;
+    send_json(&server, "DELETE", &format!("/api/users/alice/todos/{}", todo["data"]["id"].as_str().unwrap()), Value::Null).await;
+
+    let dispatcher = dispatcher(&server, 3);
+    for _ in 0..5 {
+        dispatcher.run_once().await.unwrap();
+    }
+    assert_eq!(receiver.events().len(), 3, "no attempts after the last retry");
+
+    let deliveries = format!("/api/users/alice/webhooks/{}/deliveries", webhook_id);
+    let (_, dead) = send_json(&server, "GET", &format!("{}?status=dead", deliveries), Value::Null).await;
+    assert_eq!(dead["data"][0]["attempts"], 3);
+    assert_eq!(dead["data"][0]["last_status"], 500);
+    let delivery_id = dead["data"][0]["id"].as_str().unwrap();
+
+    let (status, _) = send_json(&server, "POST", &format!("{}/{}/redeliver", deliveries, delivery_id), Value::Null).await;
+    assert_eq!(status, 200);
+    assert_eq!(dispatcher.run_once().await.unwrap(), 1);
+
+    let (_, delivered) = send_json(&server, "GET", &format!("{}?status=delivered", deliveries), Value::Null).await;
+    assert_eq!(delivered["data"][0]["id"], delivery_id);
+
+    let (status, _) = send_json(&server, "GET", &format!("/api/users/bob/webhooks/{}/deliveries", webhook_id), Value::Null).await;
+    assert_eq!(status, 404);
+
+    let received = receiver.received.lock().unwrap();
+    assert!(received.iter().all(|request| request.header("x-webhook-delivery") == delivery_id));
+}
+
+#[tokio::test]
+async fn retries_back_off_exponentially() {
+    let policy = RetryPolicy {
+        base_delay: Duration::seconds(30),
+        max_delay: Duration::minutes(5),
+        ..RetryPolicy::default()
+    };
+    assert_eq!(policy.delay(1), Duration::seconds(30));
+    assert_eq!(policy.delay(2), Duration::seconds(60));
+    assert_eq!(policy.delay(4), Duration::seconds(240));
+    assert_eq!(policy.delay(5), Duration::minutes(5));
+    assert_eq!(policy.delay(60), Duration::minutes(5));
+}
+
+#[tokio::test]
+async fn webhook_urls_and_events_are_validated() {
+    let server = spawn_server().await;
+    let path = "/api/users/alice/webhooks";
+    let (status, _) = send_json(&server, "POST", path, json!({"url": "ftp://example.com", "events": ["todo.created"]})).await;
+    assert_eq!(status, 400);
+    let (status, _) = send_json(&server, "POST", path, json!({"url": "https://example.com", "events": []})).await;
+    assert_eq!(status, 400);
+    let (status, _) = send_json(&server, "POST", path, json!({"url": "https://example.com", "events": ["todo.eaten"]})).await;
+    assert_eq!(status, 422);
+}
+
+#[tokio::test]
+async fn private_addresses_are_refused() {
+    assert!(webhooks::is_public_ip("93.184.216.34".parse().unwrap()));
+    assert!(webhooks::is_public_ip("2606:2800:220:1::".parse().unwrap()));
+    for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1"] {
+        assert!(!webhooks::is_public_ip(ip.parse().unwrap()), "{}", ip);
+    }
+
+    let server = spawn_server().await;
+    let path = "/api/users/alice/webhooks";
+    for url in ["http://127.0.0.1:8080/hook", "http://169.254.169.254/latest/meta-data", "http://10.0.0.5/", "http://[::1]/", "http://localhost/"] {
+        let (status, _) = send_json(&server, "POST", path, json!({"url": url, "events": ["todo.created"]})).await;
+        assert_eq!(status, 400, "{}", url);
+    }
+
+    // Hooks already pointing at such addresses, by address or by name, are not delivered
+    let receiver = Receiver::start(&[]).await;
+    let urls = [receiver.url(), receiver.url().replace("127.0.0.1", "localhost")];
+    for (id, url) in ["literal", "named"].iter().zip(&urls) {
+        sqlx::query(
+            "INSERT INTO webhooks (id, user_id, url, secret, events, active, created_at, updated_at) VALUES (?, 'alice', ?, 'secret', '[\"todo.created\"]', 1, '2024-01-01T00:00:00Z', '2024-01-01T00:00:00Z')",
+        )
+        .bind(id)
+        .bind(url)
+        .execute(&server.pool)
+        .await
+        .unwrap();
+    }
+    send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Probe"})).await;
+
+    let dispatcher = Dispatcher::new(server.pool.clone(), RetryPolicy { base_delay: Duration::zero(), ..RetryPolicy::default() });
+    assert_eq!(dispatcher.run_once().await.unwrap(), 2);
+    assert!(receiver.events().is_empty());
+    let errors: Vec<Option<String>> = sqlx::query_scalar("SELECT last_error FROM webhook_deliveries")
+        .fetch_all(&server.pool)
+        .await
+        .unwrap();
+    assert!(errors.iter().all(Option::is_some));
+}
+
+#[tokio::test]
+async fn no_events_are_queued_while_webhooks_are_off() {
+    let mut config = Config::default();
+    config.features.webhooks = false;
+    let server = spawn_server_with_config(config).await;
+    sqlx::query(
+        "INSERT INTO webhooks (id, user_id, url, secret, events, active, created_at, updated_at) VALUES ('hook', 'alice', 'https://example.com/hook', 'secret', '[\"todo.created\"]', 1, '2024-01-01T00:00:00Z', '2024-01-01T00:00:00Z')",
+    )
+    .execute(&server.pool)
+    .await
+    .unwrap();
+
+    let (status, _) = send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Quiet"})).await;
+    assert_eq!(status, 200);
+    let queued: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM webhook_deliveries")
+        .fetch_one(&server.pool)
+        .await
+        .unwrap();
+    assert_eq!(queued, 0);
+}
+
+#[tokio::test]
+async fn paused_webhooks_get_no_deliveries() {
+    let server = spawn_server_for_receivers().await;
+    let receiver = Receiver::start(&[]).await;
+    let (_, webhook) = send_json(
+        &server,
+        "POST",
+        "/api/users/alice/webhooks",
+        json!({"url": receiver.url(), "events": ["todo.created"]}),
+    )
+    .await;
+    let path = format!("/api/users/alice/webhooks/{}", webhook["data"]["id"].as_str().unwrap());
+
+    send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Queued"})).await;
+    let (status, _) = send_json(&server, "PUT", &path, json!({"active": false})).await;
+    assert_eq!(status, 200);
+
+    assert_eq!(dispatcher(&server, 3).run_once().await.unwrap(), 0);
+    assert!(receiver.events().is_empty());
+    let (_, dead) = send_json(&server, "GET", &format!("{}/deliveries?status=dead", path), Value::Null).await;
+    assert_eq!(dead["data"][0]["last_error"], "webhook is paused");
+    assert_eq!(dead["data"][0]["attempts"], 0);
+}
+
+#[tokio::test]
+async fn finished_deliveries_are_purged() {
+    let server = spawn_server_for_receivers().await;
+    let receiver = Receiver::start(&[]).await;
+    send_json(&server, "POST", "/api/users/alice/webhooks", json!({"url": receiver.url(), "events": ["todo.created"]})).await;
+
+    send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Delivered"})).await;
+    assert_eq!(dispatcher(&server, 3).run_once().await.unwrap(), 1);
+    send_json(&server, "POST", "/api/users/alice/todos", json!({"title": "Still queued"})).await;
+
+    let cutoff = chrono::Utc::now() + Duration::minutes(1);
+    assert_eq!(webhooks::purge(&server.pool, cutoff).await.unwrap(), 1);
+    let left: Vec<String> = sqlx::query_scalar("SELECT status FROM webhook_deliveries")
+        .fetch_all(&server.pool)
+        .await
+        .unwrap();
+    assert_eq!(left, vec!["pending"]);
+}
@@ -0,0 +1,96 @@
+//! Files attached to todos. Contents are stored by their SHA-256 hash, so
+//! the same file attached twice is stored once.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::FromRow;
+
+/// Largest attachment accepted, in bytes
+pub const MAX_SIZE: usize = 5 * 1024 * 1024;
+
+/// Longest file name accepted, in characters
+pub const MAX_NAME_LENGTH: usize = 255;
+
+/// Content type used when the uploader does not give one
+pub const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Metadata of a file attached to a todo
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct Attachment {
+    pub id: String,
+    pub todo_id: String,
+    /// File name shown to users
+    pub name: String,
+    pub content_type: String,
+    /// Size in bytes
+    pub size: i64,
+    /// Hex SHA-256 of the contents, which is also the blob's key
+    pub hash: String,
+    pub uploaded_by: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// Set when the attachment has been removed, so that the removal syncs
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl Attachment {
+    pub fn new(todo_id: String, uploaded_by: String, name: String, content_type: String, contents: &[u8]) -> Self {
+        let now = Utc::now();
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            todo_id,
+            name,
+            content_type,
+            size: contents.len() as i64,
+            hash: content_hash(contents),
+            uploaded_by,
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+        }
+    }
+}
+
+/// Hex SHA-256 of some contents
+pub fn content_hash(contents: &[u8]) -> String {
+    hex::encode(Sha256::digest(contents))
+}
+
+/// Whether `hash` looks like a hash made by `content_hash`.
+/// Stores use hashes as file names, so anything else is refused.
+pub fn is_valid_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// Reduce an uploaded file name to its last path component, or fail if
+/// nothing usable is left
+pub fn clean_name(name: &str) -> Result<String, String> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default().trim();
+    if name.is_empty() || name == "." || name == ".." {
+        return Err("Attachment needs a file name".to_string());
+    }
+    if name.chars().any(char::is_control) {
+        return Err("File name contains control characters".to_string());
+    }
+    let length = name.chars().count();
+    if length > MAX_NAME_LENGTH {
+        return Err(format!(
+            "File name is {} characters long, at most {} are allowed",
+            length, MAX_NAME_LENGTH
+        ));
+    }
+    Ok(name.to_string())
+}
+
+/// Check that an attachment is not empty and not too large
+pub fn validate_size(size: usize) -> Result<(), String> {
+    if size == 0 {
+        return Err("Attachment is empty".to_string());
+    }
+    if size > MAX_SIZE {
+        return Err(format!("Attachment is {} bytes, at most {} are allowed", size, MAX_SIZE));
+    }
+    Ok(())
+}
@@ -0,0 +1,53 @@
+//! Comments on todos. Bodies are Markdown, rendered by the clients.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Longest comment body accepted, in characters
+pub const MAX_BODY_LENGTH: usize = 10_000;
+
+/// A comment on a todo
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct Comment {
+    pub id: String,
+    pub todo_id: String,
+    pub author_id: String,
+    /// Markdown text
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// Set when the comment has been deleted, so that the deletion syncs
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl Comment {
+    pub fn new(todo_id: String, author_id: String, body: String) -> Self {
+        let now = Utc::now();
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            todo_id,
+            author_id,
+            body,
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+        }
+    }
+}
+
+/// Check that a comment body is neither blank nor too long
+pub fn validate_body(body: &str) -> Result<(), String> {
+    if body.trim().is_empty() {
+        return Err("Comment is empty".to_string());
+    }
+    let length = body.chars().count();
+    if length > MAX_BODY_LENGTH {
+        return Err(format!(
+            "Comment is {} characters long, at most {} are allowed",
+            length, MAX_BODY_LENGTH
+        ));
+    }
+    Ok(())
+}
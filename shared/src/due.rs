@@ -0,0 +1,67 @@
+//! Due dates in the user's time zone.
+//!
+//! A timed due date is a point in time, and falls on whichever day it is in the
+//! user's time zone. An all-day due date has no time of day: it is stored as
+//! midnight UTC of its date and falls on that date in every time zone.
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+/// Time zone of users who have not chosen one
+pub const DEFAULT_TIMEZONE: Tz = Tz::UTC;
+
+/// Days after today that count as upcoming
+pub const DEFAULT_UPCOMING_DAYS: i64 = 7;
+
+/// Parse an IANA time zone name such as `Europe/Berlin`
+pub fn parse_timezone(name: &str) -> Result<Tz, String> {
+    name.parse().map_err(|e: chrono_tz::ParseError| e.to_string())
+}
+
+/// The stored due date of a todo due all day on `date`
+pub fn all_day(date: NaiveDate) -> DateTime<Utc> {
+    date.and_time(NaiveTime::MIN).and_utc()
+}
+
+/// The day a due date falls on in `timezone`
+pub fn due_day<T: TimeZone>(due: DateTime<Utc>, all_day: bool, timezone: &T) -> NaiveDate {
+    if all_day {
+        due.date_naive()
+    } else {
+        due.with_timezone(timezone).date_naive()
+    }
+}
+
+/// Where a due date falls relative to today
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DueBucket {
+    /// Due on a day before today
+    Overdue,
+    /// Due today, even if the time has already passed
+    Today,
+    /// Due on one of the next few days
+    Upcoming,
+    /// Due after that
+    Later,
+}
+
+/// Bucket a due date by the user's calendar days, with `now` in the user's time
+/// zone. Days after today up to `upcoming_days` are upcoming.
+pub fn bucket<T: TimeZone>(due: DateTime<Utc>, all_day: bool, now: &DateTime<T>, upcoming_days: i64) -> DueBucket {
+    let today = now.date_naive();
+    let day = due_day(due, all_day, &now.timezone());
+    if day < today {
+        DueBucket::Overdue
+    } else if day == today {
+        DueBucket::Today
+    } else if Duration::try_days(upcoming_days)
+        .and_then(|days| today.checked_add_signed(days))
+        .map_or(upcoming_days > 0, |last| day <= last)
+    {
+        DueBucket::Upcoming
+    } else {
+        DueBucket::Later
+    }
+}
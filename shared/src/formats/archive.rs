@@ -0,0 +1,34 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{FormatError, TodoRecord};
+
+/// Current version of the JSON archive layout.
+/// Version 2 added `list` and `tags` to each todo, version 3 `recurrence`
+/// and version 4 `parent_id`, for subtasks.
+pub const ARCHIVE_VERSION: u32 = 4;
+
+/// A full JSON export
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Archive {
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub todos: Vec<TodoRecord>,
+}
+
+pub(super) fn write(todos: &[TodoRecord]) -> Result<String, FormatError> {
+    let archive = Archive {
+        version: ARCHIVE_VERSION,
+        exported_at: Utc::now(),
+        todos: todos.to_vec(),
+    };
+    Ok(serde_json::to_string_pretty(&archive)?)
+}
+
+pub(super) fn read(input: &str) -> Result<Vec<TodoRecord>, FormatError> {
+    let archive: Archive = serde_json::from_str(input)?;
+    if archive.version > ARCHIVE_VERSION {
+        return Err(FormatError::UnsupportedVersion(archive.version));
+    }
+    Ok(archive.todos)
+}
@@ -0,0 +1,133 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{FormatError, TodoRecord};
+use crate::Priority;
+
+/// One CSV row. Everything but the title is optional on import so that
+/// hand-written spreadsheets can be imported.
+#[derive(Debug, Serialize, Deserialize)]
+struct CsvRow {
+    #[serde(default)]
+    id: Option<String>,
+    title: String,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    completed: Option<String>,
+    #[serde(default)]
+    priority: Option<Priority>,
+    #[serde(default)]
+    created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    updated_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    due_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    all_day: Option<String>,
+    #[serde(default)]
+    list: Option<String>,
+    /// Tags separated by `;`
+    #[serde(default)]
+    tags: Option<String>,
+    #[serde(default)]
+    recurrence: Option<String>,
+    /// Id of the parent todo, for subtasks
+    #[serde(default)]
+    parent_id: Option<String>,
+}
+
+pub(super) fn write(todos: &[TodoRecord]) -> Result<String, FormatError> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for todo in todos {
+        writer.serialize(CsvRow {
+            id: Some(todo.id.clone()),
+            title: todo.title.clone(),
+            description: todo.description.clone(),
+            completed: Some(todo.completed.to_string()),
+            priority: Some(todo.priority),
+            created_at: Some(todo.created_at),
+            updated_at: Some(todo.updated_at),
+            due_date: todo.due_date,
+            all_day: Some(todo.all_day.to_string()),
+            list: todo.list.clone(),
+            tags: Some(todo.tags.join(";")),
+            recurrence: todo.recurrence.clone(),
+            parent_id: todo.parent_id.clone(),
+        })?;
+    }
+
+    let bytes = writer
+        .into_inner()
+        .map_err(|e| FormatError::Csv(e.into_error().into()))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+pub(super) fn read(input: &str) -> Result<Vec<TodoRecord>, FormatError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(input.as_bytes());
+
+    let mut todos = Vec::new();
+    for (index, row) in reader.deserialize::<CsvRow>().enumerate() {
+        let row = row?;
+        // Line 1 is the header
+        let line = index + 2;
+        if row.title.is_empty() {
+            return Err(FormatError::Parse {
+                line,
+                message: "missing title".to_string(),
+            });
+        }
+
+        let mut todo = TodoRecord::new(row.title);
+        if let Some(id) = row.id {
+            todo.id = id;
+        }
+        todo.description = row.description;
+        todo.completed = match row.completed.as_deref() {
+            None => false,
+            Some(value) => parse_bool(value).ok_or_else(|| FormatError::Parse {
+                line,
+                message: format!("invalid completed value: {}", value),
+            })?,
+        };
+        todo.priority = row.priority.unwrap_or_default();
+        if let Some(created_at) = row.created_at {
+            todo.created_at = created_at;
+        }
+        todo.updated_at = row.updated_at.unwrap_or(todo.created_at);
+        todo.due_date = row.due_date;
+        todo.all_day = match row.all_day.as_deref() {
+            None => false,
+            Some(value) => parse_bool(value).ok_or_else(|| FormatError::Parse {
+                line,
+                message: format!("invalid all_day value: {}", value),
+            })?,
+        };
+        todo.list = row.list;
+        todo.tags = row
+            .tags
+            .map(|tags| {
+                tags.split(';')
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        todo.recurrence = row.recurrence;
+        todo.parent_id = row.parent_id.filter(|id| !id.is_empty());
+        todos.push(todo);
+    }
+
+    Ok(todos)
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "1" | "x" => Some(true),
+        "false" | "no" | "0" | "" => Some(false),
+        _ => None,
+    }
+}
@@ -0,0 +1,327 @@
+//! iCalendar (RFC 5545) VTODO export and import.
+//!
+//! Priorities map onto the iCalendar scale as high = 1, medium = 5 and
+//! low = 9; on import 1-4 are high, 5 (or undefined) medium and 6-9 low.
+//! The list is carried in the non-standard `X-TODO-LIST` property.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+
+use super::{FormatError, TodoRecord};
+use crate::recurrence;
+use crate::Priority;
+
+const PRODID: &str = "-//Todo Cross//Todo Cross//EN";
+const DATE_TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+const DATE_FORMAT: &str = "%Y%m%d";
+/// Maximum length of a content line in octets, excluding the line break
+const MAX_LINE_OCTETS: usize = 75;
+
+/// Write todos as VTODO components of a single calendar.
+/// `name` is advertised to calendar apps through `X-WR-CALNAME`.
+pub fn write_calendar(todos: &[TodoRecord], name: Option<&str>) -> String {
+    let mut output = String::new();
+    let mut line = |content: String| {
+        fold_line(&mut output, &content);
+    };
+
+    line("BEGIN:VCALENDAR".to_string());
+    line("VERSION:2.0".to_string());
+    line(format!("PRODID:{}", PRODID));
+    if let Some(name) = name {
+        line(format!("X-WR-CALNAME:{}", escape_text(name)));
+    }
+
+    for todo in todos {
+        line("BEGIN:VTODO".to_string());
+        line(format!("UID:{}", escape_text(&todo.id)));
+        line(format!("DTSTAMP:{}", format_date_time(todo.updated_at)));
+        line(format!("CREATED:{}", format_date_time(todo.created_at)));
+        line(format!("LAST-MODIFIED:{}", format_date_time(todo.updated_at)));
+        line(format!("SUMMARY:{}", escape_text(&todo.title)));
+        if let Some(description) = &todo.description {
+            line(format!("DESCRIPTION:{}", escape_text(description)));
+        }
+        match todo.due_date {
+            Some(due) if todo.all_day => line(format!("DUE;VALUE=DATE:{}", due.format(DATE_FORMAT))),
+            Some(due) => line(format!("DUE:{}", format_date_time(due))),
+            None => {}
+        }
+        line(format!("PRIORITY:{}", priority_value(todo.priority)));
+        if todo.completed {
+            line("STATUS:COMPLETED".to_string());
+            line(format!("COMPLETED:{}", format_date_time(todo.updated_at)));
+        } else {
+            line("STATUS:NEEDS-ACTION".to_string());
+        }
+        if !todo.tags.is_empty() {
+            let tags: Vec<String> = todo.tags.iter().map(|tag| escape_text(tag)).collect();
+            line(format!("CATEGORIES:{}", tags.join(",")));
+        }
+        if let Some(list) = &todo.list {
+            line(format!("X-TODO-LIST:{}", escape_text(list)));
+        }
+        if let Some(rule) = todo.recurrence.as_deref().filter(|rule| recurrence::is_valid_rrule(rule)) {
+            line(format!("RRULE:{}", rule));
+        }
+        // Without RELTYPE, the related todo is the parent
+        if let Some(parent_id) = &todo.parent_id {
+            line(format!("RELATED-TO:{}", escape_text(parent_id)));
+        }
+        line("END:VTODO".to_string());
+    }
+
+    line("END:VCALENDAR".to_string());
+    output
+}
+
+/// Parse every VTODO in a calendar. Other components are ignored.
+pub fn parse_calendar(input: &str) -> Result<Vec<TodoRecord>, FormatError> {
+    let mut todos = Vec::new();
+    let mut current: Option<TodoRecord> = None;
+    // Depth of components nested inside the current VTODO, such as VALARM
+    let mut nested = 0usize;
+
+    for (line_number, line) in unfold_lines(input) {
+        let property = parse_content_line(&line).ok_or_else(|| FormatError::Parse {
+            line: line_number,
+            message: format!("malformed content line: {}", line),
+        })?;
+
+        match (property.name.as_str(), current.as_mut()) {
+            ("BEGIN", None) if property.value.eq_ignore_ascii_case("VTODO") => {
+                let mut todo = TodoRecord::new(String::new());
+                todo.updated_at = todo.created_at;
+                current = Some(todo);
+            }
+            ("BEGIN", Some(_)) => nested += 1,
+            ("END", Some(_)) if nested > 0 => nested -= 1,
+            ("END", Some(_)) if property.value.eq_ignore_ascii_case("VTODO") => {
+                if let Some(mut todo) = current.take() {
+                    if todo.title.is_empty() {
+                        todo.title = "Untitled".to_string();
+                    }
+                    todos.push(todo);
+                }
+            }
+            (_, Some(todo)) if nested == 0 => apply_property(todo, &property, line_number)?,
+            _ => {}
+        }
+    }
+
+    Ok(todos)
+}
+
+struct Property {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl Property {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn apply_property(todo: &mut TodoRecord, property: &Property, line: usize) -> Result<(), FormatError> {
+    let date_time = || {
+        parse_date_time(&property.value, property.param("VALUE")).ok_or_else(|| FormatError::Parse {
+            line,
+            message: format!("invalid date: {}", property.value),
+        })
+    };
+
+    match property.name.as_str() {
+        "UID" => todo.id = unescape_text(&property.value),
+        "SUMMARY" => todo.title = unescape_text(&property.value),
+        "DESCRIPTION" => todo.description = Some(unescape_text(&property.value)),
+        "DUE" => {
+            todo.due_date = Some(date_time()?);
+            todo.all_day = is_date(&property.value, property.param("VALUE"));
+        }
+        "CREATED" => todo.created_at = date_time()?,
+        "LAST-MODIFIED" => todo.updated_at = date_time()?,
+        "COMPLETED" => todo.completed = true,
+        "STATUS" => todo.completed = property.value.eq_ignore_ascii_case("COMPLETED"),
+        "PRIORITY" => {
+            let value: u8 = property.value.trim().parse().map_err(|_| FormatError::Parse {
+                line,
+                message: format!("invalid priority: {}", property.value),
+            })?;
+            todo.priority = priority_from_value(value);
+        }
+        "CATEGORIES" => todo.tags.extend(
+            split_escaped_list(&property.value)
+                .into_iter()
+                .filter(|tag| !tag.is_empty()),
+        ),
+        "X-TODO-LIST" => todo.list = Some(unescape_text(&property.value)),
+        "RRULE" if recurrence::is_valid_rrule(&property.value) => {
+            todo.recurrence = Some(property.value.clone());
+        }
+        "RELATED-TO" if property.param("RELTYPE").is_none_or(|reltype| reltype.eq_ignore_ascii_case("PARENT")) => {
+            todo.parent_id = Some(unescape_text(&property.value));
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn priority_value(priority: Priority) -> u8 {
+    match priority {
+        Priority::High => 1,
+        Priority::Medium => 5,
+        Priority::Low => 9,
+    }
+}
+
+fn priority_from_value(value: u8) -> Priority {
+    match value {
+        1..=4 => Priority::High,
+        6..=9 => Priority::Low,
+        _ => Priority::Medium,
+    }
+}
+
+fn format_date_time(value: DateTime<Utc>) -> String {
+    value.format(DATE_TIME_FORMAT).to_string()
+}
+
+/// Parse a DATE or DATE-TIME value. Local times (with or without `TZID`)
+/// are read as UTC.
+fn parse_date_time(value: &str, value_type: Option<&str>) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if is_date(value, value_type) {
+        let date = NaiveDate::parse_from_str(value, DATE_FORMAT).ok()?;
+        return Some(date.and_hms_opt(0, 0, 0)?.and_utc());
+    }
+
+    let local = value.strip_suffix('Z').unwrap_or(value);
+    NaiveDateTime::parse_from_str(local, "%Y%m%dT%H%M%S")
+        .ok()
+        .map(|date_time| date_time.and_utc())
+}
+
+/// Whether a value is a DATE rather than a DATE-TIME
+fn is_date(value: &str, value_type: Option<&str>) -> bool {
+    value_type.is_some_and(|value_type| value_type.eq_ignore_ascii_case("DATE")) || value.trim().len() == 8
+}
+
+/// Join folded lines, yielding each logical line with the number of its first physical line
+fn unfold_lines(input: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    for (index, raw) in input.lines().enumerate() {
+        let raw = raw.strip_suffix('\r').unwrap_or(raw);
+        match (raw.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some((_, line))) => line.push_str(continuation),
+            _ if raw.trim().is_empty() => {}
+            _ => lines.push((index + 1, raw.to_string())),
+        }
+    }
+    lines
+}
+
+/// Split `NAME;PARAM=value:VALUE`, respecting quoted parameter values
+fn parse_content_line(line: &str) -> Option<Property> {
+    let mut in_quotes = false;
+    let mut colon = None;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ':' if !in_quotes => {
+                colon = Some(index);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let colon = colon?;
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+
+    let mut parts = head.split(';');
+    let name = parts.next()?.trim().to_ascii_uppercase();
+    if name.is_empty() {
+        return None;
+    }
+    let params = parts
+        .filter_map(|param| param.split_once('='))
+        .map(|(key, value)| (key.to_ascii_uppercase(), value.trim_matches('"').to_string()))
+        .collect();
+
+    Some(Property {
+        name,
+        params,
+        value: value.to_string(),
+    })
+}
+
+/// Append a content line, folded so that no physical line exceeds 75 octets
+fn fold_line(output: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > MAX_LINE_OCTETS {
+            output.push_str("\r\n ");
+            width = 1;
+        }
+        output.push(c);
+        width += c.len_utf8();
+    }
+    output.push_str("\r\n");
+}
+
+fn escape_text(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape_text(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Split a comma-separated TEXT list, keeping escaped commas
+fn split_escaped_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut escaped = false;
+    for c in value.chars() {
+        match c {
+            _ if escaped => {
+                current.push('\\');
+                current.push(c);
+                escaped = false;
+            }
+            '\\' => escaped = true,
+            ',' => items.push(unescape_text(&std::mem::take(&mut current))),
+            _ => current.push(c),
+        }
+    }
+    items.push(unescape_text(&current));
+    items.into_iter().map(|item| item.trim().to_string()).collect()
+}
@@ -0,0 +1,73 @@
+use std::collections::HashSet;
+
+use super::TodoRecord;
+
+/// Subtasks are indented under their parent. Subtasks whose parent is not
+/// among `todos`, or is a subtask itself, are written at the top level.
+pub(super) fn write(todos: &[TodoRecord]) -> String {
+    let ids: HashSet<&str> = todos.iter().map(|todo| todo.id.as_str()).collect();
+    let has_parent = |todo: &TodoRecord| todo.parent_id.as_deref().is_some_and(|parent| ids.contains(parent));
+    let top_level: HashSet<&str> = todos
+        .iter()
+        .filter(|todo| !has_parent(todo))
+        .map(|todo| todo.id.as_str())
+        .collect();
+    let is_nested = |todo: &TodoRecord| todo.parent_id.as_deref().is_some_and(|parent| top_level.contains(parent));
+
+    let mut output = String::new();
+    for todo in todos.iter().filter(|todo| !is_nested(todo)) {
+        write_item(&mut output, todo, "");
+        for subtask in todos.iter().filter(|subtask| subtask.parent_id.as_ref() == Some(&todo.id)) {
+            write_item(&mut output, subtask, "  ");
+        }
+    }
+    output
+}
+
+fn write_item(output: &mut String, todo: &TodoRecord, indent: &str) {
+    let mark = if todo.completed { 'x' } else { ' ' };
+    output.push_str(&format!("{}- [{}] {}\n", indent, mark, todo.title));
+}
+
+/// Read checklist items; any other line (headings, prose) is ignored.
+/// Indented items are subtasks of the unindented item before them.
+pub(super) fn read(input: &str) -> Vec<TodoRecord> {
+    let mut todos = Vec::new();
+    let mut parent: Option<String> = None;
+    for line in input.lines() {
+        let Some(mut todo) = parse_item(line) else {
+            continue;
+        };
+        if line.starts_with([' ', '\t']) {
+            todo.parent_id = parent.clone();
+        } else {
+            parent = Some(todo.id.clone());
+        }
+        todos.push(todo);
+    }
+    todos
+}
+
+fn parse_item(line: &str) -> Option<TodoRecord> {
+    let rest = line
+        .trim_start()
+        .strip_prefix("- ")
+        .or_else(|| line.trim_start().strip_prefix("* "))?;
+
+    let (completed, title) = if let Some(title) = rest.strip_prefix("[ ]") {
+        (false, title)
+    } else if let Some(title) = rest.strip_prefix("[x]").or_else(|| rest.strip_prefix("[X]")) {
+        (true, title)
+    } else {
+        return None;
+    };
+
+    let title = title.trim();
+    if title.is_empty() {
+        return None;
+    }
+
+    let mut todo = TodoRecord::new(title.to_string());
+    todo.completed = completed;
+    Some(todo)
+}
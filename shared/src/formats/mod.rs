@@ -0,0 +1,157 @@
+//! Export and import formats for todos.
+
+mod archive;
+mod csv;
+pub mod ical;
+mod markdown;
+pub mod todotxt;
+
+pub use archive::{Archive, ARCHIVE_VERSION};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::Priority;
+
+/// A todo as it appears in exported files, independent of how either side stores it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TodoRecord {
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub completed: bool,
+    #[serde(default)]
+    pub priority: Priority,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub due_date: Option<DateTime<Utc>>,
+    /// Whether the due date is a whole day, stored as midnight UTC
+    #[serde(default)]
+    pub all_day: bool,
+    #[serde(default)]
+    pub list: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// iCalendar RRULE value
+    #[serde(default)]
+    pub recurrence: Option<String>,
+    /// Id of the todo this one is a subtask of
+    #[serde(default)]
+    pub parent_id: Option<String>,
+}
+
+impl TodoRecord {
+    /// A new record with a fresh id and timestamps
+    pub fn new(title: String) -> Self {
+        let now = Utc::now();
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            title,
+            description: None,
+            completed: false,
+            priority: Priority::default(),
+            created_at: now,
+            updated_at: now,
+            due_date: None,
+            all_day: false,
+            list: None,
+            tags: Vec::new(),
+            recurrence: None,
+            parent_id: None,
+        }
+    }
+}
+
+/// Supported export and import formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// Versioned JSON archive
+    Json,
+    /// Flat CSV, one todo per row
+    Csv,
+    /// Markdown checklist (`- [x] title`)
+    Markdown,
+    /// One task per line in the todo.txt format
+    TodoTxt,
+    /// iCalendar VTODO components
+    #[serde(rename = "ics")]
+    ICalendar,
+}
+
+impl ExportFormat {
+    /// MIME type for serving an export of this format
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "application/json",
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Markdown => "text/markdown; charset=utf-8",
+            ExportFormat::TodoTxt => "text/plain; charset=utf-8",
+            ExportFormat::ICalendar => "text/calendar; charset=utf-8",
+        }
+    }
+
+    /// File extension for an export of this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Markdown => "md",
+            ExportFormat::TodoTxt => "txt",
+            ExportFormat::ICalendar => "ics",
+        }
+    }
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(ExportFormat::Json),
+            "csv" => Ok(ExportFormat::Csv),
+            "markdown" | "md" => Ok(ExportFormat::Markdown),
+            "todotxt" | "todo.txt" | "txt" => Ok(ExportFormat::TodoTxt),
+            "ics" | "ical" | "icalendar" => Ok(ExportFormat::ICalendar),
+            _ => Err(format!("Unknown format: {}", s)),
+        }
+    }
+}
+
+/// Errors raised while reading or writing an export
+#[derive(Debug, thiserror::Error)]
+pub enum FormatError {
+    #[error("invalid JSON archive: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid CSV: {0}")]
+    Csv(#[from] ::csv::Error),
+    #[error("unsupported archive version {0}")]
+    UnsupportedVersion(u32),
+    #[error("line {line}: {message}")]
+    Parse { line: usize, message: String },
+}
+
+/// Serialize todos in the given format
+pub fn export_todos(todos: &[TodoRecord], format: ExportFormat) -> Result<String, FormatError> {
+    match format {
+        ExportFormat::Json => archive::write(todos),
+        ExportFormat::Csv => csv::write(todos),
+        ExportFormat::Markdown => Ok(markdown::write(todos)),
+        ExportFormat::TodoTxt => Ok(todotxt::write(todos)),
+        ExportFormat::ICalendar => Ok(ical::write_calendar(todos, None)),
+    }
+}
+
+/// Parse todos from the given format
+pub fn parse_todos(input: &str, format: ExportFormat) -> Result<Vec<TodoRecord>, FormatError> {
+    match format {
+        ExportFormat::Json => archive::read(input),
+        ExportFormat::Csv => csv::read(input),
+        ExportFormat::Markdown => Ok(markdown::read(input)),
+        ExportFormat::TodoTxt => Ok(todotxt::read(input)),
+        ExportFormat::ICalendar => ical::parse_calendar(input),
+    }
+}
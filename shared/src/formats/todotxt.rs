@@ -0,0 +1,247 @@
+//! The [todo.txt](https://github.com/todotxt/todo.txt) format.
+//!
+//! Mapping onto todos:
+//! - `(A)` is high priority, `(B)` medium and `(C)` or lower is low. Tasks without
+//!   a priority are medium. Completed tasks keep their priority as `pri:X`.
+//! - The first `+project` is the list; `@context`s and any further projects are tags.
+//! - `due:YYYY-MM-DD` is the due date.
+//! - The creation date is `created_at`, and the completion date of a completed
+//!   task is its `updated_at`.
+
+use std::fmt;
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use super::TodoRecord;
+use crate::Priority;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// One line of a todo.txt file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Task {
+    pub completed: bool,
+    /// Priority letter, `A` to `Z`
+    pub priority: Option<char>,
+    pub completion_date: Option<NaiveDate>,
+    pub creation_date: Option<NaiveDate>,
+    /// The description with projects, contexts and `key:value` pairs taken out
+    pub text: String,
+    pub projects: Vec<String>,
+    pub contexts: Vec<String>,
+    pub due: Option<NaiveDate>,
+    /// Any other `key:value` pairs, in order of appearance
+    pub extensions: Vec<(String, String)>,
+}
+
+impl Task {
+    /// Parse one line. Returns `None` for blank lines.
+    pub fn parse(line: &str) -> Option<Task> {
+        let mut tokens = line.split_whitespace().peekable();
+        tokens.peek()?;
+
+        let mut task = Task::default();
+        if tokens.peek() == Some(&"x") {
+            tokens.next();
+            task.completed = true;
+            if let Some(date) = tokens.peek().and_then(|token| parse_date(token)) {
+                tokens.next();
+                task.completion_date = Some(date);
+            }
+        } else if let Some(priority) = tokens.peek().and_then(|token| parse_priority(token)) {
+            tokens.next();
+            task.priority = Some(priority);
+        }
+
+        // A creation date on a completed task is only valid after a completion date
+        if !task.completed || task.completion_date.is_some() {
+            if let Some(date) = tokens.peek().and_then(|token| parse_date(token)) {
+                tokens.next();
+                task.creation_date = Some(date);
+            }
+        }
+
+        let mut words = Vec::new();
+        for token in tokens {
+            if let Some(project) = token.strip_prefix('+').filter(|rest| !rest.is_empty()) {
+                task.projects.push(project.to_string());
+            } else if let Some(context) = token.strip_prefix('@').filter(|rest| !rest.is_empty()) {
+                task.contexts.push(context.to_string());
+            } else if let Some((key, value)) = parse_key_value(token) {
+                match (key, parse_date(value)) {
+                    ("due", Some(due)) => task.due = Some(due),
+                    _ => task.extensions.push((key.to_string(), value.to_string())),
+                }
+            } else {
+                words.push(token);
+            }
+        }
+        task.text = words.join(" ");
+
+        Some(task)
+    }
+
+    /// Convert to a todo record with a fresh id
+    pub fn to_record(&self) -> TodoRecord {
+        let mut record = TodoRecord::new(self.text.clone());
+        record.completed = self.completed;
+
+        let letter = self.priority.or_else(|| {
+            self.extensions
+                .iter()
+                .find(|(key, _)| key == "pri")
+                .and_then(|(_, value)| parse_letter(value))
+        });
+        record.priority = letter.map(priority_from_letter).unwrap_or_default();
+
+        if let Some(created) = self.creation_date {
+            record.created_at = start_of_day(created);
+        }
+        record.updated_at = match (self.completed, self.completion_date) {
+            (true, Some(completed)) => start_of_day(completed),
+            _ => record.created_at,
+        };
+        record.due_date = self.due.map(start_of_day);
+        record.all_day = self.due.is_some();
+
+        let mut projects = self.projects.iter();
+        record.list = projects.next().cloned();
+        record.tags = self.contexts.iter().chain(projects).cloned().collect();
+
+        record
+    }
+
+    /// Build a task from a todo record
+    pub fn from_record(record: &TodoRecord) -> Task {
+        let letter = letter_for(record.priority);
+        let mut task = Task {
+            completed: record.completed,
+            creation_date: Some(record.created_at.date_naive()),
+            text: record.title.split_whitespace().collect::<Vec<_>>().join(" "),
+            projects: record.list.iter().map(|list| token(list)).collect(),
+            contexts: record.tags.iter().map(|tag| token(tag)).collect(),
+            due: record.due_date.map(|due| due.date_naive()),
+            ..Task::default()
+        };
+
+        if record.completed {
+            task.completion_date = Some(record.updated_at.date_naive());
+            if let Some(letter) = letter {
+                task.extensions.push(("pri".to_string(), letter.to_string()));
+            }
+        } else {
+            task.priority = letter;
+        }
+
+        task
+    }
+}
+
+impl fmt::Display for Task {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts: Vec<String> = Vec::new();
+
+        if self.completed {
+            parts.push("x".to_string());
+            if let Some(completed) = self.completion_date {
+                parts.push(completed.format(DATE_FORMAT).to_string());
+                if let Some(created) = self.creation_date {
+                    parts.push(created.format(DATE_FORMAT).to_string());
+                }
+            }
+        } else {
+            if let Some(priority) = self.priority {
+                parts.push(format!("({})", priority));
+            }
+            if let Some(created) = self.creation_date {
+                parts.push(created.format(DATE_FORMAT).to_string());
+            }
+        }
+
+        if !self.text.is_empty() {
+            parts.push(self.text.clone());
+        }
+        parts.extend(self.projects.iter().map(|project| format!("+{}", project)));
+        parts.extend(self.contexts.iter().map(|context| format!("@{}", context)));
+        if let Some(due) = self.due {
+            parts.push(format!("due:{}", due.format(DATE_FORMAT)));
+        }
+        parts.extend(self.extensions.iter().map(|(key, value)| format!("{}:{}", key, value)));
+
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+pub(super) fn write(todos: &[TodoRecord]) -> String {
+    let mut output = String::new();
+    for todo in todos {
+        output.push_str(&Task::from_record(todo).to_string());
+        output.push('\n');
+    }
+    output
+}
+
+pub(super) fn read(input: &str) -> Vec<TodoRecord> {
+    input
+        .lines()
+        .filter_map(Task::parse)
+        .map(|task| task.to_record())
+        .collect()
+}
+
+fn priority_from_letter(letter: char) -> Priority {
+    match letter {
+        'A' => Priority::High,
+        'B' => Priority::Medium,
+        _ => Priority::Low,
+    }
+}
+
+fn letter_for(priority: Priority) -> Option<char> {
+    match priority {
+        Priority::High => Some('A'),
+        Priority::Medium => None,
+        Priority::Low => Some('C'),
+    }
+}
+
+fn parse_priority(token: &str) -> Option<char> {
+    token
+        .strip_prefix('(')
+        .and_then(|rest| rest.strip_suffix(')'))
+        .and_then(parse_letter)
+}
+
+fn parse_letter(value: &str) -> Option<char> {
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(letter), None) if letter.is_ascii_uppercase() => Some(letter),
+        _ => None,
+    }
+}
+
+fn parse_date(token: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(token, DATE_FORMAT).ok()
+}
+
+/// A `key:value` token. Keys must start with a letter so that times like
+/// `10:30` and URLs like `https://...` stay part of the text.
+fn parse_key_value(token: &str) -> Option<(&str, &str)> {
+    let (key, value) = token.split_once(':')?;
+    let starts_with_letter = key.chars().next().is_some_and(|c| c.is_ascii_alphabetic());
+    if !starts_with_letter || value.is_empty() || value.contains(':') || value.starts_with('/') {
+        return None;
+    }
+    Some((key, value))
+}
+
+/// Make a list or tag name usable as a single todo.txt token
+fn token(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join("_")
+}
+
+fn start_of_day(date: NaiveDate) -> DateTime<Utc> {
+    date.and_hms_opt(0, 0, 0)
+        .expect("midnight is a valid time")
+        .and_utc()
+}
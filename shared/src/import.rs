@@ -0,0 +1,137 @@
+//! Duplicate detection and reporting for imports.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::formats::TodoRecord;
+use crate::recurrence;
+
+/// Outcome of an import, or of a dry run previewing one
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    /// Number of todos found in the input
+    pub total: usize,
+    /// Todos that were (or would be) imported
+    pub imported: Vec<TodoRecord>,
+    /// Todos skipped because they match an existing todo or an earlier one in the input
+    pub duplicates: Vec<TodoRecord>,
+    /// Ids of imported todos that were taken elsewhere, and the new ids those todos got
+    pub reassigned_ids: BTreeMap<String, String>,
+}
+
+impl ImportReport {
+    /// Give new ids to imported todos whose ids are in `taken`, keeping their
+    /// subtasks attached
+    pub fn reassign_ids(&mut self, taken: &HashSet<String>) {
+        for todo in &mut self.imported {
+            if taken.contains(&todo.id) {
+                let id = Uuid::new_v4().to_string();
+                self.reassigned_ids.insert(std::mem::replace(&mut todo.id, id.clone()), id);
+            }
+        }
+        for todo in &mut self.imported {
+            if let Some(id) = todo.parent_id.as_ref().and_then(|parent_id| self.reassigned_ids.get(parent_id)) {
+                todo.parent_id = Some(id.clone());
+            }
+        }
+    }
+}
+
+/// Why a set of imported todos was refused
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ImportError {
+    #[error("invalid recurrence rule on todo {id}: {rule}")]
+    InvalidRecurrence { id: String, rule: String },
+    #[error("todo {id} would be a subtask of a subtask")]
+    NestedSubtask { id: String },
+}
+
+/// Check imported todos the way new todos are checked: recurrence rules must
+/// be valid, and subtasks one level deep within the import.
+///
+/// Returns the parents that are not part of the import, which the caller
+/// still has to check are existing todos that can take subtasks.
+pub fn check_imported(todos: &[TodoRecord]) -> Result<HashSet<&str>, ImportError> {
+    let parents: HashMap<&str, Option<&str>> = todos
+        .iter()
+        .map(|todo| (todo.id.as_str(), todo.parent_id.as_deref()))
+        .collect();
+    let has_subtasks: HashSet<&str> = todos.iter().filter_map(|todo| todo.parent_id.as_deref()).collect();
+
+    let mut outside = HashSet::new();
+    for todo in todos {
+        if let Some(rule) = todo.recurrence.as_deref().filter(|rule| !recurrence::is_valid_rrule(rule)) {
+            return Err(ImportError::InvalidRecurrence { id: todo.id.clone(), rule: rule.to_string() });
+        }
+        let Some(parent_id) = todo.parent_id.as_deref() else {
+            continue;
+        };
+        let nested = || ImportError::NestedSubtask { id: todo.id.clone() };
+        if has_subtasks.contains(todo.id.as_str()) {
+            return Err(nested());
+        }
+        match parents.get(parent_id) {
+            // A parent in the import must not be a subtask itself, nor the todo
+            Some(Some(_)) => return Err(nested()),
+            Some(None) => {}
+            None => {
+                outside.insert(parent_id);
+            }
+        }
+    }
+    Ok(outside)
+}
+
+/// Split incoming todos into new ones and duplicates.
+///
+/// A todo is a duplicate if its id is already known, or if a todo with the
+/// same title (ignoring case and surrounding whitespace) and due date exists.
+/// Subtasks of a duplicate become subtasks of the todo it duplicates, and
+/// subtasks whose parent is nowhere to be found become top-level todos.
+pub fn plan_import(existing: &[TodoRecord], incoming: Vec<TodoRecord>, dry_run: bool) -> ImportReport {
+    let mut ids: HashSet<String> = existing.iter().map(|todo| todo.id.clone()).collect();
+    let mut keys: HashMap<(String, Option<DateTime<Utc>>), String> =
+        existing.iter().map(|todo| (duplicate_key(todo), todo.id.clone())).collect();
+    // Ids of skipped duplicates, and the todo each duplicates
+    let mut duplicate_of: HashMap<String, String> = HashMap::new();
+
+    let total = incoming.len();
+    let mut imported = Vec::new();
+    let mut duplicates = Vec::new();
+    for todo in incoming {
+        let key = duplicate_key(&todo);
+        if ids.contains(&todo.id) {
+            duplicates.push(todo);
+        } else if let Some(original) = keys.get(&key) {
+            duplicate_of.insert(todo.id.clone(), original.clone());
+            duplicates.push(todo);
+        } else {
+            ids.insert(todo.id.clone());
+            keys.insert(key, todo.id.clone());
+            imported.push(todo);
+        }
+    }
+
+    for todo in &mut imported {
+        if let Some(parent_id) = todo.parent_id.take() {
+            let parent_id = duplicate_of.get(&parent_id).cloned().unwrap_or(parent_id);
+            todo.parent_id = ids.contains(&parent_id).then_some(parent_id);
+        }
+    }
+
+    ImportReport {
+        dry_run,
+        total,
+        imported,
+        duplicates,
+        reassigned_ids: BTreeMap::new(),
+    }
+}
+
+fn duplicate_key(todo: &TodoRecord) -> (String, Option<DateTime<Utc>>) {
+    (todo.title.trim().to_lowercase(), todo.due_date)
+}
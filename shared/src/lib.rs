@@ -0,0 +1,16 @@
+//! Types and data formats shared by the Tauri app and the sync server.
+
+pub mod attachments;
+pub mod comments;
+pub mod due;
+pub mod formats;
+pub mod import;
+pub mod pinning;
+mod priority;
+pub mod quick_add;
+pub mod recurrence;
+pub mod reminders;
+pub mod revisions;
+pub mod views;
+
+pub use priority::Priority;
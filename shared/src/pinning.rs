@@ -0,0 +1,40 @@
+//! Certificate pinning: identifying a TLS certificate by the SHA-256 hash of
+//! its DER encoding, so that a client can trust a self-signed server
+//! certificate without a certificate authority.
+
+use sha2::{Digest, Sha256};
+
+/// Fingerprint of a DER-encoded certificate, as upper-case hex pairs
+/// separated by colons, the way `openssl x509 -fingerprint -sha256` shows it
+pub fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Normalize a pin typed by a user to the format of `fingerprint`. Colons,
+/// spaces and case are ignored; `None` if it is not a SHA-256 fingerprint.
+pub fn normalize_pin(pin: &str) -> Option<String> {
+    let digits: String = pin
+        .chars()
+        .filter(|c| *c != ':' && !c.is_whitespace())
+        .collect();
+    let bytes = hex::decode(digits).ok()?;
+    if bytes.len() != 32 {
+        return None;
+    }
+    Some(
+        bytes
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<_>>()
+            .join(":"),
+    )
+}
+
+/// Whether a DER-encoded certificate matches a pin
+pub fn matches(der: &[u8], pin: &str) -> bool {
+    normalize_pin(pin).is_some_and(|pin| pin == fingerprint(der))
+}
@@ -0,0 +1,190 @@
+use chrono::Weekday;
+
+use crate::Priority;
+
+/// A unit of time in relative dates and recurrences
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+/// The words the quick-add parser understands in one language.
+///
+/// All words are lowercase; input is lowercased before it is looked up.
+pub struct Locale {
+    /// Full weekday names, recognised on their own
+    pub weekdays: &'static [(&'static str, Weekday)],
+    /// Short weekday names, only recognised after a keyword such as "on" or "next"
+    pub weekday_abbreviations: &'static [(&'static str, Weekday)],
+    /// Full and short month names
+    pub months: &'static [(&'static str, u32)],
+    /// Days relative to today, e.g. "tomorrow" = 1
+    pub relative_days: &'static [(&'static str, i64)],
+    pub units: &'static [(&'static str, Unit)],
+    /// Number words usable in relative dates, e.g. "in two days"
+    pub numbers: &'static [(&'static str, u32)],
+    /// Suffixes of ordinal day numbers, e.g. the "st" in "1st"
+    pub ordinal_suffixes: &'static [&'static str],
+    /// Priority names, written after a `!`
+    pub priorities: &'static [(&'static str, Priority)],
+    /// Named times of day
+    pub times: &'static [(&'static str, (u32, u32))],
+    pub am: &'static [&'static str],
+    pub pm: &'static [&'static str],
+    /// Words starting a recurrence, e.g. "every"
+    pub every: &'static [&'static str],
+    /// Single-word recurrences, e.g. "weekly"
+    pub frequencies: &'static [(&'static str, Unit)],
+    /// Words for Monday to Friday, e.g. "every weekday"
+    pub weekday_words: &'static [&'static str],
+    /// Words for Saturday and Sunday, e.g. "every weekend"
+    pub weekend_words: &'static [&'static str],
+    /// Words that may precede a date and are dropped with it, e.g. "by friday"
+    pub date_prepositions: &'static [&'static str],
+    /// Words that may precede a time, e.g. "at 9"
+    pub time_prepositions: &'static [&'static str],
+    /// Word before a relative offset, e.g. "in 3 days"
+    pub within: &'static [&'static str],
+    /// Word selecting the following week, month or year, e.g. "next friday"
+    pub next: &'static [&'static str],
+    /// Word selecting the current week, e.g. "this friday"
+    pub this: &'static [&'static str],
+    /// Articles before a day of the month, e.g. "on the 1st"
+    pub articles: &'static [&'static str],
+    /// Words joining weekdays in a recurrence, e.g. "every monday and thursday"
+    pub and: &'static [&'static str],
+}
+
+impl Locale {
+    pub(super) fn lookup<T: Copy>(table: &[(&str, T)], word: &str) -> Option<T> {
+        table.iter().find(|(name, _)| *name == word).map(|(_, value)| *value)
+    }
+
+    pub(super) fn is(words: &[&str], word: &str) -> bool {
+        words.contains(&word)
+    }
+}
+
+/// English
+pub const ENGLISH: Locale = Locale {
+    weekdays: &[
+        ("monday", Weekday::Mon),
+        ("tuesday", Weekday::Tue),
+        ("wednesday", Weekday::Wed),
+        ("thursday", Weekday::Thu),
+        ("friday", Weekday::Fri),
+        ("saturday", Weekday::Sat),
+        ("sunday", Weekday::Sun),
+    ],
+    weekday_abbreviations: &[
+        ("mon", Weekday::Mon),
+        ("tue", Weekday::Tue),
+        ("tues", Weekday::Tue),
+        ("wed", Weekday::Wed),
+        ("thu", Weekday::Thu),
+        ("thur", Weekday::Thu),
+        ("thurs", Weekday::Thu),
+        ("fri", Weekday::Fri),
+        ("sat", Weekday::Sat),
+        ("sun", Weekday::Sun),
+    ],
+    months: &[
+        ("january", 1),
+        ("jan", 1),
+        ("february", 2),
+        ("feb", 2),
+        ("march", 3),
+        ("mar", 3),
+        ("april", 4),
+        ("apr", 4),
+        ("may", 5),
+        ("june", 6),
+        ("jun", 6),
+        ("july", 7),
+        ("jul", 7),
+        ("august", 8),
+        ("aug", 8),
+        ("september", 9),
+        ("sep", 9),
+        ("sept", 9),
+        ("october", 10),
+        ("oct", 10),
+        ("november", 11),
+        ("nov", 11),
+        ("december", 12),
+        ("dec", 12),
+    ],
+    relative_days: &[("today", 0), ("tod", 0), ("tomorrow", 1), ("tmr", 1), ("tmrw", 1)],
+    units: &[
+        ("minute", Unit::Minute),
+        ("minutes", Unit::Minute),
+        ("min", Unit::Minute),
+        ("mins", Unit::Minute),
+        ("hour", Unit::Hour),
+        ("hours", Unit::Hour),
+        ("hr", Unit::Hour),
+        ("hrs", Unit::Hour),
+        ("day", Unit::Day),
+        ("days", Unit::Day),
+        ("week", Unit::Week),
+        ("weeks", Unit::Week),
+        ("month", Unit::Month),
+        ("months", Unit::Month),
+        ("year", Unit::Year),
+        ("years", Unit::Year),
+    ],
+    numbers: &[
+        ("a", 1),
+        ("an", 1),
+        ("one", 1),
+        ("two", 2),
+        ("three", 3),
+        ("four", 4),
+        ("five", 5),
+        ("six", 6),
+        ("seven", 7),
+        ("eight", 8),
+        ("nine", 9),
+        ("ten", 10),
+        ("other", 2),
+    ],
+    ordinal_suffixes: &["st", "nd", "rd", "th"],
+    priorities: &[
+        ("high", Priority::High),
+        ("h", Priority::High),
+        ("1", Priority::High),
+        ("medium", Priority::Medium),
+        ("med", Priority::Medium),
+        ("m", Priority::Medium),
+        ("2", Priority::Medium),
+        ("low", Priority::Low),
+        ("l", Priority::Low),
+        ("3", Priority::Low),
+    ],
+    times: &[("noon", (12, 0)), ("midday", (12, 0)), ("midnight", (0, 0))],
+    am: &["am", "a.m."],
+    pm: &["pm", "p.m."],
+    every: &["every", "each"],
+    frequencies: &[
+        ("hourly", Unit::Hour),
+        ("daily", Unit::Day),
+        ("weekly", Unit::Week),
+        ("monthly", Unit::Month),
+        ("yearly", Unit::Year),
+        ("annually", Unit::Year),
+    ],
+    weekday_words: &["weekday", "weekdays", "workday", "workdays"],
+    weekend_words: &["weekend", "weekends"],
+    date_prepositions: &["on", "by", "due", "before"],
+    time_prepositions: &["at", "@"],
+    within: &["in"],
+    next: &["next"],
+    this: &["this"],
+    articles: &["the"],
+    and: &["and", "&"],
+};
@@ -0,0 +1,625 @@
+//! Natural-language quick-add, turning a line such as
+//! `Pay rent every month on the 1st !high #finance tomorrow 9am` into the
+//! fields of a new todo.
+//!
+//! Recognised anywhere in the input:
+//! - `!high`, `!medium`, `!low` (or `!h`, `!1`, ...) set the priority
+//! - `#tag` and `@tag` add tags; `+list` sets the list
+//! - dates such as `today`, `tomorrow`, `friday`, `next monday`, `in 3 days`,
+//!   `march 5`, `5th of may`, `2024-03-05` or `on the 15th`
+//! - times such as `9am`, `9:30 pm`, `14:00`, `at 9` or `at noon`
+//! - recurrences such as `daily`, `every week`, `every other monday`,
+//!   `every weekday` or `every month on the 1st`
+//!
+//! Everything else is the title. Words are looked up in a [`Locale`], so other
+//! languages can be supported by adding word tables.
+
+mod locale;
+
+pub use locale::{Locale, Unit, ENGLISH};
+
+use chrono::{DateTime, Datelike, Duration, LocalResult, Offset, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+
+use crate::due;
+use crate::Priority;
+
+/// The fields of a todo parsed from a quick-add line
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QuickAdd {
+    pub title: String,
+    pub priority: Option<Priority>,
+    pub tags: Vec<String>,
+    pub list: Option<String>,
+    pub due_date: Option<DateTime<Utc>>,
+    /// Whether the due date is a whole day, as when no time was given
+    pub all_day: bool,
+    /// iCalendar RRULE value
+    pub recurrence: Option<String>,
+}
+
+/// Parse an English quick-add line. Relative dates are resolved against `now`,
+/// in the time zone of `now`.
+pub fn parse<Tz: TimeZone>(input: &str, now: &DateTime<Tz>) -> QuickAdd {
+    parse_with_locale(input, now, &ENGLISH)
+}
+
+/// Parse a quick-add line using the words of `locale`
+pub fn parse_with_locale<Tz: TimeZone>(input: &str, now: &DateTime<Tz>, locale: &Locale) -> QuickAdd {
+    let mut parser = Parser::new(input, now.date_naive(), locale);
+    parser.run();
+
+    let title = parser.title().unwrap_or_else(|| input.split_whitespace().collect::<Vec<_>>().join(" "));
+    let due_date = parser.due_date(now);
+    QuickAdd {
+        title,
+        priority: parser.priority,
+        tags: parser.tags,
+        list: parser.list,
+        due_date,
+        all_day: due_date.is_some() && parser.time.is_none() && !matches!(parser.date, Some(DateValue::Exact(_))),
+        recurrence: parser.recurrence.map(|recurrence| recurrence.to_rrule()),
+    }
+}
+
+/// Convert a local date and time to UTC. Times skipped by a DST transition move
+/// forward by the length of the gap; repeated times resolve to the first one.
+pub fn local_to_utc<Tz: TimeZone>(timezone: &Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    match timezone.from_local_datetime(&local) {
+        LocalResult::Single(date_time) => date_time.with_timezone(&Utc),
+        LocalResult::Ambiguous(earliest, _) => earliest.with_timezone(&Utc),
+        // Read the time with the offset in effect before the gap
+        LocalResult::None => {
+            let before = timezone.from_local_datetime(&(local - Duration::days(1))).earliest();
+            let offset = before.map_or(Duration::zero(), |before| {
+                Duration::seconds(before.offset().fix().local_minus_utc().into())
+            });
+            (local - offset).and_utc()
+        }
+    }
+}
+
+/// A date found in the input
+enum DateValue {
+    Day(NaiveDate),
+    /// An exact point in time, from offsets like "in 2 hours"
+    Exact(Duration),
+}
+
+#[derive(Debug, Default)]
+struct Recurrence {
+    frequency: Option<Unit>,
+    interval: u32,
+    by_day: Vec<Weekday>,
+    by_month_day: Option<u32>,
+}
+
+impl Recurrence {
+    fn new(frequency: Unit, interval: u32) -> Self {
+        Self {
+            frequency: Some(frequency),
+            interval,
+            ..Self::default()
+        }
+    }
+
+    fn to_rrule(&self) -> String {
+        let frequency = match self.frequency.unwrap_or(Unit::Day) {
+            Unit::Minute => "MINUTELY",
+            Unit::Hour => "HOURLY",
+            Unit::Day => "DAILY",
+            Unit::Week => "WEEKLY",
+            Unit::Month => "MONTHLY",
+            Unit::Year => "YEARLY",
+        };
+        let mut parts = vec![format!("FREQ={}", frequency)];
+        if self.interval > 1 {
+            parts.push(format!("INTERVAL={}", self.interval));
+        }
+        if !self.by_day.is_empty() {
+            let days: Vec<&str> = self.by_day.iter().map(|day| weekday_code(*day)).collect();
+            parts.push(format!("BYDAY={}", days.join(",")));
+        }
+        if let Some(day) = self.by_month_day {
+            parts.push(format!("BYMONTHDAY={}", day));
+        }
+        parts.join(";")
+    }
+
+    /// The first day on or after `today` the recurrence falls on, if it is pinned to days
+    fn first_day(&self, today: NaiveDate) -> Option<NaiveDate> {
+        if !self.by_day.is_empty() {
+            return self.by_day.iter().map(|day| upcoming(today, *day)).min();
+        }
+        let day = self.by_month_day?;
+        (0..=12)
+            .filter_map(|offset| today.checked_add_months(Months::new(offset)))
+            .filter_map(|month| month.with_day(day))
+            .find(|date| *date >= today)
+    }
+}
+
+struct Parser<'a> {
+    locale: &'a Locale,
+    tokens: Vec<&'a str>,
+    /// Lowercased tokens without trailing punctuation, for matching
+    words: Vec<String>,
+    used: Vec<bool>,
+    today: NaiveDate,
+    priority: Option<Priority>,
+    tags: Vec<String>,
+    list: Option<String>,
+    date: Option<DateValue>,
+    time: Option<NaiveTime>,
+    recurrence: Option<Recurrence>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str, today: NaiveDate, locale: &'a Locale) -> Self {
+        let tokens: Vec<&str> = input.split_whitespace().collect();
+        let words = tokens
+            .iter()
+            .map(|token| token.trim_end_matches([',', ';']).to_lowercase())
+            .collect();
+        Self {
+            locale,
+            used: vec![false; tokens.len()],
+            tokens,
+            words,
+            today,
+            priority: None,
+            tags: Vec::new(),
+            list: None,
+            date: None,
+            time: None,
+            recurrence: None,
+        }
+    }
+
+    fn run(&mut self) {
+        let mut index = 0;
+        while index < self.tokens.len() {
+            let consumed = self.parse_at(index);
+            for used in &mut self.used[index..index + consumed] {
+                *used = true;
+            }
+            index += consumed.max(1);
+        }
+    }
+
+    /// Try every kind of token at `index`, returning how many tokens were recognised
+    fn parse_at(&mut self, index: usize) -> usize {
+        if let Some(consumed) = self.sigil(index) {
+            return consumed;
+        }
+        if self.recurrence.is_none() {
+            if let Some((recurrence, consumed)) = self.recurrence_at(index) {
+                self.recurrence = Some(recurrence);
+                return consumed;
+            }
+        }
+        if self.date.is_none() {
+            if let Some((date, consumed)) = self.date_with_preposition(index) {
+                self.date = Some(date);
+                return consumed;
+            }
+        }
+        if self.time.is_none() {
+            if let Some((time, consumed)) = self.time_with_preposition(index) {
+                self.time = Some(time);
+                return consumed;
+            }
+        }
+        0
+    }
+
+    fn word(&self, index: usize) -> Option<&str> {
+        self.words.get(index).map(String::as_str)
+    }
+
+    /// `!priority`, `#tag`, `@tag` and `+list`
+    fn sigil(&mut self, index: usize) -> Option<usize> {
+        let token = self.tokens[index].trim_end_matches([',', ';']);
+        let mut chars = token.chars();
+        let sigil = chars.next()?;
+        let rest = chars.as_str();
+        if rest.is_empty() {
+            return None;
+        }
+
+        match sigil {
+            '!' if self.priority.is_none() => {
+                let priority = Locale::lookup(self.locale.priorities, &rest.to_lowercase()).or(match rest {
+                    "!!" => Some(Priority::High),
+                    "!" => Some(Priority::Medium),
+                    _ => None,
+                })?;
+                self.priority = Some(priority);
+            }
+            '#' | '@' => self.tags.push(rest.to_string()),
+            '+' if self.list.is_none() && rest.starts_with(char::is_alphabetic) => self.list = Some(rest.to_string()),
+            _ => return None,
+        }
+        Some(1)
+    }
+
+    /// `every ...` or a single frequency word like `weekly`, with an optional
+    /// `on ...` naming the days
+    fn recurrence_at(&self, index: usize) -> Option<(Recurrence, usize)> {
+        let locale = self.locale;
+        let first = self.word(index)?;
+
+        let (mut recurrence, mut consumed) = if let Some(unit) = Locale::lookup(locale.frequencies, first) {
+            (Recurrence::new(unit, 1), 1)
+        } else if Locale::is(locale.every, first) {
+            self.every(index + 1).map(|(recurrence, consumed)| (recurrence, consumed + 1))?
+        } else {
+            return None;
+        };
+
+        // "every week on monday", "every month on the 1st"
+        if Locale::is(locale.date_prepositions, self.word(index + consumed).unwrap_or_default()) {
+            let on = index + consumed + 1;
+            match recurrence.frequency {
+                Some(Unit::Week) if recurrence.by_day.is_empty() => {
+                    if let Some((days, count)) = self.weekdays(on) {
+                        recurrence.by_day = days;
+                        consumed += count + 1;
+                    }
+                }
+                Some(Unit::Month) | Some(Unit::Year) if recurrence.by_month_day.is_none() => {
+                    if let Some((day, count)) = self.day_of_month(on) {
+                        recurrence.by_month_day = Some(day);
+                        consumed += count + 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Some((recurrence, consumed))
+    }
+
+    /// What follows `every`
+    fn every(&self, index: usize) -> Option<(Recurrence, usize)> {
+        let locale = self.locale;
+        let word = self.word(index)?;
+
+        if Locale::is(locale.weekday_words, word) {
+            let mut recurrence = Recurrence::new(Unit::Week, 1);
+            recurrence.by_day = vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri];
+            return Some((recurrence, 1));
+        }
+        if Locale::is(locale.weekend_words, word) {
+            let mut recurrence = Recurrence::new(Unit::Week, 1);
+            recurrence.by_day = vec![Weekday::Sat, Weekday::Sun];
+            return Some((recurrence, 1));
+        }
+        if let Some(unit) = Locale::lookup(locale.units, word) {
+            return Some((Recurrence::new(unit, 1), 1));
+        }
+        if let Some((day, consumed)) = self.day_of_month(index) {
+            let mut recurrence = Recurrence::new(Unit::Month, 1);
+            recurrence.by_month_day = Some(day);
+            return Some((recurrence, consumed));
+        }
+
+        // "every 2 weeks", "every other monday"
+        let (interval, offset) = match self.number(index) {
+            Some(interval) if interval > 0 => (interval, 1),
+            _ => (1, 0),
+        };
+        if let Some(unit) = self.word(index + offset).and_then(|word| Locale::lookup(locale.units, word)) {
+            return Some((Recurrence::new(unit, interval), offset + 1));
+        }
+        let (days, consumed) = self.weekdays(index + offset)?;
+        let mut recurrence = Recurrence::new(Unit::Week, interval);
+        recurrence.by_day = days;
+        Some((recurrence, offset + consumed))
+    }
+
+    /// One or more weekdays joined by "and" or commas
+    fn weekdays(&self, index: usize) -> Option<(Vec<Weekday>, usize)> {
+        let mut days = vec![self.weekday(index, true)?];
+        let mut consumed = 1;
+        loop {
+            let joined = self.word(index + consumed).is_some_and(|word| Locale::is(self.locale.and, word));
+            let next = index + consumed + usize::from(joined);
+            match self.weekday(next, true) {
+                Some(day) => {
+                    if !days.contains(&day) {
+                        days.push(day);
+                    }
+                    consumed = next - index + 1;
+                }
+                None => break,
+            }
+        }
+        days.sort_by_key(|day| day.num_days_from_monday());
+        Some((days, consumed))
+    }
+
+    fn weekday(&self, index: usize, allow_abbreviation: bool) -> Option<Weekday> {
+        let word = self.word(index)?;
+        Locale::lookup(self.locale.weekdays, word).or_else(|| {
+            allow_abbreviation
+                .then(|| Locale::lookup(self.locale.weekday_abbreviations, word))
+                .flatten()
+        })
+    }
+
+    fn number(&self, index: usize) -> Option<u32> {
+        let word = self.word(index)?;
+        word.parse().ok().or_else(|| Locale::lookup(self.locale.numbers, word))
+    }
+
+    /// A day number with an ordinal suffix such as `1st`, optionally after `the`
+    fn day_of_month(&self, index: usize) -> Option<(u32, usize)> {
+        let article = self.word(index).is_some_and(|word| Locale::is(self.locale.articles, word));
+        let index = index + usize::from(article);
+        let word = self.word(index)?;
+        let digits = self
+            .locale
+            .ordinal_suffixes
+            .iter()
+            .find_map(|suffix| word.strip_suffix(suffix))?;
+        let day: u32 = digits.parse().ok()?;
+        (1..=31).contains(&day).then_some((day, usize::from(article) + 1))
+    }
+
+    /// A date, with any prepositions in front of it
+    fn date_with_preposition(&self, index: usize) -> Option<(DateValue, usize)> {
+        let mut prepositions = 0;
+        while self
+            .word(index + prepositions)
+            .is_some_and(|word| Locale::is(self.locale.date_prepositions, word))
+        {
+            prepositions += 1;
+        }
+        let (date, consumed) = self.date(index + prepositions, prepositions > 0)?;
+        Some((date, prepositions + consumed))
+    }
+
+    fn date(&self, index: usize, after_preposition: bool) -> Option<(DateValue, usize)> {
+        let locale = self.locale;
+        let word = self.word(index)?;
+        let today = self.today;
+
+        if let Some(days) = Locale::lookup(locale.relative_days, word) {
+            return Some((DateValue::Day(today + Duration::days(days)), 1));
+        }
+        if let Some(day) = self.weekday(index, after_preposition) {
+            return Some((DateValue::Day(upcoming(today, day)), 1));
+        }
+        if Locale::is(locale.this, word) {
+            let day = self.weekday(index + 1, true)?;
+            return Some((DateValue::Day(upcoming(today, day)), 2));
+        }
+        if Locale::is(locale.next, word) {
+            return self.next(index + 1).map(|date| (DateValue::Day(date), 2));
+        }
+        if Locale::is(locale.within, word) {
+            return self.offset(index + 1).map(|(date, consumed)| (date, consumed + 1));
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(word, "%Y-%m-%d") {
+            return Some((DateValue::Day(date), 1));
+        }
+        if let Some((date, consumed)) = self.calendar_date(index) {
+            return Some((DateValue::Day(date), consumed));
+        }
+        if after_preposition {
+            // "on the 15th": the next 15th of a month
+            let (day, consumed) = self.day_of_month(index)?;
+            let recurrence = Recurrence {
+                by_month_day: Some(day),
+                ..Recurrence::default()
+            };
+            return recurrence.first_day(today).map(|date| (DateValue::Day(date), consumed));
+        }
+        None
+    }
+
+    /// What follows `next`: a day of the following week, or the start of the next week, month or year
+    fn next(&self, index: usize) -> Option<NaiveDate> {
+        let today = self.today;
+        let next_week = upcoming(today + Duration::days(1), Weekday::Mon);
+        if let Some(day) = self.weekday(index, true) {
+            return Some(next_week + Duration::days(day.num_days_from_monday().into()));
+        }
+        match Locale::lookup(self.locale.units, self.word(index)?)? {
+            Unit::Week => Some(next_week),
+            Unit::Month => today.with_day(1)?.checked_add_months(Months::new(1)),
+            Unit::Year => NaiveDate::from_ymd_opt(today.year() + 1, 1, 1),
+            _ => None,
+        }
+    }
+
+    /// What follows `in`: `3 days`, `a week`, `2 hours`
+    fn offset(&self, index: usize) -> Option<(DateValue, usize)> {
+        let amount = self.number(index)?;
+        let unit = Locale::lookup(self.locale.units, self.word(index + 1)?)?;
+        let today = self.today;
+        let date = match unit {
+            Unit::Minute => DateValue::Exact(Duration::minutes(amount.into())),
+            Unit::Hour => DateValue::Exact(Duration::hours(amount.into())),
+            Unit::Day => DateValue::Day(today.checked_add_signed(Duration::days(amount.into()))?),
+            Unit::Week => DateValue::Day(today.checked_add_signed(Duration::weeks(amount.into()))?),
+            Unit::Month => DateValue::Day(today.checked_add_months(Months::new(amount))?),
+            Unit::Year => DateValue::Day(today.checked_add_months(Months::new(amount.checked_mul(12)?))?),
+        };
+        Some((date, 2))
+    }
+
+    /// `march 5`, `mar 5th 2025`, `5 march`, `5th of march`. Without a year the
+    /// date is the next one on or after today.
+    fn calendar_date(&self, index: usize) -> Option<(NaiveDate, usize)> {
+        let month_at = |index: usize| self.word(index).and_then(|word| Locale::lookup(self.locale.months, word));
+        let day_at = |index: usize| {
+            let word = self.word(index)?;
+            let digits = self
+                .locale
+                .ordinal_suffixes
+                .iter()
+                .find_map(|suffix| word.strip_suffix(suffix))
+                .unwrap_or(word);
+            digits.parse::<u32>().ok().filter(|day| (1..=31).contains(day))
+        };
+
+        let (month, day, consumed) = if let (Some(month), Some(day)) = (month_at(index), day_at(index + 1)) {
+            (month, day, 2)
+        } else if let Some(day) = day_at(index) {
+            // "5th of march"
+            let of = self.word(index + 1).is_some_and(|word| word == "of");
+            let month = month_at(index + 1 + usize::from(of))?;
+            (month, day, 2 + usize::from(of))
+        } else {
+            return None;
+        };
+
+        let year = self
+            .word(index + consumed)
+            .filter(|word| word.len() == 4)
+            .and_then(|word| word.parse::<i32>().ok());
+        if let Some(year) = year {
+            return NaiveDate::from_ymd_opt(year, month, day).map(|date| (date, consumed + 1));
+        }
+
+        let this_year = NaiveDate::from_ymd_opt(self.today.year(), month, day);
+        let date = match this_year {
+            Some(date) if date >= self.today => date,
+            _ => NaiveDate::from_ymd_opt(self.today.year() + 1, month, day)?,
+        };
+        Some((date, consumed))
+    }
+
+    /// A time, with an optional `at` in front of it. Bare hours like `9` need the `at`.
+    fn time_with_preposition(&self, index: usize) -> Option<(NaiveTime, usize)> {
+        let preposition = self
+            .word(index)
+            .is_some_and(|word| Locale::is(self.locale.time_prepositions, word));
+        let (time, consumed) = self.time(index + usize::from(preposition), preposition)?;
+        Some((time, consumed + usize::from(preposition)))
+    }
+
+    fn time(&self, index: usize, allow_bare_hour: bool) -> Option<(NaiveTime, usize)> {
+        let locale = self.locale;
+        let word = self.word(index)?;
+
+        if let Some((hour, minute)) = Locale::lookup(locale.times, word) {
+            return Some((NaiveTime::from_hms_opt(hour, minute, 0)?, 1));
+        }
+
+        // "9am", "9:30pm"
+        let suffixed = locale
+            .am
+            .iter()
+            .map(|suffix| (*suffix, false))
+            .chain(locale.pm.iter().map(|suffix| (*suffix, true)))
+            .find_map(|(suffix, pm)| word.strip_suffix(suffix).filter(|rest| !rest.is_empty()).map(|rest| (rest, pm)));
+        if let Some((clock, pm)) = suffixed {
+            return twelve_hour(clock, pm).map(|time| (time, 1));
+        }
+
+        // "9 am"
+        let meridiem = self.word(index + 1).and_then(|next| {
+            if Locale::is(locale.am, next) {
+                Some(false)
+            } else if Locale::is(locale.pm, next) {
+                Some(true)
+            } else {
+                None
+            }
+        });
+        if let Some(pm) = meridiem {
+            return twelve_hour(word, pm).map(|time| (time, 2));
+        }
+
+        // "14:00"
+        if word.contains(':') {
+            return NaiveTime::parse_from_str(word, "%H:%M").ok().map(|time| (time, 1));
+        }
+        if allow_bare_hour {
+            let hour: u32 = word.parse().ok()?;
+            return NaiveTime::from_hms_opt(hour, 0, 0).map(|time| (time, 1));
+        }
+        None
+    }
+
+    /// The unrecognised tokens, or `None` if everything was recognised
+    fn title(&self) -> Option<String> {
+        let words: Vec<&str> = self
+            .tokens
+            .iter()
+            .zip(&self.used)
+            .filter(|(_, used)| !**used)
+            .map(|(token, _)| *token)
+            .collect();
+        let title = words.join(" ");
+        let title = title.trim_end_matches([',', ';']);
+        (!title.is_empty()).then(|| title.to_string())
+    }
+
+    fn due_date<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> Option<DateTime<Utc>> {
+        let timezone = now.timezone();
+        let now = now.with_timezone(&Utc);
+        let at = |day: NaiveDate| match self.time {
+            Some(time) => local_to_utc(&timezone, day.and_time(time)),
+            None => due::all_day(day),
+        };
+
+        match (&self.date, &self.recurrence, self.time) {
+            (Some(DateValue::Exact(offset)), _, _) => now.checked_add_signed(*offset),
+            (Some(DateValue::Day(day)), _, _) => Some(at(*day)),
+            // The first occurrence still to come
+            (None, Some(recurrence), _) => {
+                let first = at(recurrence.first_day(self.today)?);
+                if first > now || self.time.is_none() {
+                    Some(first)
+                } else {
+                    recurrence.first_day(self.today + Duration::days(1)).map(at)
+                }
+            }
+            // A time on its own is the next time it comes round
+            (None, None, Some(_)) => {
+                let today = at(self.today);
+                Some(if today > now { today } else { at(self.today + Duration::days(1)) })
+            }
+            (None, None, None) => None,
+        }
+    }
+}
+
+/// The next `weekday` on or after `today`
+fn upcoming(today: NaiveDate, weekday: Weekday) -> NaiveDate {
+    let ahead = (7 + weekday.num_days_from_monday() - today.weekday().num_days_from_monday()) % 7;
+    today + Duration::days(ahead.into())
+}
+
+fn twelve_hour(clock: &str, pm: bool) -> Option<NaiveTime> {
+    let (hour, minute) = match clock.split_once([':', '.']) {
+        Some((hour, minute)) => (hour.parse::<u32>().ok()?, minute.parse::<u32>().ok()?),
+        None => (clock.parse::<u32>().ok()?, 0),
+    };
+    if !(1..=12).contains(&hour) {
+        return None;
+    }
+    let hour = match (hour, pm) {
+        (12, false) => 0,
+        (12, true) => 12,
+        (hour, true) => hour + 12,
+        (hour, false) => hour,
+    };
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+fn weekday_code(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
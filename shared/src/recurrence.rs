@@ -0,0 +1,26 @@
+//! Recurrence rules, written in the iCalendar RRULE syntax (RFC 5545 section 3.3.10).
+
+/// Frequencies accepted in the `FREQ` part of a rule
+const FREQUENCIES: [&str; 7] = ["SECONDLY", "MINUTELY", "HOURLY", "DAILY", "WEEKLY", "MONTHLY", "YEARLY"];
+
+/// Check that a rule is a list of `KEY=VALUE` parts with a valid `FREQ`,
+/// e.g. `FREQ=MONTHLY;BYMONTHDAY=1`
+pub fn is_valid_rrule(rule: &str) -> bool {
+    let mut frequency = None;
+    for part in rule.split(';') {
+        let Some((key, value)) = part.split_once('=') else {
+            return false;
+        };
+        if key.is_empty() || value.is_empty() || !key.chars().all(|c| c.is_ascii_uppercase() || c == '-') {
+            return false;
+        }
+        // Rules are written into calendars verbatim, so nothing that could end a line
+        if !value.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || matches!(c, ',' | '+' | '-')) {
+            return false;
+        }
+        if key == "FREQ" {
+            frequency = Some(value);
+        }
+    }
+    frequency.is_some_and(|frequency| FREQUENCIES.contains(&frequency))
+}
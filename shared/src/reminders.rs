@@ -0,0 +1,89 @@
+//! Reminders attached to todos.
+//!
+//! Reminders are stored on the todo itself, so snoozing or dismissing one is an
+//! ordinary edit that syncs to other devices with the rest of the todo.
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Longest offset, in minutes, a relative reminder can have from the due date
+pub const MAX_BEFORE_DUE_MINUTES: i64 = 366 * 24 * 60;
+
+/// When a reminder fires
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReminderTrigger {
+    /// At a fixed time
+    At { at: DateTime<Utc> },
+    /// A number of minutes before the todo's due date
+    BeforeDue { minutes: i64 },
+}
+
+/// A reminder for a todo
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Reminder {
+    #[serde(default = "new_id")]
+    pub id: String,
+    pub trigger: ReminderTrigger,
+    /// Fire at this time instead of the trigger time
+    #[serde(default)]
+    pub snoozed_until: Option<DateTime<Utc>>,
+    /// Set once the reminder has been dismissed; it never fires again
+    #[serde(default)]
+    pub dismissed_at: Option<DateTime<Utc>>,
+}
+
+fn new_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+impl Reminder {
+    pub fn new(trigger: ReminderTrigger) -> Self {
+        Self {
+            id: new_id(),
+            trigger,
+            snoozed_until: None,
+            dismissed_at: None,
+        }
+    }
+
+    /// Whether a relative reminder's offset is within `MAX_BEFORE_DUE_MINUTES`
+    pub fn is_valid(&self) -> bool {
+        match self.trigger {
+            ReminderTrigger::At { .. } => true,
+            ReminderTrigger::BeforeDue { minutes } => minutes.unsigned_abs() <= MAX_BEFORE_DUE_MINUTES as u64,
+        }
+    }
+
+    /// When the trigger itself fires. Relative reminders on a todo without
+    /// a due date, or whose offset is out of range, never fire.
+    pub fn trigger_time(&self, due_date: Option<DateTime<Utc>>) -> Option<DateTime<Utc>> {
+        match self.trigger {
+            ReminderTrigger::At { at } => Some(at),
+            ReminderTrigger::BeforeDue { minutes } => {
+                let due = due_date?;
+                TimeDelta::try_minutes(minutes).and_then(|offset| due.checked_sub_signed(offset))
+            }
+        }
+    }
+
+    /// When the reminder should next fire, taking snoozing and dismissal into account
+    pub fn fire_at(&self, due_date: Option<DateTime<Utc>>) -> Option<DateTime<Utc>> {
+        if self.dismissed_at.is_some() {
+            return None;
+        }
+        self.snoozed_until.or_else(|| self.trigger_time(due_date))
+    }
+
+    /// Fire again at `until`
+    pub fn snooze(&mut self, until: DateTime<Utc>) {
+        self.snoozed_until = Some(until);
+        self.dismissed_at = None;
+    }
+
+    /// Stop the reminder from firing again
+    pub fn dismiss(&mut self, at: DateTime<Utc>) {
+        self.dismissed_at = Some(at);
+        self.snoozed_until = None;
+    }
+}
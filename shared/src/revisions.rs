@@ -0,0 +1,76 @@
+//! Revision history: a field-level record of every change made to a todo.
+//!
+//! Each revision holds the fields that changed and a snapshot of the todo right
+//! after the change, so that a todo can be reverted to any earlier revision.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::types::Json;
+use sqlx::FromRow;
+
+/// Days revisions are kept unless configured otherwise
+pub const DEFAULT_RETENTION_DAYS: i64 = 90;
+
+/// Longest revisions can be kept, in days
+pub const MAX_RETENTION_DAYS: i64 = 36_500;
+
+/// Fields left out of diffs, because every change touches them
+const IGNORED_FIELDS: [&str; 1] = ["updated_at"];
+
+/// A single field's value before and after a change. A todo that did not
+/// exist before, or no longer exists after, has `null` for every field.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+/// One change to a todo
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct Revision {
+    pub id: i64,
+    pub todo_id: String,
+    /// The user who made the change, if known
+    pub actor_id: Option<String>,
+    /// The device the change was made on, if known
+    pub device_id: Option<String>,
+    /// What kind of change it was, e.g. `update` or `sync`
+    pub action: String,
+    pub changes: Json<Vec<FieldChange>>,
+    /// The todo right after the change, or `None` once it was deleted for good
+    pub snapshot: Option<Json<Value>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The fields that differ between two versions of a todo, in field name order
+pub fn diff<T: Serialize>(before: Option<&T>, after: Option<&T>) -> Vec<FieldChange> {
+    let before = fields(before);
+    let after = fields(after);
+
+    let mut names: Vec<&String> = before.keys().chain(after.keys()).collect();
+    names.sort();
+    names.dedup();
+
+    names
+        .into_iter()
+        .filter(|name| !IGNORED_FIELDS.contains(&name.as_str()))
+        .filter_map(|name| {
+            let old = before.get(name).cloned().unwrap_or(Value::Null);
+            let new = after.get(name).cloned().unwrap_or(Value::Null);
+            (old != new).then(|| FieldChange {
+                field: name.clone(),
+                before: old,
+                after: new,
+            })
+        })
+        .collect()
+}
+
+fn fields<T: Serialize>(todo: Option<&T>) -> serde_json::Map<String, Value> {
+    match todo.map(serde_json::to_value) {
+        Some(Ok(Value::Object(fields))) => fields,
+        _ => serde_json::Map::new(),
+    }
+}
@@ -0,0 +1,266 @@
+//! Smart views: named filters over todos, such as "Today" or "Overdue".
+//!
+//! The built-in views are fixed. Users can also save their own filters as
+//! [`SavedView`]s, which sync between devices like todos do.
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::FromRow;
+
+use crate::due::{self, DueBucket};
+use crate::formats::TodoRecord;
+use crate::Priority;
+
+/// Days a completed todo stays in "Recently completed"
+pub const RECENTLY_COMPLETED_DAYS: i64 = 7;
+
+/// Longest span, in days, a filter can look forward or back
+pub const MAX_FILTER_DAYS: i64 = 3650;
+
+/// Which due dates a filter matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DueFilter {
+    /// Due before today
+    Overdue,
+    /// Due today
+    Today,
+    /// Due today or in the `days` days after
+    Within { days: i64 },
+    /// Without a due date
+    None,
+    /// With any due date
+    Any,
+}
+
+/// Conditions a todo must all meet to be in a view. Unset conditions match everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ViewFilter {
+    #[serde(default)]
+    pub completed: Option<bool>,
+    /// Only todos completed (last changed) in the past this many days
+    #[serde(default)]
+    pub completed_within_days: Option<i64>,
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    #[serde(default)]
+    pub list: Option<String>,
+    /// Tags a todo must all have
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub due: Option<DueFilter>,
+    /// Case-insensitive text in the title or description
+    #[serde(default)]
+    pub search: Option<String>,
+}
+
+impl ViewFilter {
+    /// Whether the day counts in the filter are within `0..=MAX_FILTER_DAYS`
+    pub fn is_valid(&self) -> bool {
+        let in_range = |days: i64| (0..=MAX_FILTER_DAYS).contains(&days);
+        self.completed_within_days.is_none_or(in_range)
+            && !matches!(self.due, Some(DueFilter::Within { days }) if !in_range(days))
+    }
+
+    /// Whether `todo` is in the view, with `now` in the user's time zone
+    pub fn matches<T: TimeZone>(&self, todo: &TodoRecord, now: &DateTime<T>) -> bool {
+        if self.completed.is_some_and(|completed| completed != todo.completed) {
+            return false;
+        }
+        if let Some(days) = self.completed_within_days {
+            // Past the earliest representable time, every completed todo is recent
+            let since = Duration::try_days(days).and_then(|days| now.with_timezone(&Utc).checked_sub_signed(days));
+            if !todo.completed || since.is_some_and(|since| todo.updated_at < since) {
+                return false;
+            }
+        }
+        if self.priority.is_some_and(|priority| priority != todo.priority) {
+            return false;
+        }
+        if self.list.is_some() && self.list != todo.list {
+            return false;
+        }
+        if !self.tags.iter().all(|tag| todo.tags.contains(tag)) {
+            return false;
+        }
+        if let Some(search) = &self.search {
+            let search = search.to_lowercase();
+            let in_title = todo.title.to_lowercase().contains(&search);
+            let in_description = todo
+                .description
+                .as_ref()
+                .is_some_and(|description| description.to_lowercase().contains(&search));
+            if !in_title && !in_description {
+                return false;
+            }
+        }
+        match (self.due, todo.due_date) {
+            (None, _) => true,
+            (Some(DueFilter::None), due) => due.is_none(),
+            (Some(_), None) => false,
+            (Some(DueFilter::Any), Some(_)) => true,
+            (Some(filter), Some(due)) => {
+                let days = match filter {
+                    DueFilter::Within { days } => days,
+                    _ => 0,
+                };
+                let bucket = due::bucket(due, todo.all_day, now, days);
+                match filter {
+                    DueFilter::Overdue => bucket == DueBucket::Overdue,
+                    DueFilter::Today => bucket == DueBucket::Today,
+                    _ => matches!(bucket, DueBucket::Today | DueBucket::Upcoming),
+                }
+            }
+        }
+    }
+}
+
+/// The built-in views
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmartView {
+    Today,
+    #[serde(rename = "next_7_days")]
+    Next7Days,
+    Overdue,
+    NoDueDate,
+    HighPriority,
+    RecentlyCompleted,
+}
+
+impl SmartView {
+    pub const ALL: [SmartView; 6] = [
+        SmartView::Today,
+        SmartView::Next7Days,
+        SmartView::Overdue,
+        SmartView::NoDueDate,
+        SmartView::HighPriority,
+        SmartView::RecentlyCompleted,
+    ];
+
+    /// Identifier used in URLs and commands, e.g. `next_7_days`
+    pub fn id(self) -> &'static str {
+        match self {
+            SmartView::Today => "today",
+            SmartView::Next7Days => "next_7_days",
+            SmartView::Overdue => "overdue",
+            SmartView::NoDueDate => "no_due_date",
+            SmartView::HighPriority => "high_priority",
+            SmartView::RecentlyCompleted => "recently_completed",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SmartView::Today => "Today",
+            SmartView::Next7Days => "Next 7 days",
+            SmartView::Overdue => "Overdue",
+            SmartView::NoDueDate => "No due date",
+            SmartView::HighPriority => "High priority",
+            SmartView::RecentlyCompleted => "Recently completed",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<SmartView> {
+        SmartView::ALL.into_iter().find(|view| view.id() == id)
+    }
+
+    pub fn filter(self) -> ViewFilter {
+        let open = ViewFilter {
+            completed: Some(false),
+            ..ViewFilter::default()
+        };
+        match self {
+            SmartView::Today => ViewFilter {
+                due: Some(DueFilter::Today),
+                ..open
+            },
+            SmartView::Next7Days => ViewFilter {
+                due: Some(DueFilter::Within { days: 7 }),
+                ..open
+            },
+            SmartView::Overdue => ViewFilter {
+                due: Some(DueFilter::Overdue),
+                ..open
+            },
+            SmartView::NoDueDate => ViewFilter {
+                due: Some(DueFilter::None),
+                ..open
+            },
+            SmartView::HighPriority => ViewFilter {
+                priority: Some(Priority::High),
+                ..open
+            },
+            SmartView::RecentlyCompleted => ViewFilter {
+                completed: Some(true),
+                completed_within_days: Some(RECENTLY_COMPLETED_DAYS),
+                ..ViewFilter::default()
+            },
+        }
+    }
+}
+
+/// A filter saved by the user under a name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct SavedView {
+    pub id: String,
+    pub name: String,
+    pub filter: Json<ViewFilter>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// Set when the view has been deleted, so that the deletion syncs
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl SavedView {
+    pub fn new(name: String, filter: ViewFilter) -> Self {
+        let now = Utc::now();
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            name,
+            filter: Json(filter),
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+        }
+    }
+}
+
+/// Number of todos in a view, for badges
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ViewCount {
+    /// A built-in view id such as `today`, or the id of a saved view
+    pub id: String,
+    pub name: String,
+    pub count: usize,
+}
+
+/// Find a view's filter by id, among the built-in views and then `saved`
+pub fn find_filter(id: &str, saved: &[SavedView]) -> Option<ViewFilter> {
+    SmartView::from_id(id).map(SmartView::filter).or_else(|| {
+        saved
+            .iter()
+            .find(|view| view.id == id && view.deleted_at.is_none())
+            .map(|view| view.filter.0.clone())
+    })
+}
+
+/// Count the todos in every built-in view, then in every saved one
+pub fn count_views<T: TimeZone>(todos: &[TodoRecord], saved: &[SavedView], now: &DateTime<T>) -> Vec<ViewCount> {
+    let count = |filter: &ViewFilter| todos.iter().filter(|todo| filter.matches(todo, now)).count();
+
+    let built_in = SmartView::ALL.into_iter().map(|view| ViewCount {
+        id: view.id().to_string(),
+        name: view.name().to_string(),
+        count: count(&view.filter()),
+    });
+    let saved = saved.iter().filter(|view| view.deleted_at.is_none()).map(|view| ViewCount {
+        id: view.id.clone(),
+        name: view.name.clone(),
+        count: count(&view.filter),
+    });
+    built_in.chain(saved).collect()
+}
@@ -0,0 +1,36 @@
+use todo_shared::attachments::{self, Attachment};
+
+#[test]
+fn hashes_identify_contents() {
+    let receipt = Attachment::new("todo".into(), "alice".into(), "receipt.pdf".into(), "application/pdf".into(), b"%PDF-1.7");
+    let copy = Attachment::new("other".into(), "bob".into(), "copy.pdf".into(), "application/pdf".into(), b"%PDF-1.7");
+    assert_eq!(receipt.hash, copy.hash);
+    assert_eq!(receipt.size, 8);
+    assert!(attachments::is_valid_hash(&receipt.hash));
+
+    assert_eq!(
+        attachments::content_hash(b""),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert!(!attachments::is_valid_hash("../../etc/passwd"));
+    assert!(!attachments::is_valid_hash(&receipt.hash.to_uppercase()));
+}
+
+#[test]
+fn names_are_cleaned() {
+    assert_eq!(attachments::clean_name("C:\\Users\\me\\scan.png").unwrap(), "scan.png");
+    assert_eq!(attachments::clean_name("../../secret.txt").unwrap(), "secret.txt");
+    assert_eq!(attachments::clean_name(" notes.md ").unwrap(), "notes.md");
+    assert!(attachments::clean_name("dir/").is_err());
+    assert!(attachments::clean_name("..").is_err());
+    assert!(attachments::clean_name("a\nb").is_err());
+    assert!(attachments::clean_name(&"x".repeat(256)).is_err());
+}
+
+#[test]
+fn sizes_are_limited() {
+    assert!(attachments::validate_size(1).is_ok());
+    assert!(attachments::validate_size(attachments::MAX_SIZE).is_ok());
+    assert!(attachments::validate_size(0).is_err());
+    assert!(attachments::validate_size(attachments::MAX_SIZE + 1).is_err());
+}
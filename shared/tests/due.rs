@@ -0,0 +1,91 @@
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use chrono_tz::America::{Los_Angeles, New_York};
+use chrono_tz::Asia::Tokyo;
+use todo_shared::due::{self, DueBucket};
+
+fn utc(y: i32, m: u32, d: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(y, m, d, hour, minute, 0).unwrap()
+}
+
+fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(y, m, d).unwrap()
+}
+
+#[test]
+fn timed_due_dates_fall_on_the_local_day() {
+    // 03:30 UTC is still the evening before in New York, but morning in Tokyo
+    let due = utc(2024, 6, 1, 3, 30);
+
+    assert_eq!(due::due_day(due, false, &New_York), date(2024, 5, 31));
+    assert_eq!(due::due_day(due, false, &Tokyo), date(2024, 6, 1));
+}
+
+#[test]
+fn all_day_due_dates_fall_on_their_date_everywhere() {
+    let due = due::all_day(date(2024, 6, 1));
+
+    assert_eq!(due, utc(2024, 6, 1, 0, 0));
+    assert_eq!(due::due_day(due, true, &New_York), date(2024, 6, 1));
+    assert_eq!(due::due_day(due, true, &Tokyo), date(2024, 6, 1));
+    assert_eq!(due::due_day(due, true, &Los_Angeles), date(2024, 6, 1));
+}
+
+#[test]
+fn buckets_by_calendar_day() {
+    let now = New_York.with_ymd_and_hms(2024, 6, 12, 15, 0, 0).unwrap();
+    let at = |d: u32, hour: u32| New_York.with_ymd_and_hms(2024, 6, d, hour, 0, 0).unwrap().with_timezone(&Utc);
+
+    assert_eq!(due::bucket(at(11, 23), false, &now, 7), DueBucket::Overdue);
+    // Earlier today is still today, not overdue
+    assert_eq!(due::bucket(at(12, 9), false, &now, 7), DueBucket::Today);
+    assert_eq!(due::bucket(at(12, 23), false, &now, 7), DueBucket::Today);
+    assert_eq!(due::bucket(at(13, 0), false, &now, 7), DueBucket::Upcoming);
+    assert_eq!(due::bucket(at(19, 23), false, &now, 7), DueBucket::Upcoming);
+    assert_eq!(due::bucket(at(20, 0), false, &now, 7), DueBucket::Later);
+
+    assert_eq!(due::bucket(due::all_day(date(2024, 6, 11)), true, &now, 7), DueBucket::Overdue);
+    assert_eq!(due::bucket(due::all_day(date(2024, 6, 12)), true, &now, 7), DueBucket::Today);
+    assert_eq!(due::bucket(due::all_day(date(2024, 6, 13)), true, &now, 0), DueBucket::Later);
+}
+
+#[test]
+fn all_day_todos_are_not_overdue_early_in_the_day() {
+    // Midnight UTC has long passed in Los Angeles when it is already the 12th in UTC,
+    // but an all-day todo due on the 12th is due today, not yesterday
+    let now = Los_Angeles.with_ymd_and_hms(2024, 6, 12, 8, 0, 0).unwrap();
+
+    assert_eq!(due::bucket(due::all_day(date(2024, 6, 12)), true, &now, 7), DueBucket::Today);
+    assert_eq!(due::bucket(due::all_day(date(2024, 6, 12)), false, &now, 7), DueBucket::Overdue);
+}
+
+#[test]
+fn spring_forward_day_has_23_hours() {
+    // Clocks in New York skip from 2:00 to 3:00 on 2024-03-10
+    let now = New_York.with_ymd_and_hms(2024, 3, 10, 0, 30, 0).unwrap();
+
+    let late_tonight = now.with_timezone(&Utc) + Duration::hours(22);
+    let after_midnight = now.with_timezone(&Utc) + Duration::hours(23);
+
+    assert_eq!(late_tonight.with_timezone(&New_York).format("%d %H:%M").to_string(), "10 23:30");
+    assert_eq!(due::bucket(late_tonight, false, &now, 7), DueBucket::Today);
+    assert_eq!(due::bucket(after_midnight, false, &now, 7), DueBucket::Upcoming);
+}
+
+#[test]
+fn fall_back_day_has_25_hours() {
+    // Clocks in New York go back from 2:00 to 1:00 on 2024-11-03
+    let now = New_York.with_ymd_and_hms(2024, 11, 3, 0, 30, 0).unwrap();
+
+    let late_tonight = now.with_timezone(&Utc) + Duration::hours(24);
+    let after_midnight = now.with_timezone(&Utc) + Duration::hours(25);
+
+    assert_eq!(late_tonight.with_timezone(&New_York).format("%d %H:%M").to_string(), "03 23:30");
+    assert_eq!(due::bucket(late_tonight, false, &now, 7), DueBucket::Today);
+    assert_eq!(due::bucket(after_midnight, false, &now, 7), DueBucket::Upcoming);
+}
+
+#[test]
+fn parses_timezone_names() {
+    assert_eq!(due::parse_timezone("America/New_York"), Ok(New_York));
+    assert!(due::parse_timezone("Mars/Olympus_Mons").is_err());
+}
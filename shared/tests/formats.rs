@@ -0,0 +1,125 @@
+use chrono::{TimeZone, Utc};
+use todo_shared::formats::{export_todos, parse_todos, Archive, ExportFormat, FormatError, TodoRecord, ARCHIVE_VERSION};
+use todo_shared::Priority;
+
+fn record(id: &str, title: &str) -> TodoRecord {
+    let created_at = Utc.with_ymd_and_hms(2024, 3, 1, 9, 30, 0).unwrap();
+    TodoRecord {
+        id: id.to_string(),
+        title: title.to_string(),
+        description: None,
+        completed: false,
+        priority: Priority::Medium,
+        created_at,
+        updated_at: created_at,
+        due_date: None,
+        all_day: false,
+        list: None,
+        tags: Vec::new(),
+        recurrence: None,
+        parent_id: None,
+    }
+}
+
+fn sample() -> Vec<TodoRecord> {
+    let mut groceries = record("groceries", "Buy groceries, milk \"and\" eggs");
+    groceries.description = Some("From the market\non Saturday".to_string());
+    groceries.priority = Priority::High;
+    groceries.due_date = Some(Utc.with_ymd_and_hms(2024, 3, 2, 0, 0, 0).unwrap());
+    groceries.all_day = true;
+    groceries.list = Some("Home".to_string());
+    groceries.tags = vec!["errands".to_string(), "food".to_string()];
+    groceries.recurrence = Some("FREQ=WEEKLY;BYDAY=SA".to_string());
+
+    let mut milk = record("milk", "Milk");
+    milk.completed = true;
+    milk.parent_id = Some("groceries".to_string());
+    milk.updated_at = Utc.with_ymd_and_hms(2024, 3, 2, 10, 0, 0).unwrap();
+
+    vec![groceries, milk, record("call", "Call Mom")]
+}
+
+#[test]
+fn json_archive_round_trips() {
+    let todos = sample();
+    let exported = export_todos(&todos, ExportFormat::Json).unwrap();
+
+    let archive: Archive = serde_json::from_str(&exported).unwrap();
+    assert_eq!(archive.version, ARCHIVE_VERSION);
+    assert_eq!(parse_todos(&exported, ExportFormat::Json).unwrap(), todos);
+}
+
+#[test]
+fn json_archive_from_a_newer_version_is_refused() {
+    let input = format!(r#"{{"version": {}, "exported_at": "2024-03-01T00:00:00Z", "todos": []}}"#, ARCHIVE_VERSION + 1);
+
+    let error = parse_todos(&input, ExportFormat::Json).unwrap_err();
+    assert!(matches!(error, FormatError::UnsupportedVersion(version) if version == ARCHIVE_VERSION + 1));
+}
+
+#[test]
+fn csv_round_trips() {
+    let todos = sample();
+    let exported = export_todos(&todos, ExportFormat::Csv).unwrap();
+
+    assert_eq!(parse_todos(&exported, ExportFormat::Csv).unwrap(), todos);
+}
+
+#[test]
+fn csv_needs_only_a_title() {
+    let todos = parse_todos("title,completed\nWater plants,yes\nFeed cat,\n", ExportFormat::Csv).unwrap();
+
+    assert_eq!(todos.len(), 2);
+    assert_eq!(todos[0].title, "Water plants");
+    assert!(todos[0].completed);
+    assert!(!todos[1].completed);
+    assert_eq!(todos[1].priority, Priority::default());
+    assert_ne!(todos[0].id, todos[1].id);
+}
+
+#[test]
+fn csv_reports_the_line_of_a_bad_row() {
+    let error = parse_todos("title,completed\nWater plants,yes\n,no\n", ExportFormat::Csv).unwrap_err();
+    assert!(matches!(error, FormatError::Parse { line: 3, .. }));
+
+    let error = parse_todos("title,completed\nWater plants,maybe\n", ExportFormat::Csv).unwrap_err();
+    assert!(matches!(error, FormatError::Parse { line: 2, .. }));
+}
+
+#[test]
+fn markdown_round_trips_titles_completion_and_subtasks() {
+    let todos = sample();
+    let exported = export_todos(&todos, ExportFormat::Markdown).unwrap();
+    assert_eq!(
+        exported,
+        "- [ ] Buy groceries, milk \"and\" eggs\n  - [x] Milk\n- [ ] Call Mom\n"
+    );
+
+    let parsed = parse_todos(&exported, ExportFormat::Markdown).unwrap();
+    let summary: Vec<(&str, bool)> = parsed.iter().map(|todo| (todo.title.as_str(), todo.completed)).collect();
+    assert_eq!(
+        summary,
+        vec![("Buy groceries, milk \"and\" eggs", false), ("Milk", true), ("Call Mom", false)]
+    );
+    // Markdown carries no ids, so subtasks point at the new id of their parent
+    assert_eq!(parsed[1].parent_id.as_ref(), Some(&parsed[0].id));
+    assert_eq!(parsed[2].parent_id, None);
+}
+
+#[test]
+fn markdown_ignores_lines_that_are_not_checklist_items() {
+    let input = "# Weekend\n\nSome notes.\n* [X] Laundry\n- [ ]\n- plain bullet\n";
+
+    let todos = parse_todos(input, ExportFormat::Markdown).unwrap();
+    assert_eq!(todos.len(), 1);
+    assert_eq!(todos[0].title, "Laundry");
+    assert!(todos[0].completed);
+}
+
+#[test]
+fn markdown_writes_orphaned_subtasks_at_the_top_level() {
+    let mut orphan = record("orphan", "Orphan");
+    orphan.parent_id = Some("missing".to_string());
+
+    assert_eq!(export_todos(&[orphan], ExportFormat::Markdown).unwrap(), "- [ ] Orphan\n");
+}
@@ -0,0 +1,134 @@
+use chrono::{DateTime, TimeZone, Utc};
+use todo_shared::formats::ical::{parse_calendar, write_calendar};
+use todo_shared::formats::TodoRecord;
+use todo_shared::Priority;
+
+const EXPORT_GOLDEN: &str = include_str!("fixtures/ical/export.ics");
+const IMPORT_SAMPLE: &str = include_str!("fixtures/ical/import.ics");
+
+fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+}
+
+fn sample_todos() -> Vec<TodoRecord> {
+    let mut rent = TodoRecord::new("Pay rent".to_string());
+    rent.id = "0b6f3c1e-9a47-4a8e-8d0c-2f5d1d3e7a10".to_string();
+    rent.priority = Priority::High;
+    rent.created_at = at(2024, 2, 20, 8, 0);
+    rent.updated_at = at(2024, 2, 21, 9, 30);
+    rent.due_date = Some(at(2024, 3, 1, 9, 0));
+    rent.list = Some("Home".to_string());
+    rent.tags = vec!["finance".to_string(), "bills, monthly".to_string()];
+    rent.recurrence = Some("FREQ=MONTHLY;BYMONTHDAY=1".to_string());
+
+    let mut report = TodoRecord::new("Send report; with notes".to_string());
+    report.id = "5c2e8f90-1b3d-4e6a-9f7c-8d4b2a1e0f33".to_string();
+    report.description = Some(
+        "First line\nSecond line with a long sentence that needs folding because it runs past seventy-five octets"
+            .to_string(),
+    );
+    report.completed = true;
+    report.priority = Priority::Low;
+    report.created_at = at(2024, 2, 1, 12, 0);
+    report.updated_at = at(2024, 2, 2, 17, 45);
+    report.due_date = Some(at(2024, 2, 2, 18, 0));
+
+    vec![rent, report]
+}
+
+fn normalize(text: &str) -> String {
+    text.replace("\r\n", "\n")
+}
+
+#[test]
+fn export_matches_golden_file() {
+    let output = write_calendar(&sample_todos(), Some("Todos"));
+
+    assert_eq!(normalize(&output), normalize(EXPORT_GOLDEN));
+}
+
+#[test]
+fn export_uses_crlf_and_folds_long_lines() {
+    let output = write_calendar(&sample_todos(), None);
+
+    assert!(output.ends_with("\r\n"));
+    for line in output.split("\r\n") {
+        assert!(!line.contains('\n'));
+        assert!(line.len() <= 75, "line too long: {:?}", line);
+    }
+}
+
+#[test]
+fn export_round_trips() {
+    let todos = sample_todos();
+    let parsed = parse_calendar(&write_calendar(&todos, None)).unwrap();
+
+    assert_eq!(parsed, todos);
+}
+
+#[test]
+fn subtasks_are_related_to_their_parent() {
+    let mut todos = sample_todos();
+    todos[1].parent_id = Some(todos[0].id.clone());
+    let output = write_calendar(&todos, None);
+    assert!(output.contains(&format!("\r\nRELATED-TO:{}\r\n", todos[0].id)));
+    assert_eq!(parse_calendar(&output).unwrap(), todos);
+
+    // Only parents count, not siblings or children
+    let sibling = output.replace("RELATED-TO:", "RELATED-TO;RELTYPE=SIBLING:");
+    assert_eq!(parse_calendar(&sibling).unwrap()[1].parent_id, None);
+}
+
+#[test]
+fn recurrences_cannot_inject_lines() {
+    use todo_shared::recurrence::is_valid_rrule;
+
+    assert!(is_valid_rrule("FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,-1FR;UNTIL=20241231T000000Z"));
+    for rule in ["FREQ=DAILY;X=1\r\nSUMMARY:pwn", "FREQ=DAILY;X=1\nA", "FREQ=DAILY;X=a:b", "FREQ=DAILY\r\n"] {
+        assert!(!is_valid_rrule(rule), "{:?}", rule);
+    }
+
+    let mut todo = TodoRecord::new("Water plants".to_string());
+    todo.recurrence = Some("FREQ=DAILY;X=1\r\nSUMMARY:pwn".to_string());
+    let output = write_calendar(&[todo], None);
+    assert!(!output.contains("RRULE"));
+    assert!(!output.split("\r\n").any(|line| line == "SUMMARY:pwn"));
+}
+
+#[test]
+fn import_matches_expected_todos() {
+    let todos = parse_calendar(IMPORT_SAMPLE).unwrap();
+    assert_eq!(todos.len(), 3);
+
+    let groceries = &todos[0];
+    assert_eq!(groceries.id, "groceries-1@example.com");
+    assert_eq!(groceries.title, "Buy groceries, milk and eggs");
+    assert_eq!(groceries.description.as_deref(), Some("From the market\non the corner"));
+    assert_eq!(groceries.priority, Priority::High);
+    assert!(!groceries.completed);
+    assert_eq!(groceries.due_date, Some(at(2024, 3, 5, 0, 0)));
+    assert_eq!(groceries.created_at, at(2024, 3, 1, 10, 0));
+    assert_eq!(groceries.updated_at, at(2024, 3, 2, 11, 15));
+    assert_eq!(groceries.tags, vec!["errands", "food"]);
+    assert_eq!(groceries.recurrence.as_deref(), Some("FREQ=WEEKLY;BYDAY=SA"));
+
+    let dentist = &todos[1];
+    assert_eq!(dentist.title, "Call the dentist about the appointment that was moved to next week");
+    assert!(dentist.completed);
+    assert_eq!(dentist.priority, Priority::Medium);
+    assert_eq!(dentist.due_date, Some(at(2024, 3, 4, 14, 30)));
+    assert_eq!(dentist.tags, Vec::<String>::new());
+
+    let untitled = &todos[2];
+    assert_eq!(untitled.title, "Untitled");
+    assert_eq!(untitled.priority, Priority::Low);
+    assert_eq!(untitled.due_date, None);
+    assert_eq!(untitled.recurrence, None);
+}
+
+#[test]
+fn import_rejects_malformed_lines() {
+    let input = "BEGIN:VCALENDAR\r\nBEGIN:VTODO\r\nnot a property\r\nEND:VTODO\r\nEND:VCALENDAR\r\n";
+
+    assert!(parse_calendar(input).is_err());
+}
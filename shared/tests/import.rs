@@ -0,0 +1,132 @@
+use std::collections::HashSet;
+
+use chrono::{TimeZone, Utc};
+use todo_shared::formats::{export_todos, parse_todos, ExportFormat, TodoRecord};
+use todo_shared::import::{check_imported, plan_import, ImportError};
+
+fn record(id: &str, title: &str) -> TodoRecord {
+    let mut todo = TodoRecord::new(title.to_string());
+    todo.id = id.to_string();
+    todo
+}
+
+fn subtask(id: &str, title: &str, parent_id: &str) -> TodoRecord {
+    let mut todo = record(id, title);
+    todo.parent_id = Some(parent_id.to_string());
+    todo
+}
+
+fn ids(todos: &[TodoRecord]) -> Vec<&str> {
+    todos.iter().map(|todo| todo.id.as_str()).collect()
+}
+
+#[test]
+fn skips_todos_whose_id_is_known() {
+    let existing = vec![record("a", "Laundry")];
+    let incoming = vec![record("a", "Laundry, renamed"), record("b", "Dishes")];
+
+    let report = plan_import(&existing, incoming, false);
+    assert_eq!(report.total, 2);
+    assert_eq!(ids(&report.imported), vec!["b"]);
+    assert_eq!(ids(&report.duplicates), vec!["a"]);
+}
+
+#[test]
+fn skips_todos_with_the_same_title_and_due_date() {
+    let due = Utc.with_ymd_and_hms(2024, 3, 5, 0, 0, 0).unwrap();
+    let mut existing = record("a", "Pay rent");
+    existing.due_date = Some(due);
+    let mut same = record("b", "  pay RENT ");
+    same.due_date = Some(due);
+    let mut other_day = record("c", "Pay rent");
+    other_day.due_date = Some(due + chrono::Duration::days(30));
+    // Duplicates within the input count too
+    let mut repeated = record("d", "Pay rent");
+    repeated.due_date = other_day.due_date;
+
+    let report = plan_import(&[existing], vec![same, other_day, repeated], false);
+    assert_eq!(ids(&report.imported), vec!["c"]);
+    assert_eq!(ids(&report.duplicates), vec!["b", "d"]);
+}
+
+#[test]
+fn subtasks_follow_the_todo_their_parent_duplicates() {
+    let existing = vec![record("a", "Groceries")];
+    let incoming = vec![
+        record("b", "Groceries"),
+        subtask("c", "Milk", "b"),
+        subtask("d", "Stamps", "nowhere"),
+    ];
+
+    let report = plan_import(&existing, incoming, false);
+    assert_eq!(ids(&report.imported), vec!["c", "d"]);
+    assert_eq!(report.imported[0].parent_id.as_deref(), Some("a"));
+    assert_eq!(report.imported[1].parent_id, None);
+}
+
+#[test]
+fn dry_run_reports_without_changing_the_plan() {
+    let existing = vec![record("a", "Laundry")];
+    let incoming = vec![record("a", "Laundry"), record("b", "Dishes")];
+
+    let dry_run = plan_import(&existing, incoming.clone(), true);
+    let real = plan_import(&existing, incoming, false);
+    assert!(dry_run.dry_run);
+    assert!(!real.dry_run);
+    assert_eq!(dry_run.total, real.total);
+    assert_eq!(dry_run.imported, real.imported);
+    assert_eq!(dry_run.duplicates, real.duplicates);
+}
+
+#[test]
+fn reimporting_an_export_finds_only_duplicates() {
+    let todos = vec![record("a", "Laundry"), subtask("b", "Socks", "a"), record("c", "Dishes")];
+
+    for format in [ExportFormat::Json, ExportFormat::Csv, ExportFormat::Markdown] {
+        let exported = export_todos(&todos, format).unwrap();
+        let incoming = parse_todos(&exported, format).unwrap();
+
+        let report = plan_import(&todos, incoming, false);
+        assert!(report.imported.is_empty(), "{:?} imported {:?}", format, report.imported);
+        assert_eq!(report.duplicates.len(), 3);
+    }
+}
+
+#[test]
+fn reassigned_ids_carry_over_to_subtasks() {
+    let incoming = vec![record("a", "Groceries"), subtask("b", "Milk", "a")];
+    let mut report = plan_import(&[], incoming, false);
+
+    report.reassign_ids(&HashSet::from(["a".to_string()]));
+    let new_id = report.reassigned_ids["a"].clone();
+    assert_eq!(report.imported[0].id, new_id);
+    assert_eq!(report.imported[1].parent_id.as_ref(), Some(&new_id));
+}
+
+#[test]
+fn check_returns_parents_outside_the_import() {
+    let todos = vec![record("a", "Groceries"), subtask("b", "Milk", "a"), subtask("c", "Stamps", "existing")];
+
+    assert_eq!(check_imported(&todos).unwrap(), HashSet::from(["existing"]));
+}
+
+#[test]
+fn check_refuses_subtasks_of_subtasks() {
+    let todos = vec![record("a", "Groceries"), subtask("b", "Milk", "a"), subtask("c", "Oat milk", "b")];
+    assert!(matches!(check_imported(&todos), Err(ImportError::NestedSubtask { .. })));
+
+    // A todo with subtasks cannot be a subtask of an existing todo either
+    let todos = vec![subtask("a", "Groceries", "existing"), subtask("b", "Milk", "a")];
+    assert!(matches!(check_imported(&todos), Err(ImportError::NestedSubtask { .. })));
+}
+
+#[test]
+fn check_refuses_invalid_recurrence() {
+    let mut todo = record("a", "Water plants");
+    todo.recurrence = Some("FREQ=DAILY\r\nATTENDEE:evil".to_string());
+
+    assert_eq!(
+        check_imported(&[todo]),
+        Err(ImportError::InvalidRecurrence { id: "a".to_string(), rule: "FREQ=DAILY\r\nATTENDEE:evil".to_string() })
+    );
+}
@@ -0,0 +1,24 @@
+use todo_shared::pinning::{fingerprint, matches, normalize_pin};
+
+const SHA256_OF_ABC: &str = "BA:78:16:BF:8F:01:CF:EA:41:41:40:DE:5D:AE:22:23:B0:03:61:A3:96:17:7A:9C:B4:10:FF:61:F2:00:15:AD";
+
+#[test]
+fn fingerprints_are_colon_separated_sha256() {
+    assert_eq!(fingerprint(b"abc"), SHA256_OF_ABC);
+}
+
+#[test]
+fn pins_are_normalized() {
+    let plain = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    assert_eq!(normalize_pin(plain).as_deref(), Some(SHA256_OF_ABC));
+    assert_eq!(normalize_pin(&format!("  {}\n", SHA256_OF_ABC.to_lowercase())).as_deref(), Some(SHA256_OF_ABC));
+    assert_eq!(normalize_pin("BA:78:16"), None);
+    assert_eq!(normalize_pin("not a fingerprint"), None);
+}
+
+#[test]
+fn certificates_match_their_pin() {
+    assert!(matches(b"abc", "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"));
+    assert!(!matches(b"abd", SHA256_OF_ABC));
+    assert!(!matches(b"abc", ""));
+}
@@ -0,0 +1,231 @@
+use chrono::{DateTime, TimeZone, Utc};
+use chrono_tz::America::New_York;
+use chrono_tz::Asia::Tokyo;
+use chrono_tz::Tz;
+use todo_shared::quick_add::{self, Locale, QuickAdd, ENGLISH};
+use todo_shared::Priority;
+
+/// Wednesday 2024-03-13, 15:00 in New York
+fn now() -> DateTime<Tz> {
+    New_York.with_ymd_and_hms(2024, 3, 13, 15, 0, 0).unwrap()
+}
+
+/// The due date in New York time as "YYYY-MM-DD HH:MM", or "YYYY-MM-DD" if it is all day
+fn local(parsed: &QuickAdd) -> Option<String> {
+    parsed.due_date.map(|due| {
+        if parsed.all_day {
+            due.format("%Y-%m-%d").to_string()
+        } else {
+            due.with_timezone(&New_York).format("%Y-%m-%d %H:%M").to_string()
+        }
+    })
+}
+
+#[test]
+fn parses_dates_and_times() {
+    let cases = [
+        ("Buy milk", "Buy milk", None),
+        ("Buy milk today", "Buy milk", Some("2024-03-13")),
+        ("Buy milk tomorrow", "Buy milk", Some("2024-03-14")),
+        ("Call mom friday", "Call mom", Some("2024-03-15")),
+        ("Call mom on wed", "Call mom", Some("2024-03-13")),
+        ("Call mom this friday", "Call mom", Some("2024-03-15")),
+        ("Call mom next friday", "Call mom", Some("2024-03-22")),
+        ("Read book by sunday", "Read book", Some("2024-03-17")),
+        ("Review next week", "Review", Some("2024-03-18")),
+        ("Review next month", "Review", Some("2024-04-01")),
+        ("Review next year", "Review", Some("2025-01-01")),
+        ("Renew passport in 3 days", "Renew passport", Some("2024-03-16")),
+        ("Renew passport in two weeks", "Renew passport", Some("2024-03-27")),
+        ("Renew passport in a month", "Renew passport", Some("2024-04-13")),
+        ("Check oven in 2 hours", "Check oven", Some("2024-03-13 17:00")),
+        ("Check oven in 30 minutes", "Check oven", Some("2024-03-13 15:30")),
+        ("Dentist march 20 at 3pm", "Dentist", Some("2024-03-20 15:00")),
+        ("Dentist 5th of may", "Dentist", Some("2024-05-05")),
+        ("Dentist on 7 jun", "Dentist", Some("2024-06-07")),
+        ("Anniversary feb 2", "Anniversary", Some("2025-02-02")),
+        ("Taxes april 15 2025", "Taxes", Some("2025-04-15")),
+        ("Taxes due 2024-04-15", "Taxes", Some("2024-04-15")),
+        ("Pay bills on the 15th", "Pay bills", Some("2024-03-15")),
+        ("Pay bills on the 10th", "Pay bills", Some("2024-04-10")),
+        ("Standup 9am", "Standup", Some("2024-03-14 09:00")),
+        ("Standup at 16:30", "Standup", Some("2024-03-13 16:30")),
+        ("Standup at 17", "Standup", Some("2024-03-13 17:00")),
+        ("Call at 9 pm", "Call", Some("2024-03-13 21:00")),
+        ("Lunch at noon tomorrow", "Lunch", Some("2024-03-14 12:00")),
+        ("Meeting tomorrow at 9:30am", "Meeting", Some("2024-03-14 09:30")),
+        ("Meeting friday 12am", "Meeting", Some("2024-03-15 00:00")),
+        ("Feed cat, tomorrow", "Feed cat", Some("2024-03-14")),
+        ("tomorrow", "tomorrow", Some("2024-03-14")),
+        // Words that only look like dates stay in the title
+        ("May the force be with you", "May the force be with you", None),
+        ("Sun tan lotion", "Sun tan lotion", None),
+        ("Meeting with 3 people", "Meeting with 3 people", None),
+        ("Read chapter 12", "Read chapter 12", None),
+    ];
+
+    for (input, title, due) in cases {
+        let parsed = quick_add::parse(input, &now());
+        assert_eq!(parsed.title, title, "title of {:?}", input);
+        assert_eq!(local(&parsed).as_deref(), due, "due date of {:?}", input);
+    }
+}
+
+#[test]
+fn offsets_out_of_range_give_no_date() {
+    let cases = [
+        ("buy milk in 999999999 days", "buy milk in 999999999 days"),
+        ("buy milk in 999999999 weeks", "buy milk in 999999999 weeks"),
+        ("buy milk in 999999999 months", "buy milk in 999999999 months"),
+        ("buy milk in 4000000000 years", "buy milk in 4000000000 years"),
+        ("x in 4000000000 hours", "x"),
+        ("water plants every 999999999 days", "water plants"),
+    ];
+
+    for (input, title) in cases {
+        let parsed = quick_add::parse(input, &now());
+        assert_eq!(parsed.title, title, "title of {:?}", input);
+        assert_eq!(parsed.due_date, None, "due date of {:?}", input);
+    }
+}
+
+#[test]
+fn parses_recurrences() {
+    let cases = [
+        ("Report weekly", "Report", Some("FREQ=WEEKLY"), None),
+        ("Water plants every day", "Water plants", Some("FREQ=DAILY"), None),
+        ("Water plants every other day", "Water plants", Some("FREQ=DAILY;INTERVAL=2"), None),
+        ("Birthday every year", "Birthday", Some("FREQ=YEARLY"), None),
+        ("Inspect every 3 months", "Inspect", Some("FREQ=MONTHLY;INTERVAL=3"), None),
+        (
+            "Gym every monday and thursday",
+            "Gym",
+            Some("FREQ=WEEKLY;BYDAY=MO,TH"),
+            Some("2024-03-14"),
+        ),
+        (
+            "Backup every 2 weeks on fri",
+            "Backup",
+            Some("FREQ=WEEKLY;INTERVAL=2;BYDAY=FR"),
+            Some("2024-03-15"),
+        ),
+        (
+            "Clean every weekend",
+            "Clean",
+            Some("FREQ=WEEKLY;BYDAY=SA,SU"),
+            Some("2024-03-16"),
+        ),
+        (
+            "Standup every weekday at 9am",
+            "Standup",
+            Some("FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR"),
+            Some("2024-03-14 09:00"),
+        ),
+        (
+            "Standup every weekday at 4pm",
+            "Standup",
+            Some("FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR"),
+            Some("2024-03-13 16:00"),
+        ),
+        (
+            "Rent every month on the 1st",
+            "Rent",
+            Some("FREQ=MONTHLY;BYMONTHDAY=1"),
+            Some("2024-04-01"),
+        ),
+        (
+            "Rent every 15th",
+            "Rent",
+            Some("FREQ=MONTHLY;BYMONTHDAY=15"),
+            Some("2024-03-15"),
+        ),
+        (
+            "Review goals weekly next monday",
+            "Review goals",
+            Some("FREQ=WEEKLY"),
+            Some("2024-03-18"),
+        ),
+    ];
+
+    for (input, title, recurrence, due) in cases {
+        let parsed = quick_add::parse(input, &now());
+        assert_eq!(parsed.title, title, "title of {:?}", input);
+        assert_eq!(parsed.recurrence.as_deref(), recurrence, "recurrence of {:?}", input);
+        assert_eq!(local(&parsed).as_deref(), due, "due date of {:?}", input);
+    }
+}
+
+#[test]
+fn parses_priority_tags_and_list() {
+    let cases = [
+        ("Email bob !low @work +Office", "Email bob", Some(Priority::Low), vec!["work"], Some("Office")),
+        ("Ship it !!!", "Ship it", Some(Priority::High), vec![], None),
+        ("Ship it !2", "Ship it", Some(Priority::Medium), vec![], None),
+        ("Fix bug #123 #backend !high", "Fix bug", Some(Priority::High), vec!["123", "backend"], None),
+        ("Say hi! !important", "Say hi! !important", None, vec![], None),
+        ("Learn C++ +1", "Learn C++ +1", None, vec![], None),
+    ];
+
+    for (input, title, priority, tags, list) in cases {
+        let parsed = quick_add::parse(input, &now());
+        assert_eq!(parsed.title, title, "title of {:?}", input);
+        assert_eq!(parsed.priority, priority, "priority of {:?}", input);
+        assert_eq!(parsed.tags, tags, "tags of {:?}", input);
+        assert_eq!(parsed.list.as_deref(), list, "list of {:?}", input);
+    }
+}
+
+#[test]
+fn parses_the_full_example() {
+    let parsed = quick_add::parse("Pay rent every month on the 1st !high #finance tomorrow 9am", &now());
+
+    assert_eq!(parsed.title, "Pay rent");
+    assert_eq!(parsed.priority, Some(Priority::High));
+    assert_eq!(parsed.tags, vec!["finance"]);
+    assert_eq!(parsed.recurrence.as_deref(), Some("FREQ=MONTHLY;BYMONTHDAY=1"));
+    assert_eq!(local(&parsed).as_deref(), Some("2024-03-14 09:00"));
+}
+
+#[test]
+fn resolves_dates_in_the_given_time_zone() {
+    // 19:00 UTC is still the 13th in New York but already the 14th in Tokyo
+    let instant = Utc.with_ymd_and_hms(2024, 3, 13, 19, 0, 0).unwrap();
+
+    let new_york = quick_add::parse("Call tomorrow 9am", &instant.with_timezone(&New_York));
+    let tokyo = quick_add::parse("Call tomorrow 9am", &instant.with_timezone(&Tokyo));
+
+    assert_eq!(new_york.due_date, Some(Utc.with_ymd_and_hms(2024, 3, 14, 13, 0, 0).unwrap()));
+    assert_eq!(tokyo.due_date, Some(Utc.with_ymd_and_hms(2024, 3, 15, 0, 0, 0).unwrap()));
+}
+
+#[test]
+fn handles_daylight_saving_transitions() {
+    // 2:30 does not exist on 2024-03-10 in New York; it moves forward to 3:30 EDT
+    let now = New_York.with_ymd_and_hms(2024, 3, 9, 12, 0, 0).unwrap();
+    let parsed = quick_add::parse("Backup tomorrow 2:30am", &now);
+    assert_eq!(parsed.due_date, Some(Utc.with_ymd_and_hms(2024, 3, 10, 7, 30, 0).unwrap()));
+
+    // 1:30 happens twice on 2024-11-03; the first one, in EDT, is used
+    let now = New_York.with_ymd_and_hms(2024, 11, 2, 12, 0, 0).unwrap();
+    let parsed = quick_add::parse("Backup tomorrow 1:30am", &now);
+    assert_eq!(parsed.due_date, Some(Utc.with_ymd_and_hms(2024, 11, 3, 5, 30, 0).unwrap()));
+
+    // Days are counted in local time, so "in 1 day" across the change is the next day
+    let parsed = quick_add::parse("Backup in 1 day", &New_York.with_ymd_and_hms(2024, 3, 9, 12, 0, 0).unwrap());
+    assert_eq!(local(&parsed).as_deref(), Some("2024-03-10"));
+}
+
+#[test]
+fn other_locales_reuse_the_parser() {
+    let german = Locale {
+        relative_days: &[("heute", 0), ("morgen", 1), ("übermorgen", 2)],
+        time_prepositions: &["um"],
+        ..ENGLISH
+    };
+
+    let parsed = quick_add::parse_with_locale("Einkaufen übermorgen um 18:00 #haushalt", &now(), &german);
+
+    assert_eq!(parsed.title, "Einkaufen");
+    assert_eq!(parsed.tags, vec!["haushalt"]);
+    assert_eq!(local(&parsed).as_deref(), Some("2024-03-15 18:00"));
+}
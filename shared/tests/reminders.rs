@@ -0,0 +1,54 @@
+use chrono::{DateTime, TimeZone, Utc};
+use todo_shared::reminders::{self, Reminder, ReminderTrigger};
+
+fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 3, 1, hour, minute, 0).unwrap()
+}
+
+#[test]
+fn absolute_reminders_ignore_the_due_date() {
+    let reminder = Reminder::new(ReminderTrigger::At { at: at(9, 0) });
+
+    assert_eq!(reminder.fire_at(None), Some(at(9, 0)));
+    assert_eq!(reminder.fire_at(Some(at(17, 0))), Some(at(9, 0)));
+}
+
+#[test]
+fn relative_reminders_need_a_due_date() {
+    let reminder = Reminder::new(ReminderTrigger::BeforeDue { minutes: 30 });
+
+    assert_eq!(reminder.fire_at(Some(at(17, 0))), Some(at(16, 30)));
+    assert_eq!(reminder.fire_at(None), None);
+}
+
+#[test]
+fn snoozing_and_dismissing() {
+    let mut reminder = Reminder::new(ReminderTrigger::BeforeDue { minutes: 0 });
+
+    reminder.snooze(at(18, 0));
+    assert_eq!(reminder.fire_at(Some(at(17, 0))), Some(at(18, 0)));
+
+    reminder.dismiss(at(18, 1));
+    assert_eq!(reminder.fire_at(Some(at(17, 0))), None);
+}
+
+#[test]
+fn deserializes_without_an_id() {
+    let reminder: Reminder = serde_json::from_str(r#"{"trigger":{"type":"before_due","minutes":15}}"#).unwrap();
+
+    assert!(!reminder.id.is_empty());
+    assert_eq!(reminder.trigger, ReminderTrigger::BeforeDue { minutes: 15 });
+}
+
+#[test]
+fn extreme_offsets_never_fire() {
+    let reminder = Reminder::new(ReminderTrigger::BeforeDue { minutes: 100_000_000_000_000 });
+
+    assert!(!reminder.is_valid());
+    assert_eq!(reminder.fire_at(Some(at(17, 0))), None);
+    assert_eq!(Reminder::new(ReminderTrigger::BeforeDue { minutes: i64::MIN }).fire_at(Some(at(17, 0))), None);
+
+    let furthest = Reminder::new(ReminderTrigger::BeforeDue { minutes: reminders::MAX_BEFORE_DUE_MINUTES });
+    assert!(furthest.is_valid());
+    assert!(furthest.fire_at(Some(at(17, 0))).is_some());
+}
@@ -0,0 +1,58 @@
+use serde::Serialize;
+use serde_json::json;
+use todo_shared::revisions::{self, FieldChange};
+
+#[derive(Serialize)]
+struct Todo {
+    title: &'static str,
+    completed: bool,
+    tags: Vec<&'static str>,
+    updated_at: &'static str,
+}
+
+fn todo(title: &'static str, completed: bool) -> Todo {
+    Todo {
+        title,
+        completed,
+        tags: vec!["home"],
+        updated_at: "2024-01-01T00:00:00Z",
+    }
+}
+
+#[test]
+fn diffs_changed_fields_only() {
+    let before = todo("Milk", false);
+    let mut after = todo("Oat milk", true);
+    after.updated_at = "2024-01-02T00:00:00Z";
+
+    assert_eq!(
+        revisions::diff(Some(&before), Some(&after)),
+        vec![
+            FieldChange {
+                field: "completed".to_string(),
+                before: json!(false),
+                after: json!(true),
+            },
+            FieldChange {
+                field: "title".to_string(),
+                before: json!("Milk"),
+                after: json!("Oat milk"),
+            },
+        ]
+    );
+    assert_eq!(revisions::diff(Some(&before), Some(&before)), vec![]);
+}
+
+#[test]
+fn created_and_removed_todos_diff_against_null() {
+    let todo = todo("Milk", false);
+
+    let created = revisions::diff(None, Some(&todo));
+    let fields: Vec<&str> = created.iter().map(|change| change.field.as_str()).collect();
+    assert_eq!(fields, vec!["completed", "tags", "title"]);
+    assert!(created.iter().all(|change| change.before.is_null()));
+
+    let removed = revisions::diff(Some(&todo), None);
+    assert_eq!(removed.len(), 3);
+    assert!(removed.iter().all(|change| change.after.is_null()));
+}
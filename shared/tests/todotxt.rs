@@ -0,0 +1,135 @@
+use chrono::{NaiveDate, TimeZone, Utc};
+use proptest::prelude::*;
+use todo_shared::formats::todotxt::Task;
+use todo_shared::formats::{export_todos, parse_todos, ExportFormat, TodoRecord};
+use todo_shared::Priority;
+
+fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(y, m, d).unwrap()
+}
+
+#[test]
+fn parses_incomplete_task() {
+    let task = Task::parse("(A) 2024-03-01 Call Mom +Family @phone due:2024-03-05").unwrap();
+
+    assert!(!task.completed);
+    assert_eq!(task.priority, Some('A'));
+    assert_eq!(task.creation_date, Some(date(2024, 3, 1)));
+    assert_eq!(task.text, "Call Mom");
+    assert_eq!(task.projects, vec!["Family"]);
+    assert_eq!(task.contexts, vec!["phone"]);
+    assert_eq!(task.due, Some(date(2024, 3, 5)));
+}
+
+#[test]
+fn parses_completed_task() {
+    let task = Task::parse("x 2024-03-02 2024-03-01 Pay rent +Home pri:A").unwrap();
+
+    assert!(task.completed);
+    assert_eq!(task.completion_date, Some(date(2024, 3, 2)));
+    assert_eq!(task.creation_date, Some(date(2024, 3, 1)));
+    assert_eq!(task.text, "Pay rent");
+    assert_eq!(task.extensions, vec![("pri".to_string(), "A".to_string())]);
+
+    let record = task.to_record();
+    assert_eq!(record.priority, Priority::High);
+    assert_eq!(record.updated_at, Utc.with_ymd_and_hms(2024, 3, 2, 0, 0, 0).unwrap());
+}
+
+#[test]
+fn keeps_times_and_urls_in_text() {
+    let task = Task::parse("Meet at 10:30 see https://example.com").unwrap();
+
+    assert_eq!(task.text, "Meet at 10:30 see https://example.com");
+    assert!(task.extensions.is_empty());
+}
+
+#[test]
+fn maps_priorities() {
+    let priority = |line: &str| Task::parse(line).unwrap().to_record().priority;
+
+    assert_eq!(priority("(A) a"), Priority::High);
+    assert_eq!(priority("(B) a"), Priority::Medium);
+    assert_eq!(priority("(D) a"), Priority::Low);
+    assert_eq!(priority("a"), Priority::Medium);
+}
+
+#[test]
+fn maps_projects_and_contexts() {
+    let record = Task::parse("Plan trip +Travel +Summer @home").unwrap().to_record();
+
+    assert_eq!(record.list.as_deref(), Some("Travel"));
+    assert_eq!(record.tags, vec!["home", "Summer"]);
+}
+
+#[test]
+fn skips_blank_lines() {
+    let todos = parse_todos("first\n\n   \nsecond\n", ExportFormat::TodoTxt).unwrap();
+
+    assert_eq!(todos.len(), 2);
+}
+
+fn word() -> impl Strategy<Value = String> {
+    "[a-z][a-z0-9]{0,7}"
+}
+
+fn title() -> impl Strategy<Value = String> {
+    // Start with an uppercase word so a title is never mistaken for `x` or `(A)`
+    ("[A-Z][a-z]{1,7}", prop::collection::vec(word(), 0..5))
+        .prop_map(|(first, rest)| std::iter::once(first).chain(rest).collect::<Vec<_>>().join(" "))
+}
+
+fn day() -> impl Strategy<Value = NaiveDate> {
+    (0i64..20_000).prop_map(|offset| date(1990, 1, 1) + chrono::Duration::days(offset))
+}
+
+fn priority() -> impl Strategy<Value = Priority> {
+    prop_oneof![Just(Priority::Low), Just(Priority::Medium), Just(Priority::High)]
+}
+
+prop_compose! {
+    fn record()(
+        title in title(),
+        completed in any::<bool>(),
+        priority in priority(),
+        created in day(),
+        completed_on in day(),
+        due in prop::option::of(day()),
+        list in prop::option::of(word()),
+        tags in prop::collection::vec(word(), 0..4),
+    ) -> TodoRecord {
+        let midnight = |date: NaiveDate| date.and_hms_opt(0, 0, 0).unwrap().and_utc();
+        let mut record = TodoRecord::new(title);
+        record.completed = completed;
+        record.priority = priority;
+        record.created_at = midnight(created);
+        record.updated_at = if completed { midnight(completed_on) } else { record.created_at };
+        record.due_date = due.map(midnight);
+        record.all_day = due.is_some();
+        record.list = list;
+        record.tags = tags;
+        record
+    }
+}
+
+proptest! {
+    #[test]
+    fn records_round_trip(records in prop::collection::vec(record(), 0..10)) {
+        let text = export_todos(&records, ExportFormat::TodoTxt).unwrap();
+        let parsed = parse_todos(&text, ExportFormat::TodoTxt).unwrap();
+
+        prop_assert_eq!(parsed.len(), records.len());
+        for (parsed, original) in parsed.into_iter().zip(records) {
+            // todo.txt has no ids, so only the id may differ
+            prop_assert_eq!(TodoRecord { id: original.id.clone(), ..parsed }, original);
+        }
+    }
+
+    #[test]
+    fn lines_round_trip(record in record()) {
+        let line = Task::from_record(&record).to_string();
+        let task = Task::parse(&line).unwrap();
+
+        prop_assert_eq!(task.to_string(), line);
+    }
+}
@@ -0,0 +1,177 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use chrono_tz::America::New_York;
+use chrono_tz::Tz;
+use todo_shared::due;
+use todo_shared::formats::TodoRecord;
+use todo_shared::views::{self, DueFilter, SavedView, SmartView, ViewFilter};
+use todo_shared::Priority;
+
+/// Wednesday 2024-03-13, 15:00 in New York
+fn now() -> DateTime<Tz> {
+    New_York.with_ymd_and_hms(2024, 3, 13, 15, 0, 0).unwrap()
+}
+
+fn todo(title: &str, due_in_days: Option<i64>) -> TodoRecord {
+    let mut todo = TodoRecord::new(title.to_string());
+    todo.due_date = due_in_days.map(|days| due::all_day(now().date_naive() + Duration::days(days)));
+    todo.all_day = true;
+    todo
+}
+
+fn titles(todos: &[TodoRecord], filter: &ViewFilter) -> Vec<String> {
+    todos
+        .iter()
+        .filter(|todo| filter.matches(todo, &now()))
+        .map(|todo| todo.title.clone())
+        .collect()
+}
+
+fn todos() -> Vec<TodoRecord> {
+    let mut done_recently = todo("done recently", Some(-1));
+    done_recently.completed = true;
+    done_recently.updated_at = now().with_timezone(&Utc) - Duration::days(2);
+
+    let mut done_long_ago = todo("done long ago", Some(-30));
+    done_long_ago.completed = true;
+    done_long_ago.updated_at = now().with_timezone(&Utc) - Duration::days(30);
+
+    let mut urgent = todo("urgent", None);
+    urgent.priority = Priority::High;
+    urgent.tags = vec!["work".to_string(), "call".to_string()];
+    urgent.list = Some("Office".to_string());
+
+    vec![
+        todo("yesterday", Some(-1)),
+        todo("today", Some(0)),
+        todo("in a week", Some(7)),
+        todo("in a month", Some(30)),
+        urgent,
+        done_recently,
+        done_long_ago,
+    ]
+}
+
+#[test]
+fn built_in_views() {
+    let todos = todos();
+    let view = |view: SmartView| titles(&todos, &view.filter());
+
+    assert_eq!(view(SmartView::Today), vec!["today"]);
+    assert_eq!(view(SmartView::Next7Days), vec!["today", "in a week"]);
+    assert_eq!(view(SmartView::Overdue), vec!["yesterday"]);
+    assert_eq!(view(SmartView::NoDueDate), vec!["urgent"]);
+    assert_eq!(view(SmartView::HighPriority), vec!["urgent"]);
+    assert_eq!(view(SmartView::RecentlyCompleted), vec!["done recently"]);
+}
+
+#[test]
+fn timed_todos_use_the_local_day() {
+    // 01:00 UTC on the 14th is still the evening of the 13th in New York
+    let mut todo = todo("evening", None);
+    todo.due_date = Some(Utc.with_ymd_and_hms(2024, 3, 14, 1, 0, 0).unwrap());
+    todo.all_day = false;
+
+    assert!(SmartView::Today.filter().matches(&todo, &now()));
+    assert!(!SmartView::Today.filter().matches(&todo, &now().with_timezone(&Utc)));
+}
+
+#[test]
+fn custom_filters_combine_conditions() {
+    let todos = todos();
+    let filter = ViewFilter {
+        completed: Some(false),
+        tags: vec!["work".to_string()],
+        list: Some("Office".to_string()),
+        search: Some("URG".to_string()),
+        ..ViewFilter::default()
+    };
+    assert_eq!(titles(&todos, &filter), vec!["urgent"]);
+
+    let filter = ViewFilter {
+        due: Some(DueFilter::Any),
+        completed: Some(false),
+        ..ViewFilter::default()
+    };
+    assert_eq!(titles(&todos, &filter), vec!["yesterday", "today", "in a week", "in a month"]);
+}
+
+#[test]
+fn day_counts_out_of_range_are_invalid_but_safe() {
+    let todos = todos();
+    let forever = ViewFilter {
+        completed_within_days: Some(i64::MAX),
+        ..ViewFilter::default()
+    };
+    assert!(!forever.is_valid());
+    assert_eq!(titles(&todos, &forever), vec!["done recently", "done long ago"]);
+
+    let someday = ViewFilter {
+        due: Some(DueFilter::Within { days: i64::MAX }),
+        ..ViewFilter::default()
+    };
+    assert!(!someday.is_valid());
+    assert_eq!(titles(&todos, &someday), vec!["today", "in a week", "in a month"]);
+
+    let negative = ViewFilter {
+        completed_within_days: Some(-1),
+        ..ViewFilter::default()
+    };
+    assert!(!negative.is_valid());
+    for days in [0, views::MAX_FILTER_DAYS] {
+        let filter = ViewFilter {
+            completed_within_days: Some(days),
+            due: Some(DueFilter::Within { days }),
+            ..ViewFilter::default()
+        };
+        assert!(filter.is_valid(), "{}", days);
+    }
+}
+
+#[test]
+fn counts_built_in_and_saved_views() {
+    let mut saved = SavedView::new(
+        "Work".to_string(),
+        ViewFilter {
+            tags: vec!["work".to_string()],
+            ..ViewFilter::default()
+        },
+    );
+    let mut deleted = SavedView::new("Gone".to_string(), ViewFilter::default());
+    deleted.deleted_at = Some(Utc::now());
+
+    let counts = views::count_views(&todos(), &[saved.clone(), deleted], &now());
+    let counts: Vec<(&str, usize)> = counts.iter().map(|count| (count.id.as_str(), count.count)).collect();
+
+    assert_eq!(
+        counts,
+        vec![
+            ("today", 1),
+            ("next_7_days", 2),
+            ("overdue", 1),
+            ("no_due_date", 1),
+            ("high_priority", 1),
+            ("recently_completed", 1),
+            (saved.id.as_str(), 1),
+        ]
+    );
+
+    saved.name = "Renamed".to_string();
+    assert_eq!(views::find_filter(&saved.id, &[saved.clone()]), Some(saved.filter.0.clone()));
+    assert_eq!(views::find_filter("today", &[]), Some(SmartView::Today.filter()));
+    assert_eq!(views::find_filter("missing", &[saved]), None);
+}
+
+#[test]
+fn filters_round_trip_through_json() {
+    let filter = ViewFilter {
+        priority: Some(Priority::Low),
+        due: Some(DueFilter::Within { days: 3 }),
+        ..ViewFilter::default()
+    };
+
+    let json = serde_json::to_string(&filter).unwrap();
+    assert!(json.contains(r#""due":{"type":"within","days":3}"#));
+    assert_eq!(serde_json::from_str::<ViewFilter>(&json).unwrap(), filter);
+    // Hand-written filters may leave out every condition
+    assert_eq!(serde_json::from_str::<ViewFilter>("{}").unwrap(), ViewFilter::default());
+}